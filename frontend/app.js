@@ -0,0 +1,279 @@
+// The todo list itself is htmx: the server sends HTML fragments and the
+// page swaps them in. This file covers logging in and out, and errors.
+const errorSlot = document.getElementById('error');
+// Empty without a session.
+const csrfToken = JSON.parse(document.body.getAttribute('hx-headers'))['X-CSRF-Token'];
+
+document.getElementById('loggedIn').hidden = !csrfToken;
+document.getElementById('loggedOut').hidden = !!csrfToken;
+
+function showError(message) {
+  const p = document.createElement('p');
+  p.className = 'error';
+  p.setAttribute('role', 'alert');
+  p.textContent = message;
+  errorSlot.replaceChildren(p);
+}
+
+// Fragment handlers answer errors with HTML: a form with its errors in
+// place, or a message aimed at #error. htmx leaves error responses alone
+// unless told to swap them.
+document.body.addEventListener('htmx:beforeSwap', (event) => {
+  const xhr = event.detail.xhr;
+  if (xhr.status < 400) {
+    return;
+  }
+  const contentType = xhr.getResponseHeader('Content-Type') || '';
+  if (contentType.startsWith('text/html')) {
+    event.detail.shouldSwap = true;
+    event.detail.isError = false;
+    return;
+  }
+  // JSON errors from before the handlers: credentials, CSRF, rate limits,
+  // maintenance.
+  let message = xhr.statusText;
+  try {
+    message = JSON.parse(xhr.responseText).error || message;
+  } catch (_) {}
+  if (xhr.status === 401) {
+    message = 'Log in to see your todos.';
+  }
+  showError(message);
+});
+
+document.body.addEventListener('htmx:beforeRequest', () => {
+  errorSlot.replaceChildren();
+});
+
+document.body.addEventListener('htmx:sendError', () => {
+  showError('The server could not be reached.');
+});
+
+// The list's search, filters and offset are kept in the URL fragment, as
+// the query parameters GET /fragments/todos takes, so a reload or a link
+// shows the same page of the same list.
+const listControls = document.getElementById('listControls');
+const LIST_FIELDS = ['q', 'completed', 'include_archived', 'limit'];
+
+function listParams() {
+  return new URLSearchParams(location.hash.slice(1));
+}
+
+function loadList() {
+  htmx.ajax('GET', 'fragments/todos?' + listParams(), {
+    target: '#todo-list',
+    swap: 'outerHTML'
+  });
+}
+
+// Shows the fragment's search and filters in the controls.
+function fillControls() {
+  const params = listParams();
+  listControls.elements.q.value = params.get('q') || '';
+  listControls.elements.completed.value = params.get('completed') || '';
+  listControls.elements.include_archived.checked = params.get('include_archived') === 'true';
+  listControls.elements.limit.value = params.get('limit') || '';
+}
+
+// The theme and the list's default search, filters and page size are kept
+// on the server, see GET /preferences, so they follow the user from
+// browser to browser. A page opened without a fragment starts from them.
+const themeSelect = document.getElementById('theme');
+let preferences = {};
+
+function applyTheme(theme) {
+  document.documentElement.dataset.theme = theme || 'system';
+  themeSelect.value = theme || 'system';
+}
+
+function preferredHash() {
+  const params = new URLSearchParams();
+  const filter = preferences.filter || {};
+  if (filter.q) {
+    params.set('q', filter.q);
+  }
+  if (filter.completed !== undefined) {
+    params.set('completed', String(filter.completed));
+  }
+  if (filter.include_archived) {
+    params.set('include_archived', 'true');
+  }
+  if (preferences.page_size) {
+    params.set('limit', String(preferences.page_size));
+  }
+  return params.toString();
+}
+
+async function loadPreferences() {
+  const res = await fetch('preferences').catch(() => null);
+  if (res && res.ok) {
+    preferences = await res.json();
+  }
+  applyTheme(preferences.theme);
+  const hash = preferredHash();
+  if (!location.hash.slice(1) && hash) {
+    history.replaceState(null, '', '#' + hash);
+  }
+}
+
+// Saving waits for changes to settle, like the search box does.
+let saveTimer;
+function savePreferences() {
+  clearTimeout(saveTimer);
+  saveTimer = setTimeout(async () => {
+    const res = await fetch('preferences', {
+      method: 'PUT',
+      headers: { 'Content-Type': 'application/json', 'X-CSRF-Token': csrfToken },
+      body: JSON.stringify(preferences)
+    }).catch(() => null);
+    if (res && !res.ok && res.status !== 401) {
+      const data = await res.json().catch(() => ({}));
+      showError(data.error || 'Your preferences could not be saved.');
+    }
+  }, 500);
+}
+
+themeSelect.addEventListener('change', () => {
+  preferences.theme = themeSelect.value;
+  applyTheme(preferences.theme);
+  savePreferences();
+});
+
+// The list as currently searched and filtered becomes the default.
+function rememberList(params) {
+  preferences.filter = { include_archived: params.has('include_archived') };
+  if (params.get('q')) {
+    preferences.filter.q = params.get('q');
+  }
+  if (params.has('completed')) {
+    preferences.filter.completed = params.get('completed') === 'true';
+  }
+  if (params.has('limit')) {
+    preferences.page_size = Number(params.get('limit'));
+  } else {
+    delete preferences.page_size;
+  }
+  savePreferences();
+}
+
+// A new search or filter starts again at the first page. Typing replaces
+// the history entry rather than adding one per keystroke.
+let searchTimer;
+listControls.addEventListener('input', () => {
+  clearTimeout(searchTimer);
+  searchTimer = setTimeout(() => {
+    const params = new URLSearchParams();
+    for (const name of LIST_FIELDS) {
+      const field = listControls.elements[name];
+      const value = field.type === 'checkbox' ? (field.checked ? field.value : '') : field.value.trim();
+      if (value) {
+        params.set(name, value);
+      }
+    }
+    history.replaceState(null, '', params.toString() ? '#' + params : location.pathname);
+    loadList();
+    rememberList(params);
+  }, 250);
+});
+listControls.addEventListener('submit', (event) => event.preventDefault());
+
+// Previous and next go through the fragment too, so Back returns to the
+// page before.
+document.body.addEventListener('click', (event) => {
+  const button = event.target.closest('#todo-list [data-offset]');
+  if (!button) {
+    return;
+  }
+  const params = listParams();
+  if (button.dataset.offset === '0') {
+    params.delete('offset');
+  } else {
+    params.set('offset', button.dataset.offset);
+  }
+  location.hash = params.toString();
+});
+
+window.addEventListener('hashchange', () => {
+  fillControls();
+  loadList();
+});
+
+// Every change reloads the list as it is currently searched and paged.
+document.body.addEventListener('todos-changed', loadList);
+
+loadPreferences().then(() => {
+  fillControls();
+  loadList();
+});
+
+// Changes made elsewhere, in another tab or through the API, arrive over
+// GET /ws and reload the list too. A burst of them reloads it once, and a
+// dropped socket is reopened, slower each time, reloading what it missed;
+// one refused from the start, as without credentials, is left closed.
+let refreshTimer;
+let reconnectDelay = 1000;
+
+function refreshSoon() {
+  clearTimeout(refreshTimer);
+  refreshTimer = setTimeout(loadList, 200);
+}
+
+function watchChanges(reconnecting) {
+  const url = new URL('ws', document.baseURI);
+  url.protocol = url.protocol === 'https:' ? 'wss:' : 'ws:';
+  const socket = new WebSocket(url);
+  let opened = false;
+  socket.addEventListener('open', () => {
+    opened = true;
+    reconnectDelay = 1000;
+    if (reconnecting) {
+      refreshSoon();
+    }
+  });
+  socket.addEventListener('message', refreshSoon);
+  socket.addEventListener('close', () => {
+    if (!opened && !reconnecting) {
+      return;
+    }
+    setTimeout(() => watchChanges(true), reconnectDelay);
+    reconnectDelay = Math.min(reconnectDelay * 2, 30000);
+  });
+}
+
+if ('WebSocket' in window) {
+  watchChanges(false);
+}
+
+async function logIn() {
+  const username = document.getElementById('loginUser').value.trim();
+  const password = document.getElementById('loginPassword').value;
+  const res = await fetch('auth/session', {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/json' },
+    body: JSON.stringify({ username, password })
+  });
+
+  if (res.ok) {
+    // The page comes back with the new CSRF token and the user's todos.
+    location.reload();
+    return;
+  }
+  const data = await res.json().catch(() => ({}));
+  showError(data.error || 'Login failed');
+}
+
+async function logOut() {
+  await fetch('auth/session', {
+    method: 'DELETE',
+    headers: { 'X-CSRF-Token': csrfToken }
+  });
+  location.reload();
+}
+
+// Keeps the static files cached so the installed app opens at once; see
+// /sw.js. Browsers only allow it over HTTPS or on localhost.
+if ('serviceWorker' in navigator) {
+  navigator.serviceWorker.register('sw.js').catch((err) => {
+    console.warn('service worker registration failed', err);
+  });
+}
@@ -0,0 +1,2 @@
+ALTER TABLE todos ADD COLUMN due_date TEXT;
+ALTER TABLE todos ADD COLUMN priority INTEGER NOT NULL DEFAULT 3;
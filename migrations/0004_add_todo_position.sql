@@ -0,0 +1,7 @@
+-- Gap-based ordering: neighbours start 1024 apart so a todo can be moved
+-- between two others by taking the midpoint.
+ALTER TABLE todos ADD COLUMN position INTEGER NOT NULL DEFAULT 0;
+
+UPDATE todos SET position = rowid * 1024;
+
+CREATE INDEX IF NOT EXISTS idx_todos_position ON todos (position);
@@ -0,0 +1,2 @@
+-- Soft delete: a set deleted_at hides the todo everywhere until it is purged.
+ALTER TABLE todos ADD COLUMN deleted_at TEXT;
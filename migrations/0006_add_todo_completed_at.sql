@@ -0,0 +1,3 @@
+-- When the todo was last marked completed; cleared again when it is reopened.
+-- Todos completed before this column existed have no timestamp.
+ALTER TABLE todos ADD COLUMN completed_at TEXT;
@@ -0,0 +1,11 @@
+-- Audit trail of changes to each todo, newest last. Purging a todo drops its
+-- history with it.
+CREATE TABLE IF NOT EXISTS todo_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    todo_id TEXT NOT NULL REFERENCES todos(id) ON DELETE CASCADE,
+    at TEXT NOT NULL,
+    action TEXT NOT NULL,
+    changes TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_todo_history_todo ON todo_history (todo_id, id);
@@ -0,0 +1,14 @@
+-- Owners of todos. A user's id is the name they authenticate with; the
+-- `default` user owns every todo created without authentication, including
+-- all todos that predate users.
+CREATE TABLE IF NOT EXISTS users (
+    id TEXT PRIMARY KEY NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+INSERT OR IGNORE INTO users (id, created_at)
+VALUES ('default', strftime('%Y-%m-%dT%H:%M:%fZ', 'now'));
+
+ALTER TABLE todos ADD COLUMN user_id TEXT NOT NULL DEFAULT 'default';
+
+CREATE INDEX IF NOT EXISTS idx_todos_user ON todos (user_id, position);
@@ -0,0 +1,3 @@
+-- Argon2 hash of the password of a user who registered through the API.
+-- Users from the configuration, and the default user, have none here.
+ALTER TABLE users ADD COLUMN password_hash TEXT;
@@ -0,0 +1,4 @@
+-- What a user may do: 'admin' users may call `/admin/*`, members only
+-- manage their own todos.
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'member'
+    CHECK (role IN ('admin', 'member'));
@@ -0,0 +1,10 @@
+-- When each todo was created. Existing todos take the time of their first
+-- history entry; those older than the history stay NULL and match no
+-- created_after/created_before filter.
+ALTER TABLE todos ADD COLUMN created_at TEXT;
+
+UPDATE todos SET created_at = (
+    SELECT at FROM todo_history WHERE todo_history.todo_id = todos.id ORDER BY id LIMIT 1
+);
+
+CREATE INDEX IF NOT EXISTS idx_todos_user_created ON todos (user_id, created_at);
@@ -0,0 +1,13 @@
+-- Read-only links to a user's todos. `id` is the SHA-256 of the link's
+-- token, so the table alone does not give access; deleting a row revokes
+-- the link.
+CREATE TABLE IF NOT EXISTS shares (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- Comma-separated; empty shares every todo.
+    tags TEXT NOT NULL DEFAULT '',
+    created_at TEXT NOT NULL,
+    expires_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_shares_user ON shares (user_id);
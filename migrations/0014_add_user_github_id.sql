@@ -0,0 +1,6 @@
+-- GitHub's numeric user id for users who sign in with GitHub. Logins can be
+-- renamed on GitHub; this id cannot.
+ALTER TABLE users ADD COLUMN github_id INTEGER;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_github_id ON users (github_id)
+    WHERE github_id IS NOT NULL;
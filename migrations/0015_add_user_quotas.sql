@@ -0,0 +1,4 @@
+-- Per-user overrides of the configured quotas; NULL means the default for
+-- the user's role.
+ALTER TABLE users ADD COLUMN max_todos INTEGER CHECK (max_todos >= 0);
+ALTER TABLE users ADD COLUMN writes_per_minute INTEGER CHECK (writes_per_minute >= 0);
@@ -0,0 +1,4 @@
+-- Completed todos moved out of the default list by the archival task, or
+-- with `?include_archived=true` back into it. Reopening a todo unarchives it.
+ALTER TABLE todos ADD COLUMN archived INTEGER NOT NULL DEFAULT 0;
+CREATE INDEX idx_todos_archivable ON todos (completed_at) WHERE completed = 1 AND archived = 0;
@@ -0,0 +1,7 @@
+-- Each user's UI preferences, as the JSON object PUT /preferences
+-- accepted. Without authentication they all belong to `default`.
+CREATE TABLE IF NOT EXISTS preferences (
+    user_id TEXT PRIMARY KEY NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    data TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
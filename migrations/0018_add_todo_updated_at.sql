@@ -0,0 +1,9 @@
+-- When each todo was last written, tags and position included, so the list
+-- can tell clients whether it changed. Existing todos take the time of
+-- their latest history entry.
+ALTER TABLE todos ADD COLUMN updated_at TEXT;
+
+UPDATE todos SET updated_at = COALESCE(
+    (SELECT MAX(at) FROM todo_history WHERE todo_history.todo_id = todos.id),
+    created_at
+);
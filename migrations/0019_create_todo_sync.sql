@@ -0,0 +1,33 @@
+-- The last write to each todo, numbered in one sequence across all users,
+-- for GET /todos/changes. A todo keeps its row once purged, as the
+-- tombstone telling clients to drop it. Triggers keep it current, so every
+-- way of writing todos is covered; a rewritten row is deleted and inserted
+-- again to take the next number.
+CREATE TABLE IF NOT EXISTS todo_sync (
+    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+    todo_id TEXT NOT NULL UNIQUE,
+    user_id TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_todo_sync_user ON todo_sync (user_id, seq);
+
+INSERT INTO todo_sync (todo_id, user_id)
+SELECT id, user_id FROM todos ORDER BY updated_at, id;
+
+CREATE TRIGGER IF NOT EXISTS todo_sync_insert AFTER INSERT ON todos
+BEGIN
+    DELETE FROM todo_sync WHERE todo_id = NEW.id;
+    INSERT INTO todo_sync (todo_id, user_id) VALUES (NEW.id, NEW.user_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS todo_sync_update AFTER UPDATE ON todos
+BEGIN
+    DELETE FROM todo_sync WHERE todo_id = NEW.id;
+    INSERT INTO todo_sync (todo_id, user_id) VALUES (NEW.id, NEW.user_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS todo_sync_delete AFTER DELETE ON todos
+BEGIN
+    DELETE FROM todo_sync WHERE todo_id = OLD.id;
+    INSERT INTO todo_sync (todo_id, user_id) VALUES (OLD.id, OLD.user_id);
+END;
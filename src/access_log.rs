@@ -0,0 +1,370 @@
+//! One line per request, written to its own file apart from the
+//! application log. Lines are handed to a writer thread over a bounded
+//! channel, so a slow or failing disk never holds up a response.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError};
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::Context;
+use axum::{
+    body::HttpBody,
+    extract::State,
+    http::{header, Request},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::auth::Principal;
+use crate::rate_limit;
+use crate::shares;
+
+/// Lines buffered for the writer thread; beyond this they are dropped.
+const QUEUE_LEN: usize = 8192;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessLogConfig {
+    /// Off while unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    pub format: AccessLogFormat,
+    pub rotate: Rotation,
+    /// Size at which the file is rotated with `rotate = "size"`.
+    pub max_size_mb: u64,
+    /// Rotated files kept next to the live one, as `<path>.1` (newest)
+    /// through `<path>.<keep>`.
+    pub keep: usize,
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            format: AccessLogFormat::Combined,
+            rotate: Rotation::Size,
+            max_size_mb: 100,
+            keep: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+    /// Apache/nginx combined log format plus the latency in milliseconds.
+    Combined,
+    Json,
+}
+
+impl FromStr for AccessLogFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "combined" => Ok(AccessLogFormat::Combined),
+            "json" => Ok(AccessLogFormat::Json),
+            _ => Err("expected combined or json".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rotation {
+    /// Once the file reaches `max_size_mb`.
+    Size,
+    /// On the first write after midnight UTC.
+    Daily,
+}
+
+impl FromStr for Rotation {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "size" => Ok(Rotation::Size),
+            "daily" => Ok(Rotation::Daily),
+            _ => Err("expected size or daily".to_string()),
+        }
+    }
+}
+
+/// Lines lost to write errors or a full queue since startup.
+pub static LOST_LINES: AtomicU64 = AtomicU64::new(0);
+
+/// Set once the first loss has been logged, so a broken disk produces one
+/// warning rather than one per request.
+static REPORTED: AtomicBool = AtomicBool::new(false);
+
+fn lose_line(reason: &str, error: Option<&io::Error>) {
+    LOST_LINES.fetch_add(1, Ordering::Relaxed);
+    if !REPORTED.swap(true, Ordering::Relaxed) {
+        match error {
+            Some(err) => {
+                tracing::warn!(error = %err, "{}; further losses are only counted", reason)
+            }
+            None => tracing::warn!("{}; further losses are only counted", reason),
+        }
+    }
+}
+
+/// Handle shared by the middleware; cloning the sender is all requests do.
+#[derive(Debug)]
+pub struct AccessLog {
+    tx: SyncSender<String>,
+    format: AccessLogFormat,
+    trust_forwarded_for: bool,
+}
+
+impl AccessLog {
+    /// Opens the log file and starts its writer thread. `None` while no
+    /// path is configured; an unopenable path is a startup error.
+    pub fn start(
+        config: &AccessLogConfig,
+        trust_forwarded_for: bool,
+    ) -> anyhow::Result<Option<Self>> {
+        let Some(path) = &config.path else {
+            return Ok(None);
+        };
+        let file = RotatingFile::open(path, config)
+            .with_context(|| format!("failed to open access log {}", path.display()))?;
+
+        let (tx, rx) = mpsc::sync_channel(QUEUE_LEN);
+        std::thread::Builder::new()
+            .name("access-log".into())
+            .spawn(move || write_lines(file, rx))
+            .context("failed to start access log writer")?;
+
+        tracing::info!(path = %path.display(), "writing access log");
+        Ok(Some(Self {
+            tx,
+            format: config.format,
+            trust_forwarded_for,
+        }))
+    }
+}
+
+/// Writes queued lines, flushing whenever the queue runs dry so the file is
+/// current while idle. Returns once every sender is gone.
+fn write_lines(mut file: RotatingFile, rx: Receiver<String>) {
+    loop {
+        let line = match rx.try_recv() {
+            Ok(line) => line,
+            Err(TryRecvError::Empty) => {
+                if let Err(err) = file.flush() {
+                    lose_line("failed to flush access log", Some(&err));
+                }
+                match rx.recv() {
+                    Ok(line) => line,
+                    Err(_) => break,
+                }
+            }
+            Err(TryRecvError::Disconnected) => break,
+        };
+        if let Err(err) = file.write_line(&line) {
+            lose_line("failed to write access log", Some(&err));
+        }
+    }
+    let _ = file.flush();
+}
+
+/// The live log file plus what is needed to decide when to rotate it.
+struct RotatingFile {
+    path: PathBuf,
+    rotate: Rotation,
+    max_bytes: u64,
+    keep: usize,
+    writer: BufWriter<File>,
+    written: u64,
+    opened_on: NaiveDate,
+}
+
+impl RotatingFile {
+    fn open(path: &Path, config: &AccessLogConfig) -> io::Result<Self> {
+        let (writer, written) = Self::open_file(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            rotate: config.rotate,
+            max_bytes: config.max_size_mb.saturating_mul(1024 * 1024),
+            keep: config.keep,
+            writer,
+            written,
+            opened_on: Utc::now().date_naive(),
+        })
+    }
+
+    fn open_file(path: &Path) -> io::Result<(BufWriter<File>, u64)> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let len = file.metadata()?.len();
+        Ok((BufWriter::new(file), len))
+    }
+
+    fn due(&self, next_len: u64) -> bool {
+        match self.rotate {
+            Rotation::Size => self.written > 0 && self.written + next_len > self.max_bytes,
+            Rotation::Daily => Utc::now().date_naive() != self.opened_on,
+        }
+    }
+
+    /// Shifts `<path>.N` to `<path>.N+1`, dropping the oldest, and starts a
+    /// fresh file. With `keep = 0` the old file is simply removed.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        let numbered = |n: usize| {
+            let mut name = self.path.clone().into_os_string();
+            name.push(format!(".{}", n));
+            PathBuf::from(name)
+        };
+
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(numbered(self.keep));
+            for n in (1..self.keep).rev() {
+                let from = numbered(n);
+                if from.exists() {
+                    fs::rename(&from, numbered(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, numbered(1))?;
+        }
+
+        let (writer, written) = Self::open_file(&self.path)?;
+        self.writer = writer;
+        self.written = written;
+        self.opened_on = Utc::now().date_naive();
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.due(len) {
+            self.rotate()?;
+        }
+        self.writer.write_all(line.as_bytes())?;
+        self.writer.write_all(b"\n")?;
+        self.written += len;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// What one line records about a request.
+struct Entry {
+    time: DateTime<Utc>,
+    client: Option<String>,
+    /// The [`Principal`] the request was authenticated as.
+    user: Option<String>,
+    method: String,
+    path: String,
+    version: String,
+    status: u16,
+    bytes: Option<u64>,
+    latency_ms: f64,
+    referer: Option<String>,
+    user_agent: Option<String>,
+}
+
+impl Entry {
+    fn render(&self, format: AccessLogFormat) -> String {
+        match format {
+            AccessLogFormat::Combined => format!(
+                "{} - {} [{}] \"{} {} {}\" {} {} \"{}\" \"{}\" {:.3}",
+                self.client.as_deref().unwrap_or("-"),
+                self.user.as_deref().unwrap_or("-"),
+                self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+                self.method,
+                escape(&self.path),
+                self.version,
+                self.status,
+                self.bytes
+                    .map(|bytes| bytes.to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+                escape(self.referer.as_deref().unwrap_or("-")),
+                escape(self.user_agent.as_deref().unwrap_or("-")),
+                self.latency_ms,
+            ),
+            AccessLogFormat::Json => json!({
+                "timestamp": self.time.to_rfc3339(),
+                "client_ip": self.client,
+                "user": self.user,
+                "method": self.method,
+                "path": self.path,
+                "status": self.status,
+                "bytes": self.bytes,
+                "latency_ms": self.latency_ms,
+                "referer": self.referer,
+                "user_agent": self.user_agent,
+            })
+            .to_string(),
+        }
+    }
+}
+
+/// Keeps client-controlled strings inside their quotes and on one line.
+fn escape(value: &str) -> String {
+    value.escape_default().to_string()
+}
+
+/// Queues one line per request once the response head is ready. Never
+/// waits: when the writer falls behind the line is dropped and counted.
+pub async fn record<B>(
+    State(log): State<Arc<AccessLog>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let header = |name| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned)
+    };
+    let time = Utc::now();
+    let client = rate_limit::client_ip(&req, log.trust_forwarded_for).map(|ip| ip.to_string());
+    let method = req.method().to_string();
+    let path = req
+        .uri()
+        .path_and_query()
+        .map_or_else(|| req.uri().path(), |pq| pq.as_str());
+    let path = shares::redact(path).into_owned();
+    let version = format!("{:?}", req.version());
+    let referer = header(header::REFERER).map(|referer| shares::redact(&referer).into_owned());
+    let user_agent = header(header::USER_AGENT);
+
+    let start = Instant::now();
+    let response = next.run(req).await;
+
+    let entry = Entry {
+        time,
+        client,
+        user: response
+            .extensions()
+            .get::<Principal>()
+            .map(|Principal(id)| id.clone()),
+        method,
+        path,
+        version,
+        status: response.status().as_u16(),
+        bytes: response.body().size_hint().exact(),
+        latency_ms: start.elapsed().as_secs_f64() * 1000.0,
+        referer,
+        user_agent,
+    };
+    if log.tx.try_send(entry.render(log.format)).is_err() {
+        lose_line("access log queue is full", None);
+    }
+
+    response
+}
@@ -0,0 +1,298 @@
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{FromRequest, State},
+    http::{Request, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use utoipa::ToSchema;
+
+use crate::auth::{self, Authenticator, UserId};
+use crate::config::Config;
+use crate::db;
+use crate::error::{ApiError, FieldErrors};
+use crate::extract::JsonBody;
+
+pub const REGISTER_PATH: &str = "/auth/register";
+pub const PASSWORD_PATH: &str = "/auth/password";
+
+pub const USERNAME_LEN: RangeInclusive<usize> = 3..=32;
+pub const PASSWORD_LEN: RangeInclusive<usize> = 8..=128;
+
+/// Who may create an account through the API.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Registration {
+    /// Nobody; users come from the configuration only.
+    #[default]
+    Closed,
+    /// Anyone.
+    Open,
+    /// Whoever presents one of `auth.invite_codes`.
+    Invite,
+}
+
+impl FromStr for Registration {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "closed" => Ok(Registration::Closed),
+            "open" => Ok(Registration::Open),
+            "invite" => Ok(Registration::Invite),
+            _ => Err("expected closed, open or invite".to_string()),
+        }
+    }
+}
+
+/// What a user may do beyond managing their own todos.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// May also call `/admin/*`.
+    Admin,
+    Member,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Member => "member",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "admin" => Ok(Role::Admin),
+            "member" => Ok(Role::Member),
+            _ => Err(format!("unknown role {:?}", value)),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct Register {
+    #[serde(default)]
+    username: String,
+    #[serde(default)]
+    password: String,
+    invite_code: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ChangePassword {
+    #[serde(default)]
+    current_password: String,
+    #[serde(default)]
+    new_password: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Account {
+    id: String,
+    role: Role,
+    created_at: DateTime<Utc>,
+}
+
+/// Lowercase ASCII letters, digits, `.`, `_` and `-`, starting with a
+/// letter or digit.
+fn check_username(username: &str) -> Result<(), String> {
+    if !USERNAME_LEN.contains(&username.len()) {
+        return Err(format!(
+            "must be {} to {} characters",
+            USERNAME_LEN.start(),
+            USERNAME_LEN.end()
+        ));
+    }
+    let allowed = |ch: char| ch.is_ascii_lowercase() || ch.is_ascii_digit();
+    if !username.starts_with(allowed)
+        || !username
+            .chars()
+            .all(|ch| allowed(ch) || matches!(ch, '.' | '_' | '-'))
+    {
+        return Err(
+            "may only contain a-z, 0-9, '.', '_' and '-', and must start with a letter or digit"
+                .to_string(),
+        );
+    }
+    Ok(())
+}
+
+fn check_password(password: &str) -> Result<(), String> {
+    if !PASSWORD_LEN.contains(&password.chars().count()) {
+        return Err(format!(
+            "must be {} to {} characters",
+            PASSWORD_LEN.start(),
+            PASSWORD_LEN.end()
+        ));
+    }
+    Ok(())
+}
+
+/// Hashes off the async workers, like verification.
+async fn hash(password: String) -> Result<String, ApiError> {
+    tokio::task::spawn_blocking(move || auth::hash_password(&password))
+        .await
+        .map_err(|err| ApiError::internal().caused_by(err))?
+        .map_err(|err| ApiError::internal().caused_by(err))
+}
+
+/// `POST /auth/register`: creates a user from `{"username", "password"}`,
+/// plus `"invite_code"` when registration is by invite. `201` with the new
+/// account, `409` when the name is taken, `403` while registration is closed
+/// or for a wrong invite code; wrong codes count toward the failed-login
+/// throttle. The first user to register becomes an admin while there is
+/// none, as does anyone listed in `admin.users`.
+#[utoipa::path(
+    post,
+    path = "/auth/register",
+    tag = "account",
+    request_body = Register,
+    responses(
+        (status = 201, description = "The new account", body = Account),
+        (status = 403, description = "Registration is closed, or a wrong invite code", body = ErrorBody),
+        (status = 409, description = "The username is taken", body = ErrorBody),
+        (status = 422, description = "An invalid username or password", body = ErrorBody),
+    )
+)]
+pub async fn register(
+    State(auth): State<Arc<Authenticator>>,
+    State(config): State<Arc<Config>>,
+    State(db): State<SqlitePool>,
+    req: Request<Body>,
+) -> Response {
+    if auth.registration() == Registration::Closed {
+        return ApiError::new(StatusCode::FORBIDDEN, "registration is closed").into_response();
+    }
+    let ip = auth.client_ip(&req);
+    if let Some(response) = auth.refuse_throttled(ip) {
+        return response;
+    }
+
+    let register = match JsonBody::<Register>::from_request(req, &()).await {
+        Ok(JsonBody(register)) => register,
+        Err(err) => return err.into_response(),
+    };
+    if auth.registration() == Registration::Invite {
+        let code = register.invite_code.as_deref().unwrap_or_default();
+        if !auth.invite_code_matches(code) {
+            auth.record_failure(ip);
+            tracing::warn!(client = ?ip, "rejected invalid invite code");
+            return ApiError::new(StatusCode::FORBIDDEN, "invalid invite code").into_response();
+        }
+    }
+
+    let mut errors = FieldErrors::default();
+    errors.check("username", check_username(&register.username));
+    errors.check("password", check_password(&register.password));
+    if let Err(err) = errors.finish() {
+        return err.into_response();
+    }
+
+    let named_admin = config.admin.users.contains(&register.username);
+    let result = async {
+        let hash = hash(register.password).await?;
+        Ok::<_, ApiError>(db::create_user(&db, &register.username, &hash, named_admin).await?)
+    }
+    .await;
+    match result {
+        Ok(Some(role)) => {
+            tracing::info!(user = %register.username, role = role.as_str(), "registered user");
+            let account = Account {
+                id: register.username,
+                role,
+                created_at: Utc::now(),
+            };
+            (StatusCode::CREATED, Json(account)).into_response()
+        }
+        Ok(None) => ApiError::new(StatusCode::CONFLICT, "username is taken").into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+/// `POST /auth/password`: replaces the password of the registered user
+/// making the request, given `{"current_password", "new_password"}`. Users
+/// from the configuration change theirs there, so they get `403`, as does a
+/// wrong current password, which counts toward the failed-login throttle.
+/// Tokens issued before stay valid until they expire.
+#[utoipa::path(
+    post,
+    path = "/auth/password",
+    tag = "account",
+    request_body = ChangePassword,
+    responses(
+        (status = 204, description = "Changed"),
+        (status = 403, description = "A wrong current password, or a configured user", body = ErrorBody),
+        (status = 422, description = "An invalid new password", body = ErrorBody),
+    )
+)]
+pub async fn change_password(
+    State(auth): State<Arc<Authenticator>>,
+    State(db): State<SqlitePool>,
+    UserId(user): UserId,
+    req: Request<Body>,
+) -> Response {
+    if user == db::DEFAULT_USER_ID {
+        return ApiError::new(
+            StatusCode::FORBIDDEN,
+            "log in as a user to change their password",
+        )
+        .into_response();
+    }
+    let ip = auth.client_ip(&req);
+    if let Some(response) = auth.refuse_throttled(ip) {
+        return response;
+    }
+
+    let change = match JsonBody::<ChangePassword>::from_request(req, &()).await {
+        Ok(JsonBody(change)) => change,
+        Err(err) => return err.into_response(),
+    };
+    let mut errors = FieldErrors::default();
+    errors.check("new_password", check_password(&change.new_password));
+    if let Err(err) = errors.finish() {
+        return err.into_response();
+    }
+
+    let result = async {
+        let Some(current) = db::password_hash(&db, &user).await? else {
+            return Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                "this user's password is set in the server configuration",
+            ));
+        };
+        if !auth::verify_hash(Some(current), change.current_password).await {
+            auth.record_failure(ip);
+            tracing::warn!(client = ?ip, "rejected wrong current password");
+            return Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                "current password is incorrect",
+            ));
+        }
+        auth.clear_failures(ip);
+
+        let hash = hash(change.new_password).await?;
+        db::set_password_hash(&db, &user, &hash).await?;
+        Ok(())
+    }
+    .await;
+    match result {
+        Ok(()) => {
+            tracing::info!(user = %user, "changed password");
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(err) => err.into_response(),
+    }
+}
@@ -0,0 +1,355 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use askama::Template;
+use axum::{
+    extract::{Path, State},
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tokio::sync::Mutex;
+use utoipa::ToSchema;
+
+use crate::accounts::Role;
+use crate::auth::{keys_match, presented_key, UserId};
+use crate::backup::{self, LastBackup};
+use crate::config::Config;
+use crate::db::{self, TodoCounts};
+use crate::error::ApiError;
+use crate::extract::JsonBody;
+use crate::health::StartedAt;
+use crate::history::{self, RecentEntry};
+use crate::maintenance::{Maintenance, Status};
+use crate::sessions::Session;
+
+/// Changes listed on the overview.
+const RECENT_CHANGES: i64 = 20;
+
+/// Where soft-deleted todos are purged; admin-only though outside `/admin`.
+pub const TRASH_PATH: &str = "/todos/trash";
+
+/// Whether `path` is `/admin`, under it, or [`TRASH_PATH`].
+pub fn is_admin_path(path: &str) -> bool {
+    path == "/admin" || path.starts_with("/admin/") || path == TRASH_PATH
+}
+
+/// Guards `/admin`, `/admin/*` and [`TRASH_PATH`]. The admin API key passes; otherwise the authenticated
+/// user needs the admin role, and a member gets `403` with
+/// `"code": "admin_required"`. Without a user: `401` without a key, `403`
+/// with a wrong one or when no admin key is configured at all.
+pub async fn require_admin<B>(
+    State(config): State<Arc<Config>>,
+    State(db): State<SqlitePool>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let key = presented_key(req.headers());
+    if let (Some(expected), Some(key)) = (&config.admin.api_key, key) {
+        if keys_match(key, expected.expose()) {
+            return next.run(req).await;
+        }
+    }
+
+    if let Some(UserId(user)) = req.extensions().get::<UserId>().cloned() {
+        return match db::user_role(&db, &user).await {
+            Ok(Some(Role::Admin)) => next.run(req).await,
+            Ok(_) => {
+                tracing::warn!(%user, "refused admin endpoint to non-admin");
+                ApiError::new(StatusCode::FORBIDDEN, "admin role required")
+                    .with_detail("code", "admin_required")
+                    .into_response()
+            }
+            Err(err) => ApiError::from(err).into_response(),
+        };
+    }
+
+    match (&config.admin.api_key, key) {
+        (None, _) => {
+            ApiError::new(StatusCode::FORBIDDEN, "admin endpoints are disabled").into_response()
+        }
+        (Some(_), None) => {
+            ApiError::new(StatusCode::UNAUTHORIZED, "missing admin API key").into_response()
+        }
+        (Some(_), Some(_)) => {
+            ApiError::new(StatusCode::FORBIDDEN, "invalid admin API key").into_response()
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct UserRole {
+    #[serde(default, skip_deserializing)]
+    id: String,
+    role: Role,
+}
+
+/// `PUT /admin/users/:id/role`: makes a user an admin or a member with
+/// `{"role": "admin" | "member"}`. `404` for an unknown user, `403` for the
+/// default user, which stands in for API keys and unauthenticated requests,
+/// and `409` for demoting the last admin.
+#[utoipa::path(
+    put,
+    path = "/admin/users/{id}/role",
+    tag = "admin",
+    params(("id" = String, Path, description = "The user's id")),
+    request_body = UserRole,
+    responses(
+        (status = 200, description = "The user's new role", body = UserRole),
+        (status = 403, description = "The default user", body = ErrorBody),
+        (status = 404, description = "No such user", body = ErrorBody),
+        (status = 409, description = "The last admin", body = ErrorBody),
+    )
+)]
+pub async fn set_role(
+    State(db): State<SqlitePool>,
+    Path(id): Path<String>,
+    JsonBody(change): JsonBody<UserRole>,
+) -> Result<Json<UserRole>, ApiError> {
+    if id == db::DEFAULT_USER_ID {
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "the default user's role cannot change",
+        ));
+    }
+    if change.role == Role::Member
+        && db::user_role(&db, &id).await? == Some(Role::Admin)
+        && db::other_admins(&db, &id).await? == 0
+    {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "cannot demote the last admin",
+        ));
+    }
+    if !db::set_role(&db, &id, change.role).await? {
+        return Err(ApiError::new(StatusCode::NOT_FOUND, "user not found"));
+    }
+
+    tracing::info!(user = %id, role = change.role.as_str(), "changed user role");
+    Ok(Json(UserRole {
+        id,
+        role: change.role,
+    }))
+}
+
+/// Held while a VACUUM runs so two can never overlap.
+static VACUUM_LOCK: Mutex<()> = Mutex::const_new(());
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct VacuumReport {
+    before_bytes: u64,
+    after_bytes: u64,
+    reclaimed_bytes: i64,
+    duration_ms: u64,
+}
+
+fn file_size(config: &Config) -> Result<u64, ApiError> {
+    std::fs::metadata(&config.database.path)
+        .map(|meta| meta.len())
+        .map_err(|err| {
+            tracing::error!(error = %err, "failed to stat database file");
+            ApiError::internal().caused_by(format!("failed to stat database file: {}", err))
+        })
+}
+
+/// `POST /admin/vacuum`: rebuilds the database file with `VACUUM`, then runs
+/// `PRAGMA optimize`. It runs on its own pooled connection in a separate
+/// task, so other requests keep their connections and a client giving up
+/// does not abort it halfway. Writers that collide with it wait at most the
+/// SQLite busy timeout.
+#[utoipa::path(
+    post,
+    path = "/admin/vacuum",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Vacuumed", body = VacuumReport),
+        (status = 409, description = "A vacuum is already running", body = ErrorBody),
+    )
+)]
+pub async fn vacuum(
+    State(db): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+) -> Result<Json<VacuumReport>, ApiError> {
+    let Ok(guard) = VACUUM_LOCK.try_lock() else {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "a vacuum is already running",
+        ));
+    };
+
+    let before_bytes = file_size(&config)?;
+    let start = Instant::now();
+
+    let task = tokio::spawn(async move {
+        let _guard = guard;
+        let mut conn = db.acquire().await?;
+        sqlx::query("VACUUM").execute(&mut *conn).await?;
+        sqlx::query("PRAGMA optimize").execute(&mut *conn).await?;
+        Ok::<_, sqlx::Error>(())
+    });
+    task.await
+        .map_err(|err| ApiError::internal().caused_by(err))??;
+
+    let after_bytes = file_size(&config)?;
+    let report = VacuumReport {
+        before_bytes,
+        after_bytes,
+        reclaimed_bytes: before_bytes as i64 - after_bytes as i64,
+        duration_ms: start.elapsed().as_millis() as u64,
+    };
+
+    tracing::info!(
+        duration_ms = report.duration_ms,
+        before_bytes,
+        after_bytes,
+        reclaimed_bytes = report.reclaimed_bytes,
+        "database vacuumed"
+    );
+
+    Ok(Json(report))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PurgeReport {
+    purged: u64,
+}
+
+/// `DELETE /todos/trash`: removes every user's soft-deleted todos for good,
+/// with their tags and history.
+#[utoipa::path(
+    delete,
+    path = "/todos/trash",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Purged", body = PurgeReport),
+    )
+)]
+pub async fn purge_trash(State(db): State<SqlitePool>) -> Result<Json<PurgeReport>, ApiError> {
+    let purged = db::purge_deleted(&db).await?;
+    tracing::info!(purged, "purged soft-deleted todos");
+    Ok(Json(PurgeReport { purged }))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct StorageReport {
+    file_bytes: u64,
+    file_size: String,
+    /// Every row in `todos`, soft-deleted ones included since they take
+    /// space too.
+    todos: i64,
+    avg_bytes_per_todo: Option<u64>,
+    avg_size_per_todo: Option<String>,
+}
+
+/// `1536` as `"1.5 KiB"`.
+fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// `GET /admin/storage`: the database file size and what that comes to per
+/// todo, a rough guide to when archiving is worth it. The average covers
+/// the whole file, indexes, tags and history included; it is `null` while
+/// there are no todos.
+#[utoipa::path(
+    get,
+    path = "/admin/storage",
+    tag = "admin",
+    responses((status = 200, description = "Database size", body = StorageReport))
+)]
+pub async fn storage(
+    State(db): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+) -> Result<Json<StorageReport>, ApiError> {
+    let file_bytes = file_size(&config)?;
+    let (todos,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM todos")
+        .fetch_one(&db)
+        .await?;
+
+    let avg_bytes_per_todo = (todos > 0).then(|| file_bytes / todos as u64);
+    Ok(Json(StorageReport {
+        file_bytes,
+        file_size: human_bytes(file_bytes),
+        todos,
+        avg_bytes_per_todo,
+        avg_size_per_todo: avg_bytes_per_todo.map(human_bytes),
+    }))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Overview {
+    todos: TodoCounts,
+    file_bytes: u64,
+    file_size: String,
+    uptime_seconds: u64,
+    maintenance: Status,
+    /// `null` until a backup is taken through `/admin/backup`.
+    last_backup: Option<LastBackup>,
+    /// The latest recorded changes to any user's todos, newest first.
+    recent_changes: Vec<RecentEntry>,
+}
+
+/// `GET /admin/overview`: todo counts, database size, uptime, maintenance
+/// mode, the last backup and recent changes, for the dashboard at `/admin`
+/// and for outside tooling alike.
+#[utoipa::path(
+    get,
+    path = "/admin/overview",
+    tag = "admin",
+    responses((status = 200, description = "The state of the server", body = Overview))
+)]
+pub async fn overview(
+    State(db): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    State(StartedAt(started)): State<StartedAt>,
+    State(maintenance): State<Maintenance>,
+) -> Result<Json<Overview>, ApiError> {
+    let file_bytes = file_size(&config)?;
+    Ok(Json(Overview {
+        todos: db::todo_counts(&db).await?,
+        file_bytes,
+        file_size: human_bytes(file_bytes),
+        uptime_seconds: started.elapsed().as_secs(),
+        maintenance: maintenance.status(),
+        last_backup: backup::last(),
+        recent_changes: history::recent(&db, RECENT_CHANGES).await?,
+    }))
+}
+
+#[derive(Template)]
+#[template(path = "admin.html")]
+struct Dashboard<'a> {
+    base: &'a str,
+    csrf_token: String,
+}
+
+/// `GET /admin`: the dashboard. It shows `/admin/overview` and has buttons
+/// for switching maintenance mode, taking a backup and vacuuming, each
+/// showing its result on the page.
+pub async fn dashboard(
+    State(config): State<Arc<Config>>,
+    session: Option<Extension<Session>>,
+) -> Result<Html<String>, ApiError> {
+    Dashboard {
+        base: config.server.base_path.as_str(),
+        csrf_token: session
+            .map(|Extension(session)| session.csrf_token)
+            .unwrap_or_default(),
+    }
+    .render()
+    .map(Html)
+    .map_err(|err| ApiError::internal().caused_by(err))
+}
@@ -0,0 +1,38 @@
+use std::time::Duration;
+
+use chrono::{Duration as ChronoDuration, Utc};
+use sqlx::SqlitePool;
+
+use crate::config::TodosConfig;
+use crate::db;
+
+/// Archives todos completed more than `archive_after_days` ago, now and then
+/// every `archive_interval_secs`, for as long as the process runs. Does
+/// nothing unless `archive_after_days` is set.
+pub fn spawn(config: &TodosConfig, db: SqlitePool) {
+    let Some(days) = config.archive_after_days else {
+        return;
+    };
+    let Some(age) = i64::try_from(days).ok().and_then(ChronoDuration::try_days) else {
+        tracing::warn!(days, "archive_after_days is too large; not archiving");
+        return;
+    };
+    let interval = Duration::from_secs(config.archive_interval_secs.max(1));
+    tracing::info!(
+        days,
+        interval_secs = interval.as_secs(),
+        "archiving completed todos"
+    );
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match db::archive_completed(&db, Utc::now() - age).await {
+                Ok(0) => tracing::debug!("no todos to archive"),
+                Ok(archived) => tracing::info!(archived, "archived completed todos"),
+                Err(err) => tracing::warn!(error = %err, "failed to archive completed todos"),
+            }
+        }
+    });
+}
@@ -0,0 +1,758 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail};
+use argon2::password_hash::{
+    rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+};
+use argon2::Argon2;
+use axum::{
+    async_trait,
+    body::Body,
+    extract::{FromRequest, FromRequestParts, MatchedPath, State},
+    http::{header, request::Parts, HeaderMap, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use subtle::ConstantTimeEq;
+use tracing::Span;
+use utoipa::ToSchema;
+
+use crate::accounts::{self, Registration};
+use crate::admin;
+use crate::config::{AdminConfig, Secret};
+use crate::db;
+use crate::docs;
+use crate::error::ApiError;
+use crate::extract::JsonBody;
+use crate::frontend;
+use crate::github::{self, GitHub, GithubConfig};
+use crate::health;
+use crate::jwt::{Claims, JwtConfig, TokenError, Tokens};
+use crate::pages;
+use crate::pwa;
+use crate::rate_limit;
+use crate::sessions::{self, Session, SessionConfig};
+use crate::shares;
+use crate::version;
+
+/// API keys guard every API route while `api_keys` is non-empty, as do
+/// tokens from `POST /auth/login` once `jwt` is configured; Basic auth, once
+/// configured, guards the HTML page as well.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub api_keys: Vec<Secret>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub basic: Option<UserConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jwt: Option<JwtConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub github: Option<GithubConfig>,
+    /// Also require a key for the HTML page at `/`.
+    pub protect_ui: bool,
+    /// Also require credentials for `/health`, `/livez`, `/readyz` and
+    /// `/version`.
+    pub protect_health: bool,
+    /// Who may create an account at `POST /auth/register`.
+    pub registration: Registration,
+    /// Codes accepted when `registration` is `invite`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub invite_codes: Vec<Secret>,
+    /// Cookie sessions for the HTML page, available with token login.
+    pub session: SessionConfig,
+}
+
+/// A user with a password, for HTTP Basic auth or token login.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserConfig {
+    pub user: String,
+    /// Argon2 hash in PHC form (`$argon2id$v=19$...`), as printed by
+    /// `todo_api hash-password`.
+    pub password_hash: Secret,
+}
+
+/// Realm sent in the Basic and Bearer challenges.
+const REALM: &str = "todos";
+
+pub const LOGIN_PATH: &str = "/auth/login";
+pub const REFRESH_PATH: &str = "/auth/refresh";
+
+/// Failed Basic logins allowed per client within `FAILURE_WINDOW` before
+/// further attempts are refused without checking.
+const MAX_FAILURES: u32 = 5;
+const FAILURE_WINDOW: Duration = Duration::from_secs(60);
+/// Upper bound on tracked clients, like the rate limiter's.
+const MAX_TRACKED_FAILURES: usize = 10_000;
+
+/// Who a request was authenticated as, set on the response so outer layers
+/// such as the access log can report it.
+#[derive(Debug, Clone)]
+pub struct Principal(pub String);
+
+/// The user a request acts for: the one who authenticated with Basic auth
+/// or a token, otherwise [`db::DEFAULT_USER_ID`]. API keys act for the
+/// default user too. Set on the request by [`authenticate`].
+#[derive(Debug, Clone)]
+pub struct UserId(pub String);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for UserId {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts
+            .extensions
+            .get::<UserId>()
+            .cloned()
+            .unwrap_or_else(|| UserId(db::DEFAULT_USER_ID.to_string())))
+    }
+}
+
+/// The API key presented as `X-Api-Key: <key>` or
+/// `Authorization: Bearer <key>`.
+pub fn presented_key(headers: &HeaderMap) -> Option<&str> {
+    if let Some(key) = headers.get("x-api-key").and_then(|v| v.to_str().ok()) {
+        return Some(key);
+    }
+
+    presented_bearer(headers)
+}
+
+/// The token from `Authorization: Bearer <token>`.
+fn presented_bearer(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+/// `(user, password)` from `Authorization: Basic <base64>`.
+fn presented_basic(headers: &HeaderMap) -> Option<(String, String)> {
+    let encoded = headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Basic ")?;
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (user, password) = decoded.split_once(':')?;
+    Some((user.to_string(), password.to_string()))
+}
+
+/// Compares digests rather than the keys themselves so neither the contents
+/// nor the length of the expected key leak through timing.
+pub fn keys_match(presented: &str, expected: &str) -> bool {
+    let presented = Sha256::digest(presented.as_bytes());
+    let expected = Sha256::digest(expected.as_bytes());
+    presented.ct_eq(&expected).into()
+}
+
+/// Short, stable id for a key that is safe to log: the first 8 hex digits
+/// of its SHA-256.
+fn fingerprint(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .take(4)
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[derive(Debug)]
+struct Failures {
+    count: u32,
+    since: Instant,
+}
+
+/// Argon2id hash of `password` in PHC form, under a fresh random salt.
+pub fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)?
+        .to_string())
+}
+
+/// Stands in for the hash of a user that does not exist.
+fn dummy_hash() -> &'static str {
+    static HASH: OnceLock<String> = OnceLock::new();
+    HASH.get_or_init(|| hash_password("no such user").unwrap_or_default())
+}
+
+/// Whether `password` matches `hash`, a PHC string. Without a hash a dummy
+/// one is checked anyway, so an unknown user takes as long as a wrong
+/// password. Argon2 is slow by design; it runs off the async workers.
+pub async fn verify_hash(hash: Option<String>, password: String) -> bool {
+    let known = hash.is_some();
+    let hash = hash.unwrap_or_else(|| dummy_hash().to_string());
+    let password_ok = tokio::task::spawn_blocking(move || {
+        PasswordHash::new(&hash)
+            .map(|hash| {
+                Argon2::default()
+                    .verify_password(password.as_bytes(), &hash)
+                    .is_ok()
+            })
+            .unwrap_or(false)
+    })
+    .await
+    .unwrap_or(false);
+    known && password_ok
+}
+
+/// The hash of `user` among `users`. Every name is compared in constant
+/// time.
+fn configured_hash(users: &[UserConfig], user: &str) -> Option<String> {
+    users
+        .iter()
+        .fold(None, |matched, candidate| {
+            if keys_match(user, &candidate.user) {
+                Some(candidate)
+            } else {
+                matched
+            }
+        })
+        .map(|user| user.password_hash.expose().to_string())
+}
+
+/// Middleware state: the configured credentials plus recent failed logins
+/// per client.
+#[derive(Debug)]
+pub struct Authenticator {
+    config: AuthConfig,
+    tokens: Option<Tokens>,
+    github: Option<GitHub>,
+    /// Stands in for user credentials on `/admin/*`.
+    admin_key: Option<Secret>,
+    /// Registered users and sessions.
+    db: SqlitePool,
+    trust_forwarded_for: bool,
+    /// Whether frontend files are served for unrouted paths.
+    static_files: bool,
+    failures: Mutex<HashMap<IpAddr, Failures>>,
+}
+
+impl Authenticator {
+    /// Fails on a password hash that is not a valid PHC string, so a typo
+    /// surfaces at startup rather than as every login failing, and on a
+    /// missing token signing key in release builds.
+    pub fn new(
+        config: &AuthConfig,
+        admin: &AdminConfig,
+        trust_forwarded_for: bool,
+        static_files: bool,
+        db: SqlitePool,
+    ) -> anyhow::Result<Self> {
+        if let Some(basic) = &config.basic {
+            PasswordHash::new(basic.password_hash.expose())
+                .map_err(|err| anyhow!("invalid auth.basic.password_hash: {}", err))?;
+        }
+        let tokens = config.jwt.as_ref().map(Tokens::new).transpose()?;
+        if config.registration != Registration::Closed && tokens.is_none() {
+            bail!("auth.registration needs token login; configure [auth.jwt]");
+        }
+        if config.registration == Registration::Invite && config.invite_codes.is_empty() {
+            bail!("auth.registration = \"invite\" needs auth.invite_codes");
+        }
+        let github = config.github.as_ref().map(GitHub::new).transpose()?;
+        if github.is_some() && tokens.is_none() {
+            bail!("auth.github needs token login; configure [auth.jwt]");
+        }
+        Ok(Self {
+            config: config.clone(),
+            tokens,
+            github,
+            admin_key: admin.api_key.clone(),
+            db,
+            trust_forwarded_for,
+            static_files,
+            failures: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Everyone who can log in, so each has a `users` row before their
+    /// first request.
+    pub fn user_ids(&self) -> Vec<&str> {
+        let basic = self.config.basic.iter();
+        let tokens = self.tokens.iter().flat_map(|tokens| tokens.users());
+        basic.chain(tokens).map(|user| user.user.as_str()).collect()
+    }
+
+    pub fn session_config(&self) -> &SessionConfig {
+        &self.config.session
+    }
+
+    /// Checks a login against the users in `auth.jwt.users`, then the
+    /// registered ones; configured users take precedence. Always `false`
+    /// without token login.
+    pub async fn check_password(&self, user: &str, password: String) -> Result<bool, sqlx::Error> {
+        let configured = self
+            .tokens
+            .as_ref()
+            .and_then(|tokens| configured_hash(tokens.users(), user));
+        let hash = match configured {
+            Some(hash) => Some(hash),
+            None if self.tokens.is_some() => db::password_hash(&self.db, user).await?,
+            None => None,
+        };
+        Ok(verify_hash(hash, password).await)
+    }
+
+    pub fn registration(&self) -> Registration {
+        self.config.registration
+    }
+
+    /// Whether `code` is one of the invite codes, comparing every one.
+    pub fn invite_code_matches(&self, code: &str) -> bool {
+        self.config
+            .invite_codes
+            .iter()
+            .filter(|invite| keys_match(code, invite.expose()))
+            .count()
+            > 0
+    }
+
+    /// The session for the request's cookie, when sessions are available and
+    /// it has one.
+    async fn session(&self, headers: &HeaderMap) -> Option<Result<Option<Session>, sqlx::Error>> {
+        self.tokens.as_ref()?;
+        let token = sessions::presented_cookie(headers)?;
+        Some(sessions::resolve(&self.db, &self.config.session, token).await)
+    }
+
+    /// Whether the `/auth/*` routes are served.
+    pub fn issues_tokens(&self) -> bool {
+        self.tokens.is_some()
+    }
+
+    /// GitHub sign-in, when configured.
+    pub fn github(&self) -> Option<&GitHub> {
+        self.github.as_ref()
+    }
+
+    fn is_enabled(&self) -> bool {
+        !self.config.api_keys.is_empty() || self.config.basic.is_some() || self.tokens.is_some()
+    }
+
+    /// Routes that stay open unless configured otherwise. Logging in needs
+    /// no token, share links are their own credential, and static UI files
+    /// are as open as the page at `/`.
+    fn is_exempt<B>(&self, req: &Request<B>) -> bool {
+        let (method, path) = (req.method(), req.uri().path());
+        let ui_open = !self.config.protect_ui && self.config.basic.is_none();
+        match path {
+            LOGIN_PATH
+            | accounts::REGISTER_PATH
+            | github::GITHUB_PATH
+            | github::CALLBACK_PATH
+            | frontend::FAVICON_ICO_PATH
+            | frontend::FAVICON_SVG_PATH
+            | frontend::ICON_192_PATH
+            | frontend::ICON_512_PATH
+            | frontend::ROBOTS_PATH
+            | pwa::MANIFEST_PATH
+            | pwa::SERVICE_WORKER_PATH => true,
+            sessions::SESSION_PATH => method == Method::POST,
+            "/" => ui_open,
+            #[cfg(all(feature = "graphql", debug_assertions))]
+            crate::graphql::GRAPHIQL_PATH => ui_open,
+            health::HEALTH_PATH
+            | health::LIVEZ_PATH
+            | health::READYZ_PATH
+            | version::VERSION_PATH => !self.config.protect_health,
+            _ if path.starts_with(shares::SHARED_PREFIX) => true,
+            _ if docs::is_docs_path(path) => ui_open,
+            // Routed requests carry their route; the rest are static files.
+            _ => {
+                self.static_files
+                    && ui_open
+                    && matches!(*method, Method::GET | Method::HEAD)
+                    && req.extensions().get::<MatchedPath>().is_none()
+            }
+        }
+    }
+
+    /// Whether the request is for an admin path with the admin API key, which
+    /// needs no user; [`crate::admin::require_admin`] takes it from there.
+    fn presents_admin_key<B>(&self, req: &Request<B>) -> bool {
+        let (Some(expected), Some(key)) = (&self.admin_key, presented_key(req.headers())) else {
+            return false;
+        };
+        admin::is_admin_path(req.uri().path()) && keys_match(key, expected.expose())
+    }
+
+    /// The user a bare credential acts for, for callers without HTTP
+    /// requests such as gRPC: an API key or a token, as it would be sent in
+    /// `X-Api-Key` or `Authorization: Bearer`. Basic auth and sessions are
+    /// not accepted. Fails with the status [`authenticate`] would answer.
+    #[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+    pub fn bearer_user(&self, presented: Option<&str>) -> Result<String, ApiError> {
+        if !self.is_enabled() {
+            return Ok(db::DEFAULT_USER_ID.to_string());
+        }
+        let Some(presented) = presented else {
+            return Err(ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                "missing credentials",
+            ));
+        };
+
+        let matched = self.config.api_keys.iter().fold(false, |matched, key| {
+            keys_match(presented, key.expose()) | matched
+        });
+        if matched {
+            return Ok(db::DEFAULT_USER_ID.to_string());
+        }
+        if let Some(tokens) = &self.tokens {
+            return tokens
+                .verify(presented)
+                .map(|claims| claims.sub)
+                .map_err(|err| {
+                    tracing::warn!(code = err.code(), "rejected token");
+                    ApiError::new(StatusCode::UNAUTHORIZED, err.message())
+                        .with_detail("code", err.code())
+                });
+        }
+        if self.config.api_keys.is_empty() {
+            Err(ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                "missing credentials",
+            ))
+        } else {
+            tracing::warn!("rejected invalid API key");
+            Err(ApiError::new(StatusCode::FORBIDDEN, "invalid API key"))
+        }
+    }
+
+    /// The client to count failed logins against.
+    pub fn client_ip<B>(&self, req: &Request<B>) -> Option<IpAddr> {
+        rate_limit::client_ip(req, self.trust_forwarded_for)
+    }
+
+    /// `429` when the client failed too many logins lately. Clients without
+    /// an IP, over the Unix socket, are never throttled.
+    pub fn refuse_throttled(&self, ip: Option<IpAddr>) -> Option<Response> {
+        let ip = ip?;
+        let failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+        let throttled = failures.get(&ip).is_some_and(|failures| {
+            failures.count >= MAX_FAILURES && failures.since.elapsed() < FAILURE_WINDOW
+        });
+        drop(failures);
+        throttled.then(|| self.too_many_failures(ip))
+    }
+
+    pub fn record_failure(&self, ip: Option<IpAddr>) {
+        let Some(ip) = ip else {
+            return;
+        };
+        let mut failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+        if !failures.contains_key(&ip) && failures.len() >= MAX_TRACKED_FAILURES {
+            failures.retain(|_, failures| failures.since.elapsed() < FAILURE_WINDOW);
+        }
+        let now = Instant::now();
+        let entry = failures.entry(ip).or_insert(Failures {
+            count: 0,
+            since: now,
+        });
+        if now.duration_since(entry.since) >= FAILURE_WINDOW {
+            *entry = Failures {
+                count: 0,
+                since: now,
+            };
+        }
+        entry.count += 1;
+    }
+
+    pub fn clear_failures(&self, ip: Option<IpAddr>) {
+        let Some(ip) = ip else {
+            return;
+        };
+        let mut failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+        failures.remove(&ip);
+    }
+
+    /// `429` for a client that failed too many logins.
+    fn too_many_failures(&self, ip: IpAddr) -> Response {
+        tracing::warn!(client = %ip, "too many failed logins");
+        let mut response =
+            ApiError::new(StatusCode::TOO_MANY_REQUESTS, "too many failed logins").into_response();
+        response
+            .headers_mut()
+            .insert("retry-after", HeaderValue::from(FAILURE_WINDOW.as_secs()));
+        response
+    }
+
+    /// `401` asking for credentials, with a Basic challenge when Basic auth
+    /// is configured so browsers show their login prompt, or a Bearer one
+    /// when tokens are.
+    fn challenge(&self, message: &str) -> Response {
+        let mut response = ApiError::new(StatusCode::UNAUTHORIZED, message).into_response();
+        let challenge = if self.config.basic.is_some() {
+            Some(format!("Basic realm=\"{}\", charset=\"UTF-8\"", REALM))
+        } else if self.tokens.is_some() {
+            Some(format!("Bearer realm=\"{}\"", REALM))
+        } else {
+            None
+        };
+        if let Some(value) = challenge.and_then(|c| HeaderValue::from_str(&c).ok()) {
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, value);
+        }
+        response
+    }
+
+    /// `401` for a token that failed verification, with its error code in
+    /// both the body and the Bearer challenge.
+    fn reject_token(&self, err: TokenError) -> Response {
+        let mut response = ApiError::new(StatusCode::UNAUTHORIZED, err.message())
+            .with_detail("code", err.code())
+            .into_response();
+        let challenge = format!(
+            "Bearer realm=\"{}\", error=\"invalid_token\", error_description=\"{}\"",
+            REALM,
+            err.message()
+        );
+        if let Ok(value) = HeaderValue::from_str(&challenge) {
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, value);
+        }
+        response
+    }
+}
+
+/// Requires Basic credentials, one of the API keys, a token or a session
+/// cookie, whichever are configured: `401` without credentials, with wrong
+/// Basic ones, with an expired or invalid token or with an expired or
+/// logged-out session (told apart by `code`), `403` with an unknown API key
+/// or with a session cookie but without its CSRF token on a request that
+/// changes something. The admin API key is accepted on `/admin/*` only.
+/// Every key is compared, so timing does not reveal which one was close.
+/// Clients that fail Basic auth `MAX_FAILURES` times within
+/// `FAILURE_WINDOW` get `429` until the window ends. The authenticated user,
+/// or the key's fingerprint, goes on the request span as `principal` and
+/// becomes the [`Principal`]; keys, passwords and tokens are never logged.
+/// The [`UserId`] and, for a token, its [`Claims`] or, for a cookie, its
+/// [`Session`] are added to the request.
+pub async fn authenticate<B>(
+    State(auth): State<Arc<Authenticator>>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let config = &auth.config;
+    if !auth.is_enabled() {
+        return next.run(req).await;
+    }
+    if auth.is_exempt(&req) {
+        // Open routes still learn about a session, so the page can offer
+        // to log out instead of in.
+        if let Some(Ok(Some(session))) = auth.session(req.headers()).await {
+            req.extensions_mut().insert(session);
+        }
+        return next.run(req).await;
+    }
+    if auth.presents_admin_key(&req) {
+        return next.run(req).await;
+    }
+
+    if let (Some(basic), Some((user, password))) = (&config.basic, presented_basic(req.headers())) {
+        let ip = auth.client_ip(&req);
+        if let Some(response) = auth.refuse_throttled(ip) {
+            return response;
+        }
+
+        let hash = configured_hash(std::slice::from_ref(basic), &user);
+        if !verify_hash(hash, password).await {
+            auth.record_failure(ip);
+            tracing::warn!(client = ?ip, "rejected invalid credentials");
+            return auth.challenge("invalid credentials");
+        }
+        auth.clear_failures(ip);
+        req.extensions_mut().insert(UserId(user.clone()));
+        return run_as(format!("user:{}", user), req, next).await;
+    }
+
+    // A bearer value may be an API key or a token; it is taken for a token
+    // only when no key matches.
+    let matched = presented_key(req.headers()).and_then(|presented| {
+        config.api_keys.iter().fold(None, |matched, key| {
+            if keys_match(presented, key.expose()) {
+                Some(key)
+            } else {
+                matched
+            }
+        })
+    });
+    if let Some(key) = matched {
+        return run_as(format!("key:{}", fingerprint(key.expose())), req, next).await;
+    }
+
+    if let (Some(tokens), Some(token)) = (&auth.tokens, presented_bearer(req.headers())) {
+        return match tokens.verify(token) {
+            Ok(claims) => {
+                let principal = format!("user:{}", claims.sub);
+                req.extensions_mut().insert(UserId(claims.sub.clone()));
+                req.extensions_mut().insert(claims);
+                run_as(principal, req, next).await
+            }
+            Err(err) => {
+                tracing::warn!(code = err.code(), "rejected token");
+                auth.reject_token(err)
+            }
+        };
+    }
+
+    if let Some(resolved) = auth.session(req.headers()).await {
+        let session = match resolved {
+            Ok(Some(session)) => session,
+            Ok(None) => {
+                return ApiError::new(StatusCode::UNAUTHORIZED, "session expired or logged out")
+                    .with_detail("code", "session_expired")
+                    .into_response()
+            }
+            Err(err) => return ApiError::from(err).into_response(),
+        };
+        // The page's own forms cannot set headers; their handlers check the
+        // token in the form instead.
+        let form_post = req.uri().path().starts_with(pages::FORM_PREFIX);
+        if sessions::is_state_changing(req.method())
+            && !form_post
+            && !sessions::csrf_matches(req.headers(), &session)
+        {
+            tracing::warn!("rejected request without a valid CSRF token");
+            return ApiError::new(StatusCode::FORBIDDEN, "missing or invalid CSRF token")
+                .with_detail("code", "csrf_failed")
+                .into_response();
+        }
+        let principal = format!("user:{}", session.user_id);
+        req.extensions_mut().insert(UserId(session.user_id.clone()));
+        req.extensions_mut().insert(session);
+        return run_as(principal, req, next).await;
+    }
+
+    if !config.api_keys.is_empty() && presented_key(req.headers()).is_some() {
+        tracing::warn!("rejected invalid API key");
+        return ApiError::new(StatusCode::FORBIDDEN, "invalid API key").into_response();
+    }
+
+    if config.basic.is_some() || auth.tokens.is_some() {
+        auth.challenge("missing credentials")
+    } else {
+        auth.challenge("missing API key")
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct Login {
+    username: String,
+    password: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct IssuedToken {
+    access_token: String,
+    token_type: &'static str,
+    /// Seconds until the token expires.
+    expires_in: u64,
+}
+
+fn issue(tokens: &Tokens, user_id: &str) -> Response {
+    match tokens.issue(user_id) {
+        Ok(access_token) => Json(IssuedToken {
+            access_token,
+            token_type: "Bearer",
+            expires_in: tokens.ttl().as_secs(),
+        })
+        .into_response(),
+        Err(err) => {
+            tracing::error!(error = %err, "failed to sign token");
+            ApiError::internal().caused_by(err).into_response()
+        }
+    }
+}
+
+/// `POST /auth/login`: trades `{"username", "password"}` of a user in
+/// `auth.jwt.users`, or of a registered one, for a token. Configured users
+/// take precedence over registered ones of the same name. Failures count
+/// toward the same per-client throttle as Basic auth.
+#[utoipa::path(
+    post,
+    path = "/auth/login",
+    tag = "account",
+    request_body = Login,
+    responses(
+        (status = 200, description = "A token", body = IssuedToken),
+        (status = 401, description = "Wrong username or password", body = ErrorBody),
+        (status = 429, description = "Too many failed logins from this client", body = ErrorBody),
+    )
+)]
+pub async fn login(State(auth): State<Arc<Authenticator>>, req: Request<Body>) -> Response {
+    let Some(tokens) = &auth.tokens else {
+        return ApiError::new(StatusCode::NOT_FOUND, "not found").into_response();
+    };
+    let ip = auth.client_ip(&req);
+    if let Some(response) = auth.refuse_throttled(ip) {
+        return response;
+    }
+
+    let login = match JsonBody::<Login>::from_request(req, &()).await {
+        Ok(JsonBody(login)) => login,
+        Err(err) => return err.into_response(),
+    };
+    match auth.check_password(&login.username, login.password).await {
+        Ok(true) => {}
+        Ok(false) => {
+            auth.record_failure(ip);
+            tracing::warn!(client = ?ip, "rejected invalid credentials");
+            return ApiError::new(StatusCode::UNAUTHORIZED, "invalid credentials").into_response();
+        }
+        Err(err) => return ApiError::from(err).into_response(),
+    }
+    auth.clear_failures(ip);
+
+    tracing::info!(user = %login.username, "issued token");
+    issue(tokens, &login.username)
+}
+
+/// `POST /auth/refresh`: a new token for the user of the still valid one
+/// presented, so clients can renew before it expires without the password.
+#[utoipa::path(
+    post,
+    path = "/auth/refresh",
+    tag = "account",
+    responses(
+        (status = 200, description = "A new token", body = IssuedToken),
+        (status = 401, description = "No valid bearer token", body = ErrorBody),
+    )
+)]
+pub async fn refresh(
+    State(auth): State<Arc<Authenticator>>,
+    claims: Option<Extension<Claims>>,
+) -> Response {
+    let Some(tokens) = &auth.tokens else {
+        return ApiError::new(StatusCode::NOT_FOUND, "not found").into_response();
+    };
+    match claims {
+        Some(Extension(claims)) => issue(tokens, &claims.sub),
+        None => auth.challenge("refresh requires a bearer token"),
+    }
+}
+
+async fn run_as<B>(principal: String, req: Request<B>, next: Next<B>) -> Response {
+    Span::current().record("principal", principal.as_str());
+    let mut response = next.run(req).await;
+    response.extensions_mut().insert(Principal(principal));
+    response
+}
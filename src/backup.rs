@@ -0,0 +1,192 @@
+use std::ffi::{CStr, CString};
+use std::fs::File;
+use std::os::raw::c_int;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use axum::{
+    body::StreamBody,
+    extract::State,
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use libsqlite3_sys as ffi;
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tokio_util::io::ReaderStream;
+use utoipa::ToSchema;
+
+use crate::config::Config;
+use crate::error::ApiError;
+
+/// Pages copied per step. Between steps the source is unlocked, so writers
+/// wait at most for one step rather than the whole copy.
+const PAGES_PER_STEP: c_int = 256;
+/// Pause between steps, and before retrying a step the source was busy for.
+const STEP_PAUSE: Duration = Duration::from_millis(10);
+const BUSY_TIMEOUT_MS: c_int = 5000;
+
+/// Held while a backup runs so two never fill the disk side by side.
+static BACKUP_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// The last backup taken since the server started, if any.
+static LAST_BACKUP: RwLock<Option<LastBackup>> = RwLock::new(None);
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct LastBackup {
+    pub at: DateTime<Utc>,
+    pub bytes: u64,
+    pub duration_ms: u64,
+}
+
+/// The last backup taken since the server started; backups made by other
+/// means, or before a restart, are not known.
+pub fn last() -> Option<LastBackup> {
+    LAST_BACKUP
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+        .clone()
+}
+
+/// A connection opened outside the pool, closed on drop.
+struct Connection(*mut ffi::sqlite3);
+
+impl Connection {
+    fn open(path: &Path, flags: c_int) -> Result<Self, String> {
+        let name = CString::new(path.to_string_lossy().as_bytes())
+            .map_err(|_| format!("database path {} contains a NUL", path.display()))?;
+        let mut handle = ptr::null_mut();
+        let rc = unsafe { ffi::sqlite3_open_v2(name.as_ptr(), &mut handle, flags, ptr::null()) };
+        let conn = Self(handle);
+        if rc != ffi::SQLITE_OK {
+            return Err(format!(
+                "failed to open {}: {}",
+                path.display(),
+                conn.error()
+            ));
+        }
+        unsafe { ffi::sqlite3_busy_timeout(conn.0, BUSY_TIMEOUT_MS) };
+        Ok(conn)
+    }
+
+    fn error(&self) -> String {
+        if self.0.is_null() {
+            return "out of memory".to_string();
+        }
+        unsafe { CStr::from_ptr(ffi::sqlite3_errmsg(self.0)) }
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        unsafe { ffi::sqlite3_close(self.0) };
+    }
+}
+
+/// Copies the database at `source` into a new file at `dest` with SQLite's
+/// online backup API. The copy is consistent as of its end: a write to the
+/// source between steps makes SQLite start over.
+fn snapshot(source: &Path, dest: &Path) -> Result<(), String> {
+    let source = Connection::open(source, ffi::SQLITE_OPEN_READONLY)?;
+    let dest = Connection::open(dest, ffi::SQLITE_OPEN_READWRITE | ffi::SQLITE_OPEN_CREATE)?;
+
+    let main = c"main";
+    let backup =
+        unsafe { ffi::sqlite3_backup_init(dest.0, main.as_ptr(), source.0, main.as_ptr()) };
+    if backup.is_null() {
+        return Err(format!("failed to start backup: {}", dest.error()));
+    }
+    // Done or failed when it stops returning these; `finish` tells which.
+    while let ffi::SQLITE_OK | ffi::SQLITE_BUSY | ffi::SQLITE_LOCKED =
+        unsafe { ffi::sqlite3_backup_step(backup, PAGES_PER_STEP) }
+    {
+        std::thread::sleep(STEP_PAUSE);
+    }
+    match unsafe { ffi::sqlite3_backup_finish(backup) } {
+        ffi::SQLITE_OK => Ok(()),
+        _ => Err(format!("backup failed: {}", dest.error())),
+    }
+}
+
+/// Takes the snapshot into a temporary file and opens it. The file is
+/// unlinked once open, so it goes away with the handle however the
+/// download ends.
+fn take(source: PathBuf) -> Result<File, String> {
+    let dest = std::env::temp_dir().join(format!("todos-backup-{}.db", ulid::Ulid::new()));
+    let taken = snapshot(&source, &dest).and_then(|()| {
+        File::open(&dest).map_err(|err| format!("failed to open the snapshot: {}", err))
+    });
+    if let Err(err) = std::fs::remove_file(&dest) {
+        tracing::warn!(error = %err, path = %dest.display(), "failed to remove backup file");
+    }
+    taken
+}
+
+/// `GET /admin/backup`: a consistent copy of the database as a `.db`
+/// download, made with the online backup API on a blocking thread. Writers
+/// carry on meanwhile, waiting at most for one step of the copy. `409`
+/// while another backup is running.
+#[utoipa::path(
+    get,
+    path = "/admin/backup",
+    operation_id = "backup",
+    tag = "admin",
+    responses(
+        (status = 200, description = "The database file", body = Vec<u8>, content_type = "application/vnd.sqlite3"),
+        (status = 409, description = "A backup is already running", body = ErrorBody),
+    )
+)]
+pub async fn download(State(config): State<Arc<Config>>) -> Result<Response, ApiError> {
+    let Ok(guard) = BACKUP_LOCK.try_lock() else {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "a backup is already running",
+        ));
+    };
+
+    let start = Instant::now();
+    let source = config.database.path.clone();
+    let file = tokio::task::spawn_blocking(move || {
+        let _guard = guard;
+        take(source)
+    })
+    .await
+    .map_err(|err| ApiError::internal().caused_by(err))?
+    .map_err(|err| {
+        tracing::error!(error = %err, "backup failed");
+        ApiError::internal().caused_by(err)
+    })?;
+    let bytes = file
+        .metadata()
+        .map_err(|err| ApiError::internal().caused_by(err))?
+        .len();
+
+    let duration_ms = start.elapsed().as_millis() as u64;
+    tracing::info!(bytes, duration_ms, "database backed up");
+    *LAST_BACKUP.write().unwrap_or_else(|err| err.into_inner()) = Some(LastBackup {
+        at: Utc::now(),
+        bytes,
+        duration_ms,
+    });
+
+    let filename = format!("todos-{}.db", Utc::now().format("%Y%m%dT%H%M%SZ"));
+    let mut response =
+        StreamBody::new(ReaderStream::new(tokio::fs::File::from_std(file))).into_response();
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/vnd.sqlite3"),
+    );
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(bytes));
+    if let Ok(disposition) =
+        HeaderValue::from_str(&format!("attachment; filename=\"{}\"", filename))
+    {
+        headers.insert(header::CONTENT_DISPOSITION, disposition);
+    }
+    Ok(response)
+}
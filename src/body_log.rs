@@ -0,0 +1,143 @@
+//! `LOG_BODIES`: request and response bodies in the debug log, for
+//! reproducing what a client sent and got back. Only JSON bodies of a known,
+//! modest size are read; secrets are masked before anything is logged.
+
+use axum::{
+    body::{self, Body, Full, HttpBody},
+    http::{header, HeaderMap, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::Value;
+
+use crate::error::ApiError;
+
+/// Longest body text logged; the rest is cut off.
+const MAX_LOGGED_CHARS: usize = 2048;
+/// Larger bodies, or ones of unknown length such as streams, are passed
+/// through unread.
+const MAX_BUFFERED_BYTES: u64 = 256 * 1024;
+const REDACTED: &str = "[redacted]";
+
+/// Headers whose values are never logged.
+const SECRET_HEADERS: &[&str] = &[
+    "authorization",
+    "cookie",
+    "set-cookie",
+    "x-api-key",
+    "x-csrf-token",
+];
+/// JSON fields whose values are never logged, at any depth.
+const SECRET_FIELDS: &[&str] = &[
+    "password",
+    "current_password",
+    "new_password",
+    "token",
+    "access_token",
+    "refresh_token",
+    "csrf_token",
+    "client_secret",
+];
+
+/// Whether the body can be read and logged: JSON, uncompressed, and with a
+/// known length under the buffering limit.
+fn loggable(headers: &HeaderMap, size: Option<u64>) -> bool {
+    let json = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|mime| {
+            let mime = mime.trim();
+            mime == "application/json" || mime.ends_with("+json")
+        });
+    json && !headers.contains_key(header::CONTENT_ENCODING)
+        && size.is_some_and(|size| size <= MAX_BUFFERED_BYTES)
+}
+
+fn redact_value(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                if SECRET_FIELDS.contains(&key.to_ascii_lowercase().as_str()) {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact_value(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_value),
+        _ => {}
+    }
+}
+
+/// The body as logged: JSON with secrets masked, or the raw text when it
+/// does not parse, cut to `MAX_LOGGED_CHARS`.
+fn describe(bytes: &[u8]) -> String {
+    let text = match serde_json::from_slice::<Value>(bytes) {
+        Ok(mut value) => {
+            redact_value(&mut value);
+            value.to_string()
+        }
+        Err(_) => String::from_utf8_lossy(bytes).into_owned(),
+    };
+    match text.char_indices().nth(MAX_LOGGED_CHARS) {
+        Some((cut, _)) => format!("{}... ({} bytes in all)", &text[..cut], bytes.len()),
+        None => text,
+    }
+}
+
+fn describe_headers(headers: &HeaderMap) -> String {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if SECRET_HEADERS.contains(&name.as_str()) {
+                REDACTED
+            } else {
+                value.to_str().unwrap_or("[binary]")
+            };
+            format!("{}: {}", name, value)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Logs the request's headers and body and the response's body at `debug`,
+/// inside the request's span. Bodies that [`loggable`] turns down are
+/// passed through as they are and logged as skipped.
+pub async fn record(req: Request<Body>, next: Next<Body>) -> Response {
+    let (parts, body) = req.into_parts();
+    let headers = describe_headers(&parts.headers);
+    let req = if loggable(&parts.headers, body.size_hint().exact()) {
+        let bytes = match hyper::body::to_bytes(body).await {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                return ApiError::new(StatusCode::BAD_REQUEST, "failed to read the request body")
+                    .caused_by(err)
+                    .into_response()
+            }
+        };
+        tracing::debug!(headers, body = %describe(&bytes), "request body");
+        Request::from_parts(parts, Body::from(bytes))
+    } else {
+        tracing::debug!(headers, "request body not logged");
+        Request::from_parts(parts, body)
+    };
+
+    let response = next.run(req).await;
+    let (parts, body) = response.into_parts();
+    if !loggable(&parts.headers, body.size_hint().exact()) {
+        tracing::debug!(status = parts.status.as_u16(), "response body not logged");
+        return Response::from_parts(parts, body);
+    }
+    match hyper::body::to_bytes(body).await {
+        Ok(bytes) => {
+            tracing::debug!(
+                status = parts.status.as_u16(),
+                body = %describe(&bytes),
+                "response body"
+            );
+            Response::from_parts(parts, body::boxed(Full::from(bytes)))
+        }
+        Err(err) => ApiError::internal().caused_by(err).into_response(),
+    }
+}
@@ -0,0 +1,78 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::State,
+    http::{HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use sqlx::SqlitePool;
+
+use crate::config::TodosConfig;
+
+pub const COUNT_WARNING_HEADER: &str = "x-todo-count-warning";
+
+/// Tracks the todo row count against an operator-chosen threshold. The count
+/// is refreshed in the background so requests never pay for `COUNT(*)`.
+#[derive(Debug)]
+pub struct CountWarning {
+    threshold: i64,
+    refresh: Duration,
+    count: AtomicI64,
+}
+
+impl CountWarning {
+    /// `None` unless a warning threshold is configured.
+    pub fn new(config: &TodosConfig) -> Option<Self> {
+        Some(Self {
+            threshold: config.count_warning_threshold?,
+            refresh: Duration::from_secs(config.count_refresh_secs.max(1)),
+            count: AtomicI64::new(0),
+        })
+    }
+
+    async fn refresh_count(&self, db: &SqlitePool) {
+        match sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM todos WHERE deleted_at IS NULL")
+            .fetch_one(db)
+            .await
+        {
+            Ok(count) => self.count.store(count, Ordering::Relaxed),
+            Err(err) => tracing::warn!(error = %err, "failed to refresh todo count"),
+        }
+    }
+
+    /// Refreshes the cached count now and then on every tick, for as long
+    /// as the process runs.
+    pub fn spawn_refresh(self: &Arc<Self>, db: SqlitePool) {
+        let this = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(this.refresh);
+            loop {
+                ticker.tick().await;
+                this.refresh_count(&db).await;
+            }
+        });
+    }
+}
+
+/// Adds `X-Todo-Count-Warning` to every response while the cached count is
+/// above the threshold.
+pub async fn annotate<B>(
+    State(warning): State<Arc<CountWarning>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let mut response = next.run(req).await;
+
+    let count = warning.count.load(Ordering::Relaxed);
+    if count > warning.threshold {
+        let value = format!("count={}; threshold={}", count, warning.threshold);
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            response.headers_mut().insert(COUNT_WARNING_HEADER, value);
+        }
+    }
+
+    response
+}
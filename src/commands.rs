@@ -0,0 +1,307 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Context};
+use chrono::{DateTime, Utc};
+use clap::{Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::auth;
+use crate::config::Config;
+use crate::db::{self, TodoFilter};
+use crate::history::Action;
+use crate::models::{
+    check_priority, parse_timestamp, responses, TodoChanges, TodoRow, DEFAULT_PRIORITY,
+    PRIORITY_RANGE,
+};
+use crate::tags::{self, Tags};
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// Run the HTTP server (the default when no subcommand is given)
+    Serve,
+    /// Write every todo to a file, or stdout
+    Export {
+        #[arg(long, value_enum, default_value_t = FileFormat::Json)]
+        format: FileFormat,
+        /// Output file [default: stdout]
+        #[arg(long, value_name = "FILE")]
+        out: Option<PathBuf>,
+    },
+    /// Import todos from a JSON or CSV export; todos with a known id are overwritten
+    Import {
+        file: PathBuf,
+        /// [default: guessed from the file extension]
+        #[arg(long, value_enum)]
+        format: Option<FileFormat>,
+    },
+    /// Apply pending database migrations and exit
+    Migrate,
+    /// Print all todos
+    List,
+    /// Add a todo
+    Add {
+        #[arg(required = true, num_args = 1..)]
+        title: Vec<String>,
+        /// Tag to attach; repeat for several
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+        /// Due date, `YYYY-MM-DD` or an RFC 3339 timestamp
+        #[arg(long, value_name = "DATE", value_parser = parse_timestamp)]
+        due: Option<DateTime<Utc>>,
+        /// 1 (lowest) to 5 (most urgent)
+        #[arg(long, default_value_t = DEFAULT_PRIORITY, value_parser = clap::value_parser!(i64).range(PRIORITY_RANGE))]
+        priority: i64,
+    },
+    /// Mark a todo as completed
+    Done { id: String },
+    /// Read a password from stdin and print its Argon2 hash for
+    /// `auth.basic.password_hash` or `auth.jwt.users`
+    HashPassword,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FileFormat {
+    Json,
+    Csv,
+}
+
+/// One CSV row. Tags are comma-separated inside a single column.
+#[derive(Debug, Serialize, Deserialize)]
+struct CsvRecord {
+    #[serde(default)]
+    id: Option<String>,
+    title: String,
+    #[serde(default)]
+    completed: bool,
+    #[serde(default)]
+    completed_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    tags: String,
+    #[serde(default)]
+    due_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    priority: Option<i64>,
+}
+
+/// One todo in a JSON import. Only the title is required.
+#[derive(Debug, Deserialize)]
+struct ImportTodo {
+    #[serde(default)]
+    id: Option<String>,
+    title: String,
+    #[serde(default)]
+    completed: bool,
+    #[serde(default)]
+    completed_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    due_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    priority: Option<i64>,
+}
+
+/// Runs a terminal subcommand against the local database, acting on the
+/// default user's todos. The pool is already migrated.
+pub async fn run(command: Command, config: &Config, db: &SqlitePool) -> anyhow::Result<()> {
+    match command {
+        Command::Serve => unreachable!("serve is handled by main"),
+        Command::Migrate => {
+            println!("database is up to date");
+            Ok(())
+        }
+        Command::Export { format, out } => export(db, format, out).await,
+        Command::Import { file, format } => import(config, db, file, format).await,
+        Command::List => {
+            for todo in db::list_todos(db, db::DEFAULT_USER_ID, &TodoFilter::default()).await? {
+                print!("{}", todo.to_plain_text());
+            }
+            Ok(())
+        }
+        Command::Add {
+            title,
+            tags,
+            due,
+            priority,
+        } => {
+            let title = config.prepare_title(title.join(" "));
+            if title.trim().is_empty() {
+                bail!("title must not be empty");
+            }
+            let mut todo = TodoRow {
+                id: config.todos.id_format.generate(),
+                title,
+                completed: false,
+                completed_at: None,
+                tags: Tags(
+                    tags::normalize(tags).map_err(|err| anyhow!(err.message().to_string()))?,
+                ),
+                due_date: due,
+                priority,
+                position: 0,
+                archived: false,
+            };
+
+            let mut tx = db.begin().await?;
+            if !db::save_todo(&mut tx, db::DEFAULT_USER_ID, &mut todo, Action::Created).await? {
+                bail!("id {} belongs to another user", todo.id);
+            }
+            tx.commit().await?;
+
+            print!("{}", todo.to_plain_text());
+            Ok(())
+        }
+        Command::HashPassword => {
+            let mut password = String::new();
+            io::stdin().read_line(&mut password)?;
+            let password = password.trim_end_matches(['\r', '\n']);
+            if password.is_empty() {
+                bail!("password must not be empty");
+            }
+            let hash = auth::hash_password(password)
+                .map_err(|err| anyhow!("failed to hash password: {}", err))?;
+            println!("{}", hash);
+            Ok(())
+        }
+        Command::Done { id } => {
+            let changes = TodoChanges {
+                completed: Some(true),
+                ..TodoChanges::default()
+            };
+
+            let mut conn = db.acquire().await?;
+            match db::update_todo(&mut conn, db::DEFAULT_USER_ID, &id, changes).await? {
+                Some(todo) => {
+                    print!("{}", todo.to_plain_text());
+                    Ok(())
+                }
+                None => bail!("todo {} not found", id),
+            }
+        }
+    }
+}
+
+async fn export(db: &SqlitePool, format: FileFormat, out: Option<PathBuf>) -> anyhow::Result<()> {
+    let todos = db::list_todos(db, db::DEFAULT_USER_ID, &TodoFilter::default()).await?;
+    let count = todos.len();
+
+    let writer: Box<dyn Write> = match &out {
+        Some(path) => {
+            Box::new(BufWriter::new(File::create(path).with_context(|| {
+                format!("failed to create {}", path.display())
+            })?))
+        }
+        None => Box::new(io::stdout().lock()),
+    };
+
+    match format {
+        FileFormat::Json => {
+            let mut writer = writer;
+            serde_json::to_writer_pretty(&mut writer, &responses(todos))?;
+            writeln!(writer)?;
+            writer.flush()?;
+        }
+        FileFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(writer);
+            for todo in &todos {
+                writer.serialize(CsvRecord {
+                    id: Some(todo.id.clone()),
+                    title: todo.title.clone(),
+                    completed: todo.completed,
+                    completed_at: todo.completed_at,
+                    tags: todo.tags.0.join(","),
+                    due_date: todo.due_date,
+                    priority: Some(todo.priority),
+                })?;
+            }
+            writer.flush()?;
+        }
+    }
+
+    if let Some(path) = out {
+        eprintln!("exported {} todos to {}", count, path.display());
+    }
+    Ok(())
+}
+
+async fn import(
+    config: &Config,
+    db: &SqlitePool,
+    file: PathBuf,
+    format: Option<FileFormat>,
+) -> anyhow::Result<()> {
+    let format = match format {
+        Some(format) => format,
+        None if file
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("csv")) =>
+        {
+            FileFormat::Csv
+        }
+        None => FileFormat::Json,
+    };
+    let reader = File::open(&file).with_context(|| format!("failed to open {}", file.display()))?;
+
+    let items: Vec<ImportTodo> = match format {
+        FileFormat::Json => serde_json::from_reader(reader)
+            .with_context(|| format!("{} is not a JSON list of todos", file.display()))?,
+        FileFormat::Csv => csv::Reader::from_reader(reader)
+            .deserialize::<CsvRecord>()
+            .map(|record| {
+                record.map(|record| ImportTodo {
+                    id: record.id.filter(|id| !id.is_empty()),
+                    title: record.title,
+                    completed: record.completed,
+                    completed_at: record.completed_at,
+                    tags: record.tags.split(',').map(str::to_owned).collect(),
+                    due_date: record.due_date,
+                    priority: record.priority,
+                })
+            })
+            .collect::<Result<_, _>>()
+            .with_context(|| format!("{} is not a CSV export", file.display()))?,
+    };
+
+    let mut tx = db.begin().await?;
+    for (index, item) in items.iter().enumerate() {
+        let title = config.prepare_title(item.title.clone());
+        if title.trim().is_empty() {
+            bail!("item {}: title must not be empty", index + 1);
+        }
+        let tags = tags::normalize(
+            item.tags
+                .iter()
+                .filter(|tag| !tag.trim().is_empty())
+                .cloned()
+                .collect(),
+        )
+        .map_err(|err| anyhow!("item {}: {}", index + 1, err.message()))?;
+        let priority = check_priority(item.priority.unwrap_or(DEFAULT_PRIORITY))
+            .map_err(|err| anyhow!("item {}: priority {}", index + 1, err))?;
+
+        let mut todo = TodoRow {
+            id: item
+                .id
+                .clone()
+                .unwrap_or_else(|| config.todos.id_format.generate()),
+            title,
+            completed: item.completed,
+            completed_at: item.completed_at.filter(|_| item.completed),
+            tags: Tags(tags),
+            due_date: item.due_date,
+            priority,
+            position: 0,
+            archived: false,
+        };
+        if !db::save_todo(&mut tx, db::DEFAULT_USER_ID, &mut todo, Action::Imported).await? {
+            bail!("item {}: id {} belongs to another user", index + 1, todo.id);
+        }
+    }
+    tx.commit().await?;
+
+    println!("imported {} todos", items.len());
+    Ok(())
+}
@@ -0,0 +1,797 @@
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context};
+use clap::Parser;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::access_log::AccessLogConfig;
+use crate::auth::{AuthConfig, UserConfig};
+use crate::commands::Command;
+use crate::github::GithubConfig;
+use crate::grpc::GrpcConfig;
+use crate::jwt::JwtConfig;
+use crate::latency_budget::LatencyBudgetConfig;
+use crate::load_shed::LoadShedConfig;
+use crate::logging::LogFormat;
+use crate::metrics::MetricsConfig;
+use crate::quotas::QuotaConfig;
+use crate::rate_limit::RateLimitConfig;
+use crate::reporting::ReportingConfig;
+use crate::trailing_slash::TrailingSlash;
+
+/// Config file read when `--config` is not given, if it exists.
+pub const DEFAULT_CONFIG_PATH: &str = "todo.toml";
+
+/// Command-line flags. These override both the config file and env vars.
+/// They are global so they work before or after the subcommand; running
+/// without a subcommand serves, as the binary always has.
+#[derive(Debug, Default, Parser)]
+#[command(version, about = "A small Todo API backed by SQLite")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// TOML config file [default: ./todo.toml when present]
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Address to listen on, e.g. 127.0.0.1:3000 or [::1]:3000; repeat to
+    /// listen on several
+    #[arg(long, global = true)]
+    pub addr: Vec<SocketAddr>,
+
+    /// Listen on this Unix domain socket instead of TCP
+    #[arg(long, global = true, value_name = "PATH")]
+    pub unix_socket: Option<PathBuf>,
+
+    /// Serve the UI from this directory instead of the built-in page
+    #[arg(long, global = true, value_name = "DIR")]
+    pub static_dir: Option<PathBuf>,
+
+    /// SQLite database file
+    #[arg(long, global = true, value_name = "FILE")]
+    pub db: Option<PathBuf>,
+
+    /// Log output format
+    #[arg(long, global = true, value_enum, value_name = "FORMAT")]
+    pub log_format: Option<LogFormat>,
+
+    /// Log filter, e.g. `info` or `todo_api=debug,sqlx=warn`
+    #[arg(long, global = true, value_name = "FILTER")]
+    pub log_level: Option<String>,
+
+    /// Print the effective configuration as TOML and exit
+    #[arg(long, global = true)]
+    pub print_config: bool,
+}
+
+/// Effective configuration: defaults, then the TOML file, then environment
+/// variables, then command-line flags.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub log: LogConfig,
+    pub access_log: AccessLogConfig,
+    pub reporting: ReportingConfig,
+    pub cors: CorsConfig,
+    pub rate_limit: RateLimitConfig,
+    pub quotas: QuotaConfig,
+    pub load_shed: LoadShedConfig,
+    pub metrics: MetricsConfig,
+    pub latency_budget: LatencyBudgetConfig,
+    pub grpc: GrpcConfig,
+    pub todos: TodosConfig,
+    pub admin: AdminConfig,
+    pub auth: AuthConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// TCP addresses to listen on; one listener each. A single address may
+    /// be written as a plain string.
+    #[serde(alias = "addr", deserialize_with = "one_or_many")]
+    pub addrs: Vec<SocketAddr>,
+    /// Listen on this Unix domain socket. TCP is then off unless
+    /// `keep_tcp_listener` is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unix_socket: Option<PathBuf>,
+    pub unix_socket_mode: FileMode,
+    pub keep_tcp_listener: bool,
+    /// Serve a JSON name/version document at `/` instead of the HTML UI.
+    pub disable_ui: bool,
+    /// Serve the UI from this directory: `index.html` at `/` and other
+    /// files at their paths. The built-in page is used while it is absent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub static_dir: Option<PathBuf>,
+    /// Answer `GET`s for unknown paths without a file extension with the
+    /// page at `/`, for a frontend that routes in the browser.
+    pub spa_fallback: bool,
+    /// The body of `/robots.txt`. Unset, it disallows every crawler.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub robots_txt: Option<String>,
+    /// Turn off the API documentation at `/docs`.
+    pub disable_docs: bool,
+    /// Handlers running longer than this answer `503`.
+    pub request_timeout_secs: u64,
+    /// Keep accepting for this long after a shutdown signal while
+    /// `/readyz` reports draining.
+    pub shutdown_delay_secs: u64,
+    pub trailing_slash: TrailingSlash,
+    /// Also accept `"true"`/`"false"` and `1`/`0` for boolean body fields.
+    pub lenient_json: bool,
+    /// The path the app is served under, such as `/todos-app`, behind a
+    /// proxy or when nested in another router. Links and redirects the app
+    /// generates start with it.
+    pub base_path: BasePath,
+}
+
+impl ServerConfig {
+    pub fn listen_tcp(&self) -> bool {
+        self.unix_socket.is_none() || self.keep_tcp_listener
+    }
+}
+
+/// Unix permission bits, written in octal: `"660"` or `"0660"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct FileMode(pub u32);
+
+impl FromStr for FileMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let digits = value.strip_prefix("0o").unwrap_or(value);
+        match u32::from_str_radix(digits, 8) {
+            Ok(mode) if mode <= 0o777 => Ok(FileMode(mode)),
+            _ => Err(format!(
+                "expected octal permissions like 660, got {:?}",
+                value
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for FileMode {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<FileMode> for String {
+    fn from(mode: FileMode) -> Self {
+        format!("{:04o}", mode.0)
+    }
+}
+
+/// A path prefix: empty, or starting with `/` and not ending with one.
+/// `"/"` and a trailing slash are accepted and dropped.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct BasePath(String);
+
+impl BasePath {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// `path`, which starts with `/`, under this prefix. `/` itself is the
+    /// prefix alone, which is where a nested router serves its root.
+    pub fn url(&self, path: &str) -> String {
+        if path == "/" && !self.is_root() {
+            return self.0.clone();
+        }
+        format!("{}{}", self.0, path)
+    }
+}
+
+impl FromStr for BasePath {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let trimmed = value.trim_end_matches('/');
+        if !value.is_empty() && !value.starts_with('/') {
+            return Err(format!("expected a path starting with /, got {:?}", value));
+        }
+        if trimmed.contains(['?', '#', '"', '<', '>', ' ']) || trimmed.contains("//") {
+            return Err(format!("expected a plain path, got {:?}", value));
+        }
+        Ok(BasePath(trimmed.to_owned()))
+    }
+}
+
+impl TryFrom<String> for BasePath {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<BasePath> for String {
+    fn from(base: BasePath) -> Self {
+        base.0
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            addrs: vec![SocketAddr::from(([127, 0, 0, 1], 3000))],
+            unix_socket: None,
+            unix_socket_mode: FileMode(0o660),
+            keep_tcp_listener: false,
+            disable_ui: false,
+            static_dir: None,
+            spa_fallback: false,
+            robots_txt: None,
+            disable_docs: false,
+            request_timeout_secs: 10,
+            shutdown_delay_secs: 0,
+            trailing_slash: TrailingSlash::default(),
+            lenient_json: false,
+            base_path: BasePath::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub path: PathBuf,
+    /// A read-only replica of `path` for list and single-todo reads.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_path: Option<PathBuf>,
+    pub max_connections: u32,
+    /// Connections opened at startup and kept open when idle.
+    pub min_connections: u32,
+    /// Open the `min_connections` this far apart at startup instead of
+    /// back to back, to spare a database shared with others.
+    pub connect_ramp_ms: u64,
+    pub acquire_timeout_secs: u64,
+    /// Connect attempts after the first before giving up at startup.
+    pub connect_retries: u32,
+    /// Wait before the first retry, doubling before each further one.
+    pub connect_delay_ms: u64,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("data/todos.db"),
+            read_path: None,
+            max_connections: 10,
+            min_connections: 0,
+            connect_ramp_ms: 0,
+            acquire_timeout_secs: 30,
+            connect_retries: 3,
+            connect_delay_ms: 500,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// `tracing` filter directive.
+    pub level: String,
+    /// Defaults to pretty in debug builds and JSON in release builds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<LogFormat>,
+    /// Log JSON request and response bodies, secrets masked, at `debug`.
+    pub bodies: bool,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            format: None,
+            bodies: false,
+        }
+    }
+}
+
+impl LogConfig {
+    pub fn format(&self) -> LogFormat {
+        self.format.unwrap_or_else(LogFormat::default_for_build)
+    }
+}
+
+/// CORS stays off while `allowed_origins` is empty.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    /// Exact origins, or `["*"]` for any.
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    pub max_age_secs: u64,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: Vec::new(),
+            allowed_methods: ["GET", "POST", "PUT", "PATCH", "DELETE"]
+                .map(String::from)
+                .to_vec(),
+            allowed_headers: ["content-type", "authorization", "x-api-key", "x-request-id"]
+                .map(String::from)
+                .to_vec(),
+            max_age_secs: 600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TodosConfig {
+    /// Collapse runs of whitespace in titles to a single space and trim the
+    /// ends before storing.
+    pub normalize_titles: bool,
+    /// Refuse to create a todo whose title, ignoring case, matches another
+    /// open todo of the same user.
+    pub unique_title_per_user: bool,
+    /// Add `X-Todo-Count-Warning` to responses above this many todos.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count_warning_threshold: Option<i64>,
+    pub count_refresh_secs: u64,
+    /// How ids of new todos are generated.
+    pub id_format: IdFormat,
+    /// Archive todos completed more than this many days ago; off when
+    /// unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive_after_days: Option<u64>,
+    /// How often the archival task runs.
+    pub archive_interval_secs: u64,
+}
+
+impl Default for TodosConfig {
+    fn default() -> Self {
+        Self {
+            normalize_titles: false,
+            unique_title_per_user: false,
+            count_warning_threshold: None,
+            count_refresh_secs: 30,
+            id_format: IdFormat::default(),
+            archive_after_days: None,
+            archive_interval_secs: 3600,
+        }
+    }
+}
+
+/// Format of generated todo ids. Lookups take any stored id, so switching
+/// leaves existing todos reachable.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdFormat {
+    /// Random v4 UUIDs.
+    #[default]
+    Uuid,
+    /// ULIDs, which sort by creation time: 48 bits of milliseconds, then
+    /// 80 random bits.
+    Ulid,
+}
+
+impl FromStr for IdFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "uuid" => Ok(IdFormat::Uuid),
+            "ulid" => Ok(IdFormat::Ulid),
+            _ => Err("expected uuid or ulid".to_string()),
+        }
+    }
+}
+
+impl IdFormat {
+    pub fn generate(self) -> String {
+        match self {
+            IdFormat::Uuid => Uuid::new_v4().to_string(),
+            IdFormat::Ulid => Ulid::new().to_string(),
+        }
+    }
+}
+
+/// `/admin/*` endpoints answer `403` until an API key is configured or a
+/// user has the admin role.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<Secret>,
+    /// Users made admins at startup, or when they register.
+    pub users: Vec<String>,
+}
+
+/// A configured secret. Serializes as `"[redacted]"` so it never appears in
+/// `--print-config` output or the startup log.
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Secret([redacted])")
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str("[redacted]")
+    }
+}
+
+impl FromStr for Secret {
+    type Err = std::convert::Infallible;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(Secret(value.to_string()))
+    }
+}
+
+impl Config {
+    /// Resolves the effective configuration for `cli`. An explicit
+    /// `--config` must exist; the default `todo.toml` is optional.
+    pub fn load(cli: &Cli) -> anyhow::Result<Self> {
+        let mut config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_PATH))?
+            }
+            None => Self::default(),
+        };
+
+        config.apply_env()?;
+        config.apply_cli(cli);
+        Ok(config)
+    }
+
+    /// Parses a TOML file. Parse errors carry the file name plus the line and
+    /// column reported by the TOML parser.
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+        toml::from_str(&text).map_err(|err| anyhow!("{}: {}", path.display(), err))
+    }
+
+    fn apply_env(&mut self) -> anyhow::Result<()> {
+        env_parse_list("BIND_ADDR", &mut self.server.addrs)?;
+        env_parse_opt("UNIX_SOCKET", &mut self.server.unix_socket)?;
+        env_parse("UNIX_SOCKET_MODE", &mut self.server.unix_socket_mode)?;
+        env_bool("KEEP_TCP_LISTENER", &mut self.server.keep_tcp_listener)?;
+        env_bool("DISABLE_UI", &mut self.server.disable_ui)?;
+        env_parse_opt("STATIC_DIR", &mut self.server.static_dir)?;
+        env_bool("SPA_FALLBACK", &mut self.server.spa_fallback)?;
+        env_parse_opt("ROBOTS_TXT", &mut self.server.robots_txt)?;
+        env_bool("DISABLE_DOCS", &mut self.server.disable_docs)?;
+        env_parse(
+            "REQUEST_TIMEOUT_SECS",
+            &mut self.server.request_timeout_secs,
+        )?;
+        env_parse("SHUTDOWN_DELAY_SECS", &mut self.server.shutdown_delay_secs)?;
+        env_parse("TRAILING_SLASH", &mut self.server.trailing_slash)?;
+        env_bool("LENIENT_JSON", &mut self.server.lenient_json)?;
+        env_parse("BASE_PATH", &mut self.server.base_path)?;
+
+        env_parse("DATABASE_PATH", &mut self.database.path)?;
+        env_parse_opt("READ_DATABASE_PATH", &mut self.database.read_path)?;
+        if let Ok(url) = std::env::var("READ_DATABASE_URL") {
+            if std::env::var_os("READ_DATABASE_PATH").is_some() {
+                return Err(anyhow!(
+                    "READ_DATABASE_URL and READ_DATABASE_PATH are both set; set one"
+                ));
+            }
+            let path = sqlite_path(&url)
+                .map_err(|err| anyhow!("invalid value {:?} for READ_DATABASE_URL: {}", url, err))?;
+            self.database.read_path = Some(path);
+        }
+        env_parse("DB_MAX_CONNECTIONS", &mut self.database.max_connections)?;
+        env_parse("DB_MIN_CONNECTIONS", &mut self.database.min_connections)?;
+        env_parse("DB_CONNECT_RAMP", &mut self.database.connect_ramp_ms)?;
+        env_parse(
+            "DB_ACQUIRE_TIMEOUT_SECS",
+            &mut self.database.acquire_timeout_secs,
+        )?;
+        env_parse("DB_CONNECT_RETRIES", &mut self.database.connect_retries)?;
+        env_parse("DB_CONNECT_DELAY", &mut self.database.connect_delay_ms)?;
+
+        env_parse("RUST_LOG", &mut self.log.level)?;
+        env_parse_opt("LOG_FORMAT", &mut self.log.format)?;
+        env_bool("LOG_BODIES", &mut self.log.bodies)?;
+
+        let access = &mut self.access_log;
+        env_parse_opt("ACCESS_LOG_PATH", &mut access.path)?;
+        env_parse("ACCESS_LOG_FORMAT", &mut access.format)?;
+        env_parse("ACCESS_LOG_ROTATE", &mut access.rotate)?;
+        env_parse("ACCESS_LOG_MAX_SIZE_MB", &mut access.max_size_mb)?;
+        env_parse("ACCESS_LOG_KEEP", &mut access.keep)?;
+
+        env_parse_opt("SENTRY_DSN", &mut self.reporting.sentry_dsn)?;
+
+        env_list("CORS_ALLOWED_ORIGINS", &mut self.cors.allowed_origins);
+        env_list("CORS_ALLOWED_METHODS", &mut self.cors.allowed_methods);
+        env_list("CORS_ALLOWED_HEADERS", &mut self.cors.allowed_headers);
+        env_parse("CORS_MAX_AGE_SECS", &mut self.cors.max_age_secs)?;
+
+        let limits = &mut self.rate_limit;
+        env_bool("RATE_LIMIT_ENABLED", &mut limits.enabled)?;
+        env_parse("RATE_LIMIT_READ_PER_SEC", &mut limits.read.per_second)?;
+        env_parse("RATE_LIMIT_READ_BURST", &mut limits.read.burst)?;
+        env_parse("RATE_LIMIT_WRITE_PER_SEC", &mut limits.write.per_second)?;
+        env_parse("RATE_LIMIT_WRITE_BURST", &mut limits.write.burst)?;
+        env_bool("TRUST_X_FORWARDED_FOR", &mut limits.trust_forwarded_for)?;
+        env_parse("RATE_LIMIT_MAX_CLIENTS", &mut limits.max_tracked_clients)?;
+
+        let quotas = &mut self.quotas;
+        env_parse_opt("QUOTA_MAX_TODOS", &mut quotas.max_todos)?;
+        env_parse_opt("QUOTA_WRITES_PER_MINUTE", &mut quotas.writes_per_minute)?;
+        env_parse_opt("QUOTA_ADMIN_MAX_TODOS", &mut quotas.admin_max_todos)?;
+        env_parse_opt(
+            "QUOTA_ADMIN_WRITES_PER_MINUTE",
+            &mut quotas.admin_writes_per_minute,
+        )?;
+
+        env_bool("LOAD_SHED_ENABLED", &mut self.load_shed.enabled)?;
+        env_parse(
+            "MAX_CONCURRENT_REQUESTS",
+            &mut self.load_shed.max_concurrent_requests,
+        )?;
+        env_parse(
+            "LOAD_SHED_RETRY_AFTER_SECS",
+            &mut self.load_shed.retry_after_secs,
+        )?;
+
+        env_bool("METRICS_ENABLED", &mut self.metrics.enabled)?;
+        env_parse_opt("METRICS_ADDR", &mut self.metrics.addr)?;
+        env_bool("METRICS_INCLUDE_SELF", &mut self.metrics.include_self)?;
+
+        env_bool("LATENCY_BUDGET_ENABLED", &mut self.latency_budget.enabled)?;
+        env_parse("LATENCY_BUDGET_MS", &mut self.latency_budget.default_ms)?;
+        env_bool("SERVER_TIMING", &mut self.latency_budget.server_timing)?;
+
+        env_parse_opt("GRPC_ADDR", &mut self.grpc.addr)?;
+
+        env_bool("NORMALIZE_TITLES", &mut self.todos.normalize_titles)?;
+        env_bool(
+            "UNIQUE_TITLE_PER_USER",
+            &mut self.todos.unique_title_per_user,
+        )?;
+        env_parse_opt(
+            "TODO_COUNT_WARNING_THRESHOLD",
+            &mut self.todos.count_warning_threshold,
+        )?;
+        env_parse(
+            "TODO_COUNT_REFRESH_SECS",
+            &mut self.todos.count_refresh_secs,
+        )?;
+        env_parse("ID_FORMAT", &mut self.todos.id_format)?;
+        env_parse_opt("ARCHIVE_AFTER_DAYS", &mut self.todos.archive_after_days)?;
+        env_parse(
+            "ARCHIVE_INTERVAL_SECS",
+            &mut self.todos.archive_interval_secs,
+        )?;
+
+        env_parse_opt("ADMIN_API_KEY", &mut self.admin.api_key)?;
+        env_list("ADMIN_USERS", &mut self.admin.users);
+
+        env_parse_list("API_KEYS", &mut self.auth.api_keys)?;
+        env_bool("AUTH_PROTECT_UI", &mut self.auth.protect_ui)?;
+        match (
+            std::env::var("BASIC_AUTH_USER"),
+            std::env::var("BASIC_AUTH_PASSWORD_HASH"),
+        ) {
+            (Ok(user), Ok(hash)) => {
+                self.auth.basic = Some(UserConfig {
+                    user,
+                    password_hash: hash.parse()?,
+                })
+            }
+            (Err(_), Err(_)) => {}
+            _ => {
+                return Err(anyhow!(
+                    "BASIC_AUTH_USER and BASIC_AUTH_PASSWORD_HASH must be set together"
+                ))
+            }
+        }
+        env_bool("AUTH_PROTECT_HEALTH", &mut self.auth.protect_health)?;
+        env_parse("REGISTRATION", &mut self.auth.registration)?;
+        env_parse_list("REGISTRATION_INVITE_CODES", &mut self.auth.invite_codes)?;
+        env_parse(
+            "SESSION_IDLE_TIMEOUT_SECS",
+            &mut self.auth.session.idle_timeout_secs,
+        )?;
+        env_bool(
+            "SESSION_COOKIE_SECURE",
+            &mut self.auth.session.secure_cookie,
+        )?;
+        if let Ok(key) = std::env::var("JWT_SIGNING_KEY") {
+            self.auth
+                .jwt
+                .get_or_insert_with(JwtConfig::default)
+                .signing_key = Some(key.parse()?);
+        }
+        if let Some(jwt) = &mut self.auth.jwt {
+            env_parse("JWT_TTL_SECS", &mut jwt.ttl_secs)?;
+        }
+        if let Ok(client_id) = std::env::var("GITHUB_CLIENT_ID") {
+            self.auth
+                .github
+                .get_or_insert_with(GithubConfig::default)
+                .client_id = client_id;
+        }
+        if let Some(github) = &mut self.auth.github {
+            env_parse_opt("GITHUB_CLIENT_SECRET", &mut github.client_secret)?;
+            env_parse("GITHUB_CALLBACK_URL", &mut github.callback_url)?;
+        }
+
+        Ok(())
+    }
+
+    fn apply_cli(&mut self, cli: &Cli) {
+        if !cli.addr.is_empty() {
+            self.server.addrs = cli.addr.clone();
+        }
+        if let Some(path) = &cli.unix_socket {
+            self.server.unix_socket = Some(path.clone());
+        }
+        if let Some(dir) = &cli.static_dir {
+            self.server.static_dir = Some(dir.clone());
+        }
+        if let Some(db) = &cli.db {
+            self.database.path = db.clone();
+        }
+        if let Some(format) = cli.log_format {
+            self.log.format = Some(format);
+        }
+        if let Some(level) = &cli.log_level {
+            self.log.level = level.clone();
+        }
+    }
+
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    /// Single-line rendering for the startup log.
+    pub fn summary(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    /// Applies the configured title normalization.
+    pub fn prepare_title(&self, title: String) -> String {
+        if self.todos.normalize_titles {
+            title.split_whitespace().collect::<Vec<_>>().join(" ")
+        } else {
+            title
+        }
+    }
+}
+
+fn env_parse<T>(key: &str, target: &mut T) -> anyhow::Result<()>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Ok(raw) = std::env::var(key) {
+        *target = raw
+            .parse()
+            .map_err(|err| anyhow!("invalid value {:?} for {}: {}", raw, key, err))?;
+    }
+    Ok(())
+}
+
+fn env_parse_opt<T>(key: &str, target: &mut Option<T>) -> anyhow::Result<()>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Ok(raw) = std::env::var(key) {
+        let value = raw
+            .parse()
+            .map_err(|err| anyhow!("invalid value {:?} for {}: {}", raw, key, err))?;
+        *target = Some(value);
+    }
+    Ok(())
+}
+
+/// The file a `sqlite:` URL such as `sqlite:///litefs/todos.db` names.
+fn sqlite_path(url: &str) -> Result<PathBuf, String> {
+    let path = url
+        .strip_prefix("sqlite://")
+        .or_else(|| url.strip_prefix("sqlite:"))
+        .ok_or("expected a sqlite: URL; the database is SQLite")?;
+    if path.is_empty() || path.starts_with(':') {
+        return Err("expected the path of a database file".to_string());
+    }
+    if path.contains('?') {
+        return Err("URL parameters are not supported".to_string());
+    }
+    Ok(PathBuf::from(path))
+}
+
+fn env_parse_list<T>(key: &str, target: &mut Vec<T>) -> anyhow::Result<()>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Ok(raw) = std::env::var(key) {
+        *target = raw
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| {
+                item.parse()
+                    .map_err(|err| anyhow!("invalid value {:?} for {}: {}", item, key, err))
+            })
+            .collect::<anyhow::Result<_>>()?;
+    }
+    Ok(())
+}
+
+fn env_bool(key: &str, target: &mut bool) -> anyhow::Result<()> {
+    if let Ok(raw) = std::env::var(key) {
+        *target = match raw.to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => true,
+            "0" | "false" | "no" | "off" => false,
+            _ => {
+                return Err(anyhow!(
+                    "invalid value {:?} for {}: expected true or false",
+                    raw,
+                    key
+                ))
+            }
+        };
+    }
+    Ok(())
+}
+
+fn env_list(key: &str, target: &mut Vec<String>) {
+    if let Ok(raw) = std::env::var(key) {
+        *target = raw
+            .split(',')
+            .map(|item| item.trim().to_string())
+            .filter(|item| !item.is_empty())
+            .collect();
+    }
+}
+
+/// Accepts either a single value or a list of them.
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        One(T),
+        Many(Vec<T>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
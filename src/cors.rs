@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+use axum::{
+    http::{header, HeaderName, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+use crate::config::CorsConfig;
+
+/// Builds a `CorsLayer` from the `[cors]` settings. Returns `None` when no
+/// origins are configured, leaving CORS off.
+pub fn layer(config: &CorsConfig) -> Option<CorsLayer> {
+    let origins = &config.allowed_origins;
+    if origins.is_empty() {
+        return None;
+    }
+
+    let allow_origin = if origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::from(Any)
+    } else {
+        AllowOrigin::list(
+            origins
+                .iter()
+                .filter_map(|origin| HeaderValue::from_str(origin).ok()),
+        )
+    };
+
+    let methods: Vec<Method> = config
+        .allowed_methods
+        .iter()
+        .filter_map(|method| method.to_ascii_uppercase().parse().ok())
+        .collect();
+
+    let headers: Vec<HeaderName> = config
+        .allowed_headers
+        .iter()
+        .filter_map(|name| name.parse().ok())
+        .collect();
+
+    tracing::info!(origins = ?origins, "CORS enabled");
+
+    Some(
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods(methods)
+            .allow_headers(headers)
+            .max_age(Duration::from_secs(config.max_age_secs)),
+    )
+}
+
+/// `CorsLayer` answers preflights itself with an empty `200`; report them as
+/// `204 No Content` instead.
+pub async fn preflight_no_content<B>(req: Request<B>, next: Next<B>) -> Response {
+    let is_preflight = req.method() == Method::OPTIONS
+        && req
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+
+    let mut response = next.run(req).await;
+    if is_preflight && response.status() == StatusCode::OK {
+        *response.status_mut() = StatusCode::NO_CONTENT;
+    }
+    response
+}
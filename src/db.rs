@@ -0,0 +1,1096 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use sqlx::migrate::{MigrateError, Migrator};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::{Executor, QueryBuilder, Sqlite, SqliteConnection, SqlitePool};
+use tokio::sync::mpsc;
+use tokio_stream::StreamExt;
+use utoipa::ToSchema;
+
+use crate::accounts::Role;
+use crate::config::DatabaseConfig;
+use crate::history::{self, Action};
+use crate::models::{TodoChanges, TodoRow};
+use crate::quotas::Limits;
+use crate::tags::{self, TagMode, Tags};
+
+/// Schema migrations from `migrations/`, embedded at compile time.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Owner of todos when no user is authenticated. Created by migration.
+pub const DEFAULT_USER_ID: &str = "default";
+
+/// Longest wait between two connect attempts.
+const MAX_CONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// The pool for reads that can do with a slightly stale answer: a
+/// read-only replica when `database.read_path` is set, otherwise the
+/// primary pool itself.
+#[derive(Clone)]
+pub struct ReadDb(pub SqlitePool);
+
+/// Opens the pool, creating the database file and its directory if needed.
+/// A failed connect is retried `connect_retries` times with doubling
+/// delays, so a database that is still starting up does not stop the
+/// process.
+pub async fn connect(config: &DatabaseConfig) -> anyhow::Result<SqlitePool> {
+    let path = &config.path;
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+
+    // SQLite only enforces REFERENCES clauses when asked to, per connection.
+    // sqlx happens to default to on; spell it out so a tag row can never
+    // point at a todo that does not exist.
+    let connect_options = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(true)
+        .foreign_keys(true);
+    open_pool(config, connect_options).await
+}
+
+/// Opens the replica at `path` read-only, with the primary's pool settings
+/// and retries. The file must already exist; whatever keeps it in step with
+/// the primary, such as LiteFS or Litestream, owns it.
+pub async fn connect_replica(config: &DatabaseConfig, path: &Path) -> anyhow::Result<SqlitePool> {
+    let connect_options = SqliteConnectOptions::new().filename(path).read_only(true);
+    open_pool(config, connect_options).await
+}
+
+/// Opens a pool on a `sqlite:` URL, without retries, for embedding the app
+/// and for tests. `sqlite::memory:` gives a database of its own that lives
+/// as long as the pool does, so one connection is always kept open.
+pub async fn connect_url(url: &str) -> anyhow::Result<SqlitePool> {
+    let connect_options = SqliteConnectOptions::from_str(url)
+        .with_context(|| format!("invalid database URL {}", url))?
+        .create_if_missing(true)
+        .foreign_keys(true);
+    let db = SqlitePoolOptions::new()
+        .min_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect_with(connect_options)
+        .await?;
+    Ok(db)
+}
+
+async fn open_pool(
+    config: &DatabaseConfig,
+    connect_options: SqliteConnectOptions,
+) -> anyhow::Result<SqlitePool> {
+    if config.min_connections > config.max_connections {
+        anyhow::bail!(
+            "database.min_connections ({}) is above max_connections ({})",
+            config.min_connections,
+            config.max_connections
+        );
+    }
+    let ramp = Duration::from_millis(config.connect_ramp_ms);
+    let mut delay = Duration::from_millis(config.connect_delay_ms);
+    let mut attempt = 1;
+    loop {
+        let options = SqlitePoolOptions::new()
+            .max_connections(config.max_connections)
+            .min_connections(config.min_connections)
+            .acquire_timeout(Duration::from_secs(config.acquire_timeout_secs));
+        // With a ramp the pool starts empty and `ramp_up` fills it; sqlx
+        // would otherwise open every minimum connection at once here. It
+        // still keeps the minimum once connections start to expire.
+        let result = if ramp.is_zero() {
+            options.connect_with(connect_options.clone()).await
+        } else {
+            let db = options.connect_lazy_with(connect_options.clone());
+            match db.acquire().await {
+                Ok(conn) => {
+                    drop(conn);
+                    Ok(db)
+                }
+                Err(err) => Err(err),
+            }
+        };
+        match result {
+            Ok(db) => {
+                if attempt > 1 {
+                    tracing::info!(attempt, "connected to database");
+                }
+                if !ramp.is_zero() {
+                    ramp_up(&db, config.min_connections, ramp).await;
+                }
+                tracing::info!(
+                    path = %connect_options.get_filename().display(),
+                    min_connections = config.min_connections,
+                    max_connections = config.max_connections,
+                    open = db.size(),
+                    ramp_ms = config.connect_ramp_ms,
+                    "database pool ready"
+                );
+                return Ok(db);
+            }
+            Err(err) if attempt <= config.connect_retries => {
+                tracing::warn!(
+                    attempt,
+                    retries = config.connect_retries,
+                    delay_ms = delay.as_millis() as u64,
+                    error = %err,
+                    "failed to connect to database; retrying"
+                );
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_CONNECT_DELAY);
+                attempt += 1;
+            }
+            Err(err) => {
+                return Err(anyhow::Error::new(err).context(format!(
+                    "failed to connect to database after {} attempts",
+                    attempt
+                )))
+            }
+        }
+    }
+}
+
+/// Opens connections one at a time, `step` apart, until the pool holds
+/// `target`. They are held until the last is open, since an idle one would
+/// just be handed back, then all returned to the pool. A failure stops the
+/// ramp early; the pool opens the rest on demand.
+async fn ramp_up(db: &SqlitePool, target: u32, step: Duration) {
+    let mut held = Vec::new();
+    while db.size() < target {
+        tokio::time::sleep(step).await;
+        match db.acquire().await {
+            Ok(conn) => held.push(conn),
+            Err(err) => {
+                tracing::warn!(open = db.size(), target, error = %err, "stopped opening database connections");
+                break;
+            }
+        }
+    }
+}
+
+pub async fn migrate(db: &SqlitePool) -> Result<(), MigrateError> {
+    MIGRATOR.run(db).await
+}
+
+/// Opens a connection and runs a query shaped like `GET /todos`, so the
+/// first request does not pay for connecting, preparing the statement and
+/// reading the pages it needs. Failures are only logged; the server starts
+/// either way.
+pub async fn warm_up(db: &SqlitePool) {
+    let start = Instant::now();
+    let filter = TodoFilter::default();
+    let result = async {
+        sqlx::query("SELECT 1").execute(db).await?;
+        list_query(DEFAULT_USER_ID, &filter)
+            .push(" LIMIT 1")
+            .build_query_as::<TodoRow>()
+            .fetch_optional(db)
+            .await
+    }
+    .await;
+
+    let duration_ms = start.elapsed().as_millis() as u64;
+    match result {
+        Ok(_) => tracing::info!(duration_ms, "database warmed up"),
+        Err(err) => tracing::warn!(duration_ms, error = %err, "database warmup failed"),
+    }
+}
+
+/// Adds a `users` row for each of `ids` that lacks one.
+#[tracing::instrument(skip_all)]
+pub async fn ensure_users(db: &SqlitePool, ids: &[&str]) -> Result<(), sqlx::Error> {
+    for id in ids {
+        sqlx::query("INSERT OR IGNORE INTO users (id, created_at) VALUES (?, ?)")
+            .bind(id)
+            .bind(Utc::now())
+            .execute(db)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Column list for selecting a full `TodoRow`, tags included.
+pub fn todo_columns() -> String {
+    format!(
+        "id, title, completed, completed_at, due_date, priority, position, archived, {}",
+        tags::TAGS_COLUMN
+    )
+}
+
+/// The stored password hash of a registered user. `None` when there is no
+/// such user, or when their password is set in the configuration instead.
+#[tracing::instrument(skip_all)]
+pub async fn password_hash(db: &SqlitePool, user_id: &str) -> Result<Option<String>, sqlx::Error> {
+    let hash: Option<Option<String>> =
+        sqlx::query_scalar("SELECT password_hash FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_optional(db)
+            .await?;
+    Ok(hash.flatten())
+}
+
+/// Adds a user with a password, as an admin when `admin` is set or there is
+/// no admin yet. Returns the new user's role, or `None`, changing nothing,
+/// when the id is taken.
+#[tracing::instrument(skip_all)]
+pub async fn create_user(
+    db: &SqlitePool,
+    user_id: &str,
+    password_hash: &str,
+    admin: bool,
+) -> Result<Option<Role>, sqlx::Error> {
+    let role: Option<String> = sqlx::query_scalar(
+        "INSERT INTO users (id, password_hash, created_at, role)
+         VALUES (?1, ?2, ?3, CASE
+             WHEN ?4 OR NOT EXISTS (SELECT 1 FROM users WHERE role = 'admin') THEN 'admin'
+             ELSE 'member'
+         END)
+         ON CONFLICT (id) DO NOTHING
+         RETURNING role",
+    )
+    .bind(user_id)
+    .bind(password_hash)
+    .bind(Utc::now())
+    .bind(admin)
+    .fetch_optional(db)
+    .await?;
+    role.as_deref().map(parse_role).transpose()
+}
+
+fn parse_role(role: &str) -> Result<Role, sqlx::Error> {
+    role.parse()
+        .map_err(|err: String| sqlx::Error::Decode(err.into()))
+}
+
+/// The local user for GitHub account `github_id`, created as `login` on
+/// its first sign-in, as an admin when `admin` is set or there is no admin
+/// yet. `None` when `login` is already the id of another user, who is never
+/// linked automatically.
+#[tracing::instrument(skip_all)]
+pub async fn github_user(
+    db: &SqlitePool,
+    github_id: i64,
+    login: &str,
+    admin: bool,
+) -> Result<Option<String>, sqlx::Error> {
+    let existing: Option<String> = sqlx::query_scalar("SELECT id FROM users WHERE github_id = ?")
+        .bind(github_id)
+        .fetch_optional(db)
+        .await?;
+    if existing.is_some() {
+        return Ok(existing);
+    }
+
+    let created: Option<String> = sqlx::query_scalar(
+        "INSERT INTO users (id, github_id, created_at, role)
+         VALUES (?1, ?2, ?3, CASE
+             WHEN ?4 OR NOT EXISTS (SELECT 1 FROM users WHERE role = 'admin') THEN 'admin'
+             ELSE 'member'
+         END)
+         ON CONFLICT (id) DO NOTHING
+         RETURNING id",
+    )
+    .bind(login)
+    .bind(github_id)
+    .bind(Utc::now())
+    .bind(admin)
+    .fetch_optional(db)
+    .await?;
+    if created.is_some() {
+        tracing::info!(user = %login, "created user from GitHub");
+    }
+    Ok(created)
+}
+
+/// A user's role, `None` when there is no such user.
+#[tracing::instrument(skip_all)]
+pub async fn user_role(db: &SqlitePool, user_id: &str) -> Result<Option<Role>, sqlx::Error> {
+    let role: Option<String> = sqlx::query_scalar("SELECT role FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(db)
+        .await?;
+    role.as_deref().map(parse_role).transpose()
+}
+
+/// Changes a user's role. Returns `false` when there is no such user.
+#[tracing::instrument(skip_all)]
+pub async fn set_role(db: &SqlitePool, user_id: &str, role: Role) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("UPDATE users SET role = ? WHERE id = ?")
+        .bind(role.as_str())
+        .bind(user_id)
+        .execute(db)
+        .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// A user's role and quota overrides. `None` when there is no such user.
+#[tracing::instrument(skip_all)]
+pub async fn user_limits(
+    db: &SqlitePool,
+    user_id: &str,
+) -> Result<Option<(Role, Limits)>, sqlx::Error> {
+    let row: Option<(String, Option<i64>, Option<i64>)> =
+        sqlx::query_as("SELECT role, max_todos, writes_per_minute FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_optional(db)
+            .await?;
+    let Some((role, max_todos, writes_per_minute)) = row else {
+        return Ok(None);
+    };
+    let limits = Limits {
+        max_todos: max_todos.map(|max| max as u64),
+        writes_per_minute: writes_per_minute.map(|max| max as u32),
+    };
+    Ok(Some((parse_role(&role)?, limits)))
+}
+
+/// Replaces a user's quota overrides. Returns `false` when there is no
+/// such user.
+#[tracing::instrument(skip_all)]
+pub async fn set_user_limits(
+    db: &SqlitePool,
+    user_id: &str,
+    limits: &Limits,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("UPDATE users SET max_todos = ?, writes_per_minute = ? WHERE id = ?")
+        .bind(limits.max_todos.map(|max| max as i64))
+        .bind(limits.writes_per_minute)
+        .bind(user_id)
+        .execute(db)
+        .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// How many todos the user has, not counting soft-deleted ones.
+#[tracing::instrument(skip_all)]
+pub async fn count_todos<'e, E>(db: E, user_id: &str) -> Result<u64, sqlx::Error>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let count: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM todos WHERE user_id = ? AND deleted_at IS NULL")
+            .bind(user_id)
+            .fetch_one(db)
+            .await?;
+    Ok(count as u64)
+}
+
+/// How many users other than `user_id` are admins.
+#[tracing::instrument(skip_all)]
+pub async fn other_admins(db: &SqlitePool, user_id: &str) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE role = 'admin' AND id != ?")
+        .bind(user_id)
+        .fetch_one(db)
+        .await
+}
+
+/// Replaces a registered user's password hash.
+#[tracing::instrument(skip_all)]
+pub async fn set_password_hash(
+    db: &SqlitePool,
+    user_id: &str,
+    password_hash: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE users SET password_hash = ? WHERE id = ?")
+        .bind(password_hash)
+        .bind(user_id)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+pub struct TodoFilter {
+    pub tags: Vec<String>,
+    pub tag_mode: TagMode,
+    /// Only todos without any tags.
+    pub untagged: bool,
+    /// Created at or after this time.
+    pub created_after: Option<DateTime<Utc>>,
+    /// Created before this time.
+    pub created_before: Option<DateTime<Utc>>,
+    pub include_archived: bool,
+    pub completed: Option<bool>,
+    /// Completed todos only if completed at or after this time; open todos
+    /// are not affected.
+    pub completed_after: Option<DateTime<Utc>>,
+    /// Only todos whose title contains this, ignoring ASCII case.
+    pub title_contains: Option<String>,
+}
+
+impl TodoFilter {
+    /// Whether the filter leaves out any of the user's current todos.
+    pub fn is_narrowed(&self) -> bool {
+        !self.tags.is_empty()
+            || self.untagged
+            || self.created_after.is_some()
+            || self.created_before.is_some()
+            || self.completed.is_some()
+            || self.completed_after.is_some()
+            || self.title_contains.is_some()
+    }
+}
+
+fn list_query<'a>(user_id: &'a str, filter: &'a TodoFilter) -> QueryBuilder<'a, Sqlite> {
+    let mut query = filtered_query(
+        format!("SELECT {} FROM todos", todo_columns()),
+        user_id,
+        filter,
+    );
+    query.push(" ORDER BY position, id");
+    query
+}
+
+/// `select` over the user's todos matching `filter`, unordered.
+fn filtered_query<'a>(
+    select: String,
+    user_id: &'a str,
+    filter: &'a TodoFilter,
+) -> QueryBuilder<'a, Sqlite> {
+    let mut query: QueryBuilder<Sqlite> =
+        QueryBuilder::new(select + " WHERE deleted_at IS NULL AND user_id = ");
+    query.push_bind(user_id);
+    if !filter.include_archived {
+        query.push(" AND archived = 0");
+    }
+
+    if !filter.tags.is_empty() {
+        query.push(" AND id IN (SELECT todo_id FROM todo_tags WHERE tag IN (");
+        let mut list = query.separated(", ");
+        for tag in &filter.tags {
+            list.push_bind(tag.clone());
+        }
+        query.push(")");
+        if filter.tag_mode == TagMode::All {
+            query
+                .push(" GROUP BY todo_id HAVING COUNT(DISTINCT tag) = ")
+                .push_bind(filter.tags.len() as i64);
+        }
+        query.push(")");
+    }
+    if filter.untagged {
+        query.push(" AND NOT EXISTS (SELECT 1 FROM todo_tags WHERE todo_tags.todo_id = todos.id)");
+    }
+    if let Some(after) = filter.created_after {
+        query.push(" AND created_at >= ").push_bind(after);
+    }
+    if let Some(before) = filter.created_before {
+        query.push(" AND created_at < ").push_bind(before);
+    }
+    if let Some(completed) = filter.completed {
+        query.push(" AND completed = ").push_bind(completed);
+    }
+    if let Some(after) = filter.completed_after {
+        query
+            .push(" AND (completed = 0 OR completed_at >= ")
+            .push_bind(after)
+            .push(")");
+    }
+    if let Some(term) = &filter.title_contains {
+        query
+            .push(" AND title LIKE '%' || ")
+            .push_bind(escape_like(term))
+            .push(" || '%' ESCAPE '\\'");
+    }
+    query
+}
+
+/// Escapes `term` for a `LIKE ... ESCAPE '\'` pattern.
+fn escape_like(term: &str) -> String {
+    term.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn list_todos<'e, E>(
+    db: E,
+    user_id: &str,
+    filter: &TodoFilter,
+) -> Result<Vec<TodoRow>, sqlx::Error>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    list_query(user_id, filter)
+        .build_query_as::<TodoRow>()
+        .fetch_all(db)
+        .await
+}
+
+/// One page of the user's todos matching `filter`, `limit` of them from
+/// `offset` on, or all from `offset` without a limit, along with how many
+/// match in all.
+#[tracing::instrument(skip_all)]
+pub async fn list_page(
+    db: &SqlitePool,
+    user_id: &str,
+    filter: &TodoFilter,
+    limit: Option<i64>,
+    offset: i64,
+) -> Result<(Vec<TodoRow>, i64), sqlx::Error> {
+    let total = filtered_query("SELECT COUNT(*) FROM todos".to_string(), user_id, filter)
+        .build_query_scalar::<i64>()
+        .fetch_one(db)
+        .await?;
+    let mut query = list_query(user_id, filter);
+    query
+        .push(" LIMIT ")
+        .push_bind(limit.unwrap_or(-1))
+        .push(" OFFSET ")
+        .push_bind(offset);
+    let todos = query.build_query_as::<TodoRow>().fetch_all(db).await?;
+    Ok((todos, total))
+}
+
+/// What the user's todos matching a filter add up to, cheaply: any write to
+/// one of them, or any change in which todos match, changes it.
+#[derive(Debug, PartialEq, Eq, sqlx::FromRow)]
+pub struct ListVersion {
+    pub last_updated: Option<String>,
+    pub count: i64,
+    /// Open todos past their due date, which turn overdue without a write.
+    /// Counted against SQLite's clock to the second, so a todo may count a
+    /// second after the list has called it overdue, never before.
+    pub overdue: i64,
+}
+
+/// The [`ListVersion`] of the user's todos matching `filter`.
+#[tracing::instrument(skip_all)]
+pub async fn list_version(
+    db: &SqlitePool,
+    user_id: &str,
+    filter: &TodoFilter,
+) -> Result<ListVersion, sqlx::Error> {
+    filtered_query(
+        "SELECT MAX(updated_at) AS last_updated, COUNT(*) AS count,
+         COUNT(*) FILTER (WHERE completed = 0
+                          AND due_date < strftime('%Y-%m-%dT%H:%M:%S', 'now')) AS overdue
+         FROM todos"
+            .to_string(),
+        user_id,
+        filter,
+    )
+    .build_query_as::<ListVersion>()
+    .fetch_one(db)
+    .await
+}
+
+/// Sends the user's todos matching `filter` to `tx` one at a time as they come off
+/// the cursor, so memory use does not grow with the table. Stops after the
+/// first error or once the receiver is dropped.
+#[tracing::instrument(skip_all)]
+pub async fn stream_todos(
+    db: &SqlitePool,
+    user_id: &str,
+    filter: &TodoFilter,
+    tx: mpsc::Sender<Result<TodoRow, sqlx::Error>>,
+) {
+    let mut query = list_query(user_id, filter);
+    let mut rows = query.build_query_as::<TodoRow>().fetch(db);
+
+    while let Some(row) = rows.next().await {
+        let failed = row.is_err();
+        if tx.send(row).await.is_err() || failed {
+            break;
+        }
+    }
+}
+
+/// The todo with `id`, or `None` when it does not exist or belongs to
+/// another user; callers cannot tell the two apart.
+#[tracing::instrument(skip_all)]
+pub async fn find_todo<'e, E>(
+    db: E,
+    user_id: &str,
+    id: &str,
+) -> Result<Option<TodoRow>, sqlx::Error>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let sql = format!(
+        "SELECT {} FROM todos WHERE id = ? AND user_id = ? AND deleted_at IS NULL",
+        todo_columns()
+    );
+    sqlx::query_as::<_, TodoRow>(&sql)
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(db)
+        .await
+}
+
+/// The user's todos whose title or one of whose tags contains `term`, ignoring ASCII
+/// case. Each todo appears once, ranked by its best match: title starting
+/// with the term, then title containing it, then a tag only.
+#[tracing::instrument(skip_all)]
+pub async fn search_todos<'e, E>(
+    db: E,
+    user_id: &str,
+    term: &str,
+) -> Result<Vec<TodoRow>, sqlx::Error>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let escaped = escape_like(term);
+    let sql = format!(
+        "SELECT {} FROM todos
+         LEFT JOIN todo_tags ON todo_tags.todo_id = todos.id
+         WHERE todos.deleted_at IS NULL AND todos.user_id = ?2
+           AND (todos.title LIKE '%' || ?1 || '%' ESCAPE '\\'
+                OR todo_tags.tag LIKE '%' || ?1 || '%' ESCAPE '\\')
+         GROUP BY todos.id
+         ORDER BY MIN(CASE
+                    WHEN todos.title LIKE ?1 || '%' ESCAPE '\\' THEN 0
+                    WHEN todos.title LIKE '%' || ?1 || '%' ESCAPE '\\' THEN 1
+                    ELSE 2
+                  END),
+                  todos.position, todos.id",
+        todo_columns()
+    );
+    sqlx::query_as::<_, TodoRow>(&sql)
+        .bind(escaped)
+        .bind(user_id)
+        .fetch_all(db)
+        .await
+}
+
+/// One of the user's open todos picked at random, optionally among those
+/// tagged `tag`.
+#[tracing::instrument(skip_all)]
+pub async fn random_todo<'e, E>(
+    db: E,
+    user_id: &str,
+    tag: Option<&str>,
+) -> Result<Option<TodoRow>, sqlx::Error>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(format!(
+        "SELECT {} FROM todos WHERE completed = 0 AND deleted_at IS NULL AND user_id = ",
+        todo_columns()
+    ));
+    query.push_bind(user_id);
+    if let Some(tag) = tag {
+        query
+            .push(" AND id IN (SELECT todo_id FROM todo_tags WHERE tag = ")
+            .push_bind(tag)
+            .push(")");
+    }
+    query.push(" ORDER BY RANDOM() LIMIT 1");
+
+    query.build_query_as::<TodoRow>().fetch_optional(db).await
+}
+
+/// Whether the user has an open todo titled `title`, ignoring ASCII case.
+#[tracing::instrument(skip_all)]
+pub async fn open_title_taken<'e, E>(db: E, user_id: &str, title: &str) -> Result<bool, sqlx::Error>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM todos
+         WHERE user_id = ? AND lower(title) = lower(?) AND completed = 0 AND deleted_at IS NULL)",
+    )
+    .bind(user_id)
+    .bind(title)
+    .fetch_one(db)
+    .await
+}
+
+/// Spacing given to todos appended at the end. A move takes the midpoint
+/// between two neighbours; once a gap is used up every todo is renumbered.
+pub const POSITION_GAP: i64 = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    Before,
+    After,
+}
+
+/// Inserts `todo` for the user at the end of their list, or overwrites (and
+/// restores, if soft-deleted) their stored todo with the same id in place,
+/// along with its tags. `todo.position` is set to the stored position. The
+/// whole todo is recorded in its history under `action`. Returns `false`,
+/// changing nothing, when the id is taken by another user's todo.
+#[tracing::instrument(skip_all)]
+pub async fn save_todo(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    todo: &mut TodoRow,
+    action: Action,
+) -> Result<bool, sqlx::Error> {
+    let now = Utc::now();
+    let position: Option<i64> = sqlx::query_scalar(
+        "INSERT INTO todos (id, user_id, title, completed, completed_at, due_date, priority, created_at, updated_at, position)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, (SELECT COALESCE(MAX(position), 0) + ? FROM todos))
+         ON CONFLICT (id) DO UPDATE SET title = excluded.title, completed = excluded.completed,
+         completed_at = excluded.completed_at, due_date = excluded.due_date, priority = excluded.priority, deleted_at = NULL,
+         archived = todos.archived AND excluded.completed, updated_at = excluded.updated_at
+         WHERE todos.user_id = excluded.user_id
+         RETURNING position",
+    )
+    .bind(&todo.id)
+    .bind(user_id)
+    .bind(&todo.title)
+    .bind(todo.completed)
+    .bind(todo.completed_at)
+    .bind(todo.due_date)
+    .bind(todo.priority)
+    .bind(now)
+    .bind(now)
+    .bind(POSITION_GAP)
+    .fetch_optional(&mut *conn)
+    .await?;
+    let Some(position) = position else {
+        return Ok(false);
+    };
+    todo.position = position;
+
+    tags::replace(conn, &todo.id, &todo.tags.0).await?;
+
+    let snapshot = json!({
+        "title": todo.title,
+        "completed": todo.completed,
+        "completed_at": todo.completed_at,
+        "tags": todo.tags,
+        "due_date": todo.due_date,
+        "priority": todo.priority,
+    });
+    if let serde_json::Value::Object(changes) = snapshot {
+        history::record(conn, &todo.id, action, changes).await?;
+    }
+    Ok(true)
+}
+
+/// Moves todo `id` directly before or after `target`; both must exist and
+/// belong to the user. Run in a transaction, since a move may renumber all
+/// of the user's todos first.
+#[tracing::instrument(skip_all)]
+pub async fn move_todo(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    id: &str,
+    target: &str,
+    placement: Placement,
+) -> Result<(), sqlx::Error> {
+    let mut renumbered = false;
+    loop {
+        let target_position: i64 = sqlx::query_scalar("SELECT position FROM todos WHERE id = ?")
+            .bind(target)
+            .fetch_one(&mut *conn)
+            .await?;
+        let neighbour_sql = match placement {
+            Placement::Before => {
+                "SELECT MAX(position) FROM todos
+                 WHERE position < ? AND id != ? AND user_id = ? AND deleted_at IS NULL"
+            }
+            Placement::After => {
+                "SELECT MIN(position) FROM todos
+                 WHERE position > ? AND id != ? AND user_id = ? AND deleted_at IS NULL"
+            }
+        };
+        let neighbour: Option<i64> = sqlx::query_scalar(neighbour_sql)
+            .bind(target_position)
+            .bind(id)
+            .bind(user_id)
+            .fetch_one(&mut *conn)
+            .await?;
+
+        let position = match (neighbour, placement) {
+            (Some(neighbour), _) if (neighbour - target_position).abs() >= 2 || renumbered => {
+                (neighbour + target_position).div_euclid(2)
+            }
+            (Some(_), _) => {
+                renumber_positions(conn, user_id).await?;
+                renumbered = true;
+                continue;
+            }
+            (None, Placement::Before) => target_position - POSITION_GAP,
+            (None, Placement::After) => target_position + POSITION_GAP,
+        };
+
+        sqlx::query("UPDATE todos SET position = ?, updated_at = ? WHERE id = ?")
+            .bind(position)
+            .bind(Utc::now())
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+        return Ok(());
+    }
+}
+
+/// Spreads the user's todos `POSITION_GAP` apart, keeping their order.
+/// Their positions all change, so they all count as updated.
+#[tracing::instrument(skip_all)]
+async fn renumber_positions(conn: &mut SqliteConnection, user_id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE todos SET position = ranked.rank * ?, updated_at = ?
+         FROM (SELECT id, ROW_NUMBER() OVER (ORDER BY position, id) AS rank
+               FROM todos WHERE user_id = ?) AS ranked
+         WHERE todos.id = ranked.id",
+    )
+    .bind(POSITION_GAP)
+    .bind(Utc::now())
+    .bind(user_id)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Applies `changes` to the user's todo with `id`, returning `None` when
+/// they have no such todo. The row is written by a single `UPDATE`; run this in a transaction
+/// so a tag change lands together with it. Shared by every update path so
+/// they all behave identically.
+#[tracing::instrument(skip_all)]
+pub async fn update_todo(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    id: &str,
+    changes: TodoChanges,
+) -> Result<Option<TodoRow>, sqlx::Error> {
+    let Some(mut todo) = find_todo(&mut *conn, user_id, id).await? else {
+        return Ok(None);
+    };
+    let before = todo.clone();
+
+    if let Some(title) = changes.title {
+        todo.title = title;
+    }
+    if let Some(completed) = changes.completed {
+        todo.set_completed(completed);
+    }
+    if let Some(due_date) = changes.due_date {
+        todo.due_date = due_date;
+    }
+    if let Some(priority) = changes.priority {
+        todo.priority = priority;
+    }
+    if let Some(tags) = changes.tags {
+        tags::replace(conn, &todo.id, &tags).await?;
+        todo.tags = Tags(tags);
+    }
+
+    sqlx::query(
+        "UPDATE todos SET title = ?, completed = ?, completed_at = ?, due_date = ?, priority = ?,
+         archived = ?, updated_at = ?
+         WHERE id = ?",
+    )
+    .bind(&todo.title)
+    .bind(todo.completed)
+    .bind(todo.completed_at)
+    .bind(todo.due_date)
+    .bind(todo.priority)
+    .bind(todo.archived)
+    .bind(Utc::now())
+    .bind(&todo.id)
+    .execute(&mut *conn)
+    .await?;
+
+    let mut changed = Map::new();
+    if todo.title != before.title {
+        changed.insert("title".into(), json!(todo.title));
+    }
+    if todo.completed != before.completed {
+        changed.insert("completed".into(), json!(todo.completed));
+        changed.insert("completed_at".into(), json!(todo.completed_at));
+    }
+    if todo.archived != before.archived {
+        changed.insert("archived".into(), json!(todo.archived));
+    }
+    if todo.tags != before.tags {
+        changed.insert("tags".into(), json!(todo.tags));
+    }
+    if todo.due_date != before.due_date {
+        changed.insert("due_date".into(), json!(todo.due_date));
+    }
+    if todo.priority != before.priority {
+        changed.insert("priority".into(), json!(todo.priority));
+    }
+    if !changed.is_empty() {
+        history::record(conn, &todo.id, Action::Updated, changed).await?;
+    }
+
+    Ok(Some(todo))
+}
+
+/// Soft-deletes the user's todo, or with `purge` removes it and its tags for
+/// good (soft-deleted or not). Returns whether there was anything to delete.
+#[tracing::instrument(skip_all)]
+pub async fn delete_todo(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    id: &str,
+    purge: bool,
+) -> Result<bool, sqlx::Error> {
+    if !purge {
+        let result = sqlx::query(
+            "UPDATE todos SET deleted_at = ?1, updated_at = ?1
+             WHERE id = ?2 AND user_id = ?3 AND deleted_at IS NULL",
+        )
+        .bind(Utc::now())
+        .bind(id)
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await?;
+        let deleted = result.rows_affected() == 1;
+        if deleted {
+            history::record(conn, id, Action::Deleted, Map::new()).await?;
+        }
+        return Ok(deleted);
+    }
+
+    sqlx::query(
+        "DELETE FROM todo_tags
+         WHERE todo_id IN (SELECT id FROM todos WHERE id = ? AND user_id = ?)",
+    )
+    .bind(id)
+    .bind(user_id)
+    .execute(&mut *conn)
+    .await?;
+
+    let result = sqlx::query("DELETE FROM todos WHERE id = ? AND user_id = ?")
+        .bind(id)
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+/// Removes every user's soft-deleted todos and their tags; history goes
+/// with them by cascade. Returns how many todos were removed.
+#[tracing::instrument(skip_all)]
+pub async fn purge_deleted(db: &SqlitePool) -> Result<u64, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    sqlx::query(
+        "DELETE FROM todo_tags
+         WHERE todo_id IN (SELECT id FROM todos WHERE deleted_at IS NOT NULL)",
+    )
+    .execute(&mut *tx)
+    .await?;
+    let result = sqlx::query("DELETE FROM todos WHERE deleted_at IS NOT NULL")
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(result.rows_affected())
+}
+
+/// What `GET /todos/group` counts todos by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupField {
+    Priority,
+    Completed,
+    /// A todo counts once under each of its tags; untagged ones not at all.
+    Tag,
+}
+
+impl GroupField {
+    pub const NAMES: &'static [&'static str] = &["priority", "completed", "tag"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "priority" => Some(GroupField::Priority),
+            "completed" => Some(GroupField::Completed),
+            "tag" => Some(GroupField::Tag),
+            _ => None,
+        }
+    }
+}
+
+/// How many of the user's todos matching `filter` fall under each value of
+/// `by`, in order of the value, counted by SQLite with `GROUP BY`.
+#[tracing::instrument(skip_all)]
+pub async fn group_counts(
+    db: &SqlitePool,
+    user_id: &str,
+    filter: &TodoFilter,
+    by: GroupField,
+) -> Result<Vec<(Value, i64)>, sqlx::Error> {
+    let (select, group) = match by {
+        GroupField::Priority => ("SELECT priority, COUNT(*) FROM todos", "priority"),
+        GroupField::Completed => ("SELECT completed, COUNT(*) FROM todos", "completed"),
+        GroupField::Tag => (
+            "SELECT todo_tags.tag, COUNT(*) FROM todos \
+             JOIN todo_tags ON todo_tags.todo_id = todos.id",
+            "todo_tags.tag",
+        ),
+    };
+    let mut query = filtered_query(select.to_string(), user_id, filter);
+    query.push(format!(" GROUP BY {0} ORDER BY {0}", group));
+    Ok(match by {
+        GroupField::Priority => query
+            .build_query_as::<(i64, i64)>()
+            .fetch_all(db)
+            .await?
+            .into_iter()
+            .map(|(priority, count)| (json!(priority), count))
+            .collect(),
+        GroupField::Completed => query
+            .build_query_as::<(bool, i64)>()
+            .fetch_all(db)
+            .await?
+            .into_iter()
+            .map(|(completed, count)| (json!(completed), count))
+            .collect(),
+        GroupField::Tag => query
+            .build_query_as::<(String, i64)>()
+            .fetch_all(db)
+            .await?
+            .into_iter()
+            .map(|(tag, count)| (json!(tag), count))
+            .collect(),
+    })
+}
+
+/// Todos across all users, soft-deleted ones counted apart.
+#[derive(Debug, Serialize, sqlx::FromRow, ToSchema)]
+pub struct TodoCounts {
+    pub total: i64,
+    pub open: i64,
+    pub completed: i64,
+    /// Completed and archived; also counted in `completed`.
+    pub archived: i64,
+    /// Soft-deleted, not in any of the above.
+    pub deleted: i64,
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn todo_counts(db: &SqlitePool) -> Result<TodoCounts, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT COUNT(*) FILTER (WHERE deleted_at IS NULL) AS total,
+                COUNT(*) FILTER (WHERE deleted_at IS NULL AND NOT completed) AS open,
+                COUNT(*) FILTER (WHERE deleted_at IS NULL AND completed) AS completed,
+                COUNT(*) FILTER (WHERE deleted_at IS NULL AND archived) AS archived,
+                COUNT(*) FILTER (WHERE deleted_at IS NOT NULL) AS deleted
+         FROM todos",
+    )
+    .fetch_one(db)
+    .await
+}
+
+/// Archives every todo completed before `cutoff`, recording each in its
+/// history, and returns how many there were.
+#[tracing::instrument(skip_all)]
+pub async fn archive_completed(db: &SqlitePool, cutoff: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let ids: Vec<String> = sqlx::query_scalar(
+        "UPDATE todos SET archived = 1, updated_at = ?
+         WHERE completed = 1 AND archived = 0 AND deleted_at IS NULL AND completed_at < ?
+         RETURNING id",
+    )
+    .bind(Utc::now())
+    .bind(cutoff)
+    .fetch_all(&mut *tx)
+    .await?;
+    for id in &ids {
+        let mut changes = Map::new();
+        changes.insert("archived".into(), json!(true));
+        history::record(&mut tx, id, Action::Updated, changes).await?;
+    }
+    tx.commit().await?;
+    Ok(ids.len() as u64)
+}
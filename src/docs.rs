@@ -0,0 +1,340 @@
+//! The API described as OpenAPI 3 at `/docs/openapi.json`, and Swagger UI
+//! at `/docs` to browse and try it. The document is built from the
+//! handlers' annotations, then trimmed and given security schemes to match
+//! the running configuration.
+
+use std::sync::Arc;
+
+use askama::Template;
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderValue, StatusCode},
+    response::{Html, IntoResponse, Response},
+    Extension, Json,
+};
+use utoipa::openapi::security::{
+    ApiKey, ApiKeyValue, Http, HttpAuthScheme, SecurityRequirement, SecurityScheme,
+};
+use utoipa::openapi::server::Server;
+use utoipa::openapi::{Content, PathItemType, Ref, ResponseBuilder};
+use utoipa::OpenApi;
+
+use crate::config::Config;
+use crate::error::ApiError;
+use crate::handlers::todos;
+use crate::sessions::{self, Session};
+use crate::{accounts, admin, auth, backup, db, error, health, history, maintenance};
+use crate::{live, markdown, metrics, models, preferences, quotas, shares, sync, tags, version};
+
+pub const DOCS_PATH: &str = "/docs";
+pub const SPEC_PATH: &str = "/docs/openapi.json";
+
+/// Swagger UI's files served under `/docs/`; the rest of its distribution,
+/// including its own `index.html`, stays unserved.
+const ASSETS: &[&str] = &["swagger-ui.css", "swagger-ui-bundle.js"];
+/// The assets change only with the binary.
+const ASSET_CACHE_CONTROL: &str = "public, max-age=86400";
+
+/// Paths anyone may call, whatever else needs credentials.
+const PUBLIC_PATHS: &[&str] = &[
+    auth::LOGIN_PATH,
+    accounts::REGISTER_PATH,
+    "/shared/{token}",
+    metrics::METRICS_PATH,
+];
+/// Public unless `auth.protect_health` is set.
+const PROBE_PATHS: &[&str] = &[
+    health::HEALTH_PATH,
+    health::LIVEZ_PATH,
+    health::READYZ_PATH,
+    version::VERSION_PATH,
+];
+
+/// Whether `path` is `/docs` or under it.
+pub fn is_docs_path(path: &str) -> bool {
+    path == DOCS_PATH || path.starts_with("/docs/")
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Todo API"),
+    paths(
+        todos::list_todos,
+        todos::create_todo,
+        todos::batch_update_todos,
+        todos::delete_batch,
+        todos::export_markdown,
+        todos::group_todos,
+        todos::random_todo,
+        sync::changes,
+        todos::stream_todos,
+        live::sse::events,
+        todos::get_todo,
+        todos::update_todo,
+        todos::delete_todo,
+        todos::todo_history,
+        todos::move_todo,
+        todos::merge_todo,
+        todos::apply_tags,
+        todos::search,
+        preferences::show,
+        preferences::replace,
+        shares::create,
+        shares::revoke,
+        shares::view,
+        quotas::usage,
+        auth::login,
+        auth::refresh,
+        accounts::register,
+        accounts::change_password,
+        sessions::login,
+        sessions::logout,
+        health::health,
+        health::livez,
+        health::readyz,
+        version::version,
+        metrics::render,
+        admin::overview,
+        admin::vacuum,
+        admin::purge_trash,
+        admin::storage,
+        backup::download,
+        admin::set_role,
+        quotas::set_limits,
+        maintenance::status,
+        maintenance::switch,
+    ),
+    components(schemas(
+        models::TodoResponse,
+        models::CreateTodo,
+        models::CreateTodoForm,
+        models::UpdateTodo,
+        models::MoveTodo,
+        models::MergeTodo,
+        models::ApplyTags,
+        models::DeleteBatch,
+        models::DeleteBatchResult,
+        models::BatchItemError,
+        models::BatchUpdateResult,
+        models::BatchUpdateItem,
+        models::GroupCount,
+        error::ErrorBody,
+        markdown::GroupBy,
+        tags::Tags,
+        tags::TagMode,
+        history::Entry,
+        history::Page,
+        history::RecentEntry,
+        sync::Change,
+        sync::Changes,
+        db::TodoCounts,
+        preferences::Preferences,
+        preferences::Theme,
+        preferences::DefaultFilter,
+        shares::NewShare,
+        shares::CreatedShare,
+        shares::SharedList,
+        quotas::Limits,
+        quotas::Consumption,
+        quotas::Usage,
+        quotas::UserLimits,
+        auth::Login,
+        auth::IssuedToken,
+        accounts::Register,
+        accounts::ChangePassword,
+        accounts::Account,
+        accounts::Role,
+        sessions::Started,
+        health::HealthReport,
+        version::BuildInfo,
+        admin::Overview,
+        admin::VacuumReport,
+        admin::PurgeReport,
+        admin::StorageReport,
+        admin::UserRole,
+        maintenance::Mode,
+        maintenance::Status,
+        maintenance::Change,
+        backup::LastBackup,
+    )),
+    tags(
+        (name = "todos", description = "Todos, their tags and history"),
+        (name = "preferences", description = "The UI's settings, per user"),
+        (name = "shares", description = "Read-only links to a list"),
+        (name = "account", description = "Accounts, tokens, sessions and usage"),
+        (name = "probes", description = "Health, version and metrics"),
+        (name = "admin", description = "Needs the admin role or the admin API key"),
+    )
+)]
+struct ApiDoc;
+
+/// The document for this configuration: routes that are not mounted are
+/// left out, and the security schemes are the ones that would be accepted.
+pub fn document(config: &Config) -> utoipa::openapi::OpenApi {
+    let mut doc = ApiDoc::openapi();
+    doc.info.version = env!("CARGO_PKG_VERSION").to_string();
+    doc.info.description = None;
+    doc.info.license = None;
+    if !config.server.base_path.is_root() {
+        doc.servers = Some(vec![Server::new(config.server.base_path.as_str())]);
+    }
+
+    let paths = &mut doc.paths.paths;
+    if config.auth.jwt.is_none() {
+        paths.retain(|path, _| !path.starts_with("/auth/"));
+    }
+    // Any handler can fail on the database or panic; rather than repeat
+    // that on every route, each operation gets the same `500`.
+    for item in paths.values_mut() {
+        for operation in item.operations.values_mut() {
+            operation
+                .responses
+                .responses
+                .entry("500".to_string())
+                .or_insert_with(|| {
+                    ResponseBuilder::new()
+                        .description("Internal server error")
+                        .content(
+                            "application/json",
+                            Content::new(Ref::from_schema_name("ErrorBody")),
+                        )
+                        .into()
+                });
+        }
+    }
+    if !config.metrics.enabled || config.metrics.addr.is_some() {
+        paths.remove(metrics::METRICS_PATH);
+    }
+    // `#[utoipa::path]` gives a request body one content type; `POST /todos`
+    // also takes a form.
+    let create = paths
+        .get_mut("/todos")
+        .and_then(|item| item.operations.get_mut(&PathItemType::Post))
+        .and_then(|operation| operation.request_body.as_mut());
+    if let Some(body) = create {
+        body.content.insert(
+            "application/x-www-form-urlencoded".to_string(),
+            Content::new(Ref::from_schema_name("CreateTodoForm")),
+        );
+    }
+
+    let auth = &config.auth;
+    let mut schemes = Vec::new();
+    if !auth.api_keys.is_empty() || config.admin.api_key.is_some() {
+        schemes.push((
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-Api-Key"))),
+        ));
+    }
+    if !auth.api_keys.is_empty() || config.admin.api_key.is_some() || auth.jwt.is_some() {
+        schemes.push((
+            "bearer",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+        ));
+    }
+    if auth.basic.is_some() {
+        schemes.push((
+            "basic",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Basic)),
+        ));
+    }
+    if auth.jwt.is_some() {
+        schemes.push((
+            "session",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::with_description(
+                sessions::COOKIE_NAME,
+                "Set by POST /auth/session. Changes also need its CSRF token in X-CSRF-Token.",
+            ))),
+        ));
+    }
+    if schemes.is_empty() {
+        return doc;
+    }
+
+    doc.security = Some(
+        schemes
+            .iter()
+            .map(|(name, _)| SecurityRequirement::new(*name, Vec::<String>::new()))
+            .collect(),
+    );
+    let components = doc.components.get_or_insert_with(Default::default);
+    components.add_security_schemes_from_iter(schemes);
+
+    for (path, item) in doc.paths.paths.iter_mut() {
+        let public = PUBLIC_PATHS.contains(&path.as_str())
+            || (!auth.protect_health && PROBE_PATHS.contains(&path.as_str()));
+        for (method, operation) in item.operations.iter_mut() {
+            let session_login =
+                path == sessions::SESSION_PATH && matches!(method, PathItemType::Post);
+            if public || session_login {
+                operation.security = Some(Vec::new());
+            }
+        }
+    }
+    doc
+}
+
+/// `GET /docs/openapi.json`: the OpenAPI document.
+pub async fn spec(State(config): State<Arc<Config>>) -> Response {
+    if config.server.disable_docs {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    Json(document(&config)).into_response()
+}
+
+#[derive(Template)]
+#[template(path = "docs.html")]
+struct Page<'a> {
+    base: &'a str,
+    csrf_token: String,
+}
+
+/// `GET /docs`: Swagger UI on the document. Requests made from it carry the
+/// session's CSRF token, so a logged-in browser can try changes too.
+pub async fn page(
+    State(config): State<Arc<Config>>,
+    session: Option<Extension<Session>>,
+) -> Result<Response, ApiError> {
+    if config.server.disable_docs {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    Page {
+        base: config.server.base_path.as_str(),
+        csrf_token: session
+            .map(|Extension(session)| session.csrf_token)
+            .unwrap_or_default(),
+    }
+    .render()
+    .map(|html| Html(html).into_response())
+    .map_err(|err| ApiError::internal().caused_by(err))
+}
+
+/// `GET /docs/:file`: Swagger UI's stylesheet and script, compiled into the
+/// binary.
+pub async fn asset(State(config): State<Arc<Config>>, Path(file): Path<String>) -> Response {
+    if config.server.disable_docs || !ASSETS.contains(&file.as_str()) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let swagger = Arc::new(utoipa_swagger_ui::Config::new([SPEC_PATH]));
+    match utoipa_swagger_ui::serve(&file, swagger) {
+        Ok(Some(asset)) => {
+            let content_type = HeaderValue::from_str(&asset.content_type)
+                .unwrap_or(HeaderValue::from_static("application/octet-stream"));
+            (
+                [
+                    (header::CONTENT_TYPE, content_type),
+                    (
+                        header::CACHE_CONTROL,
+                        HeaderValue::from_static(ASSET_CACHE_CONTROL),
+                    ),
+                ],
+                asset.bytes.into_owned(),
+            )
+                .into_response()
+        }
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(err) => ApiError::internal()
+            .caused_by(err.to_string())
+            .into_response(),
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use askama::Template;
+use axum::{
+    body::{self, Full},
+    extract::State,
+    http::{header, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use serde_json::{json, Map, Value};
+use utoipa::ToSchema;
+
+use crate::config::Config;
+use crate::metrics;
+use crate::request_id;
+
+/// Error returned by handlers. Renders as
+/// `{"error": "...", "request_id": "..."}` so clients can quote the id
+/// when reporting a problem, or as a small HTML page for browsers; see
+/// [`negotiate`].
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+    details: Map<String, Value>,
+    /// What actually went wrong behind a `500`; never sent to the client.
+    cause: Option<String>,
+}
+
+/// The body of an [`ApiError`], for the API documentation only; errors are
+/// built as JSON in `into_response`.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct ErrorBody {
+    error: String,
+    request_id: String,
+    /// Each invalid field with its problems, on validation failures.
+    errors: Option<HashMap<String, Vec<String>>>,
+    /// A stable name for errors clients act on, such as
+    /// `todo_quota_exceeded`.
+    code: Option<String>,
+}
+
+tokio::task_local! {
+    /// The base path, when errors are rendered as pages.
+    static PAGE_BASE: Option<String>;
+}
+
+/// An error as a page for a browser: the status, the message and the
+/// request id, with a way back to the list.
+#[derive(Template)]
+#[template(path = "error.html")]
+struct ErrorPage<'a> {
+    status: u16,
+    reason: &'a str,
+    message: &'a str,
+    request_id: Option<String>,
+    base: &'a str,
+    /// The page at `/`: `base` itself, or `/` at the root.
+    home: &'a str,
+}
+
+/// The page for an error, or `None` when it fails to render, in which case
+/// the JSON body is sent after all.
+fn render_page(status: StatusCode, message: &str, base: &str) -> Option<String> {
+    ErrorPage {
+        base,
+        home: if base.is_empty() { "/" } else { base },
+        status: status.as_u16(),
+        reason: status.canonical_reason().unwrap_or("Error"),
+        message,
+        request_id: request_id::current(),
+    }
+    .render()
+    .map_err(|err| tracing::warn!(error = %err, "failed to render error page"))
+    .ok()
+}
+
+/// Decides once per request whether errors are rendered as pages: when
+/// `Accept` prefers `text/html`, as a browser navigating does, and the
+/// request is not htmx's, whose page shows JSON errors itself. Every
+/// [`ApiError`] raised while handling it follows, as do bare error statuses
+/// without a body, such as `405` from the router.
+pub async fn negotiate<B>(
+    State(config): State<Arc<Config>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let page =
+        crate::prefers(req.headers(), "text/html") && !req.headers().contains_key("hx-request");
+    let base = page.then(|| config.server.base_path.as_str().to_owned());
+    let response = PAGE_BASE.scope(base.clone(), next.run(req)).await;
+
+    let status = response.status();
+    let bare = (status.is_client_error() || status.is_server_error())
+        && !response.headers().contains_key(header::CONTENT_TYPE);
+    let Some(base) = base.filter(|_| bare) else {
+        return response;
+    };
+    let Some(html) = render_page(status, status.canonical_reason().unwrap_or("Error"), &base)
+    else {
+        return response;
+    };
+    let (mut parts, _) = response.into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/html; charset=utf-8"),
+    );
+    Response::from_parts(parts, body::boxed(Full::from(html)))
+}
+
+/// Response extension marking an internal server error, carrying its cause
+/// for the error reporter.
+#[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "sentry"), allow(dead_code))]
+pub struct InternalError(pub String);
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+            details: Map::new(),
+            cause: None,
+        }
+    }
+
+    pub fn validation(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNPROCESSABLE_ENTITY, message)
+    }
+
+    /// Adds an extra top-level key to the error body.
+    pub fn with_detail(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.details.insert(key.to_string(), value.into());
+        self
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn not_found() -> Self {
+        Self::new(StatusCode::NOT_FOUND, "todo not found")
+    }
+
+    pub fn internal() -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "internal server error")
+    }
+
+    /// Records the underlying failure of an internal error for reporting.
+    pub fn caused_by(mut self, cause: impl std::fmt::Display) -> Self {
+        self.cause = Some(cause.to_string());
+        self
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(err: sqlx::Error) -> Self {
+        tracing::error!(error = %err, "database error");
+        metrics::DB_ERRORS.fetch_add(1, Ordering::Relaxed);
+        Self::internal().caused_by(err)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let base = PAGE_BASE.try_with(Clone::clone).ok().flatten();
+        let mut response = match base.map(|base| render_page(self.status, &self.message, &base)) {
+            Some(Some(html)) => (self.status, Html(html)).into_response(),
+            _ => {
+                let mut body = self.details;
+                body.insert("error".into(), json!(self.message));
+                body.insert("request_id".into(), json!(request_id::current()));
+                (self.status, Json(Value::Object(body))).into_response()
+            }
+        };
+        if self.status == StatusCode::INTERNAL_SERVER_ERROR {
+            let cause = self.cause.unwrap_or(self.message);
+            response.extensions_mut().insert(InternalError(cause));
+        }
+        response
+    }
+}
+
+/// The gRPC status for the REST one: the message is kept, and what the JSON
+/// body would carry besides, such as `code` and `errors`, goes in the
+/// status details as a JSON object.
+#[cfg(feature = "grpc")]
+impl From<ApiError> for tonic::Status {
+    fn from(err: ApiError) -> Self {
+        use tonic::Code;
+
+        let code = match err.status {
+            StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => Code::InvalidArgument,
+            StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+            StatusCode::FORBIDDEN
+                if err.details.get("code") == Some(&json!("todo_quota_exceeded")) =>
+            {
+                Code::ResourceExhausted
+            }
+            StatusCode::FORBIDDEN => Code::PermissionDenied,
+            StatusCode::NOT_FOUND => Code::NotFound,
+            StatusCode::CONFLICT => Code::AlreadyExists,
+            StatusCode::TOO_MANY_REQUESTS => Code::ResourceExhausted,
+            StatusCode::SERVICE_UNAVAILABLE
+                if err.details.get("code") == Some(&json!(crate::server::REQUEST_TIMEOUT_CODE)) =>
+            {
+                Code::DeadlineExceeded
+            }
+            StatusCode::SERVICE_UNAVAILABLE => Code::Unavailable,
+            _ => Code::Internal,
+        };
+        if code == Code::Internal {
+            let cause = err.cause.as_deref().unwrap_or(&err.message);
+            tracing::error!(error = cause, "gRPC call failed");
+        }
+        if err.details.is_empty() {
+            return tonic::Status::new(code, err.message);
+        }
+        let details = serde_json::to_vec(&err.details).unwrap_or_default();
+        tonic::Status::with_details(code, err.message, details.into())
+    }
+}
+
+/// The GraphQL error for the REST one: the message is kept, and what the
+/// JSON body would carry besides, such as `code` and `errors`, goes in
+/// `extensions` with the status and the request id.
+#[cfg(feature = "graphql")]
+impl From<ApiError> for crate::graphql::Error {
+    fn from(err: ApiError) -> Self {
+        let mut extensions = err.details;
+        extensions.insert("status".into(), json!(err.status.as_u16()));
+        extensions.insert("request_id".into(), json!(request_id::current()));
+        let internal = (err.status == StatusCode::INTERNAL_SERVER_ERROR).then(|| {
+            let cause = err.cause.unwrap_or_else(|| err.message.clone());
+            tracing::error!(error = %cause, "GraphQL field failed");
+            InternalError(cause)
+        });
+        Self {
+            message: err.message,
+            extensions,
+            internal,
+            ..Self::new("")
+        }
+    }
+}
+
+/// Collects validation failures so a request with several bad fields is
+/// rejected once, listing all of them, instead of on the first.
+#[derive(Debug, Default)]
+pub struct FieldErrors(Vec<(String, String)>);
+
+impl FieldErrors {
+    pub fn add(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.0.push((field.into(), message.into()));
+    }
+
+    /// Records the error of a failed check under `field`.
+    pub fn check<T>(&mut self, field: &'static str, result: Result<T, String>) -> Option<T> {
+        result.map_err(|message| self.add(field, message)).ok()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The problems recorded for `field`, joined for showing next to it.
+    pub fn messages(&self, field: &str) -> Option<String> {
+        let messages: Vec<&str> = self
+            .0
+            .iter()
+            .filter(|(name, _)| *name == field)
+            .map(|(_, message)| message.as_str())
+            .collect();
+        (!messages.is_empty()).then(|| messages.join("; "))
+    }
+
+    /// `422` with an `errors` object mapping each bad field to the list of
+    /// its problems, for forms to highlight the offending inputs.
+    pub fn finish(self) -> Result<(), ApiError> {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+        Err(self.into_error())
+    }
+
+    /// The `422` for the recorded failures, for callers that hold on to
+    /// them before deciding how to report them.
+    pub fn into_error(self) -> ApiError {
+        let message = self
+            .0
+            .iter()
+            .map(|(field, message)| format!("{}: {}", field, message))
+            .collect::<Vec<_>>()
+            .join("; ");
+        let mut errors = Map::new();
+        for (field, message) in self.0 {
+            if let Value::Array(messages) = errors
+                .entry(field)
+                .or_insert_with(|| Value::Array(Vec::new()))
+            {
+                messages.push(json!(message));
+            }
+        }
+
+        ApiError::validation(message).with_detail("errors", errors)
+    }
+}
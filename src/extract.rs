@@ -0,0 +1,125 @@
+//! Body extractors whose rejections are [`ApiError`]s: a body that is not
+//! JSON, or not the JSON or form a route expects, gets the same envelope,
+//! request id included, as every other error instead of axum's plain text.
+//! A body of the wrong shape is a `422` whose `errors` are keyed by where
+//! in the body each problem is, as hand-written validation reports them.
+
+use axum::{
+    async_trait,
+    body::{Bytes, HttpBody},
+    extract::{
+        rejection::{BytesRejection, JsonRejection},
+        FromRequest,
+    },
+    http::{header, HeaderMap, Request, StatusCode},
+    BoxError,
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::error::{ApiError, FieldErrors};
+
+/// [`axum::Json`], rejecting with an [`ApiError`].
+#[derive(Debug)]
+pub struct JsonBody<T>(pub T);
+
+/// [`axum::Form`] for request bodies, rejecting with an [`ApiError`].
+#[derive(Debug)]
+pub struct FormBody<T>(pub T);
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for JsonBody<T>
+where
+    T: DeserializeOwned,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        // axum checks the content type and the syntax; the shape is checked
+        // here, where the path to a bad value is kept.
+        let axum::Json(value) = axum::Json::<Value>::from_request(req, state).await?;
+        from_value(value).map(JsonBody)
+    }
+}
+
+/// Whether the body is `application/x-www-form-urlencoded`, parameters
+/// such as `charset` aside.
+pub fn is_form(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|media| {
+            media
+                .trim()
+                .eq_ignore_ascii_case("application/x-www-form-urlencoded")
+        })
+}
+
+/// `value` as a `T`, or the `422` saying where it does not fit.
+pub fn from_value<T: DeserializeOwned>(value: Value) -> Result<T, ApiError> {
+    serde_path_to_error::deserialize(value).map_err(|err| {
+        let path = err.path().to_string();
+        shape_error(&path, err.into_inner().to_string())
+    })
+}
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for FormBody<T>
+where
+    T: DeserializeOwned,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        if !is_form(req.headers()) {
+            return Err(ApiError::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "Form requests must have `Content-Type: application/x-www-form-urlencoded`",
+            ));
+        }
+        let bytes = Bytes::from_request(req, state).await?;
+        let fields = serde_urlencoded::Deserializer::new(form_urlencoded::parse(&bytes));
+        serde_path_to_error::deserialize(fields)
+            .map(FormBody)
+            .map_err(|err| {
+                let path = err.path().to_string();
+                shape_error(&path, err.into_inner().to_string())
+            })
+    }
+}
+
+/// The `422` for a body that does not fit its type: keyed by the path to
+/// the bad value, such as `completed` or `[2].title`, or, for a field that
+/// is missing or unknown, by the path to the field; `.` is the body as a
+/// whole.
+fn shape_error(path: &str, message: String) -> ApiError {
+    let field = match message.split('`').nth(1) {
+        Some(name) if message.contains(" field `") && path == "." => name.to_string(),
+        Some(name) if message.contains(" field `") => format!("{}.{}", path, name),
+        _ => path.to_string(),
+    };
+    let mut errors = FieldErrors::default();
+    errors.add(field, message);
+    errors.into_error()
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        ApiError::new(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<BytesRejection> for ApiError {
+    fn from(rejection: BytesRejection) -> Self {
+        ApiError::new(rejection.status(), rejection.body_text())
+    }
+}
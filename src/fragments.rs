@@ -0,0 +1,415 @@
+//! HTML fragments for the htmx page at `/`: the todo list, one row, and the
+//! create and edit forms. Changes go through the same validation and
+//! storage code as the JSON handlers.
+
+use std::sync::Arc;
+
+use askama::Template;
+use axum::{
+    body::{self, Body},
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use serde::Deserialize;
+
+use crate::auth::UserId;
+use crate::config::Config;
+use crate::db::{self, ReadDb};
+use crate::error::{ApiError, FieldErrors};
+use crate::extract::FormBody;
+use crate::frontend;
+use crate::handlers::todos;
+use crate::live::Live;
+use crate::models::{parse_number, ListPage, ListParams, TodoChanges, UpdateTodo};
+use crate::pages::{FormErrors, Item, NewTodo};
+use crate::quotas::Quotas;
+use crate::request_id;
+use crate::sessions::Session;
+use crate::Db;
+
+/// Where the page shows errors that do not belong to a form.
+const ERROR_TARGET: &str = "#error";
+/// Event triggered on the page after every change, on which the list
+/// reloads itself.
+const CHANGED_EVENT: &str = "todos-changed";
+/// Todos per page of the list.
+const PAGE_SIZE: i64 = 20;
+
+/// Whether htmx made the request, rather than the browser navigating.
+fn from_htmx(headers: &HeaderMap) -> bool {
+    headers
+        .get("hx-request")
+        .is_some_and(|value| value.as_bytes() == b"true")
+}
+
+/// Fragments only make sense swapped into the page. Anything else, such as
+/// a bookmarked `/fragments/todos` or a form posted with JavaScript off,
+/// gets the whole page: a `GET` directly, other methods by `303` to `/`.
+/// Responses vary on `HX-Request` so caches keep the two apart.
+pub async fn require_htmx<B>(
+    State(config): State<Arc<Config>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let mut response = if from_htmx(req.headers()) {
+        next.run(req).await
+    } else if req.method() == Method::GET {
+        let session = req.extensions().get::<Session>().cloned();
+        frontend::page(&config, session).await
+    } else {
+        Redirect::to(&config.server.base_path.url("/")).into_response()
+    };
+    response
+        .headers_mut()
+        .append(header::VARY, HeaderValue::from_static("hx-request"));
+    response
+}
+
+#[derive(Template)]
+#[template(path = "fragments/error.html")]
+struct ErrorFragment<'a> {
+    message: &'a str,
+    request_id: Option<String>,
+}
+
+/// An error as a fragment for the page's error slot, keeping the status
+/// and, for a `500`, the cause for the error reporter.
+pub struct FragmentError(ApiError);
+
+impl From<ApiError> for FragmentError {
+    fn from(err: ApiError) -> Self {
+        Self(err)
+    }
+}
+
+impl From<sqlx::Error> for FragmentError {
+    fn from(err: sqlx::Error) -> Self {
+        Self(err.into())
+    }
+}
+
+impl IntoResponse for FragmentError {
+    fn into_response(self) -> Response {
+        let html = ErrorFragment {
+            message: self.0.message(),
+            request_id: request_id::current(),
+        }
+        .render()
+        .unwrap_or_else(|_| "<p class=\"error\">internal server error</p>".to_string());
+
+        let (mut parts, _) = self.0.into_response().into_parts();
+        parts.headers.remove(header::CONTENT_LENGTH);
+        parts.headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/html; charset=utf-8"),
+        );
+        parts
+            .headers
+            .insert("hx-retarget", HeaderValue::from_static(ERROR_TARGET));
+        parts
+            .headers
+            .insert("hx-reswap", HeaderValue::from_static("innerHTML"));
+        Response::from_parts(parts, body::boxed(Body::from(html)))
+    }
+}
+
+/// A response to a change, telling the page to refresh the list, so it
+/// shows the todos as stored, in order, with changes from other tabs too.
+pub struct Changed<T>(T);
+
+impl<T: IntoResponse> IntoResponse for Changed<T> {
+    fn into_response(self) -> Response {
+        let mut response = self.0.into_response();
+        response
+            .headers_mut()
+            .insert("hx-trigger", HeaderValue::from_static(CHANGED_EVENT));
+        response
+    }
+}
+
+fn render(template: impl Template) -> Result<Html<String>, FragmentError> {
+    template
+        .render()
+        .map(Html)
+        .map_err(|err| FragmentError(ApiError::internal().caused_by(err)))
+}
+
+#[derive(Template)]
+#[template(path = "fragments/todo_list.html")]
+struct ListFragment {
+    todos: Vec<Item>,
+    /// Whether a search or filter narrowed the list, so an empty one means
+    /// nothing matched rather than nothing to do.
+    filtered: bool,
+    /// Matching todos across all pages.
+    total: i64,
+    offset: i64,
+    limit: i64,
+}
+
+impl ListFragment {
+    fn first(&self) -> i64 {
+        self.offset + 1
+    }
+
+    fn last(&self) -> i64 {
+        self.offset + self.todos.len() as i64
+    }
+
+    /// Offset of the page before, if this is not the first.
+    fn previous(&self) -> Option<i64> {
+        (self.offset > 0).then(|| (self.offset - self.limit).max(0))
+    }
+
+    /// Offset of the page after, if there is one.
+    fn next(&self) -> Option<i64> {
+        (self.offset + self.limit < self.total).then_some(self.offset + self.limit)
+    }
+}
+
+#[derive(Template)]
+#[template(path = "fragments/todo_row.html")]
+struct RowFragment {
+    todo: Item,
+}
+
+#[derive(Template)]
+#[template(path = "fragments/todo_form.html")]
+struct CreateFragment {
+    form: NewTodo,
+    errors: FormErrors,
+}
+
+/// The edit form as submitted. `due_date_was` is the date the form was
+/// filled in with, so saving an untouched date keeps its time of day.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct EditTodo {
+    title: String,
+    due_date: String,
+    due_date_was: String,
+    priority: String,
+}
+
+#[derive(Template)]
+#[template(path = "fragments/todo_edit.html")]
+struct EditFragment {
+    id: String,
+    form: EditTodo,
+    errors: FormErrors,
+}
+
+impl EditTodo {
+    fn filled(todo: Item) -> Self {
+        let due_date = todo.due.unwrap_or_default();
+        Self {
+            title: todo.title,
+            due_date_was: due_date.clone(),
+            due_date,
+            priority: todo.priority.to_string(),
+        }
+    }
+
+    /// Reads the form as `PUT /todos/:id` reads its JSON body; a blank due
+    /// date clears it.
+    fn check(&self, config: &Config) -> Result<TodoChanges, FieldErrors> {
+        let mut errors = FieldErrors::default();
+        let priority = match self.priority.trim() {
+            "" => None,
+            value => errors.check("priority", parse_number(value)),
+        };
+        let due_date = match self.due_date.trim() {
+            value if value == self.due_date_was.trim() => None,
+            "" => Some(None),
+            value => Some(Some(value.to_string())),
+        };
+        UpdateTodo {
+            title: Some(config.prepare_title(self.title.clone())),
+            completed: None,
+            tags: None,
+            due_date,
+            priority,
+        }
+        .check(errors)
+    }
+}
+
+async fn find(db: &Db, user: &str, id: &str) -> Result<Item, FragmentError> {
+    db::find_todo(db, user, id)
+        .await?
+        .map(Item::from)
+        .ok_or_else(|| ApiError::not_found().into())
+}
+
+/// `GET /fragments/todos`: a page of the list, searched, filtered and
+/// paged with the query parameters `GET /todos` takes, `PAGE_SIZE` todos at
+/// a time unless `limit` says otherwise.
+pub async fn list(
+    State(ReadDb(db)): State<ReadDb>,
+    UserId(user): UserId,
+    Query(params): Query<ListParams>,
+    Query(page): Query<ListPage>,
+) -> Result<Html<String>, FragmentError> {
+    let filter = params.filter()?;
+    let (limit, offset) = page.validate()?;
+    let limit = limit.unwrap_or(PAGE_SIZE);
+    let (todos, total) = db::list_page(&db, &user, &filter, Some(limit), offset).await?;
+    render(ListFragment {
+        todos: todos.into_iter().map(Item::from).collect(),
+        filtered: filter.is_narrowed(),
+        total,
+        offset,
+        limit,
+    })
+}
+
+/// `GET /fragments/todos/new`: an empty create form.
+pub async fn new_form() -> Result<Html<String>, FragmentError> {
+    render(CreateFragment {
+        form: NewTodo::default(),
+        errors: FormErrors::default(),
+    })
+}
+
+/// `POST /fragments/todos`: adds a todo, answering with an empty form; the
+/// list picks up the new row as it refreshes. A rejected form comes back as
+/// filled in, with its errors, and the status the JSON API would give.
+pub async fn create(
+    State(db): State<Db>,
+    State(config): State<Arc<Config>>,
+    State(quotas): State<Arc<Quotas>>,
+    State(live): State<Live>,
+    UserId(user): UserId,
+    FormBody(form): FormBody<NewTodo>,
+) -> Result<Response, FragmentError> {
+    let mut todo = match form.check(&config) {
+        Ok(todo) => todo,
+        Err(errors) => {
+            let errors = FormErrors::from(&errors);
+            let html = render(CreateFragment { form, errors })?;
+            return Ok((StatusCode::UNPROCESSABLE_ENTITY, html).into_response());
+        }
+    };
+    match todos::insert_todo(&db, &config, &quotas, &user, &mut todo).await {
+        Ok(()) => {
+            live.created(&user, &todo.into());
+            Ok(Changed(render(CreateFragment {
+                form: NewTodo::default(),
+                errors: FormErrors::default(),
+            })?)
+            .into_response())
+        }
+        Err(err) if err.status().is_client_error() => {
+            let html = render(CreateFragment {
+                errors: FormErrors::rejected(&err),
+                form,
+            })?;
+            Ok((err.status(), html).into_response())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// `GET /fragments/todos/:id`: one row, as after cancelling an edit.
+pub async fn row(
+    Path(id): Path<String>,
+    State(db): State<Db>,
+    UserId(user): UserId,
+) -> Result<Html<String>, FragmentError> {
+    render(RowFragment {
+        todo: find(&db, &user, &id).await?,
+    })
+}
+
+/// `GET /fragments/todos/:id/edit`: the row as a form.
+pub async fn edit_form(
+    Path(id): Path<String>,
+    State(db): State<Db>,
+    UserId(user): UserId,
+) -> Result<Html<String>, FragmentError> {
+    let todo = find(&db, &user, &id).await?;
+    render(EditFragment {
+        id,
+        form: EditTodo::filled(todo),
+        errors: FormErrors::default(),
+    })
+}
+
+/// `PUT /fragments/todos/:id`: saves the edit form, answering with the
+/// updated row, or the form again with its errors.
+pub async fn update(
+    Path(id): Path<String>,
+    State(db): State<Db>,
+    State(config): State<Arc<Config>>,
+    State(live): State<Live>,
+    UserId(user): UserId,
+    FormBody(form): FormBody<EditTodo>,
+) -> Result<Response, FragmentError> {
+    let changes = match form.check(&config) {
+        Ok(changes) => changes,
+        Err(errors) => {
+            let errors = FormErrors::from(&errors);
+            let html = render(EditFragment { id, form, errors })?;
+            return Ok((StatusCode::UNPROCESSABLE_ENTITY, html).into_response());
+        }
+    };
+
+    let mut tx = db.begin().await?;
+    let todo = db::update_todo(&mut tx, &user, &id, changes)
+        .await?
+        .ok_or_else(ApiError::not_found)?;
+    tx.commit().await?;
+
+    live.updated(&user, &todo.clone().into());
+    Ok(Changed(render(RowFragment { todo: todo.into() })?).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Toggle {
+    completed: bool,
+}
+
+/// `POST /fragments/todos/:id/toggle`: marks a todo done or open, as the
+/// checkbox says, and answers with its row.
+pub async fn toggle(
+    Path(id): Path<String>,
+    State(db): State<Db>,
+    State(live): State<Live>,
+    UserId(user): UserId,
+    FormBody(form): FormBody<Toggle>,
+) -> Result<Changed<Html<String>>, FragmentError> {
+    let changes = TodoChanges {
+        completed: Some(form.completed),
+        ..TodoChanges::default()
+    };
+    let mut tx = db.begin().await?;
+    let todo = db::update_todo(&mut tx, &user, &id, changes)
+        .await?
+        .ok_or_else(ApiError::not_found)?;
+    tx.commit().await?;
+
+    live.toggled(&user, &todo.clone().into());
+    Ok(Changed(render(RowFragment { todo: todo.into() })?))
+}
+
+/// `DELETE /fragments/todos/:id`: soft-deletes a todo; the empty answer
+/// takes its row off the page.
+pub async fn delete(
+    Path(id): Path<String>,
+    State(db): State<Db>,
+    State(live): State<Live>,
+    UserId(user): UserId,
+) -> Result<Changed<Html<&'static str>>, FragmentError> {
+    let mut tx = db.begin().await?;
+    let deleted = db::delete_todo(&mut tx, &user, &id, false).await?;
+    tx.commit().await?;
+
+    if deleted {
+        live.deleted(&user, &id);
+        Ok(Changed(Html("")))
+    } else {
+        Err(ApiError::not_found().into())
+    }
+}
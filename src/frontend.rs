@@ -0,0 +1,213 @@
+//! The browser UI: `index.html`, `style.css` and `app.js` from
+//! `frontend/`. Release builds embed them; debug builds read them from
+//! disk on every request, so edits show on the next reload.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode, Uri},
+    response::{Html, IntoResponse, Response},
+    Extension, Json,
+};
+use rust_embed::RustEmbed;
+use serde_json::json;
+
+use crate::config::Config;
+use crate::error::ApiError;
+use crate::{sessions, static_dir, version};
+
+#[derive(RustEmbed)]
+#[folder = "frontend/"]
+struct Assets;
+
+/// Served only at `/`, with its placeholders filled in.
+const INDEX: &str = "index.html";
+
+/// The page at `/`: `index.html` from `static_dir` when set and present,
+/// the embedded one otherwise. `__BUILD_INFO__`, `__SESSION_USER__`,
+/// `__CSRF_TOKEN__` and `__BASE_PATH__` are filled in.
+pub async fn page(config: &Config, session: Option<sessions::Session>) -> Response {
+    let custom = match &config.server.static_dir {
+        Some(dir) => static_dir::index(dir).await,
+        None => None,
+    };
+    let html = match custom {
+        Some(html) => html,
+        None => match Assets::get(INDEX) {
+            Some(file) => String::from_utf8_lossy(&file.data).into_owned(),
+            None => {
+                return ApiError::internal()
+                    .caused_by("frontend/index.html is missing")
+                    .into_response()
+            }
+        },
+    };
+
+    let (user, csrf_token) = session
+        .map(|session| (session.user_id, session.csrf_token))
+        .unwrap_or_default();
+    Html(
+        html.replace("__BUILD_INFO__", &version::build_info().describe())
+            .replace("__SESSION_USER__", &crate::escape_html(&user))
+            .replace("__CSRF_TOKEN__", &csrf_token)
+            .replace("__BASE_PATH__", config.server.base_path.as_str()),
+    )
+    .into_response()
+}
+
+/// The HTML frontend, or a small JSON identification when the UI is
+/// disabled for headless deployments.
+pub async fn root(
+    State(config): State<Arc<Config>>,
+    session: Option<Extension<sessions::Session>>,
+) -> Response {
+    if config.server.disable_ui {
+        return Json(json!({
+            "name": "todo-api",
+            "version": version::build_info().version,
+        }))
+        .into_response();
+    }
+    page(&config, session.map(|Extension(session)| session)).await
+}
+
+/// Quoted hex of the first 16 bytes of a SHA-256, such as a file's.
+pub fn etag(hash: &[u8; 32]) -> String {
+    let hex: String = hash[..16]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("\"{}\"", hex)
+}
+
+/// Whether `If-None-Match` names `etag`, or any tag with `*`.
+pub fn not_modified(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|candidate| candidate.trim() == "*" || candidate.trim() == etag)
+}
+
+/// Whether the last path segment looks like a file name rather than a page
+/// of a single-page app.
+fn names_file(path: &str) -> bool {
+    path.rsplit('/')
+        .next()
+        .is_some_and(|segment| segment.contains('.'))
+}
+
+/// Paths no route matches: a frontend file, with its content type, an
+/// `ETag` from its hash and `Cache-Control: no-cache` so browsers
+/// revalidate. Anything else gets the usual JSON `404`, except that with
+/// `spa_fallback` a `GET` for a path without a file extension gets the
+/// page at `/`, leaving routing to the client.
+pub async fn asset(
+    State(config): State<Arc<Config>>,
+    session: Option<Extension<sessions::Session>>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Response {
+    let reads = matches!(method, Method::GET | Method::HEAD);
+    if config.server.disable_ui || !reads {
+        return crate::handlers::fallback(uri).await.into_response();
+    }
+
+    let path = uri.path().trim_start_matches('/');
+    let embedded = if path == INDEX || path.split('/').any(|segment| segment == "..") {
+        None
+    } else {
+        Assets::get(path)
+    };
+    if let Some(file) = embedded {
+        let etag = etag(&file.metadata.sha256_hash());
+        let mut response = if not_modified(&headers, &etag) {
+            StatusCode::NOT_MODIFIED.into_response()
+        } else {
+            file.data.into_owned().into_response()
+        };
+        let headers = response.headers_mut();
+        if let Ok(content_type) = HeaderValue::from_str(file.metadata.mimetype()) {
+            headers.insert(header::CONTENT_TYPE, content_type);
+        }
+        if let Ok(etag) = HeaderValue::from_str(&etag) {
+            headers.insert(header::ETAG, etag);
+        }
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        return response;
+    }
+
+    if config.server.spa_fallback && !names_file(path) {
+        return page(&config, session.map(|Extension(session)| session)).await;
+    }
+    crate::handlers::fallback(uri).await.into_response()
+}
+
+pub const FAVICON_ICO_PATH: &str = "/favicon.ico";
+pub const FAVICON_SVG_PATH: &str = "/favicon.svg";
+/// PNG icons in the sizes home screens and install prompts ask for.
+pub const ICON_192_PATH: &str = "/icon-192.png";
+pub const ICON_512_PATH: &str = "/icon-512.png";
+pub const ROBOTS_PATH: &str = "/robots.txt";
+
+/// Browsers ask for the icon on every page, so they may keep it for a
+/// month rather than revalidating each time.
+const ICON_CACHE_CONTROL: &str = "public, max-age=2592000";
+/// Keeps crawlers out of an API that holds nothing public.
+pub const DEFAULT_ROBOTS_TXT: &str = "User-agent: *\nDisallow: /\n";
+
+fn icon(content_type: &'static str, data: &'static [u8]) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static(content_type)),
+            (
+                header::CACHE_CONTROL,
+                HeaderValue::from_static(ICON_CACHE_CONTROL),
+            ),
+        ],
+        data,
+    )
+        .into_response()
+}
+
+/// `GET /favicon.ico`, compiled in like `/version`, so it never reaches
+/// the database, static_dir or the fallback.
+pub async fn favicon_ico() -> Response {
+    icon("image/x-icon", include_bytes!("../frontend/favicon.ico"))
+}
+
+/// `GET /favicon.svg`, the icon the pages link to.
+pub async fn favicon_svg() -> Response {
+    icon("image/svg+xml", include_bytes!("../frontend/favicon.svg"))
+}
+
+/// `GET /icon-192.png`, for the web app manifest.
+pub async fn icon_192() -> Response {
+    icon("image/png", include_bytes!("../frontend/icon-192.png"))
+}
+
+/// `GET /icon-512.png`, for the web app manifest.
+pub async fn icon_512() -> Response {
+    icon("image/png", include_bytes!("../frontend/icon-512.png"))
+}
+
+/// `GET /robots.txt`: `server.robots_txt` when set, otherwise a file
+/// asking every crawler to stay away.
+pub async fn robots(State(config): State<Arc<Config>>) -> Response {
+    let body = config
+        .server
+        .robots_txt
+        .clone()
+        .unwrap_or_else(|| DEFAULT_ROBOTS_TXT.to_string());
+    (
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/plain; charset=utf-8"),
+        )],
+        body,
+    )
+        .into_response()
+}
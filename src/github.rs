@@ -0,0 +1,331 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context};
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::auth::{keys_match, Authenticator};
+use crate::config::{Config, Secret};
+use crate::db;
+use crate::error::ApiError;
+use crate::sessions::{self, random_token};
+
+pub const GITHUB_PATH: &str = "/auth/github";
+pub const CALLBACK_PATH: &str = "/auth/github/callback";
+
+/// Holds the `state` sent to GitHub until it comes back to the callback.
+const STATE_COOKIE: &str = "todo_oauth_state";
+/// How long a sign-in may take on GitHub's side.
+const STATE_MAX_AGE_SECS: u64 = 600;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// "Sign in with GitHub", enabled by an `[auth.github]` section or
+/// GITHUB_CLIENT_ID.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GithubConfig {
+    /// The OAuth app's client id.
+    pub client_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<Secret>,
+    /// Where GitHub sends users back, as registered with the OAuth app:
+    /// this server's `/auth/github/callback`.
+    pub callback_url: String,
+    /// For GitHub Enterprise Server.
+    pub web_url: String,
+    pub api_url: String,
+}
+
+impl Default for GithubConfig {
+    fn default() -> Self {
+        Self {
+            client_id: String::new(),
+            client_secret: None,
+            callback_url: String::new(),
+            web_url: "https://github.com".to_string(),
+            api_url: "https://api.github.com".to_string(),
+        }
+    }
+}
+
+/// The GitHub account a sign-in came back with.
+#[derive(Debug, Deserialize)]
+struct Account {
+    id: i64,
+    login: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AccessToken {
+    access_token: Option<String>,
+    /// GitHub reports a bad or used code with `200` and this set.
+    error: Option<String>,
+}
+
+/// Talks to GitHub for the authorization-code flow.
+pub struct GitHub {
+    config: GithubConfig,
+    client_secret: Secret,
+    http: reqwest::Client,
+}
+
+impl fmt::Debug for GitHub {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GitHub")
+            .field("client_id", &self.config.client_id)
+            .field("callback_url", &self.config.callback_url)
+            .finish_non_exhaustive()
+    }
+}
+
+impl GitHub {
+    pub fn new(config: &GithubConfig) -> anyhow::Result<Self> {
+        if config.client_id.is_empty() {
+            bail!("auth.github.client_id (GITHUB_CLIENT_ID) must be set");
+        }
+        let client_secret = config
+            .client_secret
+            .clone()
+            .filter(|secret| !secret.expose().is_empty())
+            .ok_or_else(|| {
+                anyhow!("auth.github.client_secret (GITHUB_CLIENT_SECRET) must be set")
+            })?;
+        if !config.callback_url.ends_with(CALLBACK_PATH) {
+            bail!(
+                "auth.github.callback_url (GITHUB_CALLBACK_URL) must be this server's {}",
+                CALLBACK_PATH
+            );
+        }
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .user_agent(concat!("todo-api/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+
+        Ok(Self {
+            config: config.clone(),
+            client_secret,
+            http,
+        })
+    }
+
+    /// GitHub's authorization page for a sign-in carrying `state`. No
+    /// scope is asked for: the public profile is all that is needed.
+    fn authorize_url(&self, state: &str) -> anyhow::Result<reqwest::Url> {
+        let url = format!("{}/login/oauth/authorize", self.config.web_url);
+        Ok(reqwest::Url::parse_with_params(
+            &url,
+            [
+                ("client_id", self.config.client_id.as_str()),
+                ("redirect_uri", self.config.callback_url.as_str()),
+                ("state", state),
+                ("allow_signup", "false"),
+            ],
+        )?)
+    }
+
+    /// Exchanges the callback's `code` for a token and looks up whose it is.
+    async fn account(&self, code: &str) -> anyhow::Result<Account> {
+        let token: AccessToken = self
+            .http
+            .post(format!("{}/login/oauth/access_token", self.config.web_url))
+            .header(reqwest::header::ACCEPT, "application/json")
+            .form(&[
+                ("client_id", self.config.client_id.as_str()),
+                ("client_secret", self.client_secret.expose()),
+                ("code", code),
+                ("redirect_uri", self.config.callback_url.as_str()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context("failed to read the access token response")?;
+        let access_token = match (token.access_token, token.error) {
+            (Some(access_token), None) => access_token,
+            (_, error) => bail!(
+                "GitHub refused the code: {}",
+                error.as_deref().unwrap_or("no access token")
+            ),
+        };
+
+        self.http
+            .get(format!("{}/user", self.config.api_url))
+            .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+            .bearer_auth(access_token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context("failed to read the GitHub user")
+    }
+}
+
+/// `Set-Cookie` for the sign-in state; an empty value clears it.
+fn state_cookie(auth: &Authenticator, config: &Config, value: &str) -> Option<HeaderValue> {
+    let max_age = if value.is_empty() {
+        0
+    } else {
+        STATE_MAX_AGE_SECS
+    };
+    let mut cookie = format!(
+        "{}={}; Path={}; Max-Age={}; HttpOnly; SameSite=Lax",
+        STATE_COOKIE,
+        value,
+        config.server.base_path.url(GITHUB_PATH),
+        max_age
+    );
+    if auth.session_config().secure_cookie {
+        cookie.push_str("; Secure");
+    }
+    HeaderValue::from_str(&cookie).ok()
+}
+
+fn presented_state(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == STATE_COOKIE)
+        .map(|(_, value)| value)
+}
+
+/// `GET /auth/github`: sends the browser to GitHub to sign in, remembering
+/// a fresh `state` in a short-lived cookie.
+pub async fn start(
+    State(auth): State<Arc<Authenticator>>,
+    State(config): State<Arc<Config>>,
+) -> Response {
+    let Some(github) = auth.github() else {
+        return ApiError::new(StatusCode::NOT_FOUND, "GitHub sign-in is not configured")
+            .into_response();
+    };
+    let state = random_token();
+    let url = match github.authorize_url(&state) {
+        Ok(url) => url,
+        Err(err) => return ApiError::internal().caused_by(err).into_response(),
+    };
+
+    let mut response = StatusCode::SEE_OTHER.into_response();
+    let headers = response.headers_mut();
+    if let Ok(location) = HeaderValue::from_str(url.as_str()) {
+        headers.insert(header::LOCATION, location);
+    }
+    if let Some(cookie) = state_cookie(&auth, &config, &state) {
+        headers.insert(header::SET_COOKIE, cookie);
+    }
+    response
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Callback {
+    code: Option<String>,
+    state: Option<String>,
+    /// Set instead of `code` when the user declined.
+    error: Option<String>,
+}
+
+/// `GET /auth/github/callback`: where GitHub sends the browser back. Checks
+/// `state` against the cookie (`403` with `"code": "oauth_state_mismatch"`
+/// otherwise), exchanges the code, finds or creates the local user for the
+/// GitHub account and starts a session, then redirects to the page at `/`. A new user
+/// is named after the GitHub login; when a local user of that name already
+/// exists, nothing is linked and the sign-in gets `409` with
+/// `"code": "account_exists"`.
+pub async fn callback(
+    State(auth): State<Arc<Authenticator>>,
+    State(config): State<Arc<Config>>,
+    State(db): State<SqlitePool>,
+    Query(params): Query<Callback>,
+    headers: HeaderMap,
+) -> Response {
+    let Some(github) = auth.github() else {
+        return ApiError::new(StatusCode::NOT_FOUND, "GitHub sign-in is not configured")
+            .into_response();
+    };
+    let clear_state = state_cookie(&auth, &config, "");
+    let with_cleared_state = |mut response: Response| {
+        if let Some(cookie) = clear_state.clone() {
+            response.headers_mut().append(header::SET_COOKIE, cookie);
+        }
+        response
+    };
+
+    let state_matches = match (presented_state(&headers), params.state.as_deref()) {
+        (Some(expected), Some(state)) => !state.is_empty() && keys_match(state, expected),
+        _ => false,
+    };
+    if !state_matches {
+        tracing::warn!("rejected GitHub callback with a missing or wrong state");
+        return with_cleared_state(
+            ApiError::new(StatusCode::FORBIDDEN, "invalid or expired sign-in state")
+                .with_detail("code", "oauth_state_mismatch")
+                .into_response(),
+        );
+    }
+    if let Some(error) = params.error {
+        return with_cleared_state(
+            ApiError::new(StatusCode::FORBIDDEN, "GitHub sign-in was not completed")
+                .with_detail("github_error", error)
+                .into_response(),
+        );
+    }
+    let Some(code) = params.code else {
+        return with_cleared_state(
+            ApiError::new(StatusCode::BAD_REQUEST, "missing code").into_response(),
+        );
+    };
+
+    let account = match github.account(&code).await {
+        Ok(account) => account,
+        Err(err) => {
+            tracing::warn!(error = %err, "GitHub sign-in failed");
+            return with_cleared_state(
+                ApiError::new(StatusCode::BAD_GATEWAY, "GitHub sign-in failed").into_response(),
+            );
+        }
+    };
+
+    let login = account.login.to_lowercase();
+    let named_admin = config.admin.users.contains(&login);
+    let user = match db::github_user(&db, account.id, &login, named_admin).await {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            tracing::warn!(%login, "GitHub login matches an existing local user");
+            return with_cleared_state(
+                ApiError::new(
+                    StatusCode::CONFLICT,
+                    "a user with this name already exists; sign in with its password instead",
+                )
+                .with_detail("code", "account_exists")
+                .into_response(),
+            );
+        }
+        Err(err) => return with_cleared_state(ApiError::from(err).into_response()),
+    };
+
+    let cookie = match sessions::start(&db, auth.session_config(), &user).await {
+        Ok(cookie) => cookie,
+        Err(err) => return with_cleared_state(ApiError::from(err).into_response()),
+    };
+    tracing::info!(%user, github_id = account.id, "signed in with GitHub");
+
+    let mut response = StatusCode::SEE_OTHER.into_response();
+    if let Ok(location) = HeaderValue::from_str(&config.server.base_path.url("/")) {
+        response.headers_mut().insert(header::LOCATION, location);
+    }
+    if let Some(cookie) = cookie {
+        response.headers_mut().insert(header::SET_COOKIE, cookie);
+    }
+    with_cleared_state(response)
+}
@@ -0,0 +1,41 @@
+//! GraphiQL at `/graphiql`, to try queries from a browser; debug builds
+//! only. It is loaded from unpkg rather than compiled in.
+
+use std::sync::Arc;
+
+use askama::Template;
+use axum::{
+    extract::State,
+    response::{Html, IntoResponse, Response},
+    Extension,
+};
+
+use crate::config::Config;
+use crate::error::ApiError;
+use crate::sessions::Session;
+
+pub const GRAPHIQL_PATH: &str = "/graphiql";
+
+#[derive(Template)]
+#[template(path = "graphiql.html")]
+struct Page<'a> {
+    base: &'a str,
+    csrf_token: String,
+}
+
+/// `GET /graphiql`: GraphiQL on `/graphql`. Requests made from it carry the
+/// session's CSRF token, as those from `/docs` do.
+pub async fn page(
+    State(config): State<Arc<Config>>,
+    session: Option<Extension<Session>>,
+) -> Result<Response, ApiError> {
+    Page {
+        base: config.server.base_path.as_str(),
+        csrf_token: session
+            .map(|Extension(session)| session.csrf_token)
+            .unwrap_or_default(),
+    }
+    .render()
+    .map(|html| Html(html).into_response())
+    .map_err(|err| ApiError::internal().caused_by(err))
+}
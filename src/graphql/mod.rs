@@ -0,0 +1,217 @@
+//! `POST /graphql` and `GET /graphql`: the todos as a GraphQL schema,
+//! compiled in with the `graphql` feature. Queries and mutations are
+//! answered by the same `db` functions as the REST routes, with the same
+//! validation, soft deletes and live events, and their errors carry the
+//! REST error's `code` in `extensions`. Debug builds also serve GraphiQL at
+//! `/graphiql`.
+//!
+//! The request documents are parsed and run here rather than by a GraphQL
+//! crate; the schema is small and has no interfaces, unions or
+//! subscriptions, so [`parse`], [`validate`] and [`execute`] only cover
+//! what it needs.
+
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, MethodRouter},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::auth::UserId;
+use crate::db;
+use crate::error::{ApiError, InternalError};
+use crate::extract::JsonBody;
+use crate::quotas;
+use crate::AppState;
+
+mod execute;
+#[cfg(debug_assertions)]
+mod graphiql;
+mod parse;
+mod schema;
+mod service;
+mod validate;
+
+use execute::Execution;
+use parse::{OperationKind, Pos};
+
+pub const GRAPHQL_PATH: &str = "/graphql";
+#[cfg(debug_assertions)]
+pub use graphiql::GRAPHIQL_PATH;
+
+/// Mounted among the API routes, behind the request timeout.
+pub fn routes() -> Vec<(&'static str, MethodRouter<AppState>)> {
+    #[allow(unused_mut)]
+    let mut routes = vec![(GRAPHQL_PATH, get(query).post(operation))];
+    #[cfg(debug_assertions)]
+    routes.push((GRAPHIQL_PATH, get(graphiql::page)));
+    routes
+}
+
+/// One error in a response's `errors`, as the spec lays it out.
+#[derive(Debug, Default, Serialize)]
+pub struct Error {
+    pub message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub locations: Vec<Pos>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub path: Vec<Value>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub extensions: Map<String, Value>,
+    /// What went wrong behind an internal error, for the error reporter.
+    #[serde(skip)]
+    pub internal: Option<InternalError>,
+}
+
+impl Error {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            ..Self::default()
+        }
+    }
+
+    pub fn at(message: impl Into<String>, pos: Pos) -> Self {
+        Self {
+            locations: vec![pos],
+            ..Self::new(message)
+        }
+    }
+
+    /// Sets `extensions.code`, unless the error already has one.
+    fn with_code(mut self, code: &str) -> Self {
+        self.extensions.entry("code").or_insert_with(|| json!(code));
+        self
+    }
+}
+
+/// A request, as `POST` sends it in the body.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Request {
+    query: String,
+    #[serde(default)]
+    operation_name: Option<String>,
+    #[serde(default)]
+    variables: Option<Map<String, Value>>,
+}
+
+/// A request, as `GET` sends it in the query string: `variables` is JSON.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryParams {
+    query: String,
+    #[serde(default)]
+    operation_name: Option<String>,
+    #[serde(default)]
+    variables: Option<String>,
+}
+
+/// `POST /graphql`: runs a query or a mutation.
+pub async fn operation(
+    State(state): State<AppState>,
+    UserId(user): UserId,
+    JsonBody(request): JsonBody<Request>,
+) -> Response {
+    respond(state, user, request, true).await
+}
+
+/// `GET /graphql`: runs a query. Mutations must be POSTed, so a link cannot
+/// change anything.
+pub async fn query(
+    State(state): State<AppState>,
+    UserId(user): UserId,
+    Query(params): Query<QueryParams>,
+) -> Result<Response, ApiError> {
+    let variables = params
+        .variables
+        .map(|variables| serde_json::from_str(&variables))
+        .transpose()
+        .map_err(|err| {
+            ApiError::new(
+                StatusCode::BAD_REQUEST,
+                format!("variables must be a JSON object: {}", err),
+            )
+        })?;
+    let request = Request {
+        query: params.query,
+        operation_name: params.operation_name,
+        variables,
+    };
+    Ok(respond(state, user, request, false).await)
+}
+
+/// Parses, validates and runs `request` for `user`. A document that does
+/// not parse or validate is answered with its errors and no `data`; the
+/// rest with `data` and the errors of the fields that failed. Mutations are
+/// refused during maintenance and charged to the user's write quota, as a
+/// REST write would be.
+async fn respond(state: AppState, user: String, request: Request, posted: bool) -> Response {
+    let document = match parse::document(&request.query) {
+        Ok(document) => document,
+        Err(err) => {
+            let error = Error::at(err.message, err.pos).with_code("graphql_parse_failed");
+            return reply(None, vec![error]);
+        }
+    };
+    let execution = match Execution::prepare(
+        &document,
+        request.operation_name.as_deref(),
+        request.variables.unwrap_or_default(),
+    ) {
+        Ok(execution) => execution,
+        Err(errors) => {
+            let errors = errors
+                .into_iter()
+                .map(|err| err.with_code("graphql_validation_failed"))
+                .collect();
+            return reply(None, errors);
+        }
+    };
+
+    let mutation = execution.kind() == OperationKind::Mutation;
+    if mutation && !posted {
+        let mut response = ApiError::new(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "mutations must be sent with POST",
+        )
+        .into_response();
+        response
+            .headers_mut()
+            .insert(header::ALLOW, HeaderValue::from_static("POST"));
+        return response;
+    }
+    if let Some(response) = state.maintenance.refused(mutation) {
+        return response;
+    }
+    if mutation && user != db::DEFAULT_USER_ID {
+        if let Err(response) = quotas::charge_write(&state.quotas, &state.db, &user).await {
+            return response;
+        }
+    }
+
+    let root = service::Todos::new(state, user);
+    let (data, errors) = execution.run(&root).await;
+    reply(Some(data), errors)
+}
+
+/// The response body; marked for the error reporter when a field failed
+/// internally, as a REST `500` would be.
+fn reply(data: Option<Value>, mut errors: Vec<Error>) -> Response {
+    let internal = errors.iter_mut().find_map(|err| err.internal.take());
+    let mut body = Map::new();
+    if let Some(data) = data {
+        body.insert("data".into(), data);
+    }
+    if !errors.is_empty() {
+        body.insert("errors".into(), json!(errors));
+    }
+    let mut response = Json(Value::Object(body)).into_response();
+    if let Some(internal) = internal {
+        response.extensions_mut().insert(internal);
+    }
+    response
+}
@@ -0,0 +1,869 @@
+//! GraphQL request documents, parsed by hand as the October 2021 spec
+//! writes them. Type system definitions are refused: a request has no use
+//! for them.
+
+use std::fmt;
+
+use serde::Serialize;
+
+/// Deepest nesting of selections, lists, objects and types a document may
+/// have, so a hostile one cannot exhaust the stack.
+const MAX_DEPTH: usize = 64;
+
+/// Where something starts in the document, counted from 1 as GraphQL
+/// errors report it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Pos {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub message: String,
+    pub pos: Pos,
+}
+
+#[derive(Debug, Default)]
+pub struct Document {
+    pub operations: Vec<Operation>,
+    pub fragments: Vec<Fragment>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperationKind {
+    Query,
+    Mutation,
+    Subscription,
+}
+
+#[derive(Debug)]
+pub struct Operation {
+    pub kind: OperationKind,
+    pub name: Option<String>,
+    pub variables: Vec<VariableDefinition>,
+    pub directives: Vec<Directive>,
+    pub selection: Vec<Selection>,
+    pub pos: Pos,
+}
+
+#[derive(Debug)]
+pub struct VariableDefinition {
+    pub name: String,
+    pub ty: Type,
+    pub default: Option<Value>,
+    pub pos: Pos,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    Named(String),
+    List(Box<Type>),
+    NonNull(Box<Type>),
+}
+
+#[derive(Debug)]
+pub struct Fragment {
+    pub name: String,
+    pub type_condition: String,
+    pub directives: Vec<Directive>,
+    pub selection: Vec<Selection>,
+}
+
+#[derive(Debug)]
+pub enum Selection {
+    Field(Field),
+    Spread(Spread),
+    Inline(InlineFragment),
+}
+
+#[derive(Debug)]
+pub struct Field {
+    pub alias: Option<String>,
+    pub name: String,
+    pub arguments: Vec<(String, Value)>,
+    pub directives: Vec<Directive>,
+    pub selection: Vec<Selection>,
+    pub pos: Pos,
+}
+
+impl Field {
+    /// The key the field's value has in the response.
+    pub fn key(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.name)
+    }
+}
+
+#[derive(Debug)]
+pub struct Spread {
+    pub name: String,
+    pub directives: Vec<Directive>,
+    pub pos: Pos,
+}
+
+#[derive(Debug)]
+pub struct InlineFragment {
+    pub type_condition: Option<String>,
+    pub directives: Vec<Directive>,
+    pub selection: Vec<Selection>,
+    pub pos: Pos,
+}
+
+#[derive(Debug)]
+pub struct Directive {
+    pub name: String,
+    pub arguments: Vec<(String, Value)>,
+    pub pos: Pos,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Variable(String),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Boolean(bool),
+    Null,
+    Enum(String),
+    List(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Named(name) => f.write_str(name),
+            Type::List(item) => write!(f, "[{}]", item),
+            Type::NonNull(inner) => write!(f, "{}!", inner),
+        }
+    }
+}
+
+impl Type {
+    /// The named type under any list and non-null wrappers.
+    pub fn name(&self) -> &str {
+        match self {
+            Type::Named(name) => name,
+            Type::List(inner) | Type::NonNull(inner) => inner.name(),
+        }
+    }
+
+    pub fn is_non_null(&self) -> bool {
+        matches!(self, Type::NonNull(_))
+    }
+}
+
+/// Parses a request document.
+pub fn document(source: &str) -> Result<Document, Error> {
+    let mut parser = Parser::new(source)?;
+    let mut document = Document::default();
+    if parser.peek() == &Token::End {
+        return Err(parser.error("the document has no operations"));
+    }
+    while parser.peek() != &Token::End {
+        match parser.peek() {
+            Token::Punct('{') => document.operations.push(parser.operation()?),
+            Token::Name(name) if name == "fragment" => document.fragments.push(parser.fragment()?),
+            Token::Name(name) if matches!(name.as_str(), "query" | "mutation" | "subscription") => {
+                document.operations.push(parser.operation()?)
+            }
+            _ => return Err(parser.unexpected("an operation or a fragment")),
+        }
+    }
+    Ok(document)
+}
+
+/// Parses a type such as `[String!]!`, as the schema writes them.
+pub fn ty(source: &str) -> Result<Type, Error> {
+    let mut parser = Parser::new(source)?;
+    let ty = parser.ty()?;
+    parser.expect_end()?;
+    Ok(ty)
+}
+
+/// Parses a value without variables, as default values are written.
+pub fn const_value(source: &str) -> Result<Value, Error> {
+    let mut parser = Parser::new(source)?;
+    let value = parser.value(true)?;
+    parser.expect_end()?;
+    Ok(value)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Punct(char),
+    Spread,
+    Name(String),
+    Int(i64),
+    Float(f64),
+    String(String),
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Punct(c) => write!(f, "\"{}\"", c),
+            Token::Spread => f.write_str("\"...\""),
+            Token::Name(name) => write!(f, "name \"{}\"", name),
+            Token::Int(value) => write!(f, "int {}", value),
+            Token::Float(value) => write!(f, "float {}", value),
+            Token::String(value) => write!(f, "string {:?}", value),
+            Token::End => f.write_str("the end of the document"),
+        }
+    }
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    at: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Lexer {
+    fn pos(&self) -> Pos {
+        Pos {
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.at).copied()
+    }
+
+    /// Whether the document continues with `text`.
+    fn looking_at(&self, text: &str) -> bool {
+        text.chars()
+            .enumerate()
+            .all(|(i, c)| self.chars.get(self.at + i) == Some(&c))
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.at += 1;
+        match c {
+            '\n' => {
+                self.line += 1;
+                self.column = 1;
+            }
+            // `\r\n` is one line terminator; the `\n` moves to the next line.
+            '\r' if self.peek() != Some('\n') => {
+                self.line += 1;
+                self.column = 1;
+            }
+            '\r' => {}
+            _ => self.column += 1,
+        }
+        Some(c)
+    }
+
+    fn skip(&mut self, chars: usize) {
+        for _ in 0..chars {
+            self.bump();
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> Error {
+        Error {
+            message: message.into(),
+            pos: self.pos(),
+        }
+    }
+
+    /// Every token of the document with where it starts, ending in
+    /// [`Token::End`].
+    fn tokens(mut self) -> Result<Vec<(Token, Pos)>, Error> {
+        let mut tokens = Vec::new();
+        loop {
+            self.skip_ignored();
+            let pos = self.pos();
+            let Some(c) = self.peek() else {
+                tokens.push((Token::End, pos));
+                return Ok(tokens);
+            };
+            let token = match c {
+                '!' | '$' | '&' | '(' | ')' | ':' | '=' | '@' | '[' | ']' | '{' | '|' | '}' => {
+                    self.bump();
+                    Token::Punct(c)
+                }
+                '.' => {
+                    for _ in 0..3 {
+                        if self.bump() != Some('.') {
+                            return Err(Error {
+                                message: "expected \"...\"".to_string(),
+                                pos,
+                            });
+                        }
+                    }
+                    Token::Spread
+                }
+                '"' => self.string()?,
+                '-' | '0'..='9' => self.number()?,
+                c if c == '_' || c.is_ascii_alphabetic() => Token::Name(self.name()),
+                c => return Err(self.error(format!("unexpected character {:?}", c))),
+            };
+            tokens.push((token, pos));
+        }
+    }
+
+    /// Whitespace, line terminators, commas, comments and the byte order
+    /// mark.
+    fn skip_ignored(&mut self) {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\n' | '\r' | ',' | '\u{feff}' => {
+                    self.bump();
+                }
+                '#' => {
+                    while self.peek().is_some_and(|c| c != '\n' && c != '\r') {
+                        self.bump();
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn name(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(c) = self.peek() {
+            if c != '_' && !c.is_ascii_alphanumeric() {
+                break;
+            }
+            name.push(c);
+            self.bump();
+        }
+        name
+    }
+
+    fn digits(&mut self, into: &mut String) -> Result<(), Error> {
+        if !self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            return Err(self.error("expected a digit"));
+        }
+        while let Some(c) = self.peek().filter(char::is_ascii_digit) {
+            into.push(c);
+            self.bump();
+        }
+        Ok(())
+    }
+
+    fn number(&mut self) -> Result<Token, Error> {
+        let start = self.pos();
+        let mut text = String::new();
+        if self.peek() == Some('-') {
+            text.push('-');
+            self.bump();
+        }
+        let integer_start = text.len();
+        self.digits(&mut text)?;
+        if text[integer_start..].starts_with('0') && text.len() > integer_start + 1 {
+            return Err(Error {
+                message: "numbers must not have leading zeros".to_string(),
+                pos: start,
+            });
+        }
+        let mut float = false;
+        if self.peek() == Some('.') {
+            float = true;
+            text.push('.');
+            self.bump();
+            self.digits(&mut text)?;
+        }
+        if let Some(e) = self.peek().filter(|&c| c == 'e' || c == 'E') {
+            float = true;
+            text.push(e);
+            self.bump();
+            if let Some(sign) = self.peek().filter(|&c| c == '+' || c == '-') {
+                text.push(sign);
+                self.bump();
+            }
+            self.digits(&mut text)?;
+        }
+        if self
+            .peek()
+            .is_some_and(|c| c == '.' || c == '_' || c.is_ascii_alphabetic())
+        {
+            return Err(self.error(format!("invalid number {:?}", text)));
+        }
+
+        let invalid = || Error {
+            message: format!("invalid number {:?}", text),
+            pos: start,
+        };
+        if float {
+            text.parse().map(Token::Float).map_err(|_| invalid())
+        } else {
+            text.parse().map(Token::Int).map_err(|_| invalid())
+        }
+    }
+
+    fn string(&mut self) -> Result<Token, Error> {
+        let start = self.pos();
+        if self.looking_at("\"\"\"") {
+            self.skip(3);
+            return self.block_string(start);
+        }
+        self.bump();
+
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                None | Some('\n' | '\r') => {
+                    return Err(Error {
+                        message: "unterminated string".to_string(),
+                        pos: start,
+                    })
+                }
+                Some('"') => return Ok(Token::String(value)),
+                Some('\\') => value.push(self.escape()?),
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, Error> {
+        Ok(match self.bump() {
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('/') => '/',
+            Some('b') => '\u{8}',
+            Some('f') => '\u{c}',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('u') => {
+                let unit = self.hex4()?;
+                let code = if (0xd800..0xdc00).contains(&unit) {
+                    // A surrogate pair, escaped as two `\u`s.
+                    if self.bump() != Some('\\') || self.bump() != Some('u') {
+                        return Err(self.error("invalid unicode escape"));
+                    }
+                    let low = self.hex4()?;
+                    if !(0xdc00..0xe000).contains(&low) {
+                        return Err(self.error("invalid unicode escape"));
+                    }
+                    0x10000 + ((unit - 0xd800) << 10) + (low - 0xdc00)
+                } else {
+                    unit
+                };
+                char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"))?
+            }
+            Some(c) => return Err(self.error(format!("invalid escape \\{}", c))),
+            None => return Err(self.error("unterminated string")),
+        })
+    }
+
+    fn hex4(&mut self) -> Result<u32, Error> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .bump()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| self.error("invalid unicode escape"))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    /// A `"""` string, its opening quotes already read.
+    fn block_string(&mut self, start: Pos) -> Result<Token, Error> {
+        let mut raw = String::new();
+        loop {
+            if self.looking_at("\"\"\"") {
+                self.skip(3);
+                return Ok(Token::String(block_string_value(&raw)));
+            }
+            if self.looking_at("\\\"\"\"") {
+                self.skip(4);
+                raw.push_str("\"\"\"");
+                continue;
+            }
+            match self.bump() {
+                Some(c) => raw.push(c),
+                None => {
+                    return Err(Error {
+                        message: "unterminated string".to_string(),
+                        pos: start,
+                    })
+                }
+            }
+        }
+    }
+}
+
+/// A block string's value: the common indentation of every line but the
+/// first removed, and blank lines at the start and end dropped.
+fn block_string_value(raw: &str) -> String {
+    let normalized = raw.replace("\r\n", "\n").replace('\r', "\n");
+    let lines: Vec<&str> = normalized.split('\n').collect();
+    let indent = |line: &str| line.len() - line.trim_start_matches([' ', '\t']).len();
+    let common = lines
+        .iter()
+        .skip(1)
+        .filter(|line| !line.trim_start_matches([' ', '\t']).is_empty())
+        .map(|line| indent(line))
+        .min()
+        .unwrap_or(0);
+    let mut lines: Vec<&str> = lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            if i == 0 {
+                line
+            } else {
+                &line[common.min(line.len())..]
+            }
+        })
+        .collect();
+    let blank = |line: &&str| line.trim_start_matches([' ', '\t']).is_empty();
+    while lines.first().is_some_and(blank) {
+        lines.remove(0);
+    }
+    while lines.last().is_some_and(blank) {
+        lines.pop();
+    }
+    lines.join("\n")
+}
+
+struct Parser {
+    tokens: Vec<(Token, Pos)>,
+    next: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn new(source: &str) -> Result<Self, Error> {
+        let lexer = Lexer {
+            chars: source.chars().collect(),
+            at: 0,
+            line: 1,
+            column: 1,
+        };
+        Ok(Self {
+            tokens: lexer.tokens()?,
+            next: 0,
+            depth: 0,
+        })
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.next].0
+    }
+
+    fn pos(&self) -> Pos {
+        self.tokens[self.next].1
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.next].0.clone();
+        if token != Token::End {
+            self.next += 1;
+        }
+        token
+    }
+
+    fn error(&self, message: impl Into<String>) -> Error {
+        Error {
+            message: message.into(),
+            pos: self.pos(),
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> Error {
+        self.error(format!("expected {}, found {}", expected, self.peek()))
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == &Token::Punct(c) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), Error> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("\"{}\"", c)))
+        }
+    }
+
+    fn expect_end(&self) -> Result<(), Error> {
+        match self.peek() {
+            Token::End => Ok(()),
+            _ => Err(self.unexpected("the end")),
+        }
+    }
+
+    fn name(&mut self) -> Result<String, Error> {
+        match self.peek() {
+            Token::Name(_) => match self.advance() {
+                Token::Name(name) => Ok(name),
+                _ => unreachable!(),
+            },
+            _ => Err(self.unexpected("a name")),
+        }
+    }
+
+    /// Runs `parse` one level deeper, failing past [`MAX_DEPTH`].
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, Error>) -> Result<T, Error> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("the document is nested too deeply"));
+        }
+        self.depth += 1;
+        let parsed = parse(self);
+        self.depth -= 1;
+        parsed
+    }
+
+    fn operation(&mut self) -> Result<Operation, Error> {
+        let pos = self.pos();
+        if self.peek() == &Token::Punct('{') {
+            return Ok(Operation {
+                kind: OperationKind::Query,
+                name: None,
+                variables: Vec::new(),
+                directives: Vec::new(),
+                selection: self.selection_set()?,
+                pos,
+            });
+        }
+        let kind = match self.name()?.as_str() {
+            "query" => OperationKind::Query,
+            "mutation" => OperationKind::Mutation,
+            _ => OperationKind::Subscription,
+        };
+        let name = match self.peek() {
+            Token::Name(_) => Some(self.name()?),
+            _ => None,
+        };
+        let variables = if self.peek() == &Token::Punct('(') {
+            self.variable_definitions()?
+        } else {
+            Vec::new()
+        };
+        Ok(Operation {
+            kind,
+            name,
+            variables,
+            directives: self.directives(false)?,
+            selection: self.selection_set()?,
+            pos,
+        })
+    }
+
+    fn variable_definitions(&mut self) -> Result<Vec<VariableDefinition>, Error> {
+        self.expect('(')?;
+        let mut definitions = Vec::new();
+        loop {
+            let pos = self.pos();
+            self.expect('$')?;
+            let name = self.name()?;
+            self.expect(':')?;
+            let ty = self.ty()?;
+            let default = if self.eat('=') {
+                Some(self.value(true)?)
+            } else {
+                None
+            };
+            // Directives on variables are allowed, and none apply here.
+            self.directives(true)?;
+            definitions.push(VariableDefinition {
+                name,
+                ty,
+                default,
+                pos,
+            });
+            if self.eat(')') {
+                return Ok(definitions);
+            }
+        }
+    }
+
+    fn ty(&mut self) -> Result<Type, Error> {
+        let ty = if self.eat('[') {
+            let item = self.nested(Self::ty)?;
+            self.expect(']')?;
+            Type::List(Box::new(item))
+        } else {
+            Type::Named(self.name()?)
+        };
+        Ok(if self.eat('!') {
+            Type::NonNull(Box::new(ty))
+        } else {
+            ty
+        })
+    }
+
+    fn fragment(&mut self) -> Result<Fragment, Error> {
+        let pos = self.pos();
+        self.name()?;
+        let name = self.name()?;
+        if name == "on" {
+            return Err(Error {
+                message: "a fragment cannot be named \"on\"".to_string(),
+                pos,
+            });
+        }
+        self.type_condition()?;
+        let type_condition = self.name()?;
+        Ok(Fragment {
+            name,
+            type_condition,
+            directives: self.directives(false)?,
+            selection: self.selection_set()?,
+        })
+    }
+
+    fn type_condition(&mut self) -> Result<(), Error> {
+        match self.peek() {
+            Token::Name(name) if name == "on" => {
+                self.advance();
+                Ok(())
+            }
+            _ => Err(self.unexpected("\"on\"")),
+        }
+    }
+
+    fn selection_set(&mut self) -> Result<Vec<Selection>, Error> {
+        self.expect('{')?;
+        self.nested(|parser| {
+            let mut selection = vec![parser.selection()?];
+            while !parser.eat('}') {
+                selection.push(parser.selection()?);
+            }
+            Ok(selection)
+        })
+    }
+
+    fn selection(&mut self) -> Result<Selection, Error> {
+        let pos = self.pos();
+        if self.peek() != &Token::Spread {
+            return self.field().map(Selection::Field);
+        }
+        self.advance();
+        match self.peek() {
+            Token::Name(name) if name != "on" => {
+                let name = self.name()?;
+                Ok(Selection::Spread(Spread {
+                    name,
+                    directives: self.directives(false)?,
+                    pos,
+                }))
+            }
+            _ => {
+                let type_condition = match self.peek() {
+                    Token::Name(_) => {
+                        self.type_condition()?;
+                        Some(self.name()?)
+                    }
+                    _ => None,
+                };
+                Ok(Selection::Inline(InlineFragment {
+                    type_condition,
+                    directives: self.directives(false)?,
+                    selection: self.selection_set()?,
+                    pos,
+                }))
+            }
+        }
+    }
+
+    fn field(&mut self) -> Result<Field, Error> {
+        let pos = self.pos();
+        let mut name = self.name()?;
+        let mut alias = None;
+        if self.eat(':') {
+            alias = Some(name);
+            name = self.name()?;
+        }
+        let arguments = self.arguments(false)?;
+        let directives = self.directives(false)?;
+        let selection = if self.peek() == &Token::Punct('{') {
+            self.selection_set()?
+        } else {
+            Vec::new()
+        };
+        Ok(Field {
+            alias,
+            name,
+            arguments,
+            directives,
+            selection,
+            pos,
+        })
+    }
+
+    fn arguments(&mut self, constant: bool) -> Result<Vec<(String, Value)>, Error> {
+        let mut arguments = Vec::new();
+        if !self.eat('(') {
+            return Ok(arguments);
+        }
+        loop {
+            let name = self.name()?;
+            self.expect(':')?;
+            arguments.push((name, self.value(constant)?));
+            if self.eat(')') {
+                return Ok(arguments);
+            }
+        }
+    }
+
+    fn directives(&mut self, constant: bool) -> Result<Vec<Directive>, Error> {
+        let mut directives = Vec::new();
+        while self.peek() == &Token::Punct('@') {
+            let pos = self.pos();
+            self.advance();
+            let name = self.name()?;
+            directives.push(Directive {
+                name,
+                arguments: self.arguments(constant)?,
+                pos,
+            });
+        }
+        Ok(directives)
+    }
+
+    /// A value; `constant` ones, such as defaults, cannot name variables.
+    fn value(&mut self, constant: bool) -> Result<Value, Error> {
+        let pos = self.pos();
+        Ok(match self.advance() {
+            Token::Punct('$') if !constant => Value::Variable(self.name()?),
+            Token::Int(value) => Value::Int(value),
+            Token::Float(value) => Value::Float(value),
+            Token::String(value) => Value::String(value),
+            Token::Name(name) => match name.as_str() {
+                "true" => Value::Boolean(true),
+                "false" => Value::Boolean(false),
+                "null" => Value::Null,
+                _ => Value::Enum(name),
+            },
+            Token::Punct('[') => self.nested(|parser| {
+                let mut items = Vec::new();
+                while !parser.eat(']') {
+                    items.push(parser.value(constant)?);
+                }
+                Ok(Value::List(items))
+            })?,
+            Token::Punct('{') => self.nested(|parser| {
+                let mut fields = Vec::new();
+                while !parser.eat('}') {
+                    let name = parser.name()?;
+                    parser.expect(':')?;
+                    fields.push((name, parser.value(constant)?));
+                }
+                Ok(Value::Object(fields))
+            })?,
+            token => {
+                return Err(Error {
+                    message: format!("expected a value, found {}", token),
+                    pos,
+                })
+            }
+        })
+    }
+}
@@ -0,0 +1,660 @@
+//! The schema as data: every type the endpoint serves, with the
+//! descriptions introspection reports, and the introspection types
+//! themselves. Types are written as GraphQL writes them, such as
+//! `[String!]!`, and parsed where they are needed.
+
+use serde_json::{json, Map, Value};
+
+use super::execute::{Node, Object};
+use super::parse::{self, Type};
+use crate::error::ApiError;
+
+pub struct TypeDef {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub kind: Kind,
+}
+
+pub enum Kind {
+    Scalar,
+    Object(&'static [Field]),
+    Enum(&'static [EnumValue]),
+    InputObject(&'static [Input]),
+}
+
+pub struct Field {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub args: &'static [Input],
+    pub ty: &'static str,
+}
+
+/// An argument, or a field of an input object.
+pub struct Input {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub ty: &'static str,
+    /// As a GraphQL literal, the way introspection reports it.
+    pub default: Option<&'static str>,
+}
+
+pub struct EnumValue {
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+pub struct Directive {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub locations: &'static [&'static str],
+    pub args: &'static [Input],
+}
+
+impl TypeDef {
+    pub fn field(&self, name: &str) -> Option<&'static Field> {
+        match self.kind {
+            Kind::Object(fields) => fields.iter().find(|field| field.name == name),
+            _ => None,
+        }
+    }
+
+    /// Whether a field of this type takes no selection of subfields.
+    pub fn is_leaf(&self) -> bool {
+        matches!(self.kind, Kind::Scalar | Kind::Enum(_))
+    }
+
+    pub fn is_input(&self) -> bool {
+        matches!(
+            self.kind,
+            Kind::Scalar | Kind::Enum(_) | Kind::InputObject(_)
+        )
+    }
+}
+
+impl Field {
+    pub fn ty(&self) -> Type {
+        schema_type(self.ty)
+    }
+}
+
+impl Input {
+    pub fn ty(&self) -> Type {
+        schema_type(self.ty)
+    }
+
+    /// Whether leaving it out is an error: non-null, with no default.
+    pub fn is_required(&self) -> bool {
+        self.default.is_none() && self.ty.ends_with('!')
+    }
+
+    pub fn default(&self) -> Option<parse::Value> {
+        self.default
+            .map(|literal| parse::const_value(literal).expect("schema defaults are valid literals"))
+    }
+}
+
+fn schema_type(source: &str) -> Type {
+    parse::ty(source).expect("schema types are valid")
+}
+
+const fn field(name: &'static str, ty: &'static str, description: &'static str) -> Field {
+    Field {
+        name,
+        description,
+        args: &[],
+        ty,
+    }
+}
+
+const fn field_with(
+    name: &'static str,
+    ty: &'static str,
+    args: &'static [Input],
+    description: &'static str,
+) -> Field {
+    Field {
+        name,
+        description,
+        args,
+        ty,
+    }
+}
+
+const fn input(name: &'static str, ty: &'static str, description: &'static str) -> Input {
+    Input {
+        name,
+        description,
+        ty,
+        default: None,
+    }
+}
+
+const fn defaulted(
+    name: &'static str,
+    ty: &'static str,
+    default: &'static str,
+    description: &'static str,
+) -> Input {
+    Input {
+        name,
+        description,
+        ty,
+        default: Some(default),
+    }
+}
+
+const fn value(name: &'static str, description: &'static str) -> EnumValue {
+    EnumValue { name, description }
+}
+
+pub const QUERY: &str = "Query";
+pub const MUTATION: &str = "Mutation";
+
+/// `__typename`, which every object type has.
+pub const TYPENAME: Field = field("__typename", "String!", "The name of the object's type.");
+/// `__schema` and `__type`, which only `Query` has.
+pub const SCHEMA: Field = field("__schema", "__Schema!", "The schema itself.");
+pub const TYPE: Field = field_with(
+    "__type",
+    "__Type",
+    &[input("name", "String!", "")],
+    "The type with this name, or null.",
+);
+
+const INCLUDE_DEPRECATED: &[Input] = &[defaulted("includeDeprecated", "Boolean", "false", "")];
+
+pub static TYPES: &[TypeDef] = &[
+    TypeDef {
+        name: QUERY,
+        description: "",
+        kind: Kind::Object(&[
+            field_with(
+                "todos",
+                "TodoPage!",
+                &[
+                    input(
+                        "filter",
+                        "TodoFilter",
+                        "Filters as `GET /todos` takes them.",
+                    ),
+                    input(
+                        "limit",
+                        "Int",
+                        "1 to 200 todos; without it, every todo after the cursor.",
+                    ),
+                    input(
+                        "after",
+                        "String",
+                        "An `endCursor`; the page starts after it.",
+                    ),
+                ],
+                "The caller's todos in list order.",
+            ),
+            field_with(
+                "todo",
+                "Todo",
+                &[input("id", "ID!", "")],
+                "One of the caller's todos, or null when they have none with this id.",
+            ),
+        ]),
+    },
+    TypeDef {
+        name: MUTATION,
+        description: "",
+        kind: Kind::Object(&[
+            field_with(
+                "createTodo",
+                "Todo!",
+                &[input("input", "CreateTodoInput!", "")],
+                "Adds a todo at the end of the list, as `POST /todos` does.",
+            ),
+            field_with(
+                "updateTodo",
+                "Todo!",
+                &[
+                    input("id", "ID!", ""),
+                    input("input", "UpdateTodoInput!", ""),
+                ],
+                "Changes the given fields, as `PUT /todos/:id` does.",
+            ),
+            field_with(
+                "deleteTodo",
+                "ID!",
+                &[
+                    input("id", "ID!", ""),
+                    defaulted(
+                        "purge",
+                        "Boolean",
+                        "false",
+                        "Remove it for good instead of soft-deleting it.",
+                    ),
+                ],
+                "Deletes a todo and answers with its id.",
+            ),
+            field_with(
+                "toggleTodo",
+                "Todo!",
+                &[input("id", "ID!", "")],
+                "Marks an open todo done, or a done one open again.",
+            ),
+        ]),
+    },
+    TypeDef {
+        name: "Todo",
+        description: "",
+        kind: Kind::Object(&[
+            field("id", "ID!", ""),
+            field("title", "String!", ""),
+            field("completed", "Boolean!", ""),
+            field("completedAt", "DateTime", ""),
+            field("tags", "[String!]!", "Sorted, lowercase."),
+            field("dueDate", "DateTime", ""),
+            field("priority", "Int!", "1 (lowest) to 5 (most urgent)."),
+            field("position", "Int!", "Where it is in the list."),
+            field("archived", "Boolean!", ""),
+            field("overdue", "Boolean!", "Open and past its due date."),
+        ]),
+    },
+    TypeDef {
+        name: "TodoPage",
+        description: "A page of todos, and where the next one starts.",
+        kind: Kind::Object(&[
+            field("items", "[Todo!]!", ""),
+            field(
+                "totalCount",
+                "Int!",
+                "Todos matching the filter, on every page.",
+            ),
+            field(
+                "endCursor",
+                "String",
+                "Pass as `after` for the next page; null on an empty page.",
+            ),
+            field("hasNextPage", "Boolean!", ""),
+        ]),
+    },
+    TypeDef {
+        name: "TodoFilter",
+        description: "",
+        kind: Kind::InputObject(&[
+            input("tags", "[String!]", ""),
+            defaulted("tagMode", "TagMode", "ANY", ""),
+            input(
+                "completed",
+                "Boolean",
+                "Only completed or only open todos; both when left out.",
+            ),
+            input(
+                "q",
+                "String",
+                "Only todos whose title contains this, ignoring case.",
+            ),
+            input("createdAfter", "String", "RFC 3339 or `YYYY-MM-DD`."),
+            input("createdBefore", "String", "RFC 3339 or `YYYY-MM-DD`."),
+            input(
+                "completedAfter",
+                "String",
+                "Leaves out todos completed before this time; open ones stay.",
+            ),
+            defaulted("includeArchived", "Boolean", "false", ""),
+            defaulted("untagged", "Boolean", "false", "Only todos without tags."),
+        ]),
+    },
+    TypeDef {
+        name: "CreateTodoInput",
+        description: "",
+        kind: Kind::InputObject(&[
+            input("title", "String!", ""),
+            defaulted("tags", "[String!]", "[]", ""),
+            input("dueDate", "String", "RFC 3339 or `YYYY-MM-DD`."),
+            input(
+                "dueIn",
+                "String",
+                "Due this long from now instead, as an ISO 8601 duration such as `P3D`.",
+            ),
+            input("priority", "Int", "1 to 5; 3 when left out."),
+        ]),
+    },
+    TypeDef {
+        name: "UpdateTodoInput",
+        description: "Fields left out stay as they are.",
+        kind: Kind::InputObject(&[
+            input("title", "String", ""),
+            input("completed", "Boolean", ""),
+            input("tags", "[String!]", "Replaces the todo's tags."),
+            input("dueDate", "String", "null clears the due date."),
+            input("priority", "Int", ""),
+        ]),
+    },
+    TypeDef {
+        name: "TagMode",
+        description: "",
+        kind: Kind::Enum(&[
+            value("ANY", "Todos carrying at least one of the tags."),
+            value("ALL", "Todos carrying every tag."),
+        ]),
+    },
+    TypeDef {
+        name: "DateTime",
+        description: "An RFC 3339 timestamp in UTC, such as `2030-01-02T09:30:00Z`.",
+        kind: Kind::Scalar,
+    },
+    TypeDef {
+        name: "ID",
+        description: "",
+        kind: Kind::Scalar,
+    },
+    TypeDef {
+        name: "String",
+        description: "",
+        kind: Kind::Scalar,
+    },
+    TypeDef {
+        name: "Int",
+        description: "A signed 32-bit integer.",
+        kind: Kind::Scalar,
+    },
+    TypeDef {
+        name: "Boolean",
+        description: "",
+        kind: Kind::Scalar,
+    },
+    TypeDef {
+        name: "__Schema",
+        description: "",
+        kind: Kind::Object(&[
+            field("description", "String", ""),
+            field("types", "[__Type!]!", ""),
+            field("queryType", "__Type!", ""),
+            field("mutationType", "__Type", ""),
+            field("subscriptionType", "__Type", ""),
+            field("directives", "[__Directive!]!", ""),
+        ]),
+    },
+    TypeDef {
+        name: "__Type",
+        description: "",
+        kind: Kind::Object(&[
+            field("kind", "__TypeKind!", ""),
+            field("name", "String", ""),
+            field("description", "String", ""),
+            field("specifiedByURL", "String", ""),
+            field_with("fields", "[__Field!]", INCLUDE_DEPRECATED, ""),
+            field("interfaces", "[__Type!]", ""),
+            field("possibleTypes", "[__Type!]", ""),
+            field_with("enumValues", "[__EnumValue!]", INCLUDE_DEPRECATED, ""),
+            field_with("inputFields", "[__InputValue!]", INCLUDE_DEPRECATED, ""),
+            field("ofType", "__Type", ""),
+            field("isOneOf", "Boolean", ""),
+        ]),
+    },
+    TypeDef {
+        name: "__Field",
+        description: "",
+        kind: Kind::Object(&[
+            field("name", "String!", ""),
+            field("description", "String", ""),
+            field_with("args", "[__InputValue!]!", INCLUDE_DEPRECATED, ""),
+            field("type", "__Type!", ""),
+            field("isDeprecated", "Boolean!", ""),
+            field("deprecationReason", "String", ""),
+        ]),
+    },
+    TypeDef {
+        name: "__InputValue",
+        description: "",
+        kind: Kind::Object(&[
+            field("name", "String!", ""),
+            field("description", "String", ""),
+            field("type", "__Type!", ""),
+            field("defaultValue", "String", ""),
+            field("isDeprecated", "Boolean!", ""),
+            field("deprecationReason", "String", ""),
+        ]),
+    },
+    TypeDef {
+        name: "__EnumValue",
+        description: "",
+        kind: Kind::Object(&[
+            field("name", "String!", ""),
+            field("description", "String", ""),
+            field("isDeprecated", "Boolean!", ""),
+            field("deprecationReason", "String", ""),
+        ]),
+    },
+    TypeDef {
+        name: "__Directive",
+        description: "",
+        kind: Kind::Object(&[
+            field("name", "String!", ""),
+            field("description", "String", ""),
+            field("locations", "[__DirectiveLocation!]!", ""),
+            field_with("args", "[__InputValue!]!", INCLUDE_DEPRECATED, ""),
+            field("isRepeatable", "Boolean!", ""),
+        ]),
+    },
+    TypeDef {
+        name: "__TypeKind",
+        description: "",
+        kind: Kind::Enum(&[
+            value("SCALAR", ""),
+            value("OBJECT", ""),
+            value("INTERFACE", ""),
+            value("UNION", ""),
+            value("ENUM", ""),
+            value("INPUT_OBJECT", ""),
+            value("LIST", ""),
+            value("NON_NULL", ""),
+        ]),
+    },
+    TypeDef {
+        name: "__DirectiveLocation",
+        description: "",
+        kind: Kind::Enum(&[
+            value("QUERY", ""),
+            value("MUTATION", ""),
+            value("SUBSCRIPTION", ""),
+            value("FIELD", ""),
+            value("FRAGMENT_DEFINITION", ""),
+            value("FRAGMENT_SPREAD", ""),
+            value("INLINE_FRAGMENT", ""),
+            value("VARIABLE_DEFINITION", ""),
+            value("SCHEMA", ""),
+            value("SCALAR", ""),
+            value("OBJECT", ""),
+            value("FIELD_DEFINITION", ""),
+            value("ARGUMENT_DEFINITION", ""),
+            value("INTERFACE", ""),
+            value("UNION", ""),
+            value("ENUM", ""),
+            value("ENUM_VALUE", ""),
+            value("INPUT_OBJECT", ""),
+            value("INPUT_FIELD_DEFINITION", ""),
+        ]),
+    },
+];
+
+const CONDITION: &[Input] = &[input("if", "Boolean!", "")];
+
+pub static DIRECTIVES: &[Directive] = &[
+    Directive {
+        name: "skip",
+        description: "Leaves the field or fragment out when `if` is true.",
+        locations: &["FIELD", "FRAGMENT_SPREAD", "INLINE_FRAGMENT"],
+        args: CONDITION,
+    },
+    Directive {
+        name: "include",
+        description: "Leaves the field or fragment out unless `if` is true.",
+        locations: &["FIELD", "FRAGMENT_SPREAD", "INLINE_FRAGMENT"],
+        args: CONDITION,
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static TypeDef> {
+    TYPES.iter().find(|ty| ty.name == name)
+}
+
+pub fn directive(name: &str) -> Option<&'static Directive> {
+    DIRECTIVES.iter().find(|directive| directive.name == name)
+}
+
+/// The field `name` of `parent`, the meta fields included.
+pub fn lookup_field(parent: &TypeDef, name: &str) -> Option<&'static Field> {
+    match name {
+        "__typename" => Some(&TYPENAME),
+        "__schema" if parent.name == QUERY => Some(&SCHEMA),
+        "__type" if parent.name == QUERY => Some(&TYPE),
+        _ => parent.field(name),
+    }
+}
+
+fn description(text: &str) -> Node {
+    Node::Value(if text.is_empty() {
+        Value::Null
+    } else {
+        json!(text)
+    })
+}
+
+fn not_deprecated(name: &str) -> Option<Node> {
+    match name {
+        "isDeprecated" => Some(Node::Value(json!(false))),
+        "deprecationReason" => Some(Node::Value(Value::Null)),
+        _ => None,
+    }
+}
+
+fn objects<T: Object + 'static>(items: impl IntoIterator<Item = T>) -> Node {
+    Node::List(
+        items
+            .into_iter()
+            .map(|item| Node::Object(Box::new(item)))
+            .collect(),
+    )
+}
+
+/// `__schema`.
+pub struct SchemaObject;
+
+impl Object for SchemaObject {
+    fn field(&self, name: &str, _args: &Map<String, Value>) -> Result<Node, ApiError> {
+        let named = |name: &str| Node::Object(Box::new(TypeObject(Type::Named(name.to_string()))));
+        Ok(match name {
+            "types" => objects(
+                TYPES
+                    .iter()
+                    .map(|ty| TypeObject(Type::Named(ty.name.to_string()))),
+            ),
+            "queryType" => named(QUERY),
+            "mutationType" => named(MUTATION),
+            "directives" => objects(DIRECTIVES.iter().map(DirectiveObject)),
+            _ => Node::Value(Value::Null),
+        })
+    }
+}
+
+/// `__Type`, for a named type or a list or non-null wrapper.
+pub struct TypeObject(pub Type);
+
+impl Object for TypeObject {
+    fn field(&self, name: &str, _args: &Map<String, Value>) -> Result<Node, ApiError> {
+        let named = match &self.0 {
+            Type::Named(named) => find(named),
+            Type::List(inner) | Type::NonNull(inner) => {
+                return Ok(match name {
+                    "kind" => Node::Value(json!(if self.0.is_non_null() {
+                        "NON_NULL"
+                    } else {
+                        "LIST"
+                    })),
+                    "ofType" => Node::Object(Box::new(TypeObject((**inner).clone()))),
+                    _ => Node::Value(Value::Null),
+                })
+            }
+        };
+        let Some(ty) = named else {
+            return Ok(Node::Value(Value::Null));
+        };
+        Ok(match (name, &ty.kind) {
+            ("kind", kind) => Node::Value(json!(match kind {
+                Kind::Scalar => "SCALAR",
+                Kind::Object(_) => "OBJECT",
+                Kind::Enum(_) => "ENUM",
+                Kind::InputObject(_) => "INPUT_OBJECT",
+            })),
+            ("name", _) => Node::Value(json!(ty.name)),
+            ("description", _) => description(ty.description),
+            ("fields", Kind::Object(fields)) => objects(fields.iter().map(FieldObject)),
+            ("interfaces", Kind::Object(_)) => Node::List(Vec::new()),
+            ("enumValues", Kind::Enum(values)) => objects(values.iter().map(EnumValueObject)),
+            ("inputFields", Kind::InputObject(fields)) => {
+                objects(fields.iter().map(InputValueObject))
+            }
+            ("isOneOf", Kind::InputObject(_)) => Node::Value(json!(false)),
+            _ => Node::Value(Value::Null),
+        })
+    }
+}
+
+/// `__Field`.
+struct FieldObject(&'static Field);
+
+impl Object for FieldObject {
+    fn field(&self, name: &str, _args: &Map<String, Value>) -> Result<Node, ApiError> {
+        let field = self.0;
+        Ok(match name {
+            "name" => Node::Value(json!(field.name)),
+            "description" => description(field.description),
+            "args" => objects(field.args.iter().map(InputValueObject)),
+            "type" => Node::Object(Box::new(TypeObject(field.ty()))),
+            _ => not_deprecated(name).unwrap_or(Node::Value(Value::Null)),
+        })
+    }
+}
+
+/// `__InputValue`.
+struct InputValueObject(&'static Input);
+
+impl Object for InputValueObject {
+    fn field(&self, name: &str, _args: &Map<String, Value>) -> Result<Node, ApiError> {
+        let input = self.0;
+        Ok(match name {
+            "name" => Node::Value(json!(input.name)),
+            "description" => description(input.description),
+            "type" => Node::Object(Box::new(TypeObject(input.ty()))),
+            "defaultValue" => Node::Value(json!(input.default)),
+            _ => not_deprecated(name).unwrap_or(Node::Value(Value::Null)),
+        })
+    }
+}
+
+/// `__EnumValue`.
+struct EnumValueObject(&'static EnumValue);
+
+impl Object for EnumValueObject {
+    fn field(&self, name: &str, _args: &Map<String, Value>) -> Result<Node, ApiError> {
+        Ok(match name {
+            "name" => Node::Value(json!(self.0.name)),
+            "description" => description(self.0.description),
+            _ => not_deprecated(name).unwrap_or(Node::Value(Value::Null)),
+        })
+    }
+}
+
+/// `__Directive`.
+struct DirectiveObject(&'static Directive);
+
+impl Object for DirectiveObject {
+    fn field(&self, name: &str, _args: &Map<String, Value>) -> Result<Node, ApiError> {
+        let directive = self.0;
+        Ok(match name {
+            "name" => Node::Value(json!(directive.name)),
+            "description" => description(directive.description),
+            "locations" => Node::Value(json!(directive.locations)),
+            "args" => objects(directive.args.iter().map(InputValueObject)),
+            "isRepeatable" => Node::Value(json!(false)),
+            _ => Node::Value(Value::Null),
+        })
+    }
+}
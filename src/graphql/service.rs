@@ -0,0 +1,296 @@
+//! The `Query` and `Mutation` fields on the app's state. Each is checked and
+//! answered as its REST route would be, on the same `db` functions, and
+//! fails with the error its REST route fails with; see
+//! `impl From<ApiError> for graphql::Error`.
+
+use axum::async_trait;
+use base64::Engine;
+use serde_json::{json, Map, Value};
+
+use super::execute::{self, Node, Object, Root};
+use super::parse::OperationKind;
+use crate::db::{self, ReadDb};
+use crate::error::{ApiError, FieldErrors};
+use crate::handlers::todos::insert_todo;
+use crate::models::{
+    CreateTodo, ListPage, ListParams, TodoChanges, TodoResponse, TodoRow, UpdateTodo,
+};
+use crate::tags::TagMode;
+use crate::AppState;
+
+/// The root fields for `user`.
+pub struct Todos {
+    state: AppState,
+    user: String,
+}
+
+impl Todos {
+    pub fn new(state: AppState, user: String) -> Self {
+        Self { state, user }
+    }
+
+    async fn list(&self, args: &Map<String, Value>) -> Result<Node, ApiError> {
+        let no_filter = Map::new();
+        let filter = args
+            .get("filter")
+            .and_then(Value::as_object)
+            .unwrap_or(&no_filter);
+        let params = ListParams {
+            // Tags cannot contain commas, so joining them loses nothing.
+            tags: strings(filter, "tags").map(|tags| tags.join(",")),
+            tag_mode: match string(filter, "tagMode").as_deref() {
+                Some("ALL") => TagMode::All,
+                _ => TagMode::Any,
+            },
+            created_after: string(filter, "createdAfter"),
+            created_before: string(filter, "createdBefore"),
+            include_archived: boolean(filter, "includeArchived").unwrap_or_default(),
+            completed: boolean(filter, "completed").map(|completed| completed.to_string()),
+            completed_after: string(filter, "completedAfter"),
+            q: string(filter, "q"),
+            untagged: boolean(filter, "untagged").unwrap_or_default(),
+        };
+        let page = ListPage {
+            limit: args.get("limit").and_then(Value::as_i64),
+            offset: string(args, "after")
+                .map(|cursor| offset(&cursor))
+                .transpose()?
+                .unwrap_or_default(),
+        };
+        let filter = params.filter()?;
+        let (limit, offset) = page.validate()?;
+
+        let ReadDb(db) = &self.state.read_db;
+        let (rows, total) = if page.is_whole_list() {
+            let rows = db::list_todos(db, &self.user, &filter).await?;
+            let total = rows.len() as i64;
+            (rows, total)
+        } else {
+            db::list_page(db, &self.user, &filter, limit, offset).await?
+        };
+        Ok(Node::Object(Box::new(TodoPage {
+            rows,
+            total,
+            offset,
+        })))
+    }
+
+    async fn get(&self, args: &Map<String, Value>) -> Result<Node, ApiError> {
+        let ReadDb(db) = &self.state.read_db;
+        Ok(db::find_todo(db, &self.user, &id(args))
+            .await?
+            .map_or(Node::Value(Value::Null), todo))
+    }
+
+    async fn create(&self, args: &Map<String, Value>) -> Result<Node, ApiError> {
+        let input = &input(args);
+        let payload = CreateTodo {
+            title: string(input, "title").unwrap_or_default(),
+            tags: strings(input, "tags").unwrap_or_default(),
+            due_date: string(input, "dueDate"),
+            due_in: string(input, "dueIn"),
+            priority: input.get("priority").and_then(Value::as_i64),
+        };
+        payload.check_due()?;
+
+        let config = &self.state.config;
+        let mut row = payload
+            .check(config, FieldErrors::default())
+            .map_err(FieldErrors::into_error)?;
+        insert_todo(
+            &self.state.db,
+            config,
+            &self.state.quotas,
+            &self.user,
+            &mut row,
+        )
+        .await?;
+        self.state.live.created(&self.user, &row.clone().into());
+        Ok(todo(row))
+    }
+
+    async fn update(&self, args: &Map<String, Value>) -> Result<Node, ApiError> {
+        let input = &input(args);
+        let payload = UpdateTodo {
+            title: string(input, "title").map(|title| self.state.config.prepare_title(title)),
+            completed: boolean(input, "completed"),
+            tags: strings(input, "tags"),
+            // Given as null, it clears the due date; left out, it stays.
+            due_date: input
+                .get("dueDate")
+                .map(|due| due.as_str().map(str::to_string)),
+            priority: input.get("priority").and_then(Value::as_i64),
+        };
+        let changes = payload.validate()?;
+        self.change(&id(args), changes).await
+    }
+
+    async fn delete(&self, args: &Map<String, Value>) -> Result<Node, ApiError> {
+        let id = id(args);
+        let purge = boolean(args, "purge").unwrap_or_default();
+
+        let mut tx = self.state.db.begin().await?;
+        let deleted = db::delete_todo(&mut tx, &self.user, &id, purge).await?;
+        tx.commit().await?;
+
+        if deleted {
+            self.state.live.deleted(&self.user, &id);
+            Ok(Node::Value(json!(id)))
+        } else {
+            Err(ApiError::not_found())
+        }
+    }
+
+    /// Flips `completed`, reading and writing in one transaction so two
+    /// toggles cannot both see the same state.
+    async fn toggle(&self, args: &Map<String, Value>) -> Result<Node, ApiError> {
+        let id = id(args);
+        let mut tx = self.state.db.begin().await?;
+        let current = db::find_todo(&mut *tx, &self.user, &id)
+            .await?
+            .ok_or_else(ApiError::not_found)?;
+        let changes = TodoChanges {
+            completed: Some(!current.completed),
+            ..TodoChanges::default()
+        };
+        let row = db::update_todo(&mut tx, &self.user, &id, changes)
+            .await?
+            .ok_or_else(ApiError::not_found)?;
+        tx.commit().await?;
+
+        self.state.live.toggled(&self.user, &row.clone().into());
+        Ok(todo(row))
+    }
+
+    /// Applies `changes` to the todo `id`, as `PUT /todos/:id` does.
+    async fn change(&self, id: &str, changes: TodoChanges) -> Result<Node, ApiError> {
+        let toggle = changes.is_toggle();
+
+        let mut tx = self.state.db.begin().await?;
+        let row = db::update_todo(&mut tx, &self.user, id, changes)
+            .await?
+            .ok_or_else(ApiError::not_found)?;
+        tx.commit().await?;
+
+        let published = row.clone().into();
+        if toggle {
+            self.state.live.toggled(&self.user, &published);
+        } else {
+            self.state.live.updated(&self.user, &published);
+        }
+        Ok(todo(row))
+    }
+}
+
+#[async_trait]
+impl Root for Todos {
+    async fn resolve(
+        &self,
+        kind: OperationKind,
+        field: &str,
+        args: Map<String, Value>,
+    ) -> Result<Node, ApiError> {
+        match (kind, field) {
+            (OperationKind::Query, "todos") => self.list(&args).await,
+            (OperationKind::Query, "todo") => self.get(&args).await,
+            (OperationKind::Mutation, "createTodo") => self.create(&args).await,
+            (OperationKind::Mutation, "updateTodo") => self.update(&args).await,
+            (OperationKind::Mutation, "deleteTodo") => self.delete(&args).await,
+            (OperationKind::Mutation, "toggleTodo") => self.toggle(&args).await,
+            _ => Err(ApiError::internal().caused_by(format!("no resolver for {}", field))),
+        }
+    }
+}
+
+fn string(args: &Map<String, Value>, name: &str) -> Option<String> {
+    args.get(name).and_then(Value::as_str).map(str::to_string)
+}
+
+fn strings(args: &Map<String, Value>, name: &str) -> Option<Vec<String>> {
+    args.get(name).and_then(Value::as_array).map(|items| {
+        items
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect()
+    })
+}
+
+fn boolean(args: &Map<String, Value>, name: &str) -> Option<bool> {
+    args.get(name).and_then(Value::as_bool)
+}
+
+/// The `id` argument, which every field taking one requires.
+fn id(args: &Map<String, Value>) -> String {
+    string(args, "id").unwrap_or_default()
+}
+
+/// The `input` argument, which every field taking one requires.
+fn input(args: &Map<String, Value>) -> Map<String, Value> {
+    args.get("input")
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn cursor(offset: i64) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(offset.to_string())
+}
+
+/// The offset an `endCursor` stands for.
+fn offset(cursor: &str) -> Result<i64, ApiError> {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .and_then(|decoded| decoded.parse().ok())
+        .filter(|offset: &i64| *offset >= 0)
+        .ok_or_else(|| ApiError::validation("after must be an endCursor from an earlier page"))
+}
+
+fn todo(row: TodoRow) -> Node {
+    Node::Object(Box::new(Todo(TodoResponse::from(row))))
+}
+
+/// `Todo`, with the fields a REST todo has.
+struct Todo(TodoResponse);
+
+impl Object for Todo {
+    fn field(&self, name: &str, _args: &Map<String, Value>) -> Result<Node, ApiError> {
+        let todo = &self.0;
+        Ok(match name {
+            "id" => Node::Value(json!(todo.id)),
+            "title" => Node::Value(json!(todo.title)),
+            "completed" => Node::Value(json!(todo.completed)),
+            "completedAt" => Node::Value(json!(todo.completed_at)),
+            "tags" => Node::Value(json!(todo.tags.0)),
+            "dueDate" => Node::Value(json!(todo.due_date)),
+            "priority" => execute::int(todo.priority),
+            "position" => execute::int(todo.position),
+            "archived" => Node::Value(json!(todo.archived)),
+            "overdue" => Node::Value(json!(todo.overdue)),
+            _ => Node::Value(Value::Null),
+        })
+    }
+}
+
+/// `TodoPage`: the rows of one page, and where it starts among `total`.
+struct TodoPage {
+    rows: Vec<TodoRow>,
+    total: i64,
+    offset: i64,
+}
+
+impl Object for TodoPage {
+    fn field(&self, name: &str, _args: &Map<String, Value>) -> Result<Node, ApiError> {
+        let end = self.offset + self.rows.len() as i64;
+        Ok(match name {
+            "items" => Node::List(self.rows.iter().cloned().map(todo).collect()),
+            "totalCount" => execute::int(self.total),
+            "endCursor" if self.rows.is_empty() => Node::Value(Value::Null),
+            "endCursor" => Node::Value(json!(cursor(end))),
+            "hasNextPage" => Node::Value(json!(end < self.total)),
+            _ => Node::Value(Value::Null),
+        })
+    }
+}
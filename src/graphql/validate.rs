@@ -0,0 +1,259 @@
+//! The checks a document must pass before anything runs: every field,
+//! argument, fragment, directive and variable it uses must exist where it
+//! is used. Argument values are checked as they are coerced, field by
+//! field, when the operation runs.
+
+use std::collections::HashSet;
+
+use super::parse::{self, Document, Fragment, Operation, OperationKind, Pos, Selection};
+use super::schema::{self, TypeDef};
+use super::Error;
+
+/// The errors in `operation`, and in the fragments it spreads; none when
+/// it may run.
+pub fn operation(document: &Document, operation: &Operation) -> Vec<Error> {
+    let mut validator = Validator {
+        document,
+        defined: operation
+            .variables
+            .iter()
+            .map(|variable| variable.name.as_str())
+            .collect(),
+        validated: HashSet::new(),
+        spreading: Vec::new(),
+        errors: Vec::new(),
+    };
+    let (root, location) = match operation.kind {
+        OperationKind::Query => (schema::QUERY, "QUERY"),
+        OperationKind::Mutation => (schema::MUTATION, "MUTATION"),
+        OperationKind::Subscription => {
+            validator.error(
+                "Subscriptions are not supported.".to_string(),
+                operation.pos,
+            );
+            return validator.errors;
+        }
+    };
+    for variable in &operation.variables {
+        let name = variable.ty.name();
+        if !schema::find(name).is_some_and(TypeDef::is_input) {
+            validator.error(
+                format!(
+                    "Variable \"${}\" cannot be non-input type \"{}\".",
+                    variable.name, variable.ty
+                ),
+                variable.pos,
+            );
+        }
+    }
+    validator.directives(&operation.directives, location);
+    validator.selection(
+        schema::find(root).expect("the root types exist"),
+        &operation.selection,
+    );
+    validator.errors
+}
+
+struct Validator<'d> {
+    document: &'d Document,
+    defined: HashSet<&'d str>,
+    /// Fragments already checked, which need not be again.
+    validated: HashSet<&'d str>,
+    /// The fragments being spread, innermost last, to catch cycles.
+    spreading: Vec<&'d str>,
+    errors: Vec<Error>,
+}
+
+impl<'d> Validator<'d> {
+    fn error(&mut self, message: String, pos: Pos) {
+        self.errors.push(Error::at(message, pos));
+    }
+
+    fn selection(&mut self, parent: &'static TypeDef, selection: &'d [Selection]) {
+        for selection in selection {
+            match selection {
+                Selection::Field(field) => self.field(parent, field),
+                Selection::Spread(spread) => {
+                    self.directives(&spread.directives, "FRAGMENT_SPREAD");
+                    self.spread(parent, &spread.name, spread.pos);
+                }
+                Selection::Inline(inline) => {
+                    self.directives(&inline.directives, "INLINE_FRAGMENT");
+                    if let Some(condition) = &inline.type_condition {
+                        if !self.condition(parent, condition, inline.pos) {
+                            continue;
+                        }
+                    }
+                    self.selection(parent, &inline.selection);
+                }
+            }
+        }
+    }
+
+    fn field(&mut self, parent: &'static TypeDef, field: &'d parse::Field) {
+        self.directives(&field.directives, "FIELD");
+        let Some(def) = schema::lookup_field(parent, &field.name) else {
+            self.error(
+                format!(
+                    "Cannot query field \"{}\" on type \"{}\".",
+                    field.name, parent.name
+                ),
+                field.pos,
+            );
+            return;
+        };
+        self.arguments(
+            &format!("field \"{}.{}\"", parent.name, field.name),
+            def.args,
+            &field.arguments,
+            field.pos,
+        );
+        let ty = def.ty();
+        let target = schema::find(ty.name()).expect("schema types exist");
+        match (target.is_leaf(), field.selection.is_empty()) {
+            (true, false) => self.error(
+                format!(
+                    "Field \"{}\" must not have a selection since type \"{}\" has no subfields.",
+                    field.name, ty
+                ),
+                field.pos,
+            ),
+            (false, true) => self.error(
+                format!(
+                    "Field \"{}\" of type \"{}\" must have a selection of subfields.",
+                    field.name, ty
+                ),
+                field.pos,
+            ),
+            (false, false) => self.selection(target, &field.selection),
+            (true, true) => {}
+        }
+    }
+
+    fn spread(&mut self, parent: &'static TypeDef, name: &'d str, pos: Pos) {
+        let document = self.document;
+        let Some(fragment) = document
+            .fragments
+            .iter()
+            .find(|fragment| fragment.name == name)
+        else {
+            self.error(format!("Unknown fragment \"{}\".", name), pos);
+            return;
+        };
+        if self.spreading.contains(&name) {
+            self.error(
+                format!("Cannot spread fragment \"{}\" within itself.", name),
+                pos,
+            );
+            return;
+        }
+        if !self.condition(parent, &fragment.type_condition, pos) {
+            return;
+        }
+        if self.validated.insert(name) {
+            self.fragment(parent, fragment);
+        }
+    }
+
+    fn fragment(&mut self, parent: &'static TypeDef, fragment: &'d Fragment) {
+        self.spreading.push(&fragment.name);
+        self.directives(&fragment.directives, "FRAGMENT_DEFINITION");
+        self.selection(parent, &fragment.selection);
+        self.spreading.pop();
+    }
+
+    /// Whether a fragment on `condition` may be spread where a `parent` is
+    /// selected. With no interfaces or unions in the schema, only on the
+    /// same type can it.
+    fn condition(&mut self, parent: &TypeDef, condition: &str, pos: Pos) -> bool {
+        if schema::find(condition).is_none() {
+            self.error(format!("Unknown type \"{}\".", condition), pos);
+            false
+        } else if condition != parent.name {
+            self.error(
+                format!(
+                    "Fragment on \"{}\" cannot be spread here as objects of type \"{}\" can never be of type \"{}\".",
+                    condition, parent.name, condition
+                ),
+                pos,
+            );
+            false
+        } else {
+            true
+        }
+    }
+
+    fn directives(&mut self, directives: &'d [parse::Directive], location: &str) {
+        for directive in directives {
+            let Some(def) = schema::directive(&directive.name) else {
+                self.error(
+                    format!("Unknown directive \"@{}\".", directive.name),
+                    directive.pos,
+                );
+                continue;
+            };
+            if !def.locations.contains(&location) {
+                self.error(
+                    format!(
+                        "Directive \"@{}\" may not be used on {}.",
+                        directive.name, location
+                    ),
+                    directive.pos,
+                );
+                continue;
+            }
+            self.arguments(
+                &format!("directive \"@{}\"", directive.name),
+                def.args,
+                &directive.arguments,
+                directive.pos,
+            );
+        }
+    }
+
+    fn arguments(
+        &mut self,
+        owner: &str,
+        defs: &[schema::Input],
+        given: &'d [(String, parse::Value)],
+        pos: Pos,
+    ) {
+        for (name, value) in given {
+            if !defs.iter().any(|def| def.name == name) {
+                self.error(format!("Unknown argument \"{}\" on {}.", name, owner), pos);
+            }
+            self.variables(value, pos);
+        }
+        for def in defs.iter().filter(|def| def.is_required()) {
+            if !given.iter().any(|(name, _)| name == def.name) {
+                self.error(
+                    format!(
+                        "Argument \"{}\" of type \"{}\" is required on {}, but it was not provided.",
+                        def.name, def.ty, owner
+                    ),
+                    pos,
+                );
+            }
+        }
+    }
+
+    /// Checks that the variables `value` uses are defined by the operation.
+    fn variables(&mut self, value: &'d parse::Value, pos: Pos) {
+        match value {
+            parse::Value::Variable(name) if !self.defined.contains(name.as_str()) => {
+                self.error(format!("Variable \"${}\" is not defined.", name), pos)
+            }
+            parse::Value::List(items) => {
+                for item in items {
+                    self.variables(item, pos);
+                }
+            }
+            parse::Value::Object(fields) => {
+                for (_, value) in fields {
+                    self.variables(value, pos);
+                }
+            }
+            _ => {}
+        }
+    }
+}
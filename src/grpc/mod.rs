@@ -0,0 +1,79 @@
+//! The gRPC `todos.v1.TodoService` of `proto/todos/v1/todos.proto`,
+//! compiled in with the `grpc` feature and switched on at runtime by
+//! `grpc.addr`. It runs next to the HTTP listeners on the same state, so
+//! both see the same todos, users and maintenance mode.
+
+use std::net::SocketAddr;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+
+use crate::AppState;
+
+#[cfg(feature = "grpc")]
+pub mod proto;
+#[cfg(feature = "grpc")]
+mod service;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GrpcConfig {
+    /// Serve gRPC on this address; off while unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub addr: Option<SocketAddr>,
+}
+
+/// Adds the gRPC server to `servers` when `grpc.addr` is set. It stops
+/// accepting when `shutdown` fires, like the HTTP listeners, ends open
+/// `Watch` calls and lets unary calls finish.
+pub fn spawn(
+    state: &AppState,
+    servers: &mut JoinSet<anyhow::Result<()>>,
+    shutdown: watch::Receiver<()>,
+) -> anyhow::Result<()> {
+    let Some(addr) = state.config.grpc.addr else {
+        return Ok(());
+    };
+
+    #[cfg(feature = "grpc")]
+    {
+        use anyhow::Context;
+
+        let listener = std::net::TcpListener::bind(addr)
+            .with_context(|| format!("failed to bind gRPC address {}", addr))?;
+        listener.set_nonblocking(true)?;
+        let listener = tokio::net::TcpListener::from_std(listener)?;
+        tracing::info!("📡 Serving gRPC on {}", addr);
+        servers.spawn(serve(state.clone(), listener, shutdown));
+        Ok(())
+    }
+
+    #[cfg(not(feature = "grpc"))]
+    {
+        let _ = (servers, shutdown);
+        tracing::warn!(%addr, "grpc.addr is set but this build has no grpc feature; gRPC is off");
+        Ok(())
+    }
+}
+
+/// Serves `TodoService` on `state` from `listener` until `shutdown` fires,
+/// for embedding it as [`crate::server::todo_router`] embeds the routes.
+#[cfg(feature = "grpc")]
+pub async fn serve(
+    state: AppState,
+    listener: tokio::net::TcpListener,
+    shutdown: watch::Receiver<()>,
+) -> anyhow::Result<()> {
+    let incoming = tonic::transport::server::TcpIncoming::from_listener(listener, true, None)
+        .map_err(|err| anyhow::anyhow!(err))?;
+    let todos = service::Todos::new(state, shutdown.clone());
+    let mut stopped = shutdown;
+    tonic::transport::Server::builder()
+        .add_service(proto::TodoServiceServer::new(todos))
+        .serve_with_incoming_shutdown(incoming, async move {
+            let _ = stopped.changed().await;
+        })
+        .await?;
+    Ok(())
+}
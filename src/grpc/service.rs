@@ -0,0 +1,302 @@
+//! [`TodoService`] on the app's state. Each call is checked and answered as
+//! its REST route would be, and fails with the status its REST error maps
+//! to; see `impl From<ApiError> for tonic::Status`.
+
+use std::time::Duration;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use tokio::sync::{mpsc, watch};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::codegen::BoxStream;
+use tonic::metadata::MetadataMap;
+use tonic::{Request, Response, Status};
+
+use super::proto::{self, TodoService};
+use crate::db::{self, ReadDb};
+use crate::error::{ApiError, FieldErrors};
+use crate::handlers::todos::insert_todo;
+use crate::history;
+use crate::models::{CreateTodo, ListPage, ListParams, TodoResponse, TodoRow, UpdateTodo};
+use crate::quotas;
+use crate::tags::TagMode;
+use crate::AppState;
+
+/// How often `Watch` looks for new changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+/// Changes read per look, and events buffered for a slow client.
+const WATCH_BATCH: i64 = 100;
+
+pub struct Todos {
+    state: AppState,
+    /// Fires on shutdown, ending every `Watch`.
+    shutdown: watch::Receiver<()>,
+}
+
+impl Todos {
+    pub fn new(state: AppState, shutdown: watch::Receiver<()>) -> Self {
+        Self { state, shutdown }
+    }
+
+    /// The user a call acts for, checked as the HTTP middleware checks a
+    /// request: credentials first, then maintenance mode, then, for a call
+    /// that `changes` something, the user's write quota.
+    async fn caller<M>(&self, request: &Request<M>, changes: bool) -> Result<String, Status> {
+        let user = self.state.auth.bearer_user(presented(request.metadata()))?;
+        if let Some(refusal) = self.state.maintenance.refusal(changes) {
+            return Err(refusal.into());
+        }
+        if changes && user != db::DEFAULT_USER_ID {
+            let quotas = &self.state.quotas;
+            let limits = quotas
+                .limits(&self.state.db, &user)
+                .await
+                .map_err(ApiError::from)?;
+            if quotas.charge(&user, limits.writes_per_minute).is_err() {
+                tracing::warn!(%user, "write quota exceeded");
+                return Err(quotas::write_quota_exceeded(limits.writes_per_minute).into());
+            }
+        }
+        Ok(user)
+    }
+}
+
+/// The credential in `x-api-key` or `authorization: Bearer`, the metadata
+/// matching the HTTP headers.
+fn presented(metadata: &MetadataMap) -> Option<&str> {
+    let value = |key| metadata.get(key).and_then(|value| value.to_str().ok());
+    value("x-api-key").or_else(|| {
+        value("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim)
+    })
+}
+
+fn timestamp(at: Option<DateTime<Utc>>) -> String {
+    at.map(|at| at.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+        .unwrap_or_default()
+}
+
+fn todo(row: TodoRow) -> proto::Todo {
+    let todo = TodoResponse::from(row);
+    proto::Todo {
+        id: todo.id,
+        title: todo.title,
+        completed: todo.completed,
+        completed_at: timestamp(todo.completed_at),
+        tags: todo.tags.0,
+        due_date: timestamp(todo.due_date),
+        priority: todo.priority,
+        position: todo.position,
+        archived: todo.archived,
+        overdue: todo.overdue,
+    }
+}
+
+#[tonic::async_trait]
+impl TodoService for Todos {
+    async fn list(
+        &self,
+        request: Request<proto::ListTodosRequest>,
+    ) -> Result<Response<proto::ListTodosResponse>, Status> {
+        let user = self.caller(&request, false).await?;
+        let request = request.into_inner();
+        let tag_mode = match request.tag_mode.as_deref() {
+            None | Some("any") => TagMode::Any,
+            Some("all") => TagMode::All,
+            Some(other) => {
+                return Err(Status::invalid_argument(format!(
+                    "tag_mode must be any or all, not {:?}",
+                    other
+                )))
+            }
+        };
+        let params = ListParams {
+            tags: request.tags,
+            tag_mode,
+            created_after: request.created_after,
+            created_before: request.created_before,
+            include_archived: request.include_archived,
+            completed: request.completed,
+            completed_after: request.completed_after,
+            q: request.q,
+            untagged: request.untagged,
+        };
+        let page = ListPage {
+            limit: request.limit,
+            offset: request.offset,
+        };
+        let filter = params.filter()?;
+        let (limit, offset) = page.validate()?;
+
+        let ReadDb(db) = &self.state.read_db;
+        let (todos, total) = if page.is_whole_list() {
+            let todos = db::list_todos(db, &user, &filter)
+                .await
+                .map_err(ApiError::from)?;
+            let total = todos.len() as i64;
+            (todos, total)
+        } else {
+            db::list_page(db, &user, &filter, limit, offset)
+                .await
+                .map_err(ApiError::from)?
+        };
+        Ok(Response::new(proto::ListTodosResponse {
+            todos: todos.into_iter().map(todo).collect(),
+            total,
+        }))
+    }
+
+    async fn get(
+        &self,
+        request: Request<proto::GetTodoRequest>,
+    ) -> Result<Response<proto::Todo>, Status> {
+        let user = self.caller(&request, false).await?;
+        let ReadDb(db) = &self.state.read_db;
+        db::find_todo(db, &user, &request.get_ref().id)
+            .await
+            .map_err(ApiError::from)?
+            .map(|row| Response::new(todo(row)))
+            .ok_or_else(|| ApiError::not_found().into())
+    }
+
+    async fn create(
+        &self,
+        request: Request<proto::CreateTodoRequest>,
+    ) -> Result<Response<proto::Todo>, Status> {
+        let user = self.caller(&request, true).await?;
+        let request = request.into_inner();
+        let payload = CreateTodo {
+            title: request.title,
+            tags: request.tags,
+            due_date: request.due_date,
+            due_in: request.due_in,
+            priority: request.priority,
+        };
+        payload.check_due()?;
+
+        let config = &self.state.config;
+        let mut row = payload
+            .check(config, FieldErrors::default())
+            .map_err(FieldErrors::into_error)?;
+        insert_todo(&self.state.db, config, &self.state.quotas, &user, &mut row).await?;
+        self.state.live.created(&user, &row.clone().into());
+        Ok(Response::new(todo(row)))
+    }
+
+    async fn update(
+        &self,
+        request: Request<proto::UpdateTodoRequest>,
+    ) -> Result<Response<proto::Todo>, Status> {
+        let user = self.caller(&request, true).await?;
+        let request = request.into_inner();
+        let due_date = match (request.due_date, request.clear_due_date) {
+            (Some(_), true) => {
+                return Err(Status::invalid_argument(
+                    "due_date and clear_due_date cannot both be given",
+                ))
+            }
+            (Some(due_date), false) => Some(Some(due_date)),
+            (None, true) => Some(None),
+            (None, false) => None,
+        };
+        let payload = UpdateTodo {
+            title: request
+                .title
+                .map(|title| self.state.config.prepare_title(title)),
+            completed: request.completed,
+            tags: request.replace_tags.then_some(request.tags),
+            due_date,
+            priority: request.priority,
+        };
+        let changes = payload.validate()?;
+        let toggle = changes.is_toggle();
+
+        let mut tx = self.state.db.begin().await.map_err(ApiError::from)?;
+        let row = db::update_todo(&mut tx, &user, &request.id, changes)
+            .await
+            .map_err(ApiError::from)?
+            .ok_or_else(ApiError::not_found)?;
+        tx.commit().await.map_err(ApiError::from)?;
+
+        let published = row.clone().into();
+        if toggle {
+            self.state.live.toggled(&user, &published);
+        } else {
+            self.state.live.updated(&user, &published);
+        }
+        Ok(Response::new(todo(row)))
+    }
+
+    async fn delete(
+        &self,
+        request: Request<proto::DeleteTodoRequest>,
+    ) -> Result<Response<proto::DeleteTodoResponse>, Status> {
+        let user = self.caller(&request, true).await?;
+        let request = request.into_inner();
+
+        let mut tx = self.state.db.begin().await.map_err(ApiError::from)?;
+        let deleted = db::delete_todo(&mut tx, &user, &request.id, request.purge)
+            .await
+            .map_err(ApiError::from)?;
+        tx.commit().await.map_err(ApiError::from)?;
+
+        if deleted {
+            self.state.live.deleted(&user, &request.id);
+            Ok(Response::new(proto::DeleteTodoResponse {}))
+        } else {
+            Err(ApiError::not_found().into())
+        }
+    }
+
+    /// Follows the todo history from the latest entry on, polling every
+    /// [`WATCH_INTERVAL`]. Purged todos take their history with them, so
+    /// purges are not seen.
+    async fn watch(
+        &self,
+        request: Request<proto::WatchTodosRequest>,
+    ) -> Result<Response<BoxStream<proto::TodoEvent>>, Status> {
+        let user = self.caller(&request, false).await?;
+        let db = self.state.db.clone();
+        let mut after = history::latest_id(&db).await.map_err(ApiError::from)?;
+        let mut shutdown = self.shutdown.clone();
+        let (tx, rx) = mpsc::channel(WATCH_BATCH as usize);
+
+        tokio::spawn(async move {
+            let mut poll = tokio::time::interval(WATCH_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = shutdown.changed() => return,
+                    _ = tx.closed() => return,
+                    _ = poll.tick() => {}
+                }
+                let changes = match history::since(&db, &user, after, WATCH_BATCH).await {
+                    Ok(changes) => changes,
+                    Err(err) => {
+                        let _ = tx.send(Err(ApiError::from(err).into())).await;
+                        return;
+                    }
+                };
+                for change in changes {
+                    after = change.id;
+                    let row = match db::find_todo(&db, &user, &change.todo_id).await {
+                        Ok(row) => row,
+                        Err(err) => {
+                            let _ = tx.send(Err(ApiError::from(err).into())).await;
+                            return;
+                        }
+                    };
+                    let event = proto::TodoEvent {
+                        action: change.action,
+                        todo_id: change.todo_id,
+                        todo: row.map(todo),
+                    };
+                    if tx.send(Ok(event)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+}
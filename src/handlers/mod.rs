@@ -0,0 +1,13 @@
+//! Handlers for the JSON API's own routes. Features with routes of their
+//! own, such as shares or preferences, keep their handlers in their module.
+
+use axum::http::{StatusCode, Uri};
+
+use crate::error::ApiError;
+
+pub mod todos;
+
+/// `404` for any path no route or file matches.
+pub async fn fallback(uri: Uri) -> ApiError {
+    ApiError::new(StatusCode::NOT_FOUND, "route not found").with_detail("path", uri.path())
+}
@@ -0,0 +1,776 @@
+//! The `/todos` routes, `POST /tags/apply` and `GET /search`.
+
+use std::sync::Arc;
+
+use axum::{
+    body::{Body, Bytes, StreamBody},
+    extract::{FromRequest, Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, Request, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    BoxError, Json,
+};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+
+use crate::auth::UserId;
+use crate::config::Config;
+use crate::db::{self, Placement, ReadDb};
+use crate::error::{ApiError, FieldErrors};
+use crate::extract::{self, FormBody, JsonBody};
+use crate::history::{self, Page};
+use crate::live::Live;
+use crate::models::{
+    responses, ApplyTags, BatchItemError, BatchUpdateItem, BatchUpdateParams, BatchUpdateResult,
+    CreateTodo, CreateTodoForm, DeleteBatch, DeleteBatchResult, DeleteParams, ExportParams,
+    GroupCount, GroupParams, ListPage, ListParams, MergeTodo, MoveTodo, RandomParams, SearchParams,
+    TodoChanges, TodoResponse, TodoRow, UpdateTodo, MAX_SEARCH_LEN, TOTAL_COUNT_HEADER,
+};
+use crate::prefer::Prefer;
+use crate::quotas::{self, Quotas};
+use crate::{frontend, markdown, tags, Db};
+
+/// The list's `ETag`: the user, the query as given, which carries the
+/// filters and page, and the [`db::ListVersion`] of the matching todos.
+fn list_etag(user: &str, query: Option<&str>, version: &db::ListVersion) -> String {
+    let fingerprint = format!(
+        "{}\n{}\n{:?}\n{}\n{}",
+        user,
+        query.unwrap_or_default(),
+        version.last_updated,
+        version.count,
+        version.overdue
+    );
+    frontend::etag(&Sha256::digest(fingerprint.as_bytes()).into())
+}
+
+/// `GET /todos`, optionally filtered by `?tags=a,b&tag_mode=any|all`,
+/// `?completed=` and `?q=`, and paged with `?limit=&offset=`. The number
+/// of matching todos comes back in `X-Total-Count`. Every list carries an
+/// `ETag`; sent back in `If-None-Match` it gets `304` while no matching
+/// todo has changed, without the todos being read.
+#[utoipa::path(
+    get,
+    path = "/todos",
+    tag = "todos",
+    params(
+        ListParams,
+        ListPage,
+        ("If-None-Match" = Option<String>, Header, description = "An `ETag` from an earlier list"),
+    ),
+    responses(
+        (status = 200, description = "The todos, in list order", body = [TodoResponse],
+            headers(
+                ("x-total-count" = i64, description = "Todos matching the filters"),
+                ("etag" = String, description = "Changes whenever the list would"),
+            )),
+        (status = 304, description = "Unchanged since the `If-None-Match` ETag"),
+        (status = 400, description = "An unparseable `created_after` or `created_before`", body = ErrorBody),
+        (status = 422, description = "A bad `q`, `limit` or `offset`", body = ErrorBody),
+    )
+)]
+pub async fn list_todos(
+    State(ReadDb(db)): State<ReadDb>,
+    UserId(user): UserId,
+    Query(params): Query<ListParams>,
+    Query(page): Query<ListPage>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let filter = params.filter()?;
+    let (limit, offset) = page.validate()?;
+
+    // Read before the todos, so a write in between can only make the tag
+    // older than the body, never newer.
+    let version = db::list_version(&db, &user, &filter).await?;
+    let etag = list_etag(&user, uri.query(), &version);
+    let cache_headers = [
+        (
+            header::ETAG,
+            HeaderValue::from_str(&etag).map_err(|err| ApiError::internal().caused_by(err))?,
+        ),
+        (
+            header::CACHE_CONTROL,
+            HeaderValue::from_static("private, no-cache"),
+        ),
+    ];
+    if frontend::not_modified(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+    }
+
+    let (todos, total) = if page.is_whole_list() {
+        let todos = db::list_todos(&db, &user, &filter).await?;
+        let total = todos.len() as i64;
+        (todos, total)
+    } else {
+        db::list_page(&db, &user, &filter, limit, offset).await?
+    };
+    Ok((
+        cache_headers,
+        [(TOTAL_COUNT_HEADER, HeaderValue::from(total))],
+        Json(responses(todos)),
+    )
+        .into_response())
+}
+
+/// `GET /todos/group?by=priority|completed|tag`: how many todos there are
+/// under each value of the field, filtered like `GET /todos`, for charts
+/// that should not need every row. `400` for any other `by`.
+#[utoipa::path(
+    get,
+    path = "/todos/group",
+    tag = "todos",
+    params(GroupParams, ListParams),
+    responses(
+        (status = 200, description = "A count per value, in order of the value", body = [GroupCount]),
+        (status = 400, description = "No `by`, or an unknown one", body = ErrorBody),
+    )
+)]
+pub async fn group_todos(
+    State(ReadDb(db)): State<ReadDb>,
+    UserId(user): UserId,
+    Query(params): Query<ListParams>,
+    Query(group): Query<GroupParams>,
+) -> Result<Json<Vec<GroupCount>>, ApiError> {
+    let Some(by) = group.by.as_deref().and_then(db::GroupField::parse) else {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("by must be one of {}", db::GroupField::NAMES.join(", ")),
+        ));
+    };
+    let counts = db::group_counts(&db, &user, &params.filter()?, by).await?;
+    Ok(Json(
+        counts
+            .into_iter()
+            .map(|(key, count)| GroupCount { key, count })
+            .collect(),
+    ))
+}
+
+/// `GET /todos/export.md`: the list as a Markdown checklist, sectioned by
+/// `?group_by=priority|tag|none` and filtered like `GET /todos`.
+#[utoipa::path(
+    get,
+    path = "/todos/export.md",
+    tag = "todos",
+    params(ExportParams, ListParams),
+    responses(
+        (status = 200, description = "The checklist", body = String, content_type = "text/markdown"),
+    )
+)]
+pub async fn export_markdown(
+    State(db): State<Db>,
+    UserId(user): UserId,
+    Query(params): Query<ListParams>,
+    Query(export): Query<ExportParams>,
+) -> Result<Response, ApiError> {
+    let todos = db::list_todos(&db, &user, &params.filter()?).await?;
+
+    Ok((
+        [(header::CONTENT_TYPE, markdown::CONTENT_TYPE)],
+        markdown::checklist(&todos, export.group_by),
+    )
+        .into_response())
+}
+
+/// Rows buffered between the database cursor and the response body.
+const STREAM_BUFFER: usize = 64;
+
+/// `GET /todos/stream`: the same list as `GET /todos`, written as one JSON
+/// object per line while rows are read. A database error mid-stream aborts
+/// the response, so clients see a truncated body rather than a silent end.
+#[utoipa::path(
+    get,
+    path = "/todos/stream",
+    tag = "todos",
+    params(ListParams),
+    responses(
+        (status = 200, description = "One todo per line", body = TodoResponse, content_type = "application/x-ndjson"),
+    )
+)]
+pub async fn stream_todos(
+    State(db): State<Db>,
+    UserId(user): UserId,
+    Query(params): Query<ListParams>,
+) -> Result<Response, ApiError> {
+    let filter = params.filter()?;
+    let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+    tokio::spawn(async move { db::stream_todos(&db, &user, &filter, tx).await });
+
+    let lines = ReceiverStream::new(rx).map(|row| -> Result<Bytes, BoxError> {
+        let todo = row.map_err(|err| {
+            tracing::error!(error = %err, "todo stream failed");
+            err
+        })?;
+        let mut line = serde_json::to_vec(&TodoResponse::from(todo))?;
+        line.push(b'\n');
+        Ok(Bytes::from(line))
+    });
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        StreamBody::new(lines),
+    )
+        .into_response())
+}
+
+/// Saves a new todo for `user`, refusing a duplicate open title when titles
+/// are unique, a create past the user's `max_todos` and, with `409`, an id
+/// another user's todo already has.
+pub async fn insert_todo(
+    db: &Db,
+    config: &Config,
+    quotas: &Quotas,
+    user: &str,
+    todo: &mut TodoRow,
+) -> Result<(), ApiError> {
+    let max_todos = quotas.limits(db, user).await?.max_todos;
+    let mut tx = db.begin().await?;
+    if config.todos.unique_title_per_user
+        && db::open_title_taken(&mut *tx, user, &todo.title).await?
+    {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "an open todo with this title already exists",
+        ));
+    }
+    if !db::save_todo(&mut tx, user, todo, history::Action::Created).await? {
+        return Err(
+            ApiError::new(StatusCode::CONFLICT, "a todo with this id already exists")
+                .with_detail("code", "id_taken"),
+        );
+    }
+    // Counted after the insert, which holds the write lock until commit, so
+    // concurrent creates cannot both slip under the quota.
+    if let Some(max_todos) = max_todos {
+        if db::count_todos(&mut *tx, user).await? > max_todos {
+            return Err(quotas::todo_quota_exceeded(max_todos));
+        }
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// `POST /todos`: adds a todo at the end of the list. Answers with it, or
+/// with `204` and its `Location` when `return=minimal` is asked for. The
+/// body is JSON, or a form when sent as `application/x-www-form-urlencoded`.
+#[utoipa::path(
+    post,
+    path = "/todos",
+    tag = "todos",
+    params(
+        ("return" = Option<String>, Query, description = "`minimal` or `representation`; wins over `Prefer`"),
+        ("Prefer" = Option<String>, Header, description = "`return=minimal` or `return=representation`"),
+    ),
+    request_body = CreateTodo,
+    responses(
+        (status = 200, description = "The new todo", body = TodoResponse),
+        (status = 204, description = "Created; `return=minimal` was asked for",
+            headers(("location" = String, description = "The new todo's URL"))),
+        (status = 400, description = "Both `due_date` and `due_in`", body = ErrorBody),
+        (status = 403, description = "The user's todo quota is reached", body = ErrorBody),
+        (status = 409, description = "An open todo has this title, with unique titles on, or the generated id is taken", body = ErrorBody),
+        (status = 422, description = "Invalid fields", body = ErrorBody),
+    )
+)]
+pub async fn create_todo(
+    State(db): State<Db>,
+    State(config): State<Arc<Config>>,
+    State(quotas): State<Arc<Quotas>>,
+    State(live): State<Live>,
+    UserId(user): UserId,
+    prefer: Prefer,
+    req: Request<Body>,
+) -> Result<Response, ApiError> {
+    let (payload, errors) = if extract::is_form(req.headers()) {
+        let FormBody(form) = FormBody::<CreateTodoForm>::from_request(req, &()).await?;
+        form.parse()
+    } else {
+        let JsonBody(payload) = JsonBody::<CreateTodo>::from_request(req, &()).await?;
+        (payload, FieldErrors::default())
+    };
+    payload.check_due()?;
+    let mut todo = payload
+        .check(&config, errors)
+        .map_err(FieldErrors::into_error)?;
+    insert_todo(&db, &config, &quotas, &user, &mut todo).await?;
+    let location = config.server.base_path.url(&format!("/todos/{}", todo.id));
+    let todo = TodoResponse::from(todo);
+    live.created(&user, &todo);
+    Ok(prefer.respond(todo, Some(location)))
+}
+
+/// `GET /todos/:id`: one todo, as JSON or, for `Accept: text/plain`, one
+/// line of text.
+#[utoipa::path(
+    get,
+    path = "/todos/{id}",
+    tag = "todos",
+    params(("id" = String, Path, description = "The todo's id")),
+    responses(
+        (status = 200, description = "The todo", content(
+            ("application/json" = TodoResponse),
+            ("text/plain" = String),
+        )),
+        (status = 404, description = "No such todo", body = ErrorBody),
+    )
+)]
+pub async fn get_todo(
+    Path(id): Path<String>,
+    State(ReadDb(db)): State<ReadDb>,
+    UserId(user): UserId,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    if let Some(todo) = db::find_todo(&db, &user, &id).await? {
+        if crate::prefers(&headers, "text/plain") {
+            Ok(todo.to_plain_text().into_response())
+        } else {
+            Ok(Json(TodoResponse::from(todo)).into_response())
+        }
+    } else {
+        Err(ApiError::not_found())
+    }
+}
+
+/// `GET /search?q=`: todos matching `q` in their title or tags, best
+/// matches first.
+#[utoipa::path(
+    get,
+    path = "/search",
+    tag = "todos",
+    params(SearchParams),
+    responses(
+        (status = 200, description = "Matching todos, best first", body = [TodoResponse]),
+        (status = 422, description = "An empty or overlong `q`", body = ErrorBody),
+    )
+)]
+pub async fn search(
+    State(db): State<Db>,
+    UserId(user): UserId,
+    Query(params): Query<SearchParams>,
+) -> Result<Json<Vec<TodoResponse>>, ApiError> {
+    let term = params.q.trim();
+    if term.is_empty() {
+        return Err(ApiError::validation("q must not be empty"));
+    }
+    if term.len() > MAX_SEARCH_LEN {
+        return Err(ApiError::validation(format!(
+            "q must be at most {} bytes",
+            MAX_SEARCH_LEN
+        )));
+    }
+
+    Ok(Json(responses(db::search_todos(&db, &user, term).await?)))
+}
+
+/// `GET /todos/random`: an open todo to work on next.
+#[utoipa::path(
+    get,
+    path = "/todos/random",
+    tag = "todos",
+    params(RandomParams),
+    responses(
+        (status = 200, description = "An open todo", body = TodoResponse),
+        (status = 404, description = "No open todos", body = ErrorBody),
+    )
+)]
+pub async fn random_todo(
+    State(db): State<Db>,
+    UserId(user): UserId,
+    Query(params): Query<RandomParams>,
+) -> Result<Json<TodoResponse>, ApiError> {
+    let tag = match params.tag {
+        Some(tag) => tags::normalize(vec![tag])?.pop(),
+        None => None,
+    };
+
+    match db::random_todo(&db, &user, tag.as_deref()).await? {
+        Some(todo) => Ok(Json(todo.into())),
+        None => Err(ApiError::new(StatusCode::NOT_FOUND, "no open todos")),
+    }
+}
+
+/// `PUT /todos/:id`: changes the fields given and leaves the rest. Answers
+/// with the todo, or with `204` when `return=minimal` is asked for.
+#[utoipa::path(
+    put,
+    path = "/todos/{id}",
+    tag = "todos",
+    params(
+        ("id" = String, Path, description = "The todo's id"),
+        ("return" = Option<String>, Query, description = "`minimal` or `representation`; wins over `Prefer`"),
+        ("Prefer" = Option<String>, Header, description = "`return=minimal` or `return=representation`"),
+    ),
+    request_body = UpdateTodo,
+    responses(
+        (status = 200, description = "The updated todo", body = TodoResponse),
+        (status = 204, description = "Updated; `return=minimal` was asked for"),
+        (status = 404, description = "No such todo", body = ErrorBody),
+        (status = 422, description = "Invalid fields", body = ErrorBody),
+    )
+)]
+pub async fn update_todo(
+    Path(id): Path<String>,
+    State(db): State<Db>,
+    State(config): State<Arc<Config>>,
+    State(live): State<Live>,
+    UserId(user): UserId,
+    prefer: Prefer,
+    JsonBody(mut payload): JsonBody<UpdateTodo>,
+) -> Result<Response, ApiError> {
+    payload.title = payload.title.map(|title| config.prepare_title(title));
+    let changes = payload.validate()?;
+    let toggle = changes.is_toggle();
+
+    let mut tx = db.begin().await?;
+    let todo = db::update_todo(&mut tx, &user, &id, changes).await?;
+    tx.commit().await?;
+
+    let todo = TodoResponse::from(todo.ok_or_else(ApiError::not_found)?);
+    if toggle {
+        live.toggled(&user, &todo);
+    } else {
+        live.updated(&user, &todo);
+    }
+    Ok(prefer.respond(todo, None))
+}
+
+/// `PUT /todos/batch`: applies every item in one transaction. Unknown ids and
+/// invalid items are reported per item; with `?atomic=true` any such failure
+/// rolls back the whole batch instead.
+#[utoipa::path(
+    put,
+    path = "/todos/batch",
+    tag = "todos",
+    params(BatchUpdateParams),
+    request_body = [BatchUpdateItem],
+    responses(
+        (status = 200, description = "What was updated, and what was not", body = BatchUpdateResult),
+        (status = 404, description = "Atomic batch with an unknown id; nothing changed", body = ErrorBody),
+        (status = 422, description = "Atomic batch with an invalid item; nothing changed", body = ErrorBody),
+    )
+)]
+pub async fn batch_update_todos(
+    State(db): State<Db>,
+    State(config): State<Arc<Config>>,
+    State(live): State<Live>,
+    UserId(user): UserId,
+    Query(params): Query<BatchUpdateParams>,
+    JsonBody(items): JsonBody<Vec<BatchUpdateItem>>,
+) -> Result<Json<BatchUpdateResult>, ApiError> {
+    let mut result = BatchUpdateResult {
+        updated: Vec::new(),
+        not_found: Vec::new(),
+        errors: Vec::new(),
+    };
+
+    let mut tx = db.begin().await?;
+    let mut toggles = Vec::new();
+
+    for mut item in items {
+        item.changes.title = item.changes.title.map(|title| config.prepare_title(title));
+        let changes = match item.changes.validate() {
+            Ok(changes) => changes,
+            Err(err) => {
+                result.errors.push(BatchItemError {
+                    id: item.id,
+                    error: err.message().to_string(),
+                });
+                continue;
+            }
+        };
+
+        let toggle = changes.is_toggle();
+        match db::update_todo(&mut tx, &user, &item.id, changes).await? {
+            Some(todo) => {
+                toggles.push(toggle);
+                result.updated.push(todo.into());
+            }
+            None => result.not_found.push(item.id),
+        }
+    }
+
+    if params.atomic && (!result.not_found.is_empty() || !result.errors.is_empty()) {
+        tx.rollback().await?;
+
+        let status = if result.errors.is_empty() {
+            StatusCode::NOT_FOUND
+        } else {
+            StatusCode::UNPROCESSABLE_ENTITY
+        };
+        return Err(ApiError::new(status, "batch rejected, no changes applied")
+            .with_detail("not_found", json!(result.not_found))
+            .with_detail("errors", json!(result.errors)));
+    }
+
+    tx.commit().await?;
+
+    for (todo, toggle) in result.updated.iter().zip(toggles) {
+        if toggle {
+            live.toggled(&user, todo);
+        } else {
+            live.updated(&user, todo);
+        }
+    }
+    Ok(Json(result))
+}
+
+/// `GET /todos/:id/history`: the todo's recorded changes, newest first,
+/// paged with `?limit=` (default 50) and `?offset=`.
+#[utoipa::path(
+    get,
+    path = "/todos/{id}/history",
+    tag = "todos",
+    params(("id" = String, Path, description = "The todo's id"), history::PageParams),
+    responses(
+        (status = 200, description = "A page of entries", body = Page),
+        (status = 404, description = "No such todo", body = ErrorBody),
+        (status = 422, description = "A bad `limit` or `offset`", body = ErrorBody),
+    )
+)]
+pub async fn todo_history(
+    Path(id): Path<String>,
+    State(db): State<Db>,
+    UserId(user): UserId,
+    Query(params): Query<history::PageParams>,
+) -> Result<Json<Page>, ApiError> {
+    let (limit, offset) = params.validate().map_err(ApiError::validation)?;
+    if db::find_todo(&db, &user, &id).await?.is_none() {
+        return Err(ApiError::not_found());
+    }
+
+    Ok(Json(history::page(&db, &id, limit, offset).await?))
+}
+
+/// `POST /todos/:id/move`: places one todo directly before or after another.
+#[utoipa::path(
+    post,
+    path = "/todos/{id}/move",
+    tag = "todos",
+    params(("id" = String, Path, description = "The todo's id")),
+    request_body = MoveTodo,
+    responses(
+        (status = 200, description = "The moved todo", body = TodoResponse),
+        (status = 404, description = "No such todo", body = ErrorBody),
+        (status = 422, description = "Not exactly one target, or an unknown one", body = ErrorBody),
+    )
+)]
+pub async fn move_todo(
+    Path(id): Path<String>,
+    State(db): State<Db>,
+    State(live): State<Live>,
+    UserId(user): UserId,
+    JsonBody(payload): JsonBody<MoveTodo>,
+) -> Result<Json<TodoResponse>, ApiError> {
+    let (target, placement) = match (payload.before, payload.after) {
+        (Some(target), None) => (target, Placement::Before),
+        (None, Some(target)) => (target, Placement::After),
+        _ => return Err(ApiError::validation("give exactly one of before or after")),
+    };
+    if target == id {
+        return Err(ApiError::validation(
+            "cannot move a todo relative to itself",
+        ));
+    }
+
+    let mut tx = db.begin().await?;
+    if db::find_todo(&mut *tx, &user, &id).await?.is_none() {
+        return Err(ApiError::not_found());
+    }
+    if db::find_todo(&mut *tx, &user, &target).await?.is_none() {
+        return Err(ApiError::validation("target todo not found"));
+    }
+
+    db::move_todo(&mut tx, &user, &id, &target, placement).await?;
+    let todo = db::find_todo(&mut *tx, &user, &id).await?;
+    tx.commit().await?;
+
+    let todo = TodoResponse::from(todo.ok_or_else(ApiError::not_found)?);
+    live.updated(&user, &todo);
+    Ok(Json(todo))
+}
+
+/// `POST /todos/:id/merge`: folds a duplicate into the todo named by
+/// `into`, which keeps its title and gains the duplicate's tags, and counts
+/// as completed if either was. The duplicate is soft-deleted.
+#[utoipa::path(
+    post,
+    path = "/todos/{id}/merge",
+    tag = "todos",
+    params(("id" = String, Path, description = "The duplicate")),
+    request_body = MergeTodo,
+    responses(
+        (status = 200, description = "The todo merged into", body = TodoResponse),
+        (status = 404, description = "No such duplicate", body = ErrorBody),
+        (status = 422, description = "Merging into itself or an unknown todo", body = ErrorBody),
+    )
+)]
+pub async fn merge_todo(
+    Path(id): Path<String>,
+    State(db): State<Db>,
+    State(live): State<Live>,
+    UserId(user): UserId,
+    JsonBody(payload): JsonBody<MergeTodo>,
+) -> Result<Json<TodoResponse>, ApiError> {
+    if payload.into == id {
+        return Err(ApiError::validation("cannot merge a todo into itself"));
+    }
+
+    let mut tx = db.begin().await?;
+    let Some(source) = db::find_todo(&mut *tx, &user, &id).await? else {
+        return Err(ApiError::not_found());
+    };
+    let Some(target) = db::find_todo(&mut *tx, &user, &payload.into).await? else {
+        return Err(ApiError::validation("target todo not found"));
+    };
+
+    let mut tags = target.tags.0;
+    tags.extend(source.tags.0);
+    tags.sort();
+    tags.dedup();
+    let changes = TodoChanges {
+        completed: Some(target.completed || source.completed),
+        tags: Some(tags),
+        ..TodoChanges::default()
+    };
+    let merged = db::update_todo(&mut tx, &user, &target.id, changes)
+        .await?
+        .ok_or_else(ApiError::not_found)?;
+    db::delete_todo(&mut tx, &user, &source.id, false).await?;
+    tx.commit().await?;
+
+    let merged = TodoResponse::from(merged);
+    live.updated(&user, &merged);
+    live.deleted(&user, &source.id);
+    Ok(Json(merged))
+}
+
+/// `POST /tags/apply`: adds and removes tags across many todos at once.
+/// Ids that do not exist are skipped and not counted.
+#[utoipa::path(
+    post,
+    path = "/tags/apply",
+    tag = "todos",
+    request_body = ApplyTags,
+    responses(
+        (status = 200, description = "`{\"updated\": n}`, the todos changed", body = Object),
+        (status = 422, description = "Invalid tags", body = ErrorBody),
+    )
+)]
+pub async fn apply_tags(
+    State(db): State<Db>,
+    State(live): State<Live>,
+    UserId(user): UserId,
+    JsonBody(payload): JsonBody<ApplyTags>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let mut errors = FieldErrors::default();
+    let normalize = |tags| tags::normalize(tags).map_err(|err| err.message().to_string());
+    let add = errors
+        .check("add", normalize(payload.add))
+        .unwrap_or_default();
+    let remove = errors
+        .check("remove", normalize(payload.remove))
+        .unwrap_or_default();
+    if let Some(tag) = add.iter().find(|tag| remove.contains(tag)) {
+        errors.add("remove", format!("tag '{}' is also in add", tag));
+    }
+    errors.finish()?;
+
+    let mut ids = payload.ids;
+    ids.sort();
+    ids.dedup();
+
+    let mut tx = db.begin().await?;
+    let updated = tags::apply(&mut tx, &user, &ids, &add, &remove).await?;
+    tx.commit().await?;
+
+    // Every existing id is published, changed or not.
+    if updated > 0 {
+        for id in &ids {
+            if let Some(todo) = db::find_todo(&db, &user, id).await? {
+                live.updated(&user, &todo.into());
+            }
+        }
+    }
+
+    Ok(Json(json!({ "updated": updated })))
+}
+
+/// `DELETE /todos/:id`: soft-deletes, or removes for good with
+/// `?purge=true`.
+#[utoipa::path(
+    delete,
+    path = "/todos/{id}",
+    tag = "todos",
+    params(("id" = String, Path, description = "The todo's id"), DeleteParams),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 404, description = "No such todo", body = ErrorBody),
+    )
+)]
+pub async fn delete_todo(
+    Path(id): Path<String>,
+    State(db): State<Db>,
+    State(live): State<Live>,
+    UserId(user): UserId,
+    Query(params): Query<DeleteParams>,
+) -> Result<StatusCode, ApiError> {
+    let mut tx = db.begin().await?;
+    let deleted = db::delete_todo(&mut tx, &user, &id, params.purge).await?;
+    tx.commit().await?;
+
+    if deleted {
+        live.deleted(&user, &id);
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::not_found())
+    }
+}
+
+/// `POST /todos/delete-batch`: `DELETE /todos/:id` for many ids in one
+/// transaction, with the same `?purge=true` switch.
+#[utoipa::path(
+    post,
+    path = "/todos/delete-batch",
+    tag = "todos",
+    params(DeleteParams),
+    request_body = DeleteBatch,
+    responses(
+        (status = 200, description = "What happened to each id", body = DeleteBatchResult),
+    )
+)]
+pub async fn delete_batch(
+    State(db): State<Db>,
+    State(live): State<Live>,
+    UserId(user): UserId,
+    Query(params): Query<DeleteParams>,
+    JsonBody(payload): JsonBody<DeleteBatch>,
+) -> Result<Json<DeleteBatchResult>, ApiError> {
+    let mut result = DeleteBatchResult {
+        soft_deleted: Vec::new(),
+        purged: Vec::new(),
+        already_gone: Vec::new(),
+    };
+
+    let mut ids = payload.ids;
+    ids.sort();
+    ids.dedup();
+
+    let mut tx = db.begin().await?;
+    for id in ids {
+        if !db::delete_todo(&mut tx, &user, &id, params.purge).await? {
+            result.already_gone.push(id);
+        } else if params.purge {
+            result.purged.push(id);
+        } else {
+            result.soft_deleted.push(id);
+        }
+    }
+    tx.commit().await?;
+
+    for id in result.soft_deleted.iter().chain(&result.purged) {
+        live.deleted(&user, id);
+    }
+    Ok(Json(result))
+}
@@ -0,0 +1,153 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::SqlitePool;
+use utoipa::ToSchema;
+
+use crate::db;
+
+pub const HEALTH_PATH: &str = "/health";
+pub const LIVEZ_PATH: &str = "/livez";
+pub const READYZ_PATH: &str = "/readyz";
+
+/// Upper bound on the database probe, well below typical probe timeouts.
+const DB_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// When the server started serving, for `uptime_seconds`.
+#[derive(Debug, Clone, Copy)]
+pub struct StartedAt(pub Instant);
+
+/// Cleared once shutdown begins, so `/readyz` fails while in-flight
+/// requests drain and load balancers stop routing here first.
+#[derive(Debug, Clone, Default)]
+pub struct Readiness(Arc<AtomicBool>);
+
+impl Readiness {
+    pub fn start_draining(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    fn draining(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HealthReport {
+    status: &'static str,
+    db: &'static str,
+    uptime_seconds: u64,
+}
+
+/// `GET /health`: `200` when a `SELECT 1` round-trips within
+/// `DB_CHECK_TIMEOUT`, otherwise `503` naming the failing component.
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "probes",
+    responses(
+        (status = 200, description = "Healthy", body = HealthReport),
+        (status = 503, description = "The database is failing", body = HealthReport),
+    )
+)]
+pub async fn health(
+    State(db): State<SqlitePool>,
+    State(StartedAt(started)): State<StartedAt>,
+) -> impl IntoResponse {
+    let db_status =
+        match tokio::time::timeout(DB_CHECK_TIMEOUT, sqlx::query("SELECT 1").execute(&db)).await {
+            Ok(Ok(_)) => "ok",
+            Ok(Err(err)) => {
+                tracing::warn!(error = %err, "health check: database error");
+                "error"
+            }
+            Err(_) => {
+                tracing::warn!("health check: database timed out");
+                "timeout"
+            }
+        };
+
+    let (status, overall) = if db_status == "ok" {
+        (StatusCode::OK, "ok")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+    };
+
+    (
+        status,
+        Json(HealthReport {
+            status: overall,
+            db: db_status,
+            uptime_seconds: started.elapsed().as_secs(),
+        }),
+    )
+}
+
+/// `GET /livez`: `200` whenever the runtime can answer at all. Touches
+/// nothing else, so a slow database never gets the process restarted.
+#[utoipa::path(
+    get,
+    path = "/livez",
+    tag = "probes",
+    responses((status = 200, description = "`{\"status\": \"ok\"}`", body = Object))
+)]
+pub async fn livez() -> impl IntoResponse {
+    Json(json!({ "status": "ok" }))
+}
+
+/// `GET /readyz`: `200` when the database answers, every embedded migration
+/// is applied and the server is not draining for shutdown; otherwise `503`
+/// listing the failing checks.
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "probes",
+    responses(
+        (status = 200, description = "`{\"status\": \"ok\"}`", body = Object),
+        (status = 503, description = "`{\"status\": \"unavailable\", \"failing\": [...]}`", body = Object),
+    )
+)]
+pub async fn readyz(
+    State(db): State<SqlitePool>,
+    State(readiness): State<Readiness>,
+) -> impl IntoResponse {
+    let mut failing = Vec::new();
+
+    let applied = tokio::time::timeout(
+        DB_CHECK_TIMEOUT,
+        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM _sqlx_migrations WHERE success = 1")
+            .fetch_one(&db),
+    )
+    .await;
+    match applied {
+        Ok(Ok(applied)) => {
+            if applied < db::MIGRATOR.iter().count() as i64 {
+                failing.push("migrations");
+            }
+        }
+        Ok(Err(err)) => {
+            tracing::warn!(error = %err, "readiness check: database error");
+            failing.push("db");
+        }
+        Err(_) => {
+            tracing::warn!("readiness check: database timed out");
+            failing.push("db");
+        }
+    }
+    if readiness.draining() {
+        failing.push("draining");
+    }
+
+    if failing.is_empty() {
+        (StatusCode::OK, Json(json!({ "status": "ok" })))
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "status": "unavailable", "failing": failing })),
+        )
+    }
+}
@@ -0,0 +1,191 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::{Executor, Sqlite, SqliteConnection};
+use utoipa::{IntoParams, ToSchema};
+
+/// Entries returned by `GET /todos/:id/history` when no `limit` is given.
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Created,
+    Imported,
+    Updated,
+    Deleted,
+}
+
+impl Action {
+    fn as_str(self) -> &'static str {
+        match self {
+            Action::Created => "created",
+            Action::Imported => "imported",
+            Action::Updated => "updated",
+            Action::Deleted => "deleted",
+        }
+    }
+}
+
+/// One recorded change. `changes` holds the new value of every field the
+/// change touched.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Entry {
+    pub id: i64,
+    pub at: DateTime<Utc>,
+    /// `created`, `imported`, `updated` or `deleted`.
+    pub action: String,
+    #[schema(value_type = Object)]
+    pub changes: Value,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Page {
+    pub entries: Vec<Entry>,
+    /// Older entries exist beyond this page.
+    pub has_more: bool,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PageParams {
+    /// 1 to 200; 50 when absent.
+    limit: Option<i64>,
+    #[serde(default)]
+    offset: i64,
+}
+
+impl PageParams {
+    /// `(limit, offset)`, or a message for the first invalid one.
+    pub fn validate(&self) -> Result<(i64, i64), String> {
+        let limit = self.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+        if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+            return Err(format!("limit must be between 1 and {}", MAX_PAGE_SIZE));
+        }
+        if self.offset < 0 {
+            return Err("offset must not be negative".to_string());
+        }
+        Ok((limit, self.offset))
+    }
+}
+
+/// Appends an entry for `todo_id`. Run it in the same transaction as the
+/// change it describes.
+#[tracing::instrument(skip_all)]
+pub async fn record(
+    conn: &mut SqliteConnection,
+    todo_id: &str,
+    action: Action,
+    changes: Map<String, Value>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO todo_history (todo_id, at, action, changes) VALUES (?, ?, ?, ?)")
+        .bind(todo_id)
+        .bind(Utc::now())
+        .bind(action.as_str())
+        .bind(Value::Object(changes).to_string())
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Newest entries first, skipping `offset` and returning at most `limit`.
+#[tracing::instrument(skip_all)]
+pub async fn page<'e, E>(db: E, todo_id: &str, limit: i64, offset: i64) -> Result<Page, sqlx::Error>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    // One row past the page tells whether there is more without a COUNT.
+    let rows: Vec<(i64, DateTime<Utc>, String, String)> = sqlx::query_as(
+        "SELECT id, at, action, changes FROM todo_history WHERE todo_id = ?
+         ORDER BY id DESC LIMIT ? OFFSET ?",
+    )
+    .bind(todo_id)
+    .bind(limit + 1)
+    .bind(offset)
+    .fetch_all(db)
+    .await?;
+
+    let has_more = rows.len() as i64 > limit;
+    let entries = rows
+        .into_iter()
+        .take(limit as usize)
+        .map(|(id, at, action, changes)| Entry {
+            id,
+            at,
+            action,
+            changes: serde_json::from_str(&changes).unwrap_or(Value::Null),
+        })
+        .collect();
+
+    Ok(Page { entries, has_more })
+}
+
+/// A change to any user's todo, as listed on the admin overview.
+#[derive(Debug, Serialize, sqlx::FromRow, ToSchema)]
+pub struct RecentEntry {
+    pub at: DateTime<Utc>,
+    pub todo_id: String,
+    pub user_id: String,
+    pub action: String,
+}
+
+/// The latest `limit` changes across all todos, newest first.
+pub async fn recent<'e, E>(db: E, limit: i64) -> Result<Vec<RecentEntry>, sqlx::Error>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    sqlx::query_as(
+        "SELECT todo_history.at, todo_history.todo_id, todos.user_id, todo_history.action
+         FROM todo_history JOIN todos ON todos.id = todo_history.todo_id
+         ORDER BY todo_history.id DESC LIMIT ?",
+    )
+    .bind(limit)
+    .fetch_all(db)
+    .await
+}
+
+/// One entry in the stream of changes to a user's todos.
+#[derive(Debug, sqlx::FromRow)]
+#[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+pub struct Change {
+    pub id: i64,
+    pub todo_id: String,
+    pub action: String,
+}
+
+/// The id of the latest entry for any todo, or 0 when there is none; the
+/// point to follow [`since`] from.
+#[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+pub async fn latest_id<'e, E>(db: E) -> Result<i64, sqlx::Error>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    sqlx::query_scalar("SELECT COALESCE(MAX(id), 0) FROM todo_history")
+        .fetch_one(db)
+        .await
+}
+
+/// Up to `limit` changes to `user_id`'s todos recorded after entry `after`,
+/// oldest first.
+#[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+pub async fn since<'e, E>(
+    db: E,
+    user_id: &str,
+    after: i64,
+    limit: i64,
+) -> Result<Vec<Change>, sqlx::Error>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    sqlx::query_as(
+        "SELECT todo_history.id, todo_history.todo_id, todo_history.action
+         FROM todo_history JOIN todos ON todos.id = todo_history.todo_id
+         WHERE todos.user_id = ? AND todo_history.id > ?
+         ORDER BY todo_history.id LIMIT ?",
+    )
+    .bind(user_id)
+    .bind(after)
+    .bind(limit)
+    .fetch_all(db)
+    .await
+}
@@ -0,0 +1,161 @@
+use std::fmt;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use argon2::password_hash::PasswordHash;
+use chrono::Utc;
+use jsonwebtoken::{errors::ErrorKind, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::UserConfig;
+use crate::config::Secret;
+
+/// Key a debug build signs with when none is configured. Release builds
+/// refuse to start with it, or with none at all.
+pub const DEV_SIGNING_KEY: &str = "insecure-development-signing-key";
+
+/// Token login at `POST /auth/login`, enabled by an `[auth.jwt]` section or
+/// JWT_SIGNING_KEY.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JwtConfig {
+    /// HMAC-SHA256 key tokens are signed with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signing_key: Option<Secret>,
+    /// How long a token is valid after login or refresh.
+    pub ttl_secs: u64,
+    /// Who may log in.
+    pub users: Vec<UserConfig>,
+}
+
+impl Default for JwtConfig {
+    fn default() -> Self {
+        Self {
+            signing_key: None,
+            ttl_secs: 900,
+            users: Vec::new(),
+        }
+    }
+}
+
+/// What a token asserts. Set on the request as an extension once verified.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    /// The user id.
+    pub sub: String,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenError {
+    Expired,
+    /// Malformed, signed with another key, or otherwise not one of ours.
+    Invalid,
+}
+
+impl TokenError {
+    /// Machine-readable `code` in the error body, so clients can tell a
+    /// token worth refreshing or logging in again for from a bad one.
+    pub fn code(self) -> &'static str {
+        match self {
+            TokenError::Expired => "token_expired",
+            TokenError::Invalid => "token_invalid",
+        }
+    }
+
+    pub fn message(self) -> &'static str {
+        match self {
+            TokenError::Expired => "token expired",
+            TokenError::Invalid => "invalid token",
+        }
+    }
+}
+
+/// Issues and verifies HS256 tokens.
+pub struct Tokens {
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    validation: Validation,
+    ttl: Duration,
+    users: Vec<UserConfig>,
+}
+
+impl fmt::Debug for Tokens {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tokens")
+            .field("ttl", &self.ttl)
+            .field("users", &self.users)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Tokens {
+    pub fn new(config: &JwtConfig) -> anyhow::Result<Self> {
+        let key = match config.signing_key.as_ref().map(Secret::expose) {
+            Some(key) if !key.is_empty() && key != DEV_SIGNING_KEY => key,
+            _ if cfg!(debug_assertions) => {
+                tracing::warn!(
+                    "auth.jwt.signing_key is not set; signing tokens with the development key"
+                );
+                DEV_SIGNING_KEY
+            }
+            _ => bail!("auth.jwt.signing_key (JWT_SIGNING_KEY) must be set to a secret value"),
+        };
+        if config.ttl_secs == 0 {
+            bail!("auth.jwt.ttl_secs must be at least 1");
+        }
+        for user in &config.users {
+            PasswordHash::new(user.password_hash.expose()).map_err(|err| {
+                anyhow!(
+                    "invalid auth.jwt.users password_hash for {}: {}",
+                    user.user,
+                    err
+                )
+            })?;
+        }
+        if config.users.is_empty() {
+            tracing::warn!("token login is enabled but auth.jwt.users is empty");
+        }
+
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.leeway = 0;
+        validation.set_required_spec_claims(&["exp", "sub"]);
+
+        Ok(Self {
+            encoding: EncodingKey::from_secret(key.as_bytes()),
+            decoding: DecodingKey::from_secret(key.as_bytes()),
+            validation,
+            ttl: Duration::from_secs(config.ttl_secs),
+            users: config.users.clone(),
+        })
+    }
+
+    pub fn users(&self) -> &[UserConfig] {
+        &self.users
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// A fresh token for `user_id`, valid for the configured TTL.
+    pub fn issue(&self, user_id: &str) -> Result<String, jsonwebtoken::errors::Error> {
+        let now = Utc::now().timestamp();
+        let claims = Claims {
+            sub: user_id.to_string(),
+            iat: now,
+            exp: now + self.ttl.as_secs() as i64,
+        };
+        jsonwebtoken::encode(&Header::new(Algorithm::HS256), &claims, &self.encoding)
+    }
+
+    pub fn verify(&self, token: &str) -> Result<Claims, TokenError> {
+        jsonwebtoken::decode::<Claims>(token, &self.decoding, &self.validation)
+            .map(|data| data.claims)
+            .map_err(|err| match err.kind() {
+                ErrorKind::ExpiredSignature => TokenError::Expired,
+                _ => TokenError::Invalid,
+            })
+    }
+}
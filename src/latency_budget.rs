@@ -0,0 +1,101 @@
+//! Per-route latency budgets: a request that takes longer than its route's
+//! budget is logged at `warn`, so slow handlers show up before anyone
+//! complains. Time is measured from routing until the response is ready,
+//! middleware below included, rather than per query.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{MatchedPath, State},
+    http::{HeaderValue, Method, Request},
+    middleware::Next,
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LatencyBudgetConfig {
+    pub enabled: bool,
+    /// The budget of every route not in `routes`.
+    pub default_ms: u64,
+    /// Budgets by route pattern, such as `"/todos/:id"`, or by method and
+    /// pattern, such as `"GET /todos"`, which wins over the pattern alone.
+    pub routes: BTreeMap<String, u64>,
+    /// Report each request's time in a `Server-Timing` header.
+    pub server_timing: bool,
+}
+
+impl Default for LatencyBudgetConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            default_ms: 1000,
+            // Both read or rewrite the whole database file.
+            routes: BTreeMap::from([
+                ("/admin/backup".to_string(), 30_000),
+                ("/admin/vacuum".to_string(), 30_000),
+            ]),
+            server_timing: false,
+        }
+    }
+}
+
+impl LatencyBudgetConfig {
+    /// Whether [`check`] has anything to do.
+    pub fn is_active(&self) -> bool {
+        self.enabled || self.server_timing
+    }
+
+    fn budget(&self, method: &Method, route: Option<&str>) -> Duration {
+        let ms = route
+            .and_then(|route| {
+                self.routes
+                    .get(&format!("{} {}", method, route))
+                    .or_else(|| self.routes.get(route))
+            })
+            .copied()
+            .unwrap_or(self.default_ms);
+        Duration::from_millis(ms)
+    }
+}
+
+/// Times each request against its route's budget, warning with the route
+/// and duration when it goes over, and adds `Server-Timing: app;dur=<ms>`
+/// when `server_timing` is on. Requests matching no route get the default
+/// budget.
+pub async fn check<B>(
+    State(config): State<Arc<LatencyBudgetConfig>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let method = req.method().clone();
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned());
+    let start = Instant::now();
+
+    let mut response = next.run(req).await;
+
+    let elapsed = start.elapsed();
+    let budget = config.budget(&method, route.as_deref());
+    if config.enabled && elapsed > budget {
+        tracing::warn!(
+            method = %method,
+            route = route.as_deref().unwrap_or("unmatched"),
+            duration_ms = elapsed.as_millis() as u64,
+            budget_ms = budget.as_millis() as u64,
+            "request over its latency budget"
+        );
+    }
+    if config.server_timing {
+        let timing = format!("app;dur={:.1}", elapsed.as_secs_f64() * 1000.0);
+        if let Ok(value) = HeaderValue::from_str(&timing) {
+            response.headers_mut().append("server-timing", value);
+        }
+    }
+    response
+}
@@ -0,0 +1,89 @@
+//! Opt-in leniency for loosely-typed clients. With `LENIENT_JSON` set,
+//! boolean fields in request bodies also accept `"true"`/`"false"` and
+//! `1`/`0`; by default they take only JSON booleans.
+
+use std::fmt;
+
+use axum::{extract::State, http::Request, middleware::Next, response::Response};
+use serde::de::{self, Deserializer, Unexpected, Visitor};
+
+tokio::task_local! {
+    static ENABLED: bool;
+}
+
+/// Middleware parsing the request's body leniently or not, as the router
+/// was configured; routers in one process never see each other's setting.
+pub async fn apply<B>(State(enabled): State<bool>, req: Request<B>, next: Next<B>) -> Response {
+    ENABLED.scope(enabled, next.run(req)).await
+}
+
+/// Whether the body being parsed may be lenient; never outside a request.
+pub fn is_enabled() -> bool {
+    ENABLED.try_with(|enabled| *enabled).unwrap_or(false)
+}
+
+struct BoolVisitor {
+    lenient: bool,
+}
+
+impl<'de> Visitor<'de> for BoolVisitor {
+    type Value = Option<bool>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.lenient {
+            f.write_str(r#"a boolean, "true", "false", 1 or 0"#)
+        } else {
+            f.write_str("a boolean (true or false)")
+        }
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<Self::Value, E> {
+        Ok(Some(value))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        match value {
+            "true" if self.lenient => Ok(Some(true)),
+            "false" if self.lenient => Ok(Some(false)),
+            _ => Err(E::invalid_type(Unexpected::Str(value), &self)),
+        }
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+        match value {
+            1 if self.lenient => Ok(Some(true)),
+            0 if self.lenient => Ok(Some(false)),
+            _ => Err(E::invalid_type(Unexpected::Unsigned(value), &self)),
+        }
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+        match u64::try_from(value) {
+            Ok(value) => self.visit_u64(value),
+            Err(_) => Err(E::invalid_type(Unexpected::Signed(value), &self)),
+        }
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+/// An optional boolean field, `null` meaning absent. Use with
+/// `#[serde(default, deserialize_with = "lenient::optional_bool")]`.
+pub fn optional_bool<'de, D>(deserializer: D) -> Result<Option<bool>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_any(BoolVisitor {
+        lenient: is_enabled(),
+    })
+}
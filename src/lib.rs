@@ -0,0 +1,196 @@
+//! A todo list over HTTP, on SQLite. [`app`] builds the router on a pool
+//! from [`init_db`], with the default configuration; the `todo_api` binary
+//! loads its configuration from file, environment and flags and runs
+//! [`server::serve`]. To mount the app inside another router, build an
+//! [`AppState`] and nest [`server::todo_router`] at `server.base_path`.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::Context;
+use axum::{
+    extract::FromRef,
+    http::{header, HeaderMap},
+    Router,
+};
+use sqlx::SqlitePool;
+
+mod access_log;
+mod accounts;
+mod admin;
+mod archive;
+mod auth;
+mod backup;
+mod body_log;
+mod capacity;
+pub mod commands;
+pub mod config;
+mod cors;
+pub mod db;
+mod docs;
+mod error;
+mod extract;
+mod fragments;
+mod frontend;
+mod github;
+#[cfg(feature = "graphql")]
+mod graphql;
+pub mod grpc;
+mod handlers;
+mod health;
+mod history;
+mod jwt;
+mod latency_budget;
+mod lenient;
+mod listener;
+mod live;
+mod load_shed;
+pub mod logging;
+mod maintenance;
+mod markdown;
+mod metrics;
+mod models;
+mod pages;
+mod prefer;
+mod preferences;
+mod pwa;
+mod quotas;
+mod rate_limit;
+pub mod reporting;
+mod request_id;
+pub mod server;
+mod sessions;
+mod shares;
+mod static_dir;
+mod sync;
+mod tags;
+pub mod telemetry;
+#[cfg(feature = "test-routes")]
+mod test_routes;
+mod trailing_slash;
+pub mod version;
+
+use access_log::AccessLog;
+use auth::Authenticator;
+use config::Config;
+use db::ReadDb;
+use health::{Readiness, StartedAt};
+use live::Live;
+use maintenance::Maintenance;
+use metrics::Metrics;
+use quotas::Quotas;
+
+pub type Db = SqlitePool;
+
+/// What every handler and middleware shares: the pools, the configuration
+/// and the state built from it.
+#[derive(Clone, FromRef)]
+pub struct AppState {
+    db: Db,
+    read_db: ReadDb,
+    config: Arc<Config>,
+    started_at: StartedAt,
+    readiness: Readiness,
+    metrics: Arc<Metrics>,
+    auth: Arc<Authenticator>,
+    maintenance: Maintenance,
+    quotas: Arc<Quotas>,
+    access_log: Option<Arc<AccessLog>>,
+    live: Live,
+}
+
+impl AppState {
+    /// The state for `config` on `db`, which must already be migrated: it
+    /// opens the replica and the access log when configured, and creates
+    /// the users the configured credentials name.
+    pub async fn new(config: Config, db: SqlitePool) -> anyhow::Result<Self> {
+        let auth = Authenticator::new(
+            &config.auth,
+            &config.admin,
+            config.rate_limit.trust_forwarded_for,
+            !config.server.disable_ui,
+            db.clone(),
+        )?;
+        let read_db = match &config.database.read_path {
+            Some(path) => {
+                let replica = db::connect_replica(&config.database, path)
+                    .await
+                    .with_context(|| format!("failed to open the replica at {}", path.display()))?;
+                tracing::info!("reading todos from the replica at {}", path.display());
+                ReadDb(replica)
+            }
+            None => ReadDb(db.clone()),
+        };
+
+        db::ensure_users(&db, &auth.user_ids()).await?;
+        for user in &config.admin.users {
+            if user == db::DEFAULT_USER_ID {
+                anyhow::bail!("admin.users cannot include the default user");
+            }
+            if !db::set_role(&db, user, accounts::Role::Admin).await? {
+                tracing::warn!(%user, "admin.users names no user yet; they become admin on registering");
+            }
+        }
+        let access_log =
+            AccessLog::start(&config.access_log, config.rate_limit.trust_forwarded_for)?;
+
+        Ok(Self {
+            db,
+            read_db,
+            metrics: Arc::new(Metrics::new(&config.metrics)),
+            quotas: Arc::new(Quotas::new(config.quotas.clone())),
+            config: Arc::new(config),
+            started_at: StartedAt(Instant::now()),
+            readiness: Readiness::default(),
+            auth: Arc::new(auth),
+            maintenance: Maintenance::default(),
+            access_log: access_log.map(Arc::new),
+            live: Live::default(),
+        })
+    }
+}
+
+/// The app as `todo_api serve` would run it with no configuration at all,
+/// on `pool`, ready for `into_make_service` or for requests sent straight
+/// to it as a `tower::Service`.
+///
+/// # Panics
+///
+/// If the app cannot be built on `pool`, such as when it was not opened by
+/// [`init_db`] and has no schema.
+pub async fn app(pool: SqlitePool) -> Router {
+    server::build(Config::default(), pool)
+        .await
+        .expect("failed to build the app")
+        .app
+}
+
+/// Opens a pool on a `sqlite:` URL, `sqlite::memory:` included, and brings
+/// its schema up to date.
+pub async fn init_db(url: &str) -> anyhow::Result<SqlitePool> {
+    let db = db::connect_url(url).await?;
+    db::migrate(&db).await?;
+    Ok(db)
+}
+
+/// True when the first of `media`, `application/json` or `*/*` listed in
+/// `Accept` is `media`. JSON stays the default.
+fn prefers(headers: &HeaderMap, media: &str) -> bool {
+    let Some(accept) = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+
+    accept
+        .split(',')
+        .map(|listed| listed.split(';').next().unwrap_or("").trim())
+        .find(|listed| *listed == media || matches!(*listed, "application/json" | "*/*"))
+        == Some(media)
+}
+
+/// Escapes text for use in HTML content or a quoted attribute.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,237 @@
+use std::fmt;
+use std::fs::{self, DirBuilder, Permissions};
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use anyhow::{bail, Context as _};
+use hyper::server::accept::Accept;
+use socket2::{Domain, Socket, Type};
+use tokio::net::{UnixListener, UnixStream};
+
+use crate::config::{FileMode, ServerConfig};
+
+/// A socket the server accepts connections on.
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixAccept),
+}
+
+impl fmt::Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Listener::Tcp(listener) => match listener.local_addr() {
+                Ok(addr) => write!(f, "http://{}", addr),
+                Err(_) => f.write_str("tcp"),
+            },
+            // The address a socket was bound at may since have moved.
+            Listener::Unix(UnixAccept {
+                path: Some(path), ..
+            }) => write!(f, "unix:{}", path.display()),
+            Listener::Unix(accept) => match accept.listener.local_addr() {
+                Ok(addr) => match addr.as_pathname() {
+                    Some(path) => write!(f, "unix:{}", path.display()),
+                    None => f.write_str("unix"),
+                },
+                Err(_) => f.write_str("unix"),
+            },
+        }
+    }
+}
+
+/// Listeners inherited through systemd socket activation when there are
+/// any, otherwise the TCP address and/or Unix socket from `config`.
+pub fn open(config: &ServerConfig) -> anyhow::Result<Vec<Listener>> {
+    let inherited = inherited()?;
+    if !inherited.is_empty() {
+        tracing::info!(
+            count = inherited.len(),
+            "socket activation: adopting inherited listeners"
+        );
+        return Ok(inherited);
+    }
+
+    let mut listeners = Vec::new();
+    if config.listen_tcp() {
+        if config.addrs.is_empty() {
+            bail!("no TCP address to listen on; set BIND_ADDR or --addr");
+        }
+        // Try every address before giving up, so one startup error lists
+        // all that failed rather than only the first.
+        let mut failed = Vec::new();
+        for addr in &config.addrs {
+            match bind_tcp(*addr, &config.addrs) {
+                Ok(listener) => listeners.push(Listener::Tcp(listener)),
+                Err(err) => failed.push(format!("{}: {}", addr, err)),
+            }
+        }
+        if !failed.is_empty() {
+            bail!("failed to bind {}", failed.join("; "));
+        }
+    }
+    if let Some(path) = &config.unix_socket {
+        listeners.push(Listener::Unix(UnixAccept::bind(
+            path,
+            config.unix_socket_mode,
+        )?));
+    }
+    Ok(listeners)
+}
+
+/// Binds one TCP address. An IPv6 wildcard such as `[::]:3000` is dual-stack
+/// and takes IPv4 connections too, whatever the host's `bindv6only`
+/// default, unless an IPv4 address on the same port is also configured; it
+/// is then made IPv6-only so both can bind.
+fn bind_tcp(addr: SocketAddr, all: &[SocketAddr]) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+    if addr.is_ipv6() {
+        let v4_on_same_port = all
+            .iter()
+            .any(|other| other.is_ipv4() && other.port() == addr.port());
+        socket.set_only_v6(v4_on_same_port)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    Ok(socket.into())
+}
+
+/// Takes every stream socket passed via `LISTEN_FDS`/`LISTEN_PID`.
+#[cfg(feature = "systemd")]
+fn inherited() -> anyhow::Result<Vec<Listener>> {
+    let mut fds = listenfd::ListenFd::from_env();
+    let mut listeners = Vec::new();
+
+    for idx in 0..fds.len() {
+        if let Ok(Some(listener)) = fds.take_tcp_listener(idx) {
+            listeners.push(Listener::Tcp(listener));
+        } else if let Some(listener) = fds.take_unix_listener(idx).with_context(|| {
+            format!(
+                "inherited fd {} is not a TCP or Unix stream socket",
+                idx + 3
+            )
+        })? {
+            listener.set_nonblocking(true)?;
+            listeners.push(Listener::Unix(UnixAccept {
+                listener: UnixListener::from_std(listener)?,
+                path: None,
+            }));
+        }
+    }
+    Ok(listeners)
+}
+
+#[cfg(not(feature = "systemd"))]
+fn inherited() -> anyhow::Result<Vec<Listener>> {
+    if std::env::var_os("LISTEN_FDS").is_some() {
+        tracing::warn!(
+            "LISTEN_FDS is set but this build lacks the systemd feature; binding normally"
+        );
+    }
+    Ok(Vec::new())
+}
+
+/// Feeds connections from a Unix domain socket to hyper. A socket file this
+/// process created is unlinked when this is dropped, i.e. once the server
+/// has shut down; an inherited one belongs to systemd and is left alone.
+pub struct UnixAccept {
+    listener: UnixListener,
+    path: Option<PathBuf>,
+}
+
+impl UnixAccept {
+    /// Binds `path` with the given permissions, first removing a socket file
+    /// left behind by a crashed process. Refuses to touch a path that is not
+    /// a socket or that another process is still listening on. The socket
+    /// is bound in a private directory next to `path` and moved into place
+    /// once its permissions are set, so it is never reachable with looser
+    /// ones.
+    pub fn bind(path: &Path, mode: FileMode) -> anyhow::Result<Self> {
+        remove_stale(path)?;
+
+        let name = path
+            .file_name()
+            .with_context(|| format!("{} is not a file path", path.display()))?;
+        let mut private = name.to_os_string();
+        private.push(format!(".{}.tmp", std::process::id()));
+        let private = path.with_file_name(private);
+        DirBuilder::new()
+            .mode(0o700)
+            .create(&private)
+            .with_context(|| format!("failed to create {}", private.display()))?;
+        let bound = bind_privately(&private.join(name), path, mode);
+        if let Err(err) = fs::remove_dir(&private) {
+            tracing::warn!(path = %private.display(), error = %err, "failed to remove directory");
+        }
+
+        Ok(Self {
+            listener: bound?,
+            path: Some(path.to_path_buf()),
+        })
+    }
+}
+
+/// Binds `staging`, sets its permissions and renames it to `path`; the
+/// socket file is removed again on failure.
+fn bind_privately(staging: &Path, path: &Path, mode: FileMode) -> anyhow::Result<UnixListener> {
+    let listener = UnixListener::bind(staging)
+        .with_context(|| format!("failed to bind {}", path.display()))?;
+    let placed = fs::set_permissions(staging, Permissions::from_mode(mode.0))
+        .with_context(|| format!("failed to set permissions on {}", path.display()))
+        .and_then(|()| {
+            fs::rename(staging, path)
+                .with_context(|| format!("failed to move the socket to {}", path.display()))
+        });
+    if let Err(err) = placed {
+        let _ = fs::remove_file(staging);
+        return Err(err);
+    }
+    Ok(listener)
+}
+
+impl Accept for UnixAccept {
+    type Conn = UnixStream;
+    type Error = io::Error;
+
+    fn poll_accept(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        let (stream, _) = ready!(self.listener.poll_accept(cx))?;
+        Poll::Ready(Some(Ok(stream)))
+    }
+}
+
+impl Drop for UnixAccept {
+    fn drop(&mut self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if let Err(err) = fs::remove_file(path) {
+            tracing::warn!(path = %path.display(), error = %err, "failed to remove socket");
+        }
+    }
+}
+
+fn remove_stale(path: &Path) -> anyhow::Result<()> {
+    let meta = match fs::symlink_metadata(path) {
+        Ok(meta) => meta,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => {
+            return Err(err).with_context(|| format!("failed to inspect {}", path.display()))
+        }
+    };
+
+    if !meta.file_type().is_socket() {
+        bail!("{} exists and is not a socket", path.display());
+    }
+    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+        bail!("{} is in use by another process", path.display());
+    }
+
+    tracing::warn!(path = %path.display(), "removing stale socket");
+    fs::remove_file(path).with_context(|| format!("failed to remove {}", path.display()))
+}
@@ -0,0 +1,176 @@
+//! Changes to todos pushed to clients as they are committed: over a
+//! WebSocket on `GET /ws`, or as Server-Sent Events on
+//! `GET /todos/events`. Handlers publish to a bounded broadcast channel,
+//! which never waits on a client; one too slow to keep up is counted and
+//! disconnected, and reconnects to catch up. The latest events are also
+//! kept, numbered, for SSE clients to replay after reconnecting.
+
+use std::collections::VecDeque;
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::json;
+use tokio::sync::{broadcast, watch};
+use tokio_util::sync::CancellationToken;
+
+use crate::models::TodoResponse;
+
+pub mod sse;
+pub mod ws;
+
+/// Events a client may fall behind by before it is disconnected.
+const CHANNEL_CAPACITY: usize = 256;
+/// Events kept for SSE clients to replay with `Last-Event-ID`.
+const REPLAY_CAPACITY: usize = 256;
+
+/// Clients disconnected for falling more than [`CHANNEL_CAPACITY`] events
+/// behind.
+pub static LAGGED_CLIENTS: AtomicU64 = AtomicU64::new(0);
+
+/// The changes fanned out to clients, and what [`server::serve`] needs to
+/// close their connections on shutdown.
+///
+/// [`server::serve`]: crate::server::serve
+#[derive(Clone)]
+pub struct Live(Arc<Inner>);
+
+struct Inner {
+    events: broadcast::Sender<Arc<Published>>,
+    recent: Mutex<Recent>,
+    closing: CancellationToken,
+    open: watch::Sender<usize>,
+}
+
+/// The latest events, oldest first, and the id of the last one published.
+#[derive(Default)]
+struct Recent {
+    events: VecDeque<Arc<Published>>,
+    last_id: u64,
+}
+
+struct Published {
+    /// Counts up from 1 with every event, for all users together.
+    id: u64,
+    user: String,
+    kind: &'static str,
+    json: String,
+}
+
+/// What [`Live::follow`] found to replay after an event id.
+enum Replay {
+    Events(Vec<Arc<Published>>),
+    /// The event is no longer kept, or is from before a restart.
+    Gap,
+}
+
+impl Default for Live {
+    fn default() -> Self {
+        Self(Arc::new(Inner {
+            events: broadcast::channel(CHANNEL_CAPACITY).0,
+            recent: Mutex::default(),
+            closing: CancellationToken::new(),
+            open: watch::channel(0).0,
+        }))
+    }
+}
+
+impl Live {
+    pub fn created(&self, user: &str, todo: &TodoResponse) {
+        self.publish(user, "created", &todo.id, Some(todo));
+    }
+
+    pub fn updated(&self, user: &str, todo: &TodoResponse) {
+        self.publish(user, "updated", &todo.id, Some(todo));
+    }
+
+    /// A change to `completed` alone.
+    pub fn toggled(&self, user: &str, todo: &TodoResponse) {
+        self.publish(user, "toggled", &todo.id, Some(todo));
+    }
+
+    pub fn deleted(&self, user: &str, id: &str) {
+        self.publish(user, "deleted", id, None);
+    }
+
+    fn publish(&self, user: &str, kind: &'static str, id: &str, todo: Option<&TodoResponse>) {
+        let mut event = json!({ "type": kind, "id": id });
+        if let Some(todo) = todo {
+            event["todo"] = json!(todo);
+        }
+        // Numbered, kept and sent under one lock, so ids reach every
+        // receiver in order and `follow` sees each event exactly once.
+        let mut recent = self.0.recent.lock().unwrap();
+        recent.last_id += 1;
+        let published = Arc::new(Published {
+            id: recent.last_id,
+            user: user.to_string(),
+            kind,
+            json: event.to_string(),
+        });
+        if recent.events.len() == REPLAY_CAPACITY {
+            recent.events.pop_front();
+        }
+        recent.events.push_back(Arc::clone(&published));
+        // Fails only with no receivers, which is nobody's problem.
+        let _ = self.0.events.send(published);
+    }
+
+    /// A receiver for the events from now on, and, given the id of the
+    /// last event seen, the kept events after it.
+    fn follow(&self, after: Option<u64>) -> (Replay, broadcast::Receiver<Arc<Published>>) {
+        let recent = self.0.recent.lock().unwrap();
+        let receiver = self.0.events.subscribe();
+        let Some(after) = after else {
+            return (Replay::Events(Vec::new()), receiver);
+        };
+        let oldest = recent
+            .events
+            .front()
+            .map_or(recent.last_id + 1, |event| event.id);
+        if after > recent.last_id || after + 1 < oldest {
+            return (Replay::Gap, receiver);
+        }
+        let missed = recent
+            .events
+            .iter()
+            .filter(|event| event.id > after)
+            .cloned()
+            .collect();
+        (Replay::Events(missed), receiver)
+    }
+
+    /// Has every WebSocket and event stream end.
+    pub fn close(&self) {
+        self.0.closing.cancel();
+    }
+
+    /// Waits up to `timeout` for the WebSockets to finish closing; event
+    /// streams are ordinary responses, which the servers wait for.
+    pub async fn closed(&self, timeout: Duration) {
+        let mut open = self.0.open.subscribe();
+        let all_closed = open.wait_for(|open| *open == 0);
+        if tokio::time::timeout(timeout, all_closed).await.is_err() {
+            tracing::warn!(
+                open = *self.0.open.borrow(),
+                "WebSockets still open at shutdown"
+            );
+        }
+    }
+}
+
+/// Counts one open WebSocket for as long as it lives.
+struct OpenSocket(Live);
+
+impl OpenSocket {
+    fn new(live: &Live) -> Self {
+        live.0.open.send_modify(|open| *open += 1);
+        Self(live.clone())
+    }
+}
+
+impl Drop for OpenSocket {
+    fn drop(&mut self) {
+        self.0 .0.open.send_modify(|open| *open -= 1);
+    }
+}
@@ -0,0 +1,108 @@
+//! `GET /todos/events`: the events of [`Live`] as Server-Sent Events, for
+//! clients that would rather not speak WebSocket.
+
+use std::convert::Infallible;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+
+use super::{Live, Published, Replay, LAGGED_CLIENTS};
+use crate::auth::UserId;
+
+/// Comments sent this often on a quiet stream keep proxies from timing it
+/// out.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+/// Events held for a stream between the channel and the connection.
+const STREAM_BUFFER: usize = 16;
+
+/// `GET /todos/events`: `created`, `updated`, `toggled` and `deleted`
+/// events for the user's todos, each with the message `GET /ws` would send
+/// as its data and a numbered id. Sent back in `Last-Event-ID`, as browsers
+/// do on reconnecting, the id replays the user's events since, while they
+/// are still kept; otherwise the stream starts with a `reset` event, after
+/// which the client should read the list afresh.
+#[utoipa::path(
+    get,
+    path = "/todos/events",
+    tag = "todos",
+    params(("Last-Event-ID" = Option<u64>, Header, description = "The id of the last event seen")),
+    responses(
+        (status = 200, description = "An event per change, from now on", content_type = "text/event-stream"),
+    )
+)]
+pub async fn events(
+    State(live): State<Live>,
+    UserId(user): UserId,
+    headers: HeaderMap,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let after = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().parse().unwrap_or(u64::MAX));
+    let (replay, mut receiver) = live.follow(after);
+
+    let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+    tokio::spawn(async move {
+        let closing = &live.0.closing;
+        // False once the client has gone or the server is closing.
+        let send = |event: Event| async {
+            tokio::select! {
+                _ = closing.cancelled() => false,
+                sent = tx.send(event) => sent.is_ok(),
+            }
+        };
+
+        let missed = match replay {
+            Replay::Events(missed) => missed,
+            Replay::Gap => {
+                if !send(Event::default().event("reset").data("{}")).await {
+                    return;
+                }
+                Vec::new()
+            }
+        };
+        for published in missed.iter().filter(|published| published.user == user) {
+            if !send(event(published)).await {
+                return;
+            }
+        }
+
+        // A client slow to read holds this task up, never the publishers:
+        // the channel goes on without it, and it finds out it lagged.
+        loop {
+            let published = tokio::select! {
+                _ = closing.cancelled() => return,
+                _ = tx.closed() => return,
+                received = receiver.recv() => match received {
+                    Ok(published) => published,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        LAGGED_CLIENTS.fetch_add(1, Ordering::Relaxed);
+                        tracing::warn!(%user, missed, "ending an event stream that fell behind");
+                        return;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                },
+            };
+            if published.user == user && !send(event(&published)).await {
+                return;
+            }
+        }
+    });
+
+    let stream = ReceiverStream::new(rx).map(Ok);
+    Sse::new(stream).keep_alive(KeepAlive::new().interval(HEARTBEAT_INTERVAL))
+}
+
+fn event(published: &Published) -> Event {
+    Event::default()
+        .id(published.id.to_string())
+        .event(published.kind)
+        .data(&published.json)
+}
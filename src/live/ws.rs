@@ -0,0 +1,441 @@
+//! `GET /ws`: a WebSocket carrying the events of [`Live`]. A client too
+//! slow to keep up is closed with `1013`. The frames are written here
+//! rather than by a WebSocket crate, since clients only ever send short
+//! text messages.
+
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{header, HeaderMap, HeaderValue, Request, StatusCode},
+    response::{IntoResponse, Response},
+};
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::json;
+use sha1::{Digest, Sha1};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{broadcast, mpsc};
+
+use super::{Live, OpenSocket, Published, LAGGED_CLIENTS};
+use crate::auth::UserId;
+use crate::db::{self, TodoFilter};
+use crate::error::ApiError;
+use crate::models::TodoResponse;
+use crate::Db;
+
+/// How often an idle socket is pinged.
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+/// A client heard nothing from, not even a pong, for this long is gone.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+/// A frame that cannot be written in this long means a stuck client.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+/// The longest message a client may send; they only ask for snapshots.
+const MAX_MESSAGE_LEN: usize = 4096;
+/// Appended to `Sec-WebSocket-Key` to derive `Sec-WebSocket-Accept`.
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ConnectParams {
+    /// Send a snapshot of the list before any event.
+    #[serde(default)]
+    snapshot: bool,
+}
+
+/// `GET /ws`: upgrades to a WebSocket carrying `created`, `updated`,
+/// `toggled` and `deleted` events for the user's todos, each a JSON text
+/// message with the todo's `id` and, but for `deleted`, the `todo`. A
+/// client that sends `{"type":"snapshot"}`, or connects with
+/// `?snapshot=true`, gets `{"type":"snapshot","todos":[...]}`, the list as
+/// `GET /todos` has it, with no event missed in between.
+pub async fn connect(
+    State(live): State<Live>,
+    State(db): State<Db>,
+    UserId(user): UserId,
+    Query(params): Query<ConnectParams>,
+    mut req: Request<Body>,
+) -> Response {
+    let headers = req.headers();
+    if !has_token(headers, header::CONNECTION, "upgrade")
+        || !has_token(headers, header::UPGRADE, "websocket")
+    {
+        return ApiError::new(StatusCode::BAD_REQUEST, "expected a WebSocket upgrade")
+            .into_response();
+    }
+    if headers
+        .get(header::SEC_WEBSOCKET_VERSION)
+        .is_none_or(|version| version.as_bytes() != b"13")
+    {
+        let err = ApiError::new(StatusCode::UPGRADE_REQUIRED, "only WebSocket version 13");
+        return ([(header::SEC_WEBSOCKET_VERSION, "13")], err).into_response();
+    }
+    let Some(key) = headers.get(header::SEC_WEBSOCKET_KEY) else {
+        return ApiError::validation("missing Sec-WebSocket-Key").into_response();
+    };
+    // Browsers send cookies along with a cross-site WebSocket too.
+    if let Some(origin) = headers.get(header::ORIGIN).and_then(|v| v.to_str().ok()) {
+        let host = headers.get(header::HOST).and_then(|v| v.to_str().ok());
+        let origin_host = origin.split_once("://").map(|(_, host)| host);
+        if origin_host.is_none() || origin_host != host {
+            tracing::warn!(%origin, "rejected cross-site WebSocket");
+            return ApiError::new(StatusCode::FORBIDDEN, "cross-site WebSocket").into_response();
+        }
+    }
+    let accept = accept_key(key.as_bytes());
+
+    // Subscribed before answering, so a snapshot misses nothing.
+    let events = live.0.events.subscribe();
+    let open = OpenSocket::new(&live);
+    let upgrade = hyper::upgrade::on(&mut req);
+    tokio::spawn(async move {
+        let _open = open;
+        match upgrade.await {
+            Ok(socket) => {
+                let session = Session {
+                    live,
+                    db,
+                    user,
+                    events,
+                };
+                session.run(socket, params.snapshot).await;
+            }
+            Err(err) => tracing::debug!(error = %err, "WebSocket upgrade failed"),
+        }
+    });
+
+    (
+        StatusCode::SWITCHING_PROTOCOLS,
+        [
+            (header::CONNECTION, HeaderValue::from_static("upgrade")),
+            (header::UPGRADE, HeaderValue::from_static("websocket")),
+            (header::SEC_WEBSOCKET_ACCEPT, accept),
+        ],
+    )
+        .into_response()
+}
+
+/// Whether the comma-separated header lists `token`, ignoring case.
+fn has_token(headers: &HeaderMap, name: header::HeaderName, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|listed| listed.trim().eq_ignore_ascii_case(token))
+}
+
+fn accept_key(key: &[u8]) -> HeaderValue {
+    let mut hash = Sha1::new();
+    hash.update(key);
+    hash.update(ACCEPT_GUID.as_bytes());
+    let accept = base64::engine::general_purpose::STANDARD.encode(hash.finalize());
+    HeaderValue::from_str(&accept).expect("base64 is a valid header value")
+}
+
+/// Close codes, from RFC 6455.
+mod close {
+    pub const NORMAL: u16 = 1000;
+    pub const GOING_AWAY: u16 = 1001;
+    pub const PROTOCOL_ERROR: u16 = 1002;
+    pub const UNSUPPORTED: u16 = 1003;
+    pub const TOO_BIG: u16 = 1009;
+    pub const INTERNAL_ERROR: u16 = 1011;
+    pub const TRY_AGAIN_LATER: u16 = 1013;
+}
+
+mod opcode {
+    pub const CONTINUATION: u8 = 0x0;
+    pub const TEXT: u8 = 0x1;
+    pub const BINARY: u8 = 0x2;
+    pub const CLOSE: u8 = 0x8;
+    pub const PING: u8 = 0x9;
+    pub const PONG: u8 = 0xA;
+}
+
+/// What the client sent, as read by [`read_messages`].
+enum Incoming {
+    Text(String),
+    Ping(Vec<u8>),
+    Pong,
+    Close,
+    /// A frame that ends the connection with this close code.
+    Invalid(u16),
+}
+
+struct Session {
+    live: Live,
+    db: Db,
+    user: String,
+    events: broadcast::Receiver<Arc<Published>>,
+}
+
+impl Session {
+    async fn run<S>(mut self, socket: S, snapshot: bool)
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (reader, mut writer) = tokio::io::split(socket);
+        let (incoming_tx, mut incoming) = mpsc::channel(8);
+        let reading = tokio::spawn(read_messages(reader, incoming_tx));
+
+        let code = match self.talk(&mut writer, &mut incoming, snapshot).await {
+            Ok(Some(code)) => code,
+            // The client closed first, or went away.
+            Ok(None) | Err(_) => {
+                reading.abort();
+                return;
+            }
+        };
+        // Waits briefly for the client's own close, so it hears ours before
+        // the connection drops.
+        if send(&mut writer, opcode::CLOSE, &code.to_be_bytes())
+            .await
+            .is_ok()
+        {
+            let answered = async {
+                while let Some(message) = incoming.recv().await {
+                    if matches!(message, Incoming::Close) {
+                        break;
+                    }
+                }
+            };
+            let _ = tokio::time::timeout(WRITE_TIMEOUT, answered).await;
+        }
+        let _ = writer.shutdown().await;
+        reading.abort();
+    }
+
+    /// Relays events until the socket should close with the code returned,
+    /// or `None` once the client has closed or gone.
+    async fn talk<W>(
+        &mut self,
+        writer: &mut W,
+        incoming: &mut mpsc::Receiver<Incoming>,
+        snapshot: bool,
+    ) -> std::io::Result<Option<u16>>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        if snapshot {
+            if let Err(code) = self.send_snapshot(writer).await? {
+                return Ok(Some(code));
+            }
+        }
+        let mut ping = tokio::time::interval(PING_INTERVAL);
+        ping.tick().await;
+        let mut heard = Instant::now();
+
+        loop {
+            tokio::select! {
+                _ = self.live.0.closing.cancelled() => return Ok(Some(close::GOING_AWAY)),
+                message = incoming.recv() => {
+                    heard = Instant::now();
+                    match message {
+                        Some(Incoming::Text(text)) if wants_snapshot(&text) => {
+                            if let Err(code) = self.send_snapshot(writer).await? {
+                                return Ok(Some(code));
+                            }
+                        }
+                        Some(Incoming::Text(_)) => return Ok(Some(close::UNSUPPORTED)),
+                        Some(Incoming::Ping(data)) => send(writer, opcode::PONG, &data).await?,
+                        Some(Incoming::Pong) => {}
+                        Some(Incoming::Close) => {
+                            send(writer, opcode::CLOSE, &close::NORMAL.to_be_bytes()).await?;
+                            return Ok(None);
+                        }
+                        Some(Incoming::Invalid(code)) => return Ok(Some(code)),
+                        None => return Ok(None),
+                    }
+                }
+                event = self.events.recv() => match event {
+                    Ok(event) if event.user == self.user => {
+                        send(writer, opcode::TEXT, event.json.as_bytes()).await?;
+                    }
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        LAGGED_CLIENTS.fetch_add(1, Ordering::Relaxed);
+                        tracing::warn!(user = %self.user, missed, "disconnecting a WebSocket that fell behind");
+                        return Ok(Some(close::TRY_AGAIN_LATER));
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return Ok(Some(close::GOING_AWAY)),
+                },
+                _ = ping.tick() => {
+                    if heard.elapsed() > IDLE_TIMEOUT {
+                        return Ok(Some(close::GOING_AWAY));
+                    }
+                    send(writer, opcode::PING, b"").await?;
+                }
+            }
+        }
+    }
+
+    /// Sends the user's list, or hands back the close code for a database
+    /// that could not be read.
+    async fn send_snapshot<W>(&self, writer: &mut W) -> std::io::Result<Result<(), u16>>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let todos = match db::list_todos(&self.db, &self.user, &TodoFilter::default()).await {
+            Ok(todos) => todos,
+            Err(err) => {
+                tracing::error!(error = %err, "failed to read a WebSocket snapshot");
+                return Ok(Err(close::INTERNAL_ERROR));
+            }
+        };
+        let todos: Vec<TodoResponse> = todos.into_iter().map(TodoResponse::from).collect();
+        let snapshot = json!({ "type": "snapshot", "todos": todos }).to_string();
+        send(writer, opcode::TEXT, snapshot.as_bytes()).await?;
+        Ok(Ok(()))
+    }
+}
+
+/// `{"type":"snapshot"}`, or plain `snapshot`.
+fn wants_snapshot(text: &str) -> bool {
+    #[derive(Deserialize)]
+    struct Request {
+        r#type: String,
+    }
+    text.trim() == "snapshot"
+        || serde_json::from_str::<Request>(text).is_ok_and(|request| request.r#type == "snapshot")
+}
+
+/// Writes one unmasked frame, as a server must.
+async fn send<W>(writer: &mut W, opcode: u8, payload: &[u8]) -> std::io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x80 | opcode);
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    let write = async {
+        writer.write_all(&frame).await?;
+        writer.flush().await
+    };
+    tokio::time::timeout(WRITE_TIMEOUT, write)
+        .await
+        .map_err(|_| std::io::ErrorKind::TimedOut)?
+}
+
+/// Reads the client's frames into messages until the connection ends or a
+/// frame breaks the protocol.
+async fn read_messages<R>(mut reader: R, messages: mpsc::Sender<Incoming>)
+where
+    R: AsyncRead + Unpin,
+{
+    // A text message arriving in fragments, so far.
+    let mut partial: Option<Vec<u8>> = None;
+    loop {
+        let message = match read_frame(&mut reader).await {
+            Ok(Frame {
+                fin,
+                opcode,
+                payload,
+            }) => match opcode {
+                opcode::TEXT | opcode::CONTINUATION => {
+                    let text = match (opcode, partial.take()) {
+                        (opcode::TEXT, None) => Some(Vec::new()),
+                        (opcode::CONTINUATION, Some(text)) => Some(text),
+                        _ => None,
+                    };
+                    match text {
+                        None => Incoming::Invalid(close::PROTOCOL_ERROR),
+                        Some(text) if text.len() + payload.len() > MAX_MESSAGE_LEN => {
+                            Incoming::Invalid(close::TOO_BIG)
+                        }
+                        Some(mut text) => {
+                            text.extend_from_slice(&payload);
+                            if !fin {
+                                partial = Some(text);
+                                continue;
+                            }
+                            match String::from_utf8(text) {
+                                Ok(text) => Incoming::Text(text),
+                                Err(_) => Incoming::Invalid(close::PROTOCOL_ERROR),
+                            }
+                        }
+                    }
+                }
+                opcode::BINARY => Incoming::Invalid(close::UNSUPPORTED),
+                opcode::PING => Incoming::Ping(payload),
+                opcode::PONG => Incoming::Pong,
+                opcode::CLOSE => Incoming::Close,
+                _ => Incoming::Invalid(close::PROTOCOL_ERROR),
+            },
+            Err(FrameError::TooBig) => Incoming::Invalid(close::TOO_BIG),
+            Err(FrameError::Unmasked) => Incoming::Invalid(close::PROTOCOL_ERROR),
+            Err(FrameError::Io(err)) => {
+                tracing::debug!(error = %err, "WebSocket connection ended");
+                return;
+            }
+        };
+        let last = matches!(message, Incoming::Close | Incoming::Invalid(_));
+        if messages.send(message).await.is_err() || last {
+            return;
+        }
+    }
+}
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+enum FrameError {
+    Io(std::io::Error),
+    TooBig,
+    Unmasked,
+}
+
+impl From<std::io::Error> for FrameError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+async fn read_frame<R>(reader: &mut R) -> Result<Frame, FrameError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut head = [0; 2];
+    reader.read_exact(&mut head).await?;
+    let fin = head[0] & 0x80 != 0;
+    let opcode = head[0] & 0x0F;
+    if head[1] & 0x80 == 0 {
+        return Err(FrameError::Unmasked);
+    }
+    let len = match head[1] & 0x7F {
+        126 => reader.read_u16().await? as u64,
+        127 => reader.read_u64().await?,
+        len => len as u64,
+    };
+    if len > MAX_MESSAGE_LEN as u64 {
+        return Err(FrameError::TooBig);
+    }
+    let mut mask = [0; 4];
+    reader.read_exact(&mut mask).await?;
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload).await?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok(Frame {
+        fin,
+        opcode,
+        payload,
+    })
+}
@@ -0,0 +1,64 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use axum::{
+    http::{HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use tower::layer::util::{Identity, Stack};
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::load_shed::LoadShedLayer;
+use tower::ServiceBuilder;
+
+use crate::error::ApiError;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoadShedConfig {
+    pub enabled: bool,
+    /// Requests handled at once; any beyond this are rejected immediately
+    /// rather than queued.
+    pub max_concurrent_requests: usize,
+    pub retry_after_secs: u64,
+}
+
+impl Default for LoadShedConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_concurrent_requests: 64,
+            retry_after_secs: 1,
+        }
+    }
+}
+
+/// Requests rejected since startup.
+pub static SHED_REQUESTS: AtomicU64 = AtomicU64::new(0);
+
+pub type Layer = ServiceBuilder<Stack<GlobalConcurrencyLimitLayer, Stack<LoadShedLayer, Identity>>>;
+
+/// One concurrency ceiling shared by every route the layer is applied to,
+/// with `LoadShed` turning "no permit free" into an immediate
+/// `Overloaded` error. `None` when shedding is disabled.
+pub fn layer(config: &LoadShedConfig) -> Option<Layer> {
+    config.enabled.then(|| {
+        ServiceBuilder::new()
+            .load_shed()
+            .layer(GlobalConcurrencyLimitLayer::new(
+                config.max_concurrent_requests,
+            ))
+    })
+}
+
+/// `503` with `Retry-After` for a shed request.
+pub fn overloaded(retry_after_secs: u64) -> Response {
+    let shed = SHED_REQUESTS.fetch_add(1, Ordering::Relaxed) + 1;
+    tracing::warn!(shed_total = shed, "server overloaded, request shed");
+
+    let mut response =
+        ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "server overloaded").into_response();
+    response
+        .headers_mut()
+        .insert("retry-after", HeaderValue::from(retry_after_secs));
+    response
+}
@@ -0,0 +1,195 @@
+use std::backtrace::Backtrace;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::fmt::format::{JsonFields, Writer};
+use tracing_subscriber::fmt::time::{FormatTime, SystemTime};
+use tracing_subscriber::fmt::writer::{BoxMakeWriter, MakeWriter};
+use tracing_subscriber::fmt::{self, FmtContext, FormatEvent, FormattedFields};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+use crate::telemetry;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[serde(alias = "text")]
+    #[value(alias = "text")]
+    Pretty,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "pretty" | "text" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            _ => Err("expected pretty or json".to_string()),
+        }
+    }
+}
+
+impl LogFormat {
+    /// Pretty output for local debug builds, JSON for release builds.
+    pub fn default_for_build() -> Self {
+        if cfg!(debug_assertions) {
+            LogFormat::Pretty
+        } else {
+            LogFormat::Json
+        }
+    }
+}
+
+/// Installs the global subscriber. `level` is a `tracing` filter directive;
+/// an invalid one falls back to `info` with a warning. Logs go to stdout
+/// unless `to_stderr` is set. Spans are also exported over OTLP when
+/// [`telemetry::layer`] is configured; `level` does not apply to them.
+pub fn init_tracing(format: LogFormat, level: &str, to_stderr: bool) {
+    let (filter, invalid) = match EnvFilter::try_new(level) {
+        Ok(filter) => (filter, None),
+        Err(err) => (EnvFilter::new("info"), Some(err)),
+    };
+    let writer = if to_stderr {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+    let output = match format {
+        LogFormat::Pretty => fmt::layer().with_writer(writer).boxed(),
+        LogFormat::Json => json_layer(writer).boxed(),
+    };
+    let (export, export_error) = match telemetry::layer() {
+        Ok(layer) => (layer, None),
+        Err(err) => (None, Some(err)),
+    };
+    let exporting = export.is_some();
+
+    tracing_subscriber::registry()
+        .with(export)
+        .with(output.with_filter(filter))
+        .init();
+
+    if let Some(err) = invalid {
+        tracing::warn!(level, error = %err, "invalid log level, using info");
+    }
+    if let Some(err) = export_error {
+        tracing::warn!(error = %err, "failed to start OTLP trace export");
+    } else if exporting {
+        tracing::info!("exporting traces over OTLP");
+    }
+
+    std::panic::set_hook(Box::new(log_panic));
+}
+
+/// The layer behind `LogFormat::Json`: one flat JSON object per line,
+/// written to `writer`.
+pub fn json_layer<S, W>(writer: W) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    fmt::layer()
+        .with_writer(writer)
+        .fmt_fields(JsonFields::new())
+        .event_format(FlatJson)
+}
+
+/// Reports panics through `tracing` instead of bare stderr, so they carry
+/// the current request span and land in the same log stream.
+fn log_panic(info: &std::panic::PanicHookInfo<'_>) {
+    let payload = info.payload();
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("Box<dyn Any>");
+    let location = info
+        .location()
+        .map(|location| location.to_string())
+        .unwrap_or_default();
+
+    tracing::error!(
+        panic = message,
+        location,
+        backtrace = %Backtrace::force_capture(),
+        "panicked"
+    );
+}
+
+/// One JSON object per event. Fields of every enclosing span (root first)
+/// and of the event itself are written as top-level keys, so log shippers
+/// can index `request_id` and friends without digging into nested objects.
+struct FlatJson;
+
+impl<S> FormatEvent<S, JsonFields> for FlatJson
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, JsonFields>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> std::fmt::Result {
+        let meta = event.metadata();
+        let mut object = Map::new();
+
+        let mut timestamp = String::new();
+        SystemTime.format_time(&mut Writer::new(&mut timestamp))?;
+        object.insert("timestamp".into(), Value::String(timestamp));
+        object.insert("level".into(), Value::String(meta.level().to_string()));
+        object.insert("target".into(), Value::String(meta.target().to_string()));
+
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                let extensions = span.extensions();
+                if let Some(fields) = extensions.get::<FormattedFields<JsonFields>>() {
+                    if let Ok(Value::Object(span_fields)) = serde_json::from_str(fields) {
+                        object.extend(span_fields);
+                    }
+                }
+            }
+        }
+
+        event.record(&mut JsonVisitor(&mut object));
+
+        writeln!(writer, "{}", Value::Object(object))
+    }
+}
+
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().into(), Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().into(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().into(), Value::from(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().into(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().into(), Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().into(), Value::String(format!("{:?}", value)));
+    }
+}
@@ -0,0 +1,202 @@
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{header, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::admin;
+use crate::auth;
+use crate::error::ApiError;
+use crate::extract::JsonBody;
+use crate::sessions;
+
+/// `Retry-After` sent while in maintenance unless the switch says otherwise.
+const DEFAULT_RETRY_AFTER_SECS: u64 = 60;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    #[default]
+    Off,
+    /// Reads are served, anything that could change data gets `503`.
+    ReadOnly,
+    /// Everything but `/admin/*`, logging in and the probes gets `503`.
+    Full,
+}
+
+impl Mode {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Mode::ReadOnly,
+            2 => Mode::Full,
+            _ => Mode::Off,
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            Mode::Off => 0,
+            Mode::ReadOnly => 1,
+            Mode::Full => 2,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Switch {
+    mode: AtomicU8,
+    retry_after_secs: AtomicU64,
+}
+
+/// The maintenance switch, shared by the middleware and the admin endpoint
+/// that flips it. Starts off on every start.
+#[derive(Debug, Clone)]
+pub struct Maintenance(Arc<Switch>);
+
+impl Default for Maintenance {
+    fn default() -> Self {
+        Self(Arc::new(Switch {
+            mode: AtomicU8::new(Mode::Off.as_u8()),
+            retry_after_secs: AtomicU64::new(DEFAULT_RETRY_AFTER_SECS),
+        }))
+    }
+}
+
+impl Maintenance {
+    fn mode(&self) -> Mode {
+        Mode::from_u8(self.0.mode.load(Ordering::Relaxed))
+    }
+
+    /// Switches to `mode`, returning the one before.
+    fn set(&self, mode: Mode, retry_after_secs: Option<u64>) -> Mode {
+        if let Some(secs) = retry_after_secs {
+            self.0.retry_after_secs.store(secs, Ordering::Relaxed);
+        }
+        Mode::from_u8(self.0.mode.swap(mode.as_u8(), Ordering::Relaxed))
+    }
+
+    pub fn status(&self) -> Status {
+        Status {
+            mode: self.mode(),
+            retry_after_secs: self.0.retry_after_secs.load(Ordering::Relaxed),
+        }
+    }
+
+    /// The `503` for a request in the current mode, if it is turned away;
+    /// `changes` tells whether the request could change data.
+    pub fn refusal(&self, changes: bool) -> Option<ApiError> {
+        let message = match self.mode() {
+            Mode::Off => return None,
+            Mode::ReadOnly if !changes => return None,
+            Mode::ReadOnly => "the API is read-only for maintenance",
+            Mode::Full => "the API is down for maintenance",
+        };
+        Some(
+            ApiError::new(StatusCode::SERVICE_UNAVAILABLE, message)
+                .with_detail("code", "maintenance"),
+        )
+    }
+
+    /// The [`refusal`](Self::refusal) as a response, with `Retry-After`.
+    pub fn refused(&self, changes: bool) -> Option<Response> {
+        let mut response = self.refusal(changes)?.into_response();
+        response.headers_mut().insert(
+            header::RETRY_AFTER,
+            HeaderValue::from(self.status().retry_after_secs),
+        );
+        Some(response)
+    }
+}
+
+/// Whether `path` is served whatever the mode: the admin routes, and
+/// logging in, so an admin without the API key can get the token that
+/// switches maintenance off again.
+fn stays_open(path: &str) -> bool {
+    admin::is_admin_path(path) || path == auth::LOGIN_PATH || path == sessions::SESSION_PATH
+}
+
+/// Whether only the handler can tell if the request changes data: a
+/// GraphQL POST may be a query or a mutation, so `/graphql` checks the mode
+/// itself once the operation is parsed.
+#[cfg(feature = "graphql")]
+fn checks_itself(path: &str) -> bool {
+    path == crate::graphql::GRAPHQL_PATH
+}
+
+#[cfg(not(feature = "graphql"))]
+fn checks_itself(_path: &str) -> bool {
+    false
+}
+
+/// Turns requests away with `503` and `Retry-After` while in maintenance:
+/// only changes in read-only mode, everything in full mode, except for the
+/// paths that [`stays_open`].
+pub async fn enforce<B>(
+    State(maintenance): State<Maintenance>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let path = req.uri().path();
+    if stays_open(path) || checks_itself(path) {
+        return next.run(req).await;
+    }
+    let changes = !matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    match maintenance.refused(changes) {
+        Some(response) => response,
+        None => next.run(req).await,
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Status {
+    mode: Mode,
+    retry_after_secs: u64,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct Change {
+    mode: Mode,
+    /// Kept as it was when absent.
+    retry_after_secs: Option<u64>,
+}
+
+/// `GET /admin/maintenance`: the current mode.
+#[utoipa::path(
+    get,
+    path = "/admin/maintenance",
+    operation_id = "maintenance_status",
+    tag = "admin",
+    responses((status = 200, description = "The current mode", body = Status))
+)]
+pub async fn status(State(maintenance): State<Maintenance>) -> Json<Status> {
+    Json(maintenance.status())
+}
+
+/// `POST /admin/maintenance`: switches to `{"mode": "off" | "read_only" |
+/// "full"}`, optionally with the `"retry_after_secs"` to advertise, and
+/// returns the new state. Takes effect for the next request.
+#[utoipa::path(
+    post,
+    path = "/admin/maintenance",
+    operation_id = "switch_maintenance",
+    tag = "admin",
+    request_body = Change,
+    responses((status = 200, description = "The new mode", body = Status))
+)]
+pub async fn switch(
+    State(maintenance): State<Maintenance>,
+    JsonBody(change): JsonBody<Change>,
+) -> Json<Status> {
+    let previous = maintenance.set(change.mode, change.retry_after_secs);
+    if previous != change.mode {
+        tracing::warn!(from = ?previous, to = ?change.mode, "maintenance mode changed");
+    }
+    Json(maintenance.status())
+}
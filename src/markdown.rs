@@ -0,0 +1,96 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::models::TodoRow;
+
+pub const CONTENT_TYPE: &str = "text/markdown; charset=utf-8";
+
+/// How `GET /todos/export.md` sections the checklist.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupBy {
+    /// One section per priority, most urgent first.
+    #[default]
+    Priority,
+    /// One section per tag, alphabetically; a todo with several tags is
+    /// listed under each, untagged ones come last.
+    Tag,
+    /// A single flat checklist.
+    None,
+}
+
+/// Renders `todos`, already in list order, as a Markdown checklist.
+pub fn checklist(todos: &[TodoRow], group_by: GroupBy) -> String {
+    let mut out = String::from("# Todos\n");
+
+    match group_by {
+        GroupBy::None => {
+            out.push('\n');
+            items(&mut out, todos.iter());
+        }
+        GroupBy::Priority => {
+            let mut groups: BTreeMap<i64, Vec<&TodoRow>> = BTreeMap::new();
+            for todo in todos {
+                groups.entry(todo.priority).or_default().push(todo);
+            }
+            for (priority, todos) in groups.into_iter().rev() {
+                let _ = writeln!(out, "\n## Priority {}\n", priority);
+                items(&mut out, todos.into_iter());
+            }
+        }
+        GroupBy::Tag => {
+            let mut groups: BTreeMap<&str, Vec<&TodoRow>> = BTreeMap::new();
+            let mut untagged = Vec::new();
+            for todo in todos {
+                if todo.tags.0.is_empty() {
+                    untagged.push(todo);
+                }
+                for tag in &todo.tags.0 {
+                    groups.entry(tag).or_default().push(todo);
+                }
+            }
+            for (tag, todos) in groups {
+                let _ = writeln!(out, "\n## {}\n", escape(tag));
+                items(&mut out, todos.into_iter());
+            }
+            if !untagged.is_empty() {
+                out.push_str("\n## Untagged\n\n");
+                items(&mut out, untagged.into_iter());
+            }
+        }
+    }
+
+    out
+}
+
+fn items<'a>(out: &mut String, todos: impl Iterator<Item = &'a TodoRow>) {
+    for todo in todos {
+        let mark = if todo.completed { 'x' } else { ' ' };
+        let _ = write!(out, "- [{}] {}", mark, escape(&todo.title));
+        if let Some(due) = todo.due_date {
+            let _ = write!(out, " (due {})", due.format("%Y-%m-%d"));
+        }
+        out.push('\n');
+    }
+}
+
+/// Backslash-escapes every character Markdown could read as syntax and
+/// folds line breaks into spaces, so a title always stays one literal item.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '\r' | '\n' => out.push(' '),
+            '\\' | '`' | '*' | '_' | '{' | '}' | '[' | ']' | '<' | '>' | '(' | ')' | '#' | '+'
+            | '-' | '.' | '!' | '|' | '~' => {
+                out.push('\\');
+                out.push(ch);
+            }
+            _ => out.push(ch),
+        }
+    }
+    out
+}
@@ -0,0 +1,324 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, State},
+    http::{header, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::access_log::LOST_LINES;
+use crate::error::ApiError;
+use crate::live::LAGGED_CLIENTS;
+use crate::load_shed::SHED_REQUESTS;
+
+/// Path `GET /metrics` is served on.
+pub const METRICS_PATH: &str = "/metrics";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    /// Serve `/metrics` on this address only, instead of on the API
+    /// listener, e.g. to keep scrapes on a private interface.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub addr: Option<SocketAddr>,
+    /// Count scrapes of `/metrics` in the request metrics.
+    pub include_self: bool,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            addr: None,
+            include_self: false,
+        }
+    }
+}
+
+/// Database errors since startup, counted where they become `500`s.
+pub static DB_ERRORS: AtomicU64 = AtomicU64::new(0);
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct RequestLabels {
+    route: String,
+    method: String,
+    status: u16,
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Non-cumulative counts per bucket; the extra last slot is `+Inf`.
+    buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        let slot = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[slot] += 1;
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+/// In-process registry behind `GET /metrics`.
+#[derive(Debug)]
+pub struct Metrics {
+    requests: Mutex<BTreeMap<RequestLabels, Histogram>>,
+    in_flight: AtomicI64,
+    include_self: bool,
+}
+
+impl Metrics {
+    pub fn new(config: &MetricsConfig) -> Self {
+        Self {
+            requests: Mutex::new(BTreeMap::new()),
+            in_flight: AtomicI64::new(0),
+            include_self: config.include_self,
+        }
+    }
+
+    fn record(&self, labels: RequestLabels, seconds: f64) {
+        let mut requests = self.requests.lock().unwrap_or_else(|e| e.into_inner());
+        requests.entry(labels).or_default().observe(seconds);
+    }
+
+    /// Prometheus text exposition format, version 0.0.4.
+    fn render(&self, todos: i64, completed: i64) -> String {
+        let mut out = String::new();
+
+        let requests = self.requests.lock().unwrap_or_else(|e| e.into_inner());
+        describe(
+            &mut out,
+            "http_requests_total",
+            "counter",
+            "Requests handled, by route, method and status.",
+        );
+        for (labels, histogram) in requests.iter() {
+            let _ = writeln!(
+                out,
+                "http_requests_total{{{}}} {}",
+                labels.render(),
+                histogram.count
+            );
+        }
+
+        describe(
+            &mut out,
+            "http_request_duration_seconds",
+            "histogram",
+            "Request latency, by route, method and status.",
+        );
+        for (labels, histogram) in requests.iter() {
+            let labels = labels.render();
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(&histogram.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_sum{{{}}} {}",
+                labels, histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_count{{{}}} {}",
+                labels, histogram.count
+            );
+        }
+        drop(requests);
+
+        describe(
+            &mut out,
+            "http_requests_in_flight",
+            "gauge",
+            "Requests currently being handled.",
+        );
+        let _ = writeln!(
+            out,
+            "http_requests_in_flight {}",
+            self.in_flight.load(Ordering::Relaxed)
+        );
+
+        describe(
+            &mut out,
+            "http_requests_shed_total",
+            "counter",
+            "Requests rejected by load shedding.",
+        );
+        let _ = writeln!(
+            out,
+            "http_requests_shed_total {}",
+            SHED_REQUESTS.load(Ordering::Relaxed)
+        );
+
+        describe(
+            &mut out,
+            "db_errors_total",
+            "counter",
+            "Database errors returned as 500s.",
+        );
+        let _ = writeln!(out, "db_errors_total {}", DB_ERRORS.load(Ordering::Relaxed));
+
+        describe(
+            &mut out,
+            "access_log_lost_lines_total",
+            "counter",
+            "Access log lines lost to write errors or a full queue.",
+        );
+        let _ = writeln!(
+            out,
+            "access_log_lost_lines_total {}",
+            LOST_LINES.load(Ordering::Relaxed)
+        );
+
+        describe(
+            &mut out,
+            "live_lagged_clients_total",
+            "counter",
+            "WebSocket and event stream clients disconnected for falling behind.",
+        );
+        let _ = writeln!(
+            out,
+            "live_lagged_clients_total {}",
+            LAGGED_CLIENTS.load(Ordering::Relaxed)
+        );
+
+        describe(&mut out, "todos_total", "gauge", "Todos stored.");
+        let _ = writeln!(out, "todos_total {}", todos);
+        describe(
+            &mut out,
+            "todos_completed",
+            "gauge",
+            "Completed todos stored.",
+        );
+        let _ = writeln!(out, "todos_completed {}", completed);
+
+        out
+    }
+}
+
+impl RequestLabels {
+    fn render(&self) -> String {
+        format!(
+            "route=\"{}\",method=\"{}\",status=\"{}\"",
+            escape(&self.route),
+            escape(&self.method),
+            self.status
+        )
+    }
+}
+
+fn describe(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Keeps the in-flight gauge right even when a client disconnects and the
+/// request future is dropped midway.
+struct InFlight<'a>(&'a AtomicI64);
+
+impl<'a> InFlight<'a> {
+    fn enter(gauge: &'a AtomicI64) -> Self {
+        gauge.fetch_add(1, Ordering::Relaxed);
+        Self(gauge)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Records count, latency and in-flight requests. Routes are labelled by
+/// their pattern (`/todos/:id`), never the raw path, so ids cannot blow up
+/// the number of series; requests matching no route share `unmatched`.
+pub async fn track<B>(
+    State(metrics): State<Arc<Metrics>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    if route == METRICS_PATH && !metrics.include_self {
+        return next.run(req).await;
+    }
+    let method = req.method().to_string();
+
+    let start = Instant::now();
+    let in_flight = InFlight::enter(&metrics.in_flight);
+    let response = next.run(req).await;
+    drop(in_flight);
+
+    let labels = RequestLabels {
+        route,
+        method,
+        status: response.status().as_u16(),
+    };
+    metrics.record(labels, start.elapsed().as_secs_f64());
+    response
+}
+
+/// `GET /metrics`. Todo counts are read from the database on each scrape.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    operation_id = "metrics",
+    tag = "probes",
+    responses((status = 200, description = "Prometheus text format", body = String, content_type = "text/plain"))
+)]
+pub async fn render(
+    State(metrics): State<Arc<Metrics>>,
+    State(db): State<SqlitePool>,
+) -> Result<Response, ApiError> {
+    let (todos, completed): (i64, i64) = sqlx::query_as(
+        "SELECT COUNT(*), COALESCE(SUM(completed), 0) FROM todos WHERE deleted_at IS NULL",
+    )
+    .fetch_one(&db)
+    .await?;
+
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics.render(todos, completed),
+    )
+        .into_response())
+}
@@ -0,0 +1,656 @@
+//! The todo as stored and as sent, and the bodies and query parameters of
+//! the `/todos` routes, with the checks they go through before anything is
+//! written.
+
+use std::ops::RangeInclusive;
+
+use axum::http::StatusCode;
+use chrono::{DateTime, Duration, Months, NaiveDate, NaiveTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::config::Config;
+use crate::db::TodoFilter;
+use crate::error::{ApiError, FieldErrors};
+use crate::lenient;
+use crate::markdown::GroupBy;
+use crate::tags::{self, TagMode, Tags};
+
+/// A todo as stored in the `todos` table, tags joined in.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct TodoRow {
+    pub id: String,
+    pub title: String,
+    pub completed: bool,
+    /// Set when the todo is marked completed, cleared when it is reopened.
+    pub completed_at: Option<DateTime<Utc>>,
+    #[sqlx(try_from = "Option<String>")]
+    pub tags: Tags,
+    pub due_date: Option<DateTime<Utc>>,
+    pub priority: i64,
+    /// Sort key for the list; only the relative order is meaningful.
+    pub position: i64,
+    /// Completed long enough ago to be left out of the list by default.
+    pub archived: bool,
+}
+
+impl TodoRow {
+    /// One-line rendering for `Accept: text/plain` clients, e.g.
+    /// `[x] Buy milk (id: ...)`.
+    pub fn to_plain_text(&self) -> String {
+        let mark = if self.completed { 'x' } else { ' ' };
+        format!("[{}] {} (id: {})\n", mark, self.title, self.id)
+    }
+
+    /// Marks the todo completed or open. `completed_at` only moves on an
+    /// actual transition, so re-sending the current state keeps the
+    /// original completion time. Reopening a todo unarchives it.
+    pub fn set_completed(&mut self, completed: bool) {
+        if completed != self.completed {
+            self.completed = completed;
+            self.completed_at = completed.then(Utc::now);
+            self.archived &= completed;
+        }
+    }
+
+    /// Past its due date and still open.
+    pub fn is_overdue(&self, now: DateTime<Utc>) -> bool {
+        !self.completed && self.due_date.is_some_and(|due_date| due_date < now)
+    }
+}
+
+/// A todo as clients see it: the stored fields, minus any only the server
+/// needs, plus the derived ones.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TodoResponse {
+    pub id: String,
+    pub title: String,
+    pub completed: bool,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub tags: Tags,
+    pub due_date: Option<DateTime<Utc>>,
+    pub priority: i64,
+    pub position: i64,
+    pub archived: bool,
+    /// Worked out on conversion, never stored.
+    pub overdue: bool,
+}
+
+impl From<TodoRow> for TodoResponse {
+    fn from(row: TodoRow) -> Self {
+        let overdue = row.is_overdue(Utc::now());
+        let TodoRow {
+            id,
+            title,
+            completed,
+            completed_at,
+            tags,
+            due_date,
+            priority,
+            position,
+            archived,
+        } = row;
+        Self {
+            id,
+            title,
+            completed,
+            completed_at,
+            tags,
+            due_date,
+            priority,
+            position,
+            archived,
+            overdue,
+        }
+    }
+}
+
+pub fn responses(rows: Vec<TodoRow>) -> Vec<TodoResponse> {
+    rows.into_iter().map(TodoResponse::from).collect()
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateTodo {
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// RFC 3339 timestamp or `YYYY-MM-DD`.
+    pub due_date: Option<String>,
+    /// Due this long from now instead, as an ISO 8601 duration such as
+    /// `P3D` or `PT2H30M`; not together with `due_date`.
+    #[serde(default)]
+    pub due_in: Option<String>,
+    /// 1 (lowest) to 5 (most urgent); 3 when absent.
+    pub priority: Option<i64>,
+}
+
+/// `POST /todos` as an HTML form sends it: tags comma-separated, and every
+/// field present, blank when not filled in.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct CreateTodoForm {
+    pub title: String,
+    /// Comma-separated, e.g. `work,urgent`.
+    pub tags: String,
+    pub due_date: String,
+    pub due_in: String,
+    pub priority: String,
+}
+
+impl CreateTodoForm {
+    /// The JSON body the form stands for, blank fields left out, and the
+    /// errors for fields that cannot be read, such as a priority that is
+    /// not a number, for [`CreateTodo::check`] to add to.
+    pub fn parse(self) -> (CreateTodo, FieldErrors) {
+        let mut errors = FieldErrors::default();
+        let given =
+            |value: String| Some(value.trim().to_string()).filter(|value| !value.is_empty());
+        let priority = match self.priority.trim() {
+            "" => None,
+            value => errors.check("priority", parse_number(value)),
+        };
+        let todo = CreateTodo {
+            title: self.title,
+            tags: self
+                .tags
+                .split(',')
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .map(String::from)
+                .collect(),
+            due_date: given(self.due_date),
+            due_in: given(self.due_in),
+            priority,
+        };
+        (todo, errors)
+    }
+}
+
+pub fn parse_number(value: &str) -> Result<i64, String> {
+    value
+        .parse()
+        .map_err(|_| "must be a whole number".to_string())
+}
+
+impl CreateTodo {
+    /// `400` for both `due_date` and `due_in`, which no one field is to
+    /// blame for.
+    pub fn check_due(&self) -> Result<(), ApiError> {
+        if self.due_date.is_some() && self.due_in.is_some() {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "due_date and due_in cannot both be given",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks every field, adding to `errors` already found, and builds the
+    /// row to insert, with a fresh id and the title as configured.
+    pub fn check(self, config: &Config, mut errors: FieldErrors) -> Result<TodoRow, FieldErrors> {
+        if self.title.trim().is_empty() {
+            errors.add("title", "must not be empty");
+        }
+        let tags = errors.check(
+            "tags",
+            tags::normalize(self.tags).map_err(|err| err.message().to_string()),
+        );
+        let due_date = match (self.due_date, self.due_in) {
+            (Some(value), _) => errors.check("due_date", parse_timestamp(&value)),
+            (None, Some(value)) => errors.check("due_in", due_in(&value, Utc::now())),
+            (None, None) => None,
+        };
+        let priority = self
+            .priority
+            .and_then(|priority| errors.check("priority", check_priority(priority)));
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(TodoRow {
+            id: config.todos.id_format.generate(),
+            title: config.prepare_title(self.title),
+            completed: false,
+            completed_at: None,
+            tags: Tags(tags.unwrap_or_default()),
+            due_date,
+            priority: priority.unwrap_or(DEFAULT_PRIORITY),
+            position: 0,
+            archived: false,
+        })
+    }
+}
+
+/// Priorities run from 1 (lowest) to 5 (most urgent).
+pub const PRIORITY_RANGE: RangeInclusive<i64> = 1..=5;
+pub const DEFAULT_PRIORITY: i64 = 3;
+
+pub fn check_priority(priority: i64) -> Result<i64, String> {
+    if PRIORITY_RANGE.contains(&priority) {
+        Ok(priority)
+    } else {
+        Err(format!(
+            "must be between {} and {}",
+            PRIORITY_RANGE.start(),
+            PRIORITY_RANGE.end()
+        ))
+    }
+}
+
+/// Accepts an RFC 3339 timestamp, or a plain `YYYY-MM-DD` date meaning
+/// midnight UTC.
+pub fn parse_timestamp(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| date.and_time(NaiveTime::MIN).and_utc())
+        .map_err(|_| "must be an RFC 3339 timestamp or a YYYY-MM-DD date".to_string())
+}
+
+/// `now` plus an ISO 8601 duration: `P`, then any of years `Y`, months `M`,
+/// weeks `W` and days `D`, then optionally `T` and any of hours `H`,
+/// minutes `M` and seconds `S`, each a whole number. Years and months go by
+/// the calendar, so `P1M` from January 31st lands on the last day of
+/// February.
+pub fn due_in(value: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    let invalid = || "must be an ISO 8601 duration such as P3D or PT2H30M".to_string();
+    let rest = value.strip_prefix('P').ok_or_else(invalid)?;
+    let (date, time) = match rest.split_once('T') {
+        Some((date, time)) if !time.is_empty() => (date, Some(time)),
+        Some(_) => return Err(invalid()),
+        None => (rest, None),
+    };
+    if date.is_empty() && time.is_none() {
+        return Err(invalid());
+    }
+
+    // Each part is a number followed by its unit, the units in this order.
+    let parts = |text: &str, units: &[char]| -> Result<Vec<(char, u32)>, String> {
+        let mut parts = Vec::new();
+        let mut next_unit = 0;
+        let mut number = String::new();
+        for c in text.chars() {
+            if c.is_ascii_digit() {
+                number.push(c);
+                continue;
+            }
+            let position = units[next_unit..]
+                .iter()
+                .position(|unit| *unit == c)
+                .ok_or_else(invalid)?;
+            next_unit += position + 1;
+            parts.push((c, number.parse().map_err(|_| invalid())?));
+            number.clear();
+        }
+        if number.is_empty() {
+            Ok(parts)
+        } else {
+            Err(invalid())
+        }
+    };
+
+    let mut due = now;
+    let too_far = || "is too far in the future".to_string();
+    for (unit, count) in parts(date, &['Y', 'M', 'W', 'D'])? {
+        due = match unit {
+            'Y' => count
+                .checked_mul(12)
+                .and_then(|months| due.checked_add_months(Months::new(months))),
+            'M' => due.checked_add_months(Months::new(count)),
+            'W' => due.checked_add_signed(Duration::weeks(count.into())),
+            _ => due.checked_add_signed(Duration::days(count.into())),
+        }
+        .ok_or_else(too_far)?;
+    }
+    for (unit, count) in parts(time.unwrap_or(""), &['H', 'M', 'S'])? {
+        let step = match unit {
+            'H' => Duration::hours(count.into()),
+            'M' => Duration::minutes(count.into()),
+            _ => Duration::seconds(count.into()),
+        };
+        due = due.checked_add_signed(step).ok_or_else(too_far)?;
+    }
+    Ok(due)
+}
+
+/// Distinguishes an explicit `null` (`Some(None)`) from an absent field
+/// (`None`, via `#[serde(default)]`).
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListParams {
+    /// Comma-separated tags, e.g. `?tags=work,urgent`.
+    pub tags: Option<String>,
+    #[serde(default)]
+    pub tag_mode: TagMode,
+    /// Only todos created at or after this time (RFC 3339 or `YYYY-MM-DD`).
+    pub created_after: Option<String>,
+    /// Only todos created before this time.
+    pub created_before: Option<String>,
+    /// Also list archived todos.
+    #[serde(default)]
+    pub include_archived: bool,
+    /// Only completed (`true`) or open (`false`) todos; `true,false` is
+    /// both, the same as leaving it out.
+    pub completed: Option<String>,
+    /// Leaves out todos completed before this time; open ones stay, so
+    /// with no `completed` this is "open or recently completed".
+    pub completed_after: Option<String>,
+    /// Only todos whose title contains this, ignoring case.
+    pub q: Option<String>,
+    /// Only todos without any tags; not together with `tags`.
+    #[serde(default)]
+    pub untagged: bool,
+}
+
+impl ListParams {
+    pub fn filter(&self) -> Result<TodoFilter, ApiError> {
+        let timestamp = |name: &str, value: Option<&str>| {
+            value
+                .map(parse_timestamp)
+                .transpose()
+                .map_err(|err| ApiError::new(StatusCode::BAD_REQUEST, format!("{} {}", name, err)))
+        };
+        let created_after = timestamp("created_after", self.created_after.as_deref())?;
+        let created_before = timestamp("created_before", self.created_before.as_deref())?;
+        let completed_after = timestamp("completed_after", self.completed_after.as_deref())?;
+        if let (Some(after), Some(before)) = (created_after, created_before) {
+            if after > before {
+                return Err(ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    "created_after must not be later than created_before",
+                ));
+            }
+        }
+        if self.untagged && self.tags.is_some() {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "untagged and tags cannot both be given",
+            ));
+        }
+
+        Ok(TodoFilter {
+            tags: match self.tags.as_deref() {
+                Some(param) => tags::parse_list(param)?,
+                None => Vec::new(),
+            },
+            tag_mode: self.tag_mode,
+            untagged: self.untagged,
+            created_after,
+            created_before,
+            include_archived: self.include_archived,
+            completed: match self.completed.as_deref() {
+                Some(value) => parse_completed(value)?,
+                None => None,
+            },
+            completed_after,
+            title_contains: match self.q.as_deref().map(str::trim) {
+                None | Some("") => None,
+                Some(term) if term.len() > MAX_SEARCH_LEN => {
+                    return Err(ApiError::validation(format!(
+                        "q must be at most {} bytes",
+                        MAX_SEARCH_LEN
+                    )))
+                }
+                Some(term) => Some(term.to_string()),
+            },
+        })
+    }
+}
+
+/// `?completed=` as a filter: `None` when both states are listed.
+fn parse_completed(value: &str) -> Result<Option<bool>, ApiError> {
+    let (mut open, mut done) = (false, false);
+    for state in value.split(',').map(str::trim) {
+        match state {
+            "true" => done = true,
+            "false" => open = true,
+            _ => {
+                return Err(ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    "completed must be true, false or true,false",
+                ))
+            }
+        }
+    }
+    Ok(if open && done { None } else { Some(done) })
+}
+
+/// Largest `?limit=` `GET /todos` takes.
+pub const MAX_PAGE_SIZE: i64 = 200;
+
+/// `?limit=` and `?offset=` for `GET /todos`. Without a limit the whole
+/// list from `offset` on is returned, as before paging existed.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListPage {
+    /// 1 to 200.
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub offset: i64,
+}
+
+impl ListPage {
+    pub fn validate(&self) -> Result<(Option<i64>, i64), ApiError> {
+        let in_range = |limit: &i64| (1..=MAX_PAGE_SIZE).contains(limit);
+        if let Some(limit) = self.limit.filter(|limit| !in_range(limit)) {
+            return Err(ApiError::validation(format!(
+                "limit must be between 1 and {}, not {}",
+                MAX_PAGE_SIZE, limit
+            )));
+        }
+        if self.offset < 0 {
+            return Err(ApiError::validation("offset must not be negative"));
+        }
+        Ok((self.limit, self.offset))
+    }
+
+    pub fn is_whole_list(&self) -> bool {
+        self.limit.is_none() && self.offset == 0
+    }
+}
+
+/// Header carrying how many todos match a `GET /todos`, however many of
+/// them the page holds.
+pub const TOTAL_COUNT_HEADER: &str = "x-total-count";
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportParams {
+    #[serde(default)]
+    pub group_by: GroupBy,
+}
+
+/// `?by=` for `GET /todos/group`, checked against
+/// [`db::GroupField::NAMES`] by the handler.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GroupParams {
+    /// `priority`, `completed` or `tag`.
+    pub by: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GroupCount {
+    /// The priority, `true` or `false`, or the tag.
+    #[schema(value_type = Object)]
+    pub key: serde_json::Value,
+    pub count: i64,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchParams {
+    #[serde(default)]
+    pub q: String,
+}
+
+/// Longest search term accepted by `GET /search`.
+pub const MAX_SEARCH_LEN: usize = 200;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RandomParams {
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateTodo {
+    pub title: Option<String>,
+    #[serde(default, deserialize_with = "lenient::optional_bool")]
+    pub completed: Option<bool>,
+    /// Replaces the todo's tags when present.
+    pub tags: Option<Vec<String>>,
+    /// `null` clears the due date.
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>, nullable)]
+    pub due_date: Option<Option<String>>,
+    pub priority: Option<i64>,
+}
+
+/// Validated changes to one todo; `None` leaves a field as it is.
+#[derive(Debug, Default)]
+pub struct TodoChanges {
+    pub title: Option<String>,
+    pub completed: Option<bool>,
+    pub tags: Option<Vec<String>>,
+    pub due_date: Option<Option<DateTime<Utc>>>,
+    pub priority: Option<i64>,
+}
+
+impl TodoChanges {
+    /// Whether `completed` is all that changes, as when a todo is ticked
+    /// off.
+    pub fn is_toggle(&self) -> bool {
+        self.completed.is_some()
+            && self.title.is_none()
+            && self.tags.is_none()
+            && self.due_date.is_none()
+            && self.priority.is_none()
+    }
+}
+
+impl UpdateTodo {
+    /// Checks every field before anything is written, so an update with one
+    /// bad field changes nothing and reports all bad fields at once.
+    pub fn validate(self) -> Result<TodoChanges, ApiError> {
+        self.check(FieldErrors::default())
+            .map_err(FieldErrors::into_error)
+    }
+
+    /// Like `validate`, adding to `errors` already found, and handing them
+    /// back rather than as a response.
+    pub fn check(self, mut errors: FieldErrors) -> Result<TodoChanges, FieldErrors> {
+        if let Some(title) = &self.title {
+            if title.trim().is_empty() {
+                errors.add("title", "must not be empty");
+            }
+        }
+        let tags = self.tags.and_then(|tags| {
+            errors.check(
+                "tags",
+                tags::normalize(tags).map_err(|err| err.message().to_string()),
+            )
+        });
+        let due_date = match self.due_date {
+            Some(Some(value)) => errors.check("due_date", parse_timestamp(&value)).map(Some),
+            Some(None) => Some(None),
+            None => None,
+        };
+        let priority = self
+            .priority
+            .and_then(|priority| errors.check("priority", check_priority(priority)));
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(TodoChanges {
+            title: self.title,
+            completed: self.completed,
+            tags,
+            due_date,
+            priority,
+        })
+    }
+}
+
+/// Body of `POST /todos/:id/move`; exactly one of the two is given.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MoveTodo {
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// Body of `POST /todos/:id/merge`.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MergeTodo {
+    pub into: String,
+}
+
+/// Body of `POST /tags/apply`.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ApplyTags {
+    pub ids: Vec<String>,
+    #[serde(default)]
+    pub add: Vec<String>,
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeleteParams {
+    /// Remove the row for good instead of soft-deleting it.
+    #[serde(default)]
+    pub purge: bool,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct DeleteBatch {
+    pub ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DeleteBatchResult {
+    pub soft_deleted: Vec<String>,
+    pub purged: Vec<String>,
+    /// Unknown ids, and without `purge` also ones already soft-deleted.
+    pub already_gone: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BatchUpdateItem {
+    pub id: String,
+    #[serde(flatten)]
+    pub changes: UpdateTodo,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BatchUpdateParams {
+    /// Roll the whole batch back on any unknown id or invalid item.
+    #[serde(default)]
+    pub atomic: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchItemError {
+    pub id: String,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchUpdateResult {
+    pub updated: Vec<TodoResponse>,
+    pub not_found: Vec<String>,
+    pub errors: Vec<BatchItemError>,
+}
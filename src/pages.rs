@@ -0,0 +1,434 @@
+//! `/app`: the todo list as a plain HTML page with forms, usable without
+//! JavaScript. Reads and writes go through the same storage functions as
+//! the JSON API.
+
+use std::sync::Arc;
+
+use askama::Template;
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+    Extension,
+};
+use chrono::Utc;
+use serde::Deserialize;
+
+use crate::auth::{keys_match, UserId};
+use crate::config::Config;
+use crate::db::{self, TodoFilter};
+use crate::error::{ApiError, FieldErrors};
+use crate::extract::FormBody;
+use crate::handlers::todos;
+use crate::live::Live;
+use crate::models::{parse_number, CreateTodo, TodoChanges, TodoRow};
+use crate::quotas::Quotas;
+use crate::sessions::Session;
+use crate::Db;
+
+pub const APP_PATH: &str = "/app";
+/// Form posts under this prefix carry their CSRF token as a form field,
+/// which the handlers check, rather than as a header.
+pub const FORM_PREFIX: &str = "/app/";
+
+const FLASH_COOKIE: &str = "todo_flash";
+
+/// What the last form post did, carried across its redirect in a short-lived
+/// cookie. Only the code travels; the message is looked up here.
+#[derive(Debug, Clone, Copy)]
+enum Flash {
+    Created,
+    Completed,
+    Reopened,
+    Deleted,
+    Missing,
+}
+
+impl Flash {
+    fn code(self) -> &'static str {
+        match self {
+            Flash::Created => "created",
+            Flash::Completed => "completed",
+            Flash::Reopened => "reopened",
+            Flash::Deleted => "deleted",
+            Flash::Missing => "missing",
+        }
+    }
+
+    fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "created" => Some(Flash::Created),
+            "completed" => Some(Flash::Completed),
+            "reopened" => Some(Flash::Reopened),
+            "deleted" => Some(Flash::Deleted),
+            "missing" => Some(Flash::Missing),
+            _ => None,
+        }
+    }
+
+    fn message(self) -> &'static str {
+        match self {
+            Flash::Created => "Todo added.",
+            Flash::Completed => "Todo marked done.",
+            Flash::Reopened => "Todo reopened.",
+            Flash::Deleted => "Todo deleted.",
+            Flash::Missing => "That todo no longer exists.",
+        }
+    }
+}
+
+fn flash_cookie(config: &Config, value: &str, max_age_secs: u32) -> Option<HeaderValue> {
+    let mut cookie = format!(
+        "{}={}; Path={}; Max-Age={}; HttpOnly; SameSite=Lax",
+        FLASH_COOKIE,
+        value,
+        config.server.base_path.url(APP_PATH),
+        max_age_secs
+    );
+    if config.auth.session.secure_cookie {
+        cookie.push_str("; Secure");
+    }
+    HeaderValue::from_str(&cookie).ok()
+}
+
+fn presented_flash(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == FLASH_COOKIE)
+        .map(|(_, value)| value)
+}
+
+/// `303` back to `/app`, with `flash` to show there.
+fn back(config: &Config, flash: Flash) -> Response {
+    let mut response = Redirect::to(&config.server.base_path.url(APP_PATH)).into_response();
+    if let Some(cookie) = flash_cookie(config, flash.code(), 60) {
+        response.headers_mut().insert(header::SET_COOKIE, cookie);
+    }
+    response
+}
+
+/// A todo as the HTML pages show it.
+pub struct Item {
+    pub id: String,
+    pub title: String,
+    pub completed: bool,
+    /// The due date, without the time.
+    pub due: Option<String>,
+    pub overdue: bool,
+    pub priority: i64,
+}
+
+impl From<TodoRow> for Item {
+    fn from(row: TodoRow) -> Self {
+        let overdue = row.is_overdue(Utc::now());
+        Self {
+            due: row
+                .due_date
+                .map(|due_date| due_date.format("%Y-%m-%d").to_string()),
+            id: row.id,
+            title: row.title,
+            completed: row.completed,
+            overdue,
+            priority: row.priority,
+        }
+    }
+}
+
+/// The create form as submitted, so a rejected one is shown again as typed.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct NewTodo {
+    pub title: String,
+    pub due_date: String,
+    pub priority: String,
+    csrf_token: String,
+}
+
+impl NewTodo {
+    /// Reads the form as `POST /todos` reads its JSON body: blank fields are
+    /// left out, and a priority that is not a number is reported alongside
+    /// any other bad field.
+    pub fn check(&self, config: &Config) -> Result<TodoRow, FieldErrors> {
+        let mut errors = FieldErrors::default();
+        let priority = match self.priority.trim() {
+            "" => None,
+            value => errors.check("priority", parse_number(value)),
+        };
+        let due_date = match self.due_date.trim() {
+            "" => None,
+            value => Some(value.to_string()),
+        };
+        CreateTodo {
+            title: self.title.clone(),
+            tags: Vec::new(),
+            due_date,
+            due_in: None,
+            priority,
+        }
+        .check(config, errors)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct FormErrors {
+    /// A problem with the submission as a whole, such as a duplicate title.
+    pub form: Option<String>,
+    pub title: Option<String>,
+    pub due_date: Option<String>,
+    pub priority: Option<String>,
+}
+
+impl FormErrors {
+    /// A refusal that is the user's to fix, such as a duplicate title or a
+    /// full quota, shown above the form.
+    pub fn rejected(err: &ApiError) -> Self {
+        Self {
+            form: Some(err.message().to_string()),
+            ..Self::default()
+        }
+    }
+}
+
+impl From<&FieldErrors> for FormErrors {
+    fn from(errors: &FieldErrors) -> Self {
+        Self {
+            form: None,
+            title: errors.messages("title"),
+            due_date: errors.messages("due_date"),
+            priority: errors.messages("priority"),
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "app.html")]
+struct AppPage<'a> {
+    base: &'a str,
+    user: String,
+    csrf_token: String,
+    flash: Option<&'static str>,
+    todos: Vec<Item>,
+    form: NewTodo,
+    errors: FormErrors,
+}
+
+async fn render(
+    db: &Db,
+    config: &Config,
+    user: String,
+    session: Option<Session>,
+    flash: Option<Flash>,
+    form: NewTodo,
+    errors: FormErrors,
+) -> Result<Html<String>, ApiError> {
+    let todos = db::list_todos(db, &user, &TodoFilter::default()).await?;
+    let page = AppPage {
+        base: config.server.base_path.as_str(),
+        user,
+        csrf_token: session
+            .map(|session| session.csrf_token)
+            .unwrap_or_default(),
+        flash: flash.map(Flash::message),
+        todos: todos.into_iter().map(Item::from).collect(),
+        form,
+        errors,
+    };
+    page.render()
+        .map(Html)
+        .map_err(|err| ApiError::internal().caused_by(err))
+}
+
+/// Form posts are made by the page itself: with a session, the form must
+/// carry its CSRF token, and a cross-site `Origin` is refused either way,
+/// since a browser sends basic auth credentials along unasked.
+fn check_form(
+    headers: &HeaderMap,
+    session: Option<&Session>,
+    csrf_token: &str,
+) -> Result<(), ApiError> {
+    let forged = ApiError::new(StatusCode::FORBIDDEN, "missing or invalid CSRF token")
+        .with_detail("code", "csrf_failed");
+    if session.is_some_and(|session| !keys_match(csrf_token, &session.csrf_token)) {
+        tracing::warn!("rejected form post without a valid CSRF token");
+        return Err(forged);
+    }
+    let origin = headers
+        .get(header::ORIGIN)
+        .and_then(|value| value.to_str().ok());
+    let host = headers
+        .get(header::HOST)
+        .and_then(|value| value.to_str().ok());
+    if let Some(origin) = origin {
+        let origin_host = origin.split_once("://").map(|(_, host)| host);
+        if origin_host.is_none() || origin_host != host {
+            tracing::warn!(%origin, "rejected cross-site form post");
+            return Err(forged);
+        }
+    }
+    Ok(())
+}
+
+/// `GET /app`: the user's todos with a form to add one.
+pub async fn show(
+    State(db): State<Db>,
+    State(config): State<Arc<Config>>,
+    UserId(user): UserId,
+    session: Option<Extension<Session>>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let flash = presented_flash(&headers);
+    let session = session.map(|Extension(session)| session);
+    let page = render(
+        &db,
+        &config,
+        user,
+        session,
+        flash.and_then(Flash::from_code),
+        NewTodo::default(),
+        FormErrors::default(),
+    )
+    .await?;
+
+    let mut response = page.into_response();
+    if flash.is_some() {
+        if let Some(cookie) = flash_cookie(&config, "", 0) {
+            response.headers_mut().insert(header::SET_COOKIE, cookie);
+        }
+    }
+    Ok(response)
+}
+
+/// `POST /app/todos`: adds a todo from the form and redirects back. An
+/// invalid form is shown again with `422`, its errors next to the fields.
+#[allow(clippy::too_many_arguments)]
+pub async fn create(
+    State(db): State<Db>,
+    State(config): State<Arc<Config>>,
+    State(quotas): State<Arc<Quotas>>,
+    State(live): State<Live>,
+    UserId(user): UserId,
+    session: Option<Extension<Session>>,
+    headers: HeaderMap,
+    FormBody(form): FormBody<NewTodo>,
+) -> Result<Response, ApiError> {
+    let session = session.map(|Extension(session)| session);
+    check_form(&headers, session.as_ref(), &form.csrf_token)?;
+
+    let mut todo = match form.check(&config) {
+        Ok(todo) => todo,
+        Err(errors) => {
+            let errors = FormErrors::from(&errors);
+            let page = render(&db, &config, user, session, None, form, errors).await?;
+            return Ok((StatusCode::UNPROCESSABLE_ENTITY, page).into_response());
+        }
+    };
+    match todos::insert_todo(&db, &config, &quotas, &user, &mut todo).await {
+        Ok(()) => {
+            live.created(&user, &todo.into());
+            Ok(back(&config, Flash::Created))
+        }
+        // A duplicate title or a full quota is the user's to fix, so it is
+        // shown on the form like a validation error.
+        Err(err) if err.status().is_client_error() => {
+            let page = render(
+                &db,
+                &config,
+                user,
+                session,
+                None,
+                form,
+                FormErrors::rejected(&err),
+            )
+            .await?;
+            Ok((err.status(), page).into_response())
+        }
+        Err(err) => Err(err),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Toggle {
+    completed: bool,
+    #[serde(default)]
+    csrf_token: String,
+}
+
+/// `POST /app/todos/:id/toggle`: marks a todo done or open again, as the
+/// form says rather than flipping it, so a resubmitted form is harmless.
+#[allow(clippy::too_many_arguments)]
+pub async fn toggle(
+    Path(id): Path<String>,
+    State(db): State<Db>,
+    State(config): State<Arc<Config>>,
+    State(live): State<Live>,
+    UserId(user): UserId,
+    session: Option<Extension<Session>>,
+    headers: HeaderMap,
+    FormBody(form): FormBody<Toggle>,
+) -> Result<Response, ApiError> {
+    let session = session.map(|Extension(session)| session);
+    check_form(&headers, session.as_ref(), &form.csrf_token)?;
+
+    let changes = TodoChanges {
+        completed: Some(form.completed),
+        ..TodoChanges::default()
+    };
+    let mut tx = db.begin().await?;
+    let updated = db::update_todo(&mut tx, &user, &id, changes).await?;
+    tx.commit().await?;
+
+    if let Some(todo) = &updated {
+        live.toggled(&user, &todo.clone().into());
+    }
+    Ok(back(
+        &config,
+        match updated {
+            Some(todo) if todo.completed => Flash::Completed,
+            Some(_) => Flash::Reopened,
+            None => Flash::Missing,
+        },
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Delete {
+    #[serde(default)]
+    csrf_token: String,
+}
+
+/// `POST /app/todos/:id/delete`: soft-deletes a todo, like
+/// `DELETE /todos/:id`.
+#[allow(clippy::too_many_arguments)]
+pub async fn delete(
+    Path(id): Path<String>,
+    State(db): State<Db>,
+    State(config): State<Arc<Config>>,
+    State(live): State<Live>,
+    UserId(user): UserId,
+    session: Option<Extension<Session>>,
+    headers: HeaderMap,
+    FormBody(form): FormBody<Delete>,
+) -> Result<Response, ApiError> {
+    let session = session.map(|Extension(session)| session);
+    check_form(&headers, session.as_ref(), &form.csrf_token)?;
+
+    let mut tx = db.begin().await?;
+    let deleted = db::delete_todo(&mut tx, &user, &id, false).await?;
+    tx.commit().await?;
+
+    if deleted {
+        live.deleted(&user, &id);
+    }
+    Ok(back(
+        &config,
+        if deleted {
+            Flash::Deleted
+        } else {
+            Flash::Missing
+        },
+    ))
+}
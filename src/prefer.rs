@@ -0,0 +1,121 @@
+//! What a create or update answers with: the todo, or nothing. Asked for
+//! with the standard `Prefer: return=minimal` / `return=representation`
+//! header (RFC 7240) or, where headers are awkward, `?return=`; both are
+//! read here and nowhere else.
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Query},
+    http::{header, request::Parts, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::error::ApiError;
+
+const PREFERENCE_APPLIED: &str = "preference-applied";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Return {
+    /// `204 No Content`; a create still says where the todo is in
+    /// `Location`.
+    Minimal,
+    /// The todo as the body, as without any preference.
+    Representation,
+}
+
+impl Return {
+    /// The preference as echoed in `Preference-Applied`.
+    fn applied(self) -> &'static str {
+        match self {
+            Return::Minimal => "return=minimal",
+            Return::Representation => "return=representation",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "minimal" => Some(Return::Minimal),
+            "representation" => Some(Return::Representation),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ReturnParam {
+    #[serde(rename = "return")]
+    value: Option<Return>,
+}
+
+/// The `return` preference in `Prefer` headers, if any. Preferences are
+/// comma-separated and may carry `;` parameters and quoted values; ones
+/// this server does not know are ignored, as RFC 7240 asks.
+fn from_headers(headers: &HeaderMap) -> Option<Return> {
+    headers
+        .get_all("prefer")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|preference| {
+            let token = preference.split(';').next()?.trim();
+            let (name, value) = token.split_once('=')?;
+            if !name.trim().eq_ignore_ascii_case("return") {
+                return None;
+            }
+            Return::parse(&value.trim().trim_matches('"').to_ascii_lowercase())
+        })
+        .next()
+}
+
+/// The `return` preference of a request: `?return=` when given, otherwise
+/// the `Prefer` header, otherwise none. An unknown `?return=` is `400`; an
+/// unknown header preference is ignored.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Prefer(pub Option<Return>);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Prefer {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let param = Query::<ReturnParam>::try_from_uri(&parts.uri).map_err(|_| {
+            ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "return must be minimal or representation",
+            )
+        })?;
+        Ok(Prefer(
+            param.0.value.or_else(|| from_headers(&parts.headers)),
+        ))
+    }
+}
+
+impl Prefer {
+    /// Answers with `body`, or with `204` when minimal was asked for, then
+    /// carrying `location` when given, so a client still learns where a new
+    /// todo lives. A preference that was honoured is echoed in
+    /// `Preference-Applied`.
+    pub fn respond<T: Serialize>(self, body: T, location: Option<String>) -> Response {
+        let mut response = match self.0 {
+            Some(Return::Minimal) => {
+                let location = location.and_then(|value| HeaderValue::from_str(&value).ok());
+                let mut response = StatusCode::NO_CONTENT.into_response();
+                if let Some(location) = location {
+                    response.headers_mut().insert(header::LOCATION, location);
+                }
+                response
+            }
+            _ => Json(body).into_response(),
+        };
+        if let Some(applied) = self.0 {
+            response.headers_mut().insert(
+                PREFERENCE_APPLIED,
+                HeaderValue::from_static(applied.applied()),
+            );
+        }
+        response
+    }
+}
@@ -0,0 +1,164 @@
+//! `GET /preferences` and `PUT /preferences`: the UI's settings, kept on the
+//! server so they follow a user from browser to browser. The body is a JSON
+//! object with a fixed set of keys; anything else is turned away.
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use utoipa::ToSchema;
+
+use crate::auth::UserId;
+use crate::error::{ApiError, FieldErrors};
+use crate::extract;
+use crate::models::{MAX_PAGE_SIZE, MAX_SEARCH_LEN};
+
+/// Largest body `PUT /preferences` takes; the schema fits in far less.
+const MAX_BODY_BYTES: usize = 4 * 1024;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Light,
+    Dark,
+    /// Whatever the browser prefers.
+    System,
+}
+
+/// How the list is searched and filtered when the page opens.
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DefaultFilter {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    q: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    completed: Option<bool>,
+    #[serde(default)]
+    include_archived: bool,
+}
+
+/// A user's preferences. Every key is optional; one left out means the
+/// UI's own default.
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Preferences {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    theme: Option<Theme>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    filter: Option<DefaultFilter>,
+    /// 1 to 200.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    page_size: Option<i64>,
+}
+
+impl Preferences {
+    fn validate(&self) -> Result<(), ApiError> {
+        let mut errors = FieldErrors::default();
+        if let Some(q) = self.filter.as_ref().and_then(|filter| filter.q.as_ref()) {
+            if q.len() > MAX_SEARCH_LEN {
+                errors.add(
+                    "filter.q",
+                    format!("must be at most {} bytes", MAX_SEARCH_LEN),
+                );
+            }
+        }
+        if let Some(page_size) = self.page_size {
+            if !(1..=MAX_PAGE_SIZE).contains(&page_size) {
+                errors.add(
+                    "page_size",
+                    format!("must be between 1 and {}", MAX_PAGE_SIZE),
+                );
+            }
+        }
+        errors.finish()
+    }
+}
+
+/// `GET /preferences`: the user's saved preferences, `{}` before any were
+/// saved.
+#[utoipa::path(
+    get,
+    path = "/preferences",
+    operation_id = "get_preferences",
+    tag = "preferences",
+    responses((status = 200, description = "The saved preferences", body = Preferences))
+)]
+pub async fn show(
+    State(db): State<SqlitePool>,
+    UserId(user): UserId,
+) -> Result<Json<Preferences>, ApiError> {
+    let data: Option<String> = sqlx::query_scalar("SELECT data FROM preferences WHERE user_id = ?")
+        .bind(&user)
+        .fetch_optional(&db)
+        .await?;
+    let preferences = match data {
+        Some(data) => serde_json::from_str(&data).map_err(|err| {
+            ApiError::internal().caused_by(format!("stored preferences: {}", err))
+        })?,
+        None => Preferences::default(),
+    };
+    Ok(Json(preferences))
+}
+
+/// `PUT /preferences`: replaces the user's preferences with the body.
+/// `413` past [`MAX_BODY_BYTES`]; `422` for malformed JSON, unknown keys or
+/// values out of range.
+#[utoipa::path(
+    put,
+    path = "/preferences",
+    operation_id = "replace_preferences",
+    tag = "preferences",
+    request_body = Preferences,
+    responses(
+        (status = 200, description = "The preferences as saved", body = Preferences),
+        (status = 413, description = "A body over 4 KiB", body = ErrorBody),
+        (status = 415, description = "Not JSON", body = ErrorBody),
+        (status = 422, description = "Unknown keys or bad values", body = ErrorBody),
+    )
+)]
+pub async fn replace(
+    State(db): State<SqlitePool>,
+    UserId(user): UserId,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<Preferences>, ApiError> {
+    let json = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !json {
+        return Err(ApiError::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "expected an application/json body",
+        ));
+    }
+    if body.len() > MAX_BODY_BYTES {
+        return Err(ApiError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("preferences must be at most {} bytes", MAX_BODY_BYTES),
+        ));
+    }
+    let value =
+        serde_json::from_slice(&body).map_err(|err| ApiError::validation(err.to_string()))?;
+    let preferences: Preferences = extract::from_value(value)?;
+    preferences.validate()?;
+
+    let data =
+        serde_json::to_string(&preferences).map_err(|err| ApiError::internal().caused_by(err))?;
+    sqlx::query(
+        "INSERT INTO preferences (user_id, data, updated_at) VALUES (?, ?, ?)
+         ON CONFLICT (user_id) DO UPDATE SET data = excluded.data, updated_at = excluded.updated_at",
+    )
+    .bind(&user)
+    .bind(data)
+    .bind(Utc::now())
+    .execute(&db)
+    .await?;
+    tracing::debug!(%user, "saved preferences");
+    Ok(Json(preferences))
+}
@@ -0,0 +1,104 @@
+//! What a browser needs to install the UI at `/` as an app: the web app
+//! manifest and a service worker that keeps the static files cached.
+
+use std::sync::Arc;
+
+use askama::Template;
+use axum::{
+    extract::State,
+    http::{header, HeaderValue, Uri},
+    response::{IntoResponse, Response},
+};
+use serde_json::json;
+
+use crate::config::Config;
+use crate::error::ApiError;
+use crate::frontend;
+use crate::version;
+
+pub const MANIFEST_PATH: &str = "/manifest.webmanifest";
+pub const SERVICE_WORKER_PATH: &str = "/sw.js";
+
+const THEME_COLOR: &str = "#2b6cb0";
+
+/// Files the worker caches on install and serves from the cache after. The
+/// page at `/` is left out: it carries the user's name and CSRF token.
+const PRECACHE: [&str; 6] = [
+    "/style.css",
+    "/app.js",
+    frontend::FAVICON_SVG_PATH,
+    frontend::ICON_192_PATH,
+    frontend::ICON_512_PATH,
+    MANIFEST_PATH,
+];
+
+/// `GET /manifest.webmanifest`: name, colours and icons for the installed
+/// app, which opens at `/`.
+pub async fn manifest(State(config): State<Arc<Config>>, uri: Uri) -> Response {
+    if config.server.disable_ui {
+        return crate::handlers::fallback(uri).await.into_response();
+    }
+    let base = &config.server.base_path;
+    let manifest = json!({
+        "name": "Todos",
+        "short_name": "Todos",
+        "start_url": base.url("/"),
+        "scope": base.url("/"),
+        "display": "standalone",
+        "theme_color": THEME_COLOR,
+        "background_color": "#ffffff",
+        "icons": [
+            { "src": base.url(frontend::ICON_192_PATH), "sizes": "192x192", "type": "image/png" },
+            { "src": base.url(frontend::ICON_512_PATH), "sizes": "512x512", "type": "image/png" },
+            { "src": base.url(frontend::FAVICON_SVG_PATH), "sizes": "any", "type": "image/svg+xml" },
+        ],
+    });
+    (
+        [
+            (
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/manifest+json"),
+            ),
+            (header::CACHE_CONTROL, HeaderValue::from_static("no-cache")),
+        ],
+        manifest.to_string(),
+    )
+        .into_response()
+}
+
+#[derive(Template)]
+#[template(path = "sw.js", escape = "none")]
+struct ServiceWorker {
+    /// Both as JSON, which is valid JavaScript.
+    cache_name: String,
+    precache: String,
+}
+
+/// `GET /sw.js`: the service worker, its cache named after the build so
+/// that a new release makes browsers fetch the files again and drop the old
+/// copies. Served with `no-cache` so browsers notice the new build on the
+/// next load.
+pub async fn service_worker(State(config): State<Arc<Config>>, uri: Uri) -> Response {
+    if config.server.disable_ui {
+        return crate::handlers::fallback(uri).await.into_response();
+    }
+    let build = version::build_info();
+    let worker = ServiceWorker {
+        cache_name: json!(format!("todos-{}-{}", build.version, build.git_commit)).to_string(),
+        precache: json!(PRECACHE.map(|path| config.server.base_path.url(path))).to_string(),
+    };
+    match worker.render() {
+        Ok(js) => (
+            [
+                (
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/javascript; charset=utf-8"),
+                ),
+                (header::CACHE_CONTROL, HeaderValue::from_static("no-cache")),
+            ],
+            js,
+        )
+            .into_response(),
+        Err(err) => ApiError::internal().caused_by(err).into_response(),
+    }
+}
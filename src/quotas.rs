@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use utoipa::ToSchema;
+
+use crate::accounts::Role;
+use crate::auth::UserId;
+use crate::db;
+use crate::error::ApiError;
+use crate::extract::JsonBody;
+use crate::rate_limit;
+
+pub const USAGE_PATH: &str = "/me/usage";
+
+/// Writes are counted in fixed windows of this length.
+const WINDOW: Duration = Duration::from_secs(60);
+/// Windows kept before expired ones are dropped.
+const MAX_TRACKED_WINDOWS: usize = 1024;
+
+/// Per-user limits; each is unlimited when absent. Admins get their own,
+/// and by default none at all.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotaConfig {
+    /// Todos a member may have, not counting deleted ones.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_todos: Option<u64>,
+    /// Writes (`POST`, `PUT`, `PATCH`, `DELETE`) a member may make a minute.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub writes_per_minute: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin_max_todos: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin_writes_per_minute: Option<u32>,
+}
+
+/// A user's limits, or their overrides of the configured ones.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Limits {
+    #[serde(default)]
+    pub max_todos: Option<u64>,
+    #[serde(default)]
+    pub writes_per_minute: Option<u32>,
+}
+
+#[derive(Debug)]
+struct Window {
+    started: Instant,
+    writes: u32,
+}
+
+/// The configured limits plus each user's writes in the current window.
+#[derive(Debug)]
+pub struct Quotas {
+    config: QuotaConfig,
+    windows: Mutex<HashMap<String, Window>>,
+}
+
+impl Quotas {
+    pub fn new(config: QuotaConfig) -> Self {
+        Self {
+            config,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The limits that apply to `user`: their overrides, else the defaults
+    /// for their role. Users without a `users` row count as members.
+    pub async fn limits(&self, db: &SqlitePool, user: &str) -> Result<Limits, sqlx::Error> {
+        let (role, overrides) = db::user_limits(db, user)
+            .await?
+            .unwrap_or((Role::Member, Limits::default()));
+        let defaults = match role {
+            Role::Admin => Limits {
+                max_todos: self.config.admin_max_todos,
+                writes_per_minute: self.config.admin_writes_per_minute,
+            },
+            Role::Member => Limits {
+                max_todos: self.config.max_todos,
+                writes_per_minute: self.config.writes_per_minute,
+            },
+        };
+        Ok(Limits {
+            max_todos: overrides.max_todos.or(defaults.max_todos),
+            writes_per_minute: overrides.writes_per_minute.or(defaults.writes_per_minute),
+        })
+    }
+
+    /// Counts one write for `user`, or with `limit` already reached, returns
+    /// the seconds until the window ends instead. Runs under the lock, so
+    /// concurrent writes cannot both take the last slot.
+    pub fn charge(&self, user: &str, limit: Option<u32>) -> Result<(), u64> {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        if !windows.contains_key(user) && windows.len() >= MAX_TRACKED_WINDOWS {
+            windows.retain(|_, window| now.duration_since(window.started) < WINDOW);
+        }
+
+        let window = windows.entry(user.to_string()).or_insert(Window {
+            started: now,
+            writes: 0,
+        });
+        if now.duration_since(window.started) >= WINDOW {
+            *window = Window {
+                started: now,
+                writes: 0,
+            };
+        }
+        if limit.is_some_and(|limit| window.writes >= limit) {
+            return Err(secs_left(window, now));
+        }
+        window.writes += 1;
+        Ok(())
+    }
+
+    /// `user`'s writes in the current window and the seconds until it ends.
+    fn writes(&self, user: &str) -> (u32, u64) {
+        let now = Instant::now();
+        let windows = self.windows.lock().unwrap();
+        match windows.get(user) {
+            Some(window) if now.duration_since(window.started) < WINDOW => {
+                (window.writes, secs_left(window, now))
+            }
+            _ => (0, WINDOW.as_secs()),
+        }
+    }
+}
+
+fn secs_left(window: &Window, now: Instant) -> u64 {
+    let left = WINDOW.saturating_sub(now.duration_since(window.started));
+    left.as_secs_f64().ceil().max(1.0) as u64
+}
+
+/// Charges writes to the signed-in user and answers `429` with
+/// `Retry-After` once their `writes_per_minute` is used up. Requests
+/// without a user, such as with auth off or an API key, are left to the
+/// per-client rate limit. `/graphql` charges its mutations itself, since
+/// its POSTs may be queries.
+pub async fn throttle<B>(
+    State(quotas): State<Arc<Quotas>>,
+    State(db): State<SqlitePool>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if !rate_limit::is_write(req.method()) || charged_by_handler(req.uri().path()) {
+        return next.run(req).await;
+    }
+    let Some(UserId(user)) = req.extensions().get::<UserId>().cloned() else {
+        return next.run(req).await;
+    };
+    match charge_write(&quotas, &db, &user).await {
+        Ok(()) => next.run(req).await,
+        Err(response) => response,
+    }
+}
+
+#[cfg(feature = "graphql")]
+fn charged_by_handler(path: &str) -> bool {
+    path == crate::graphql::GRAPHQL_PATH
+}
+
+#[cfg(not(feature = "graphql"))]
+fn charged_by_handler(_path: &str) -> bool {
+    false
+}
+
+/// Counts one write for `user`, or answers `429` with `Retry-After` once
+/// their `writes_per_minute` is used up.
+pub async fn charge_write(quotas: &Quotas, db: &SqlitePool, user: &str) -> Result<(), Response> {
+    let limits = quotas
+        .limits(db, user)
+        .await
+        .map_err(|err| ApiError::from(err).into_response())?;
+
+    let Err(retry_after_secs) = quotas.charge(user, limits.writes_per_minute) else {
+        return Ok(());
+    };
+    tracing::warn!(%user, "write quota exceeded");
+    let mut response = write_quota_exceeded(limits.writes_per_minute).into_response();
+    response
+        .headers_mut()
+        .insert("retry-after", HeaderValue::from(retry_after_secs));
+    Err(response)
+}
+
+/// `429` for a write past the user's `writes_per_minute`.
+pub fn write_quota_exceeded(writes_per_minute: Option<u32>) -> ApiError {
+    ApiError::new(StatusCode::TOO_MANY_REQUESTS, "write quota exceeded")
+        .with_detail("code", "write_quota_exceeded")
+        .with_detail("writes_per_minute", writes_per_minute)
+}
+
+/// `403` for a create that would take the user past `max_todos`.
+pub fn todo_quota_exceeded(max_todos: u64) -> ApiError {
+    ApiError::new(StatusCode::FORBIDDEN, "todo quota reached")
+        .with_detail("code", "todo_quota_exceeded")
+        .with_detail("max_todos", max_todos)
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Consumption {
+    used: u64,
+    /// `null` when unlimited.
+    limit: Option<u64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Usage {
+    todos: Consumption,
+    writes_per_minute: Consumption,
+    window_resets_in_secs: u64,
+}
+
+/// `GET /me/usage`: the user's todos and writes this minute against their
+/// limits.
+#[utoipa::path(
+    get,
+    path = "/me/usage",
+    tag = "account",
+    responses((status = 200, description = "Usage and limits", body = Usage))
+)]
+pub async fn usage(
+    State(quotas): State<Arc<Quotas>>,
+    State(db): State<SqlitePool>,
+    UserId(user): UserId,
+) -> Result<Json<Usage>, ApiError> {
+    let limits = quotas.limits(&db, &user).await?;
+    let todos = db::count_todos(&db, &user).await?;
+    let (writes, resets_in_secs) = quotas.writes(&user);
+
+    Ok(Json(Usage {
+        todos: Consumption {
+            used: todos,
+            limit: limits.max_todos,
+        },
+        writes_per_minute: Consumption {
+            used: writes.into(),
+            limit: limits.writes_per_minute.map(u64::from),
+        },
+        window_resets_in_secs: resets_in_secs,
+    }))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserLimits {
+    id: String,
+    #[serde(flatten)]
+    limits: Limits,
+}
+
+/// `PUT /admin/users/:id/quota`: overrides a user's limits with
+/// `{"max_todos": .., "writes_per_minute": ..}`; a `null` or missing field
+/// falls back to the configured default for their role. `404` for an
+/// unknown user.
+#[utoipa::path(
+    put,
+    path = "/admin/users/{id}/quota",
+    tag = "admin",
+    params(("id" = String, Path, description = "The user")),
+    request_body = Limits,
+    responses(
+        (status = 200, description = "The user's overrides", body = UserLimits),
+        (status = 404, description = "No such user", body = ErrorBody),
+        (status = 422, description = "A limit out of range", body = ErrorBody),
+    )
+)]
+pub async fn set_limits(
+    State(db): State<SqlitePool>,
+    Path(id): Path<String>,
+    JsonBody(limits): JsonBody<Limits>,
+) -> Result<Json<UserLimits>, ApiError> {
+    if limits.max_todos.is_some_and(|max| max > i64::MAX as u64) {
+        return Err(ApiError::validation("max_todos is too large"));
+    }
+    if !db::set_user_limits(&db, &id, &limits).await? {
+        return Err(ApiError::new(StatusCode::NOT_FOUND, "user not found"));
+    }
+
+    tracing::info!(user = %id, ?limits, "changed user quota");
+    Ok(Json(UserLimits { id, limits }))
+}
@@ -0,0 +1,279 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::ApiError;
+
+/// Sustained rate plus the burst a client may spend at once.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Quota {
+    pub per_second: f64,
+    pub burst: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    pub read: Quota,
+    pub write: Quota,
+    /// Key on the last `X-Forwarded-For` hop instead of the peer address.
+    /// Only safe when a trusted proxy always sets the header.
+    pub trust_forwarded_for: bool,
+    /// Upper bound on tracked buckets so rotating source addresses
+    /// cannot grow the table without limit.
+    pub max_tracked_clients: usize,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            read: Quota {
+                per_second: 20.0,
+                burst: 40,
+            },
+            write: Quota {
+                per_second: 5.0,
+                burst: 10,
+            },
+            trust_forwarded_for: false,
+            max_tracked_clients: 10_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Class {
+    Read,
+    Write,
+}
+
+impl Class {
+    fn of(method: &Method) -> Self {
+        if is_write(method) {
+            Class::Write
+        } else {
+            Class::Read
+        }
+    }
+}
+
+/// Whether requests with `method` are charged as writes.
+pub fn is_write(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+    )
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// When the bucket was last charged, on [`Buckets::clock`].
+    touched: u64,
+}
+
+type Key = (IpAddr, Class);
+
+/// The buckets, with the order they were last charged in, so the least
+/// recently used one is found without scanning them all. `recency` gets an
+/// entry per charge; entries older than their bucket's `touched` are stale
+/// and skipped, and dropped in bulk once they outnumber the buckets.
+#[derive(Debug, Default)]
+struct Buckets {
+    map: HashMap<Key, Bucket>,
+    recency: VecDeque<(Key, u64)>,
+    clock: u64,
+}
+
+impl Buckets {
+    /// The bucket for `key`, full when new, marked as the most recently used.
+    fn touch(&mut self, key: Key, burst: f64, now: Instant) -> &mut Bucket {
+        self.clock += 1;
+        let touched = self.clock;
+        self.map
+            .entry(key)
+            .or_insert(Bucket {
+                tokens: burst,
+                updated: now,
+                touched,
+            })
+            .touched = touched;
+        self.recency.push_back((key, touched));
+        if self.recency.len() > 2 * self.map.len() + 16 {
+            let map = &self.map;
+            self.recency
+                .retain(|(key, touched)| map.get(key).is_some_and(|b| b.touched == *touched));
+        }
+        self.map.get_mut(&key).expect("inserted above")
+    }
+
+    /// Drops the least recently used bucket; amortized O(1).
+    fn evict_oldest(&mut self) {
+        while let Some((key, touched)) = self.recency.pop_front() {
+            if self.map.get(&key).is_some_and(|b| b.touched == touched) {
+                self.map.remove(&key);
+                return;
+            }
+        }
+    }
+}
+
+/// Outcome of charging one request against a client's bucket.
+#[derive(Debug, Clone, Copy)]
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the bucket is full again.
+    pub reset_secs: u64,
+    /// Seconds until the next request would be allowed.
+    pub retry_after_secs: u64,
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: Mutex::new(Buckets::default()),
+        }
+    }
+
+    fn quota(&self, class: Class) -> Quota {
+        match class {
+            Class::Read => self.config.read,
+            Class::Write => self.config.write,
+        }
+    }
+
+    fn check(&self, ip: IpAddr, class: Class) -> Decision {
+        let quota = self.quota(class);
+        let burst = f64::from(quota.burst);
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        // A bucket dropped early only forgets how much a client has spent;
+        // the one used longest ago has most likely refilled anyway.
+        if !buckets.map.contains_key(&(ip, class))
+            && buckets.map.len() >= self.config.max_tracked_clients
+        {
+            buckets.evict_oldest();
+        }
+
+        let bucket = buckets.touch((ip, class), burst, now);
+
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * quota.per_second).min(burst);
+        bucket.updated = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+
+        let secs_until = |tokens: f64| {
+            if quota.per_second > 0.0 {
+                (tokens / quota.per_second).ceil() as u64
+            } else {
+                u64::MAX
+            }
+        };
+
+        Decision {
+            allowed,
+            limit: quota.burst,
+            remaining: bucket.tokens.floor() as u32,
+            reset_secs: secs_until(burst - bucket.tokens),
+            retry_after_secs: if allowed {
+                0
+            } else {
+                secs_until(1.0 - bucket.tokens).max(1)
+            },
+        }
+    }
+}
+
+/// The peer address, or with `trust_forwarded_for` the last
+/// `X-Forwarded-For` hop when it parses. `None` over Unix sockets.
+pub fn client_ip<B>(req: &Request<B>, trust_forwarded_for: bool) -> Option<IpAddr> {
+    if trust_forwarded_for {
+        let forwarded = req
+            .headers()
+            .get("x-forwarded-for")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next())
+            .and_then(|hop| hop.trim().parse().ok());
+        if forwarded.is_some() {
+            return forwarded;
+        }
+    }
+
+    req.extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+}
+
+fn insert_header(headers: &mut HeaderMap, name: &'static str, value: u64) {
+    headers.insert(name, HeaderValue::from(value));
+}
+
+/// Reports the client's budget from the bucket state computed for this
+/// request, so well-behaved clients can throttle themselves.
+fn insert_budget_headers(headers: &mut HeaderMap, decision: &Decision) {
+    insert_header(headers, "x-ratelimit-limit", decision.limit.into());
+    insert_header(headers, "x-ratelimit-remaining", decision.remaining.into());
+    insert_header(headers, "x-ratelimit-reset", decision.reset_secs);
+}
+
+/// Charges the request to its client's read or write bucket and answers
+/// `429 Too Many Requests` once the bucket is empty.
+pub async fn enforce<B>(
+    State(limiter): State<Arc<RateLimiter>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if !limiter.config.enabled {
+        return next.run(req).await;
+    }
+
+    // Unix socket peers have no IP; they are limited only through a
+    // trusted proxy's X-Forwarded-For.
+    let Some(ip) = client_ip(&req, limiter.config.trust_forwarded_for) else {
+        return next.run(req).await;
+    };
+
+    let decision = limiter.check(ip, Class::of(req.method()));
+    if decision.allowed {
+        let mut response = next.run(req).await;
+        insert_budget_headers(response.headers_mut(), &decision);
+        return response;
+    }
+
+    tracing::warn!(client = %ip, "rate limit exceeded");
+
+    let mut response =
+        ApiError::new(StatusCode::TOO_MANY_REQUESTS, "rate limit exceeded").into_response();
+    let headers = response.headers_mut();
+    insert_header(headers, "retry-after", decision.retry_after_secs);
+    insert_budget_headers(headers, &decision);
+    response
+}
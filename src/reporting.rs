@@ -0,0 +1,155 @@
+//! Optional Sentry error reporting, compiled in with the `sentry` feature and
+//! switched on at runtime by `reporting.sentry_dsn`. Only internal server errors and
+//! panics are reported; client errors such as `404` and `422` never are.
+
+#[cfg(feature = "sentry")]
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use axum::{http::Request, middleware::Next, response::Response};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Secret;
+
+#[cfg(feature = "sentry")]
+use crate::{error::InternalError, request_id};
+
+#[cfg(feature = "sentry")]
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReportingConfig {
+    /// Where to send reports; off while unset or blank.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sentry_dsn: Option<Secret>,
+}
+
+/// Flushes pending reports when dropped; keep it alive until exit.
+pub struct Guard {
+    #[cfg(feature = "sentry")]
+    _client: Option<sentry::ClientInitGuard>,
+}
+
+/// Starts reporting if a DSN is configured and logs whether it is active.
+pub fn init(config: &ReportingConfig) -> Guard {
+    #[cfg(feature = "sentry")]
+    {
+        init_with_transport(config, None)
+    }
+    #[cfg(not(feature = "sentry"))]
+    {
+        init_off(config)
+    }
+}
+
+/// [`init`], sending reports through `transport` rather than over HTTP when
+/// one is given, so tests can see what would have been sent.
+#[cfg(feature = "sentry")]
+pub fn init_with_transport(
+    config: &ReportingConfig,
+    transport: Option<std::sync::Arc<dyn sentry::TransportFactory>>,
+) -> Guard {
+    let dsn = configured_dsn(config);
+    let client = dsn.and_then(|dsn| match dsn.trim().parse::<sentry::types::Dsn>() {
+        Ok(dsn) => Some(sentry::init(sentry::ClientOptions {
+            dsn: Some(dsn),
+            release: sentry::release_name!(),
+            transport,
+            ..Default::default()
+        })),
+        Err(err) => {
+            tracing::warn!(error = %err, "invalid SENTRY_DSN");
+            None
+        }
+    });
+    let active = client.as_ref().is_some_and(|client| client.is_enabled());
+    ACTIVE.store(active, Ordering::Relaxed);
+    if active {
+        tracing::info!("error reporting to Sentry is active");
+    } else {
+        tracing::info!("error reporting is off");
+    }
+    Guard { _client: client }
+}
+
+#[cfg(not(feature = "sentry"))]
+fn init_off(config: &ReportingConfig) -> Guard {
+    if configured_dsn(config).is_some() {
+        tracing::warn!(
+            "SENTRY_DSN is set but this build has no sentry feature; error reporting is off"
+        );
+    } else {
+        tracing::info!("error reporting is off");
+    }
+    Guard {}
+}
+
+fn configured_dsn(config: &ReportingConfig) -> Option<&str> {
+    config
+        .sentry_dsn
+        .as_ref()
+        .map(Secret::expose)
+        .filter(|dsn| !dsn.trim().is_empty())
+}
+
+#[cfg(feature = "sentry")]
+fn enabled() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Reports responses marked with [`InternalError`], which covers database
+/// failures and panics caught further in. Passes straight through when
+/// reporting is off.
+pub async fn report<B>(req: Request<B>, next: Next<B>) -> Response {
+    #[cfg(feature = "sentry")]
+    if enabled() {
+        return sentry_report(req, next).await;
+    }
+    next.run(req).await
+}
+
+#[cfg(feature = "sentry")]
+async fn sentry_report<B>(req: Request<B>, next: Next<B>) -> Response {
+    use axum::body::HttpBody;
+
+    let method = req.method().to_string();
+    let path = crate::shares::redact(req.uri().path()).into_owned();
+    let request_bytes = content_length(req.headers());
+
+    let response = next.run(req).await;
+
+    if let Some(InternalError(cause)) = response.extensions().get::<InternalError>() {
+        // Where and how big, never what: bodies are not attached.
+        let mut sizes = std::collections::BTreeMap::new();
+        if let Some(bytes) = request_bytes {
+            sizes.insert("request_bytes".to_string(), bytes.into());
+        }
+        if let Some(bytes) = response.body().size_hint().exact() {
+            sizes.insert("response_bytes".to_string(), bytes.into());
+        }
+
+        sentry::with_scope(
+            |scope| {
+                scope.set_tag("http.method", &method);
+                scope.set_tag("http.path", &path);
+                if let Some(id) = request_id::current() {
+                    scope.set_tag("request_id", id);
+                }
+                scope.set_context("payload", sentry::protocol::Context::Other(sizes));
+            },
+            || sentry::capture_message(cause, sentry::Level::Error),
+        );
+    }
+
+    response
+}
+
+#[cfg(feature = "sentry")]
+fn content_length(headers: &axum::http::HeaderMap) -> Option<u64> {
+    headers
+        .get(axum::http::header::CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
@@ -0,0 +1,648 @@
+//! Puts the routes and middleware together for a configuration, and serves
+//! them on every configured listener until shutdown.
+
+use std::any::Any;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use axum::{
+    error_handling::HandleErrorLayer,
+    extract::MatchedPath,
+    http::{header, HeaderValue, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put, MethodRouter},
+    BoxError, Router,
+};
+use hyper::Server;
+use tokio::signal;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tower::{Layer, ServiceBuilder};
+use tower_http::catch_panic::CatchPanicLayer;
+use tower_http::compression::{
+    predicate::{NotForContentType, Predicate, SizeAbove},
+    CompressionLayer,
+};
+use tower_http::set_header::SetResponseHeaderLayer;
+use tracing::{Instrument, Span};
+
+use crate::access_log;
+use crate::auth;
+use crate::capacity::{self, CountWarning};
+use crate::config::Config;
+use crate::error::{self, ApiError};
+use crate::handlers::todos;
+use crate::health;
+use crate::listener::{self, Listener};
+use crate::maintenance;
+use crate::metrics;
+use crate::quotas;
+use crate::rate_limit::{self, RateLimiter};
+use crate::request_id::{self, RequestId};
+use crate::{accounts, admin, archive, backup, body_log, cors, docs, fragments, frontend, github};
+use crate::{grpc, latency_budget, lenient, live, load_shed, pages, preferences, pwa, reporting};
+use crate::{sessions, shares, static_dir, sync, telemetry, trailing_slash, version, AppState, Db};
+
+/// Bodies smaller than this are sent as-is; compressing them costs more
+/// than it saves.
+const MIN_COMPRESS_SIZE: u16 = 1024;
+
+/// How long shutdown waits for WebSockets to finish closing.
+const WS_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The `code` of the `503` a handler gets when it runs out of time.
+pub const REQUEST_TIMEOUT_CODE: &str = "request_timeout";
+
+/// What [`build`] puts together: the routers, and the state behind them,
+/// which [`serve`] also needs for gRPC and to shut down.
+pub struct Routers {
+    /// Every route, behind the full middleware stack, under
+    /// `server.base_path`.
+    pub app: Router,
+    /// `/metrics` alone, for when `metrics.addr` serves it elsewhere.
+    pub metrics: Router,
+    state: AppState,
+}
+
+/// Builds the app for `config` on `db`: the routes the configuration turns
+/// on, the middleware, and the background tasks, such as archiving, that
+/// go with them. `db` must already be migrated.
+pub async fn build(config: Config, db: Db) -> anyhow::Result<Routers> {
+    let state = AppState::new(config, db).await?;
+    let config = Arc::clone(&state.config);
+
+    let metrics_app = mount(metrics_routes()).with_state(state.clone());
+    let mut app = todo_router(state.clone());
+    let base = &config.server.base_path;
+    if !base.is_root() {
+        tracing::info!(base_path = base.as_str(), "serving under a base path");
+        app = Router::new()
+            .nest(base.as_str(), app)
+            .fallback(crate::handlers::fallback);
+    }
+
+    // Wraps the routers themselves rather than being one of their layers,
+    // since a router's layers only see a request once it has been routed.
+    let trailing_slash = config.server.trailing_slash;
+    tracing::info!(mode = ?trailing_slash, "{}", trailing_slash.describe());
+    if config.server.lenient_json {
+        tracing::info!("accepting \"true\"/\"false\" and 1/0 for boolean fields");
+    }
+    let normalize = |router: Router| {
+        Router::new().fallback_service(
+            middleware::from_fn_with_state(trailing_slash, trailing_slash::normalize).layer(router),
+        )
+    };
+    Ok(Routers {
+        app: normalize(app),
+        metrics: normalize(metrics_app),
+        state,
+    })
+}
+
+/// Routes as data: each path with the handlers for its methods. The
+/// router is mounted from these tables and [`paths`] lists them, so there
+/// is one place a route is added.
+type Routes = Vec<(&'static str, MethodRouter<AppState>)>;
+
+/// One router serving `routes`.
+fn mount(routes: Routes) -> Router<AppState> {
+    routes
+        .into_iter()
+        .fold(Router::new(), |router, (path, methods)| {
+            router.route(path, methods)
+        })
+}
+
+/// Every path [`todo_router`] serves under some configuration, relative to
+/// `server.base_path`, such as `/todos/:id`.
+pub fn paths() -> Vec<&'static str> {
+    [
+        admin_routes(),
+        token_routes(),
+        fragment_routes(),
+        github_routes(),
+        api_routes(),
+        streaming_routes(),
+        probe_routes(),
+        metrics_routes(),
+        #[cfg(feature = "graphql")]
+        crate::graphql::routes(),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|(path, _)| path)
+    .collect()
+}
+
+/// Behind [`admin::require_admin`].
+fn admin_routes() -> Routes {
+    vec![
+        ("/admin", get(admin::dashboard)),
+        ("/admin/overview", get(admin::overview)),
+        ("/admin/vacuum", post(admin::vacuum)),
+        (admin::TRASH_PATH, delete(admin::purge_trash)),
+        ("/admin/storage", get(admin::storage)),
+        ("/admin/backup", get(backup::download)),
+        ("/admin/users/:id/role", put(admin::set_role)),
+        ("/admin/users/:id/quota", put(quotas::set_limits)),
+        (
+            "/admin/maintenance",
+            get(maintenance::status).post(maintenance::switch),
+        ),
+    ]
+}
+
+/// Mounted when the server issues tokens.
+fn token_routes() -> Routes {
+    vec![
+        (auth::LOGIN_PATH, post(auth::login)),
+        (auth::REFRESH_PATH, post(auth::refresh)),
+        (accounts::REGISTER_PATH, post(accounts::register)),
+        (accounts::PASSWORD_PATH, post(accounts::change_password)),
+        (
+            sessions::SESSION_PATH,
+            post(sessions::login).delete(sessions::logout),
+        ),
+    ]
+}
+
+/// Behind [`fragments::require_htmx`].
+fn fragment_routes() -> Routes {
+    vec![
+        (
+            "/fragments/todos",
+            get(fragments::list).post(fragments::create),
+        ),
+        ("/fragments/todos/new", get(fragments::new_form)),
+        (
+            "/fragments/todos/:id",
+            get(fragments::row)
+                .put(fragments::update)
+                .delete(fragments::delete),
+        ),
+        ("/fragments/todos/:id/edit", get(fragments::edit_form)),
+        ("/fragments/todos/:id/toggle", post(fragments::toggle)),
+    ]
+}
+
+/// Mounted when GitHub sign-in is configured.
+fn github_routes() -> Routes {
+    vec![
+        (github::GITHUB_PATH, get(github::start)),
+        (github::CALLBACK_PATH, get(github::callback)),
+    ]
+}
+
+/// Behind the request timeout and load shedding.
+fn api_routes() -> Routes {
+    vec![
+        ("/", get(frontend::root)),
+        ("/todos", get(todos::list_todos).post(todos::create_todo)),
+        ("/todos/batch", put(todos::batch_update_todos)),
+        ("/todos/delete-batch", post(todos::delete_batch)),
+        ("/todos/export.md", get(todos::export_markdown)),
+        ("/todos/group", get(todos::group_todos)),
+        ("/todos/random", get(todos::random_todo)),
+        ("/todos/changes", get(sync::changes)),
+        (
+            "/todos/:id",
+            get(todos::get_todo)
+                .put(todos::update_todo)
+                .delete(todos::delete_todo),
+        ),
+        ("/todos/:id/history", get(todos::todo_history)),
+        ("/todos/:id/move", post(todos::move_todo)),
+        ("/todos/:id/merge", post(todos::merge_todo)),
+        ("/tags/apply", post(todos::apply_tags)),
+        ("/search", get(todos::search)),
+        (
+            "/preferences",
+            get(preferences::show).put(preferences::replace),
+        ),
+        ("/shares", post(shares::create)),
+        ("/shares/:token", delete(shares::revoke)),
+        ("/shared/:token", get(shares::view)),
+        (quotas::USAGE_PATH, get(quotas::usage)),
+        (docs::DOCS_PATH, get(docs::page)),
+        (docs::SPEC_PATH, get(docs::spec)),
+        ("/docs/:file", get(docs::asset)),
+        (pages::APP_PATH, get(pages::show)),
+        ("/app/todos", post(pages::create)),
+        ("/app/todos/:id/toggle", post(pages::toggle)),
+        ("/app/todos/:id/delete", post(pages::delete)),
+    ]
+}
+
+/// Long-lived responses, outside the request timeout.
+fn streaming_routes() -> Routes {
+    vec![
+        ("/todos/stream", get(todos::stream_todos)),
+        ("/todos/events", get(live::sse::events)),
+        ("/ws", get(live::ws::connect)),
+    ]
+}
+
+/// Outside rate limiting and load shedding.
+fn probe_routes() -> Routes {
+    vec![
+        (health::HEALTH_PATH, get(health::health)),
+        (health::LIVEZ_PATH, get(health::livez)),
+        (health::READYZ_PATH, get(health::readyz)),
+        (version::VERSION_PATH, get(version::version)),
+        (frontend::FAVICON_ICO_PATH, get(frontend::favicon_ico)),
+        (frontend::FAVICON_SVG_PATH, get(frontend::favicon_svg)),
+        (frontend::ICON_192_PATH, get(frontend::icon_192)),
+        (frontend::ICON_512_PATH, get(frontend::icon_512)),
+        (frontend::ROBOTS_PATH, get(frontend::robots)),
+        (pwa::MANIFEST_PATH, get(pwa::manifest)),
+        (pwa::SERVICE_WORKER_PATH, get(pwa::service_worker)),
+    ]
+}
+
+fn metrics_routes() -> Routes {
+    vec![(metrics::METRICS_PATH, get(metrics::render))]
+}
+
+/// Every route behind the full middleware stack, on `state`, with the
+/// background tasks that go with them. Its links and redirects start with
+/// `server.base_path`, so nest it there to serve it inside another router;
+/// nothing else about it depends on where it is mounted.
+pub fn todo_router(state: AppState) -> Router {
+    let config = Arc::clone(&state.config);
+    let db = state.db.clone();
+    let authenticator = Arc::clone(&state.auth);
+    let limiter = Arc::new(RateLimiter::new(config.rate_limit.clone()));
+
+    let request_timeout = Duration::from_secs(config.server.request_timeout_secs);
+    let retry_after_secs = config.load_shed.retry_after_secs;
+
+    let admin = mount(admin_routes()).route_layer(middleware::from_fn_with_state(
+        state.clone(),
+        admin::require_admin,
+    ));
+    let tokens = if authenticator.issues_tokens() {
+        mount(token_routes())
+    } else {
+        Router::new()
+    };
+    // The htmx page's partials; see `fragments::require_htmx`.
+    let fragments = mount(fragment_routes()).route_layer(middleware::from_fn_with_state(
+        state.clone(),
+        fragments::require_htmx,
+    ));
+    let github = if authenticator.github().is_some() {
+        mount(github_routes())
+    } else {
+        Router::new()
+    };
+
+    // Routes that must finish within the request timeout and that count
+    // toward the load-shedding ceiling. Long-lived streaming routes get
+    // merged in separately so they are not cut off.
+    let api = mount(api_routes())
+        .merge(fragments)
+        .merge(admin)
+        .merge(tokens)
+        .merge(github);
+    #[cfg(feature = "graphql")]
+    let api = api.merge(mount(crate::graphql::routes()));
+    #[cfg(feature = "test-routes")]
+    let api = api.merge(mount(crate::test_routes::routes()));
+    let api = api.route_layer(
+        ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(move |err| {
+                handle_layer_error(err, retry_after_secs)
+            }))
+            .option_layer(load_shed::layer(&config.load_shed))
+            .timeout(request_timeout),
+    );
+
+    // Gzip/brotli per Accept-Encoding, skipping tiny bodies and content that
+    // is already compressed or streamed. tower-http does not add `Vary`
+    // itself, so caches are told separately that the encoding is negotiated.
+    let compression = CompressionLayer::new().compress_when(
+        SizeAbove::new(MIN_COMPRESS_SIZE)
+            .and(NotForContentType::GRPC)
+            .and(NotForContentType::IMAGES)
+            .and(NotForContentType::const_new("text/event-stream"))
+            .and(NotForContentType::const_new("application/x-ndjson"))
+            .and(NotForContentType::const_new("application/octet-stream"))
+            .and(NotForContentType::const_new("application/vnd.sqlite3")),
+    );
+
+    let mut app = mount(streaming_routes()).merge(api);
+    app = match &config.server.static_dir {
+        Some(dir) => {
+            if !dir.is_dir() {
+                tracing::warn!(
+                    dir = %dir.display(),
+                    "static_dir does not exist; serving the built-in page"
+                );
+            }
+            static_dir::serve(app, dir, state.clone())
+        }
+        None => app.fallback(frontend::asset),
+    };
+
+    app = app.layer(middleware::from_fn_with_state(
+        config.server.lenient_json,
+        lenient::apply,
+    ));
+
+    archive::spawn(&config.todos, db.clone());
+
+    if let Some(warning) = CountWarning::new(&config.todos) {
+        let warning = Arc::new(warning);
+        warning.spawn_refresh(db.clone());
+        app = app.layer(middleware::from_fn_with_state(warning, capacity::annotate));
+    }
+
+    // Inside compression, so response bodies are still plain text here.
+    if config.log.bodies {
+        if tracing::enabled!(target: "todo_api::body_log", tracing::Level::DEBUG) {
+            tracing::warn!("LOG_BODIES is on: request and response bodies are logged");
+        } else {
+            tracing::warn!(
+                "LOG_BODIES is on, but debug logging is off for todo_api::body_log; \
+                 no bodies will be logged"
+            );
+        }
+        app = app.layer(middleware::from_fn(body_log::record));
+    }
+
+    app = app
+        .layer(compression)
+        .layer(SetResponseHeaderLayer::appending(
+            header::VARY,
+            HeaderValue::from_static("accept-encoding"),
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            quotas::throttle,
+        ))
+        .layer(middleware::from_fn_with_state(limiter, rate_limit::enforce))
+        .layer(middleware::from_fn_with_state(
+            state.maintenance.clone(),
+            maintenance::enforce,
+        ))
+        // Added after the layers above so probes skip rate limiting and load
+        // shedding; they sit outside `/todos` so no wildcard can shadow them.
+        // The icons, robots.txt and the app manifest are as cheap, and
+        // browsers and crawlers ask for them unprompted.
+        .merge(mount(probe_routes()))
+        .layer(middleware::from_fn_with_state(
+            authenticator,
+            auth::authenticate,
+        ))
+        .layer(CatchPanicLayer::custom(handle_panic))
+        .layer(middleware::from_fn(reporting::report));
+
+    let metrics_enabled = config.metrics.enabled;
+    let metrics_elsewhere = config.metrics.addr.filter(|_| metrics_enabled);
+    if metrics_enabled {
+        if metrics_elsewhere.is_none() {
+            app = app.merge(mount(metrics_routes()));
+        }
+        app = app.layer(middleware::from_fn_with_state(
+            Arc::clone(&state.metrics),
+            metrics::track,
+        ));
+    }
+    // Inside `log_requests`, so warnings carry the request span.
+    if config.latency_budget.is_active() {
+        app = app.layer(middleware::from_fn_with_state(
+            Arc::new(config.latency_budget.clone()),
+            latency_budget::check,
+        ));
+    }
+    app = app.layer(middleware::from_fn(log_requests));
+
+    // Preflights are answered by the CORS layer before reaching rate limiting
+    // or any handler, so they never touch the database.
+    if let Some(cors) = cors::layer(&config.cors) {
+        app = app
+            .layer(cors)
+            .layer(middleware::from_fn(cors::preflight_no_content));
+    }
+    if let Some(access_log) = state.access_log.clone() {
+        app = app.layer(middleware::from_fn_with_state(
+            access_log,
+            access_log::record,
+        ));
+    }
+
+    app.layer(middleware::from_fn_with_state(
+        Arc::clone(&config),
+        error::negotiate,
+    ))
+    .layer(middleware::from_fn(request_id::propagate))
+    .with_state(state)
+}
+
+/// Builds the app and serves it on the configured listeners, plus the
+/// metrics listener and gRPC when configured, until Ctrl-C or SIGTERM.
+pub async fn serve(config: Config, db: Db) -> anyhow::Result<()> {
+    let Routers {
+        app,
+        metrics: metrics_app,
+        state,
+    } = build(config, db).await?;
+    let config = Arc::clone(&state.config);
+    let readiness = state.readiness.clone();
+    let live = state.live.clone();
+    let metrics_elsewhere = config.metrics.addr.filter(|_| config.metrics.enabled);
+
+    // Every listener stops accepting on the same signal and drains in-flight
+    // requests before `serve` returns. `/readyz` fails from the signal on,
+    // and listeners stay open for the shutdown delay so load balancers can
+    // notice before connections are refused.
+    let (shutdown_tx, shutdown_rx) = watch::channel(());
+    let shutdown_delay = Duration::from_secs(config.server.shutdown_delay_secs);
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        readiness.start_draining();
+        if !shutdown_delay.is_zero() {
+            tracing::info!(
+                delay_secs = shutdown_delay.as_secs(),
+                "draining before shutdown"
+            );
+            tokio::time::sleep(shutdown_delay).await;
+        }
+        tracing::info!("shutting down");
+        live.close();
+        let _ = shutdown_tx.send(());
+    });
+    let shutdown = |mut rx: watch::Receiver<()>| async move {
+        let _ = rx.changed().await;
+    };
+
+    let mut listeners: Vec<_> = listener::open(&config.server)?
+        .into_iter()
+        .map(|listener| ("✅ Running Todo API on", listener, app.clone()))
+        .collect();
+    if let Some(addr) = metrics_elsewhere {
+        let listener = std::net::TcpListener::bind(addr)
+            .with_context(|| format!("failed to bind metrics address {}", addr))?;
+        listeners.push((
+            "📈 Serving metrics on",
+            Listener::Tcp(listener),
+            metrics_app,
+        ));
+    }
+
+    let mut servers = JoinSet::new();
+    for (what, listener, app) in listeners {
+        tracing::info!("{} {}", what, listener);
+        let shutdown = shutdown(shutdown_rx.clone());
+        match listener {
+            Listener::Tcp(listener) => {
+                let server = Server::from_tcp(listener)?;
+                servers.spawn(async move {
+                    server
+                        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                        .with_graceful_shutdown(shutdown)
+                        .await?;
+                    Ok(())
+                });
+            }
+            Listener::Unix(accept) => {
+                servers.spawn(async move {
+                    Server::builder(accept)
+                        .serve(app.into_make_service())
+                        .with_graceful_shutdown(shutdown)
+                        .await?;
+                    Ok(())
+                });
+            }
+        }
+    }
+    grpc::spawn(&state, &mut servers, shutdown_rx)?;
+
+    while let Some(result) = servers.join_next().await {
+        result??;
+    }
+    // Upgraded connections are no longer the servers' to wait for.
+    state.live.closed(WS_CLOSE_TIMEOUT).await;
+
+    Ok(())
+}
+
+/// Resolves on Ctrl-C or SIGTERM.
+async fn shutdown_signal() {
+    let terminate = async {
+        match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(mut stream) => {
+                stream.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+
+    tokio::select! {
+        _ = signal::ctrl_c() => {}
+        _ = terminate => {}
+    }
+}
+
+/// Turns a panic below this layer into the usual JSON `500`. The panic
+/// itself was already logged, with its backtrace, by the panic hook.
+fn handle_panic(payload: Box<dyn Any + Send + 'static>) -> Response {
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("Box<dyn Any>");
+    ApiError::internal()
+        .caused_by(format!("panicked: {}", message))
+        .into_response()
+}
+
+/// Runs every request inside a `request` span carrying method, path and
+/// request id, then emits one completion event with status and latency.
+async fn log_requests<B>(req: Request<B>, next: Next<B>) -> Response {
+    let request_id = req
+        .extensions()
+        .get::<RequestId>()
+        .map(|id| id.0.clone())
+        .unwrap_or_default();
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned());
+    let span = tracing::info_span!(
+        "request",
+        method = %req.method(),
+        path = %shares::redact(req.uri().path()),
+        request_id = %request_id,
+        principal = tracing::field::Empty,
+        route = tracing::field::Empty,
+        todo_id = tracing::field::Empty,
+        status = tracing::field::Empty,
+        otel.name = tracing::field::Empty,
+        otel.kind = tracing::field::Empty,
+        otel.status_code = tracing::field::Empty,
+    );
+    if let Some(route) = &route {
+        span.record("route", route.as_str());
+        if route.starts_with("/todos/:id") {
+            if let Some(id) = req.uri().path().split('/').nth(2) {
+                span.record("todo_id", id);
+            }
+        }
+    }
+    let exporting = telemetry::enabled();
+    if exporting {
+        let name = format!(
+            "{} {}",
+            req.method(),
+            route.as_deref().unwrap_or("unmatched")
+        );
+        span.record("otel.name", name.as_str());
+        span.record("otel.kind", "server");
+        telemetry::set_parent(&span, req.headers());
+    }
+    let start = Instant::now();
+
+    async move {
+        let response = next.run(req).await;
+        let status = response.status();
+
+        tracing::info!(
+            status = status.as_u16(),
+            latency_ms = start.elapsed().as_millis() as u64,
+            "request completed"
+        );
+        if exporting {
+            let span = Span::current();
+            span.record("status", status.as_u16());
+            if status.is_server_error() {
+                span.record("otel.status_code", "ERROR");
+            }
+        }
+
+        response
+    }
+    .instrument(span)
+    .await
+}
+
+/// Turns a timed-out handler into a structured `503` with code
+/// `request_timeout`. Dropping the handler future also drops any in-flight
+/// query, returning its connection to the pool.
+async fn handle_layer_error(err: BoxError, retry_after_secs: u64) -> Response {
+    if err.is::<tower::timeout::error::Elapsed>() {
+        tracing::warn!("request timed out");
+        ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "request timed out")
+            .with_detail("code", REQUEST_TIMEOUT_CODE)
+            .into_response()
+    } else if err.is::<tower::load_shed::error::Overloaded>() {
+        load_shed::overloaded(retry_after_secs)
+    } else {
+        tracing::error!(error = %err, "unhandled middleware error");
+        ApiError::internal().caused_by(err).into_response()
+    }
+}
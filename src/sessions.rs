@@ -0,0 +1,302 @@
+use std::sync::Arc;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::{
+    body::Body,
+    extract::{FromRequest, State},
+    http::{header, HeaderMap, HeaderValue, Method, Request, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use utoipa::ToSchema;
+
+use crate::auth::{keys_match, Authenticator};
+use crate::error::ApiError;
+use crate::extract::JsonBody;
+
+pub const SESSION_PATH: &str = "/auth/session";
+pub const COOKIE_NAME: &str = "todo_session";
+/// Header the page sends its CSRF token in.
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+/// `last_seen_at` is written at most this often per session, so browsing
+/// does not turn every read into a write.
+const TOUCH_INTERVAL_SECS: i64 = 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    /// A session unused for this long is logged out.
+    pub idle_timeout_secs: u64,
+    /// Mark the cookie `Secure`, for deployments served over HTTPS.
+    pub secure_cookie: bool,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout_secs: 1800,
+            secure_cookie: false,
+        }
+    }
+}
+
+/// The browser session a request was authenticated with. Set on the request
+/// so the page can embed its CSRF token.
+#[derive(Debug, Clone)]
+pub struct Session {
+    id: String,
+    pub user_id: String,
+    pub csrf_token: String,
+}
+
+/// 256 random bits, hex-encoded.
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Sessions are stored under the digest of their cookie value, so a leaked
+/// table cannot be replayed as cookies. Share links are stored the same way.
+pub fn token_digest(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// The session cookie's value, if the request carries one.
+pub fn presented_cookie(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == COOKIE_NAME)
+        .map(|(_, value)| value)
+}
+
+/// Whether a request with this method can change anything, and so needs
+/// the CSRF token when authenticated by cookie.
+pub fn is_state_changing(method: &Method) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Whether the request carries the session's CSRF token.
+pub fn csrf_matches(headers: &HeaderMap, session: &Session) -> bool {
+    headers
+        .get(CSRF_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|token| keys_match(token, &session.csrf_token))
+}
+
+/// The live session for cookie value `token`. An expired one is deleted and
+/// reported as `None`, like one that was never there or was revoked.
+#[tracing::instrument(skip_all)]
+pub async fn resolve(
+    db: &SqlitePool,
+    config: &SessionConfig,
+    token: &str,
+) -> Result<Option<Session>, sqlx::Error> {
+    let id = token_digest(token);
+    let row: Option<(String, String, DateTime<Utc>)> =
+        sqlx::query_as("SELECT user_id, csrf_token, last_seen_at FROM sessions WHERE id = ?")
+            .bind(&id)
+            .fetch_optional(db)
+            .await?;
+    let Some((user_id, csrf_token, last_seen_at)) = row else {
+        return Ok(None);
+    };
+
+    let now = Utc::now();
+    let idle = ChronoDuration::seconds(config.idle_timeout_secs as i64);
+    if now - last_seen_at > idle {
+        sqlx::query("DELETE FROM sessions WHERE id = ?")
+            .bind(&id)
+            .execute(db)
+            .await?;
+        return Ok(None);
+    }
+    if now - last_seen_at > ChronoDuration::seconds(TOUCH_INTERVAL_SECS) {
+        sqlx::query("UPDATE sessions SET last_seen_at = ? WHERE id = ?")
+            .bind(now)
+            .bind(&id)
+            .execute(db)
+            .await?;
+    }
+
+    Ok(Some(Session {
+        id,
+        user_id,
+        csrf_token,
+    }))
+}
+
+/// Starts a session for `user_id`, returning its cookie value. Sessions
+/// idle past the timeout are swept out on the way.
+#[tracing::instrument(skip_all)]
+async fn create(
+    db: &SqlitePool,
+    config: &SessionConfig,
+    user_id: &str,
+) -> Result<(String, Session), sqlx::Error> {
+    let now = Utc::now();
+    let cutoff = now - ChronoDuration::seconds(config.idle_timeout_secs as i64);
+    sqlx::query("DELETE FROM sessions WHERE last_seen_at < ?")
+        .bind(cutoff)
+        .execute(db)
+        .await?;
+
+    let token = random_token();
+    let session = Session {
+        id: token_digest(&token),
+        user_id: user_id.to_string(),
+        csrf_token: random_token(),
+    };
+    sqlx::query(
+        "INSERT INTO sessions (id, user_id, csrf_token, created_at, last_seen_at)
+         VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(&session.id)
+    .bind(&session.user_id)
+    .bind(&session.csrf_token)
+    .bind(now)
+    .bind(now)
+    .execute(db)
+    .await?;
+    Ok((token, session))
+}
+
+/// Starts a session for `user_id` and returns its `Set-Cookie`, for logins
+/// that end in a redirect; the page then reads its CSRF token from `/`.
+pub async fn start(
+    db: &SqlitePool,
+    config: &SessionConfig,
+    user_id: &str,
+) -> Result<Option<HeaderValue>, sqlx::Error> {
+    let (token, _) = create(db, config, user_id).await?;
+    tracing::info!(user = %user_id, "started session");
+    Ok(cookie(config, &token))
+}
+
+/// `Set-Cookie` for `value`; an empty value with `Max-Age=0` clears it.
+fn cookie(config: &SessionConfig, value: &str) -> Option<HeaderValue> {
+    let mut cookie = format!("{}={}; Path=/; HttpOnly; SameSite=Lax", COOKIE_NAME, value);
+    if value.is_empty() {
+        cookie.push_str("; Max-Age=0");
+    }
+    if config.secure_cookie {
+        cookie.push_str("; Secure");
+    }
+    HeaderValue::from_str(&cookie).ok()
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct Login {
+    #[serde(default)]
+    username: String,
+    #[serde(default)]
+    password: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Started {
+    user: String,
+    csrf_token: String,
+}
+
+/// `POST /auth/session`: logs the browser in with `{"username",
+/// "password"}`, checked like `POST /auth/login`, and sets the session
+/// cookie. Returns the CSRF token for the page to send back.
+#[utoipa::path(
+    post,
+    path = "/auth/session",
+    operation_id = "start_session",
+    tag = "account",
+    request_body = Login,
+    responses(
+        (status = 200, description = "Logged in; the cookie is set", body = Started,
+            headers(("set-cookie" = String, description = "The session cookie"))),
+        (status = 401, description = "Wrong username or password", body = ErrorBody),
+    )
+)]
+pub async fn login(
+    State(auth): State<Arc<Authenticator>>,
+    State(db): State<SqlitePool>,
+    req: Request<Body>,
+) -> Response {
+    let ip = auth.client_ip(&req);
+    if let Some(response) = auth.refuse_throttled(ip) {
+        return response;
+    }
+
+    let login = match JsonBody::<Login>::from_request(req, &()).await {
+        Ok(JsonBody(login)) => login,
+        Err(err) => return err.into_response(),
+    };
+    match auth.check_password(&login.username, login.password).await {
+        Ok(true) => {}
+        Ok(false) => {
+            auth.record_failure(ip);
+            tracing::warn!(client = ?ip, "rejected invalid credentials");
+            return ApiError::new(StatusCode::UNAUTHORIZED, "invalid credentials").into_response();
+        }
+        Err(err) => return ApiError::from(err).into_response(),
+    }
+    auth.clear_failures(ip);
+
+    let config = auth.session_config();
+    let (token, session) = match create(&db, config, &login.username).await {
+        Ok(created) => created,
+        Err(err) => return ApiError::from(err).into_response(),
+    };
+    tracing::info!(user = %login.username, "started session");
+
+    let mut response = Json(Started {
+        user: session.user_id,
+        csrf_token: session.csrf_token,
+    })
+    .into_response();
+    if let Some(cookie) = cookie(config, &token) {
+        response.headers_mut().insert(header::SET_COOKIE, cookie);
+    }
+    response
+}
+
+/// `DELETE /auth/session`: ends the session the request was made with and
+/// clears the cookie. Needs the CSRF token like any other change.
+#[utoipa::path(
+    delete,
+    path = "/auth/session",
+    operation_id = "end_session",
+    tag = "account",
+    responses((status = 204, description = "Logged out; the cookie is cleared"))
+)]
+pub async fn logout(
+    State(auth): State<Arc<Authenticator>>,
+    State(db): State<SqlitePool>,
+    session: Option<Extension<Session>>,
+) -> Response {
+    if let Some(Extension(session)) = session {
+        let deleted = sqlx::query("DELETE FROM sessions WHERE id = ?")
+            .bind(&session.id)
+            .execute(&db)
+            .await;
+        if let Err(err) = deleted {
+            return ApiError::from(err).into_response();
+        }
+        tracing::info!(user = %session.user_id, "ended session");
+    }
+
+    let mut response = StatusCode::NO_CONTENT.into_response();
+    if let Some(cookie) = cookie(auth.session_config(), "") {
+        response.headers_mut().insert(header::SET_COOKIE, cookie);
+    }
+    response
+}
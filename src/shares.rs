@@ -0,0 +1,288 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use utoipa::ToSchema;
+
+use crate::auth::UserId;
+use crate::config::Config;
+use crate::db::{self, TodoFilter};
+use crate::error::ApiError;
+use crate::extract::JsonBody;
+use crate::models::{responses, TodoResponse};
+use crate::sessions::{random_token, token_digest};
+use crate::tags;
+
+/// Shared views live under this prefix and need no credentials.
+pub const SHARED_PREFIX: &str = "/shared/";
+
+/// Paths whose next segment is a link's token, which is all it takes to
+/// read the list.
+const TOKEN_PREFIXES: [&str; 2] = [SHARED_PREFIX, "/shares/"];
+
+/// `path`, or a URL, with any link token in it replaced, for logs.
+pub fn redact(path: &str) -> Cow<'_, str> {
+    let mut redacted = Cow::Borrowed(path);
+    for prefix in TOKEN_PREFIXES {
+        let Some(start) = redacted.find(prefix).map(|at| at + prefix.len()) else {
+            continue;
+        };
+        let end = redacted[start..]
+            .find(['/', '?', '#'])
+            .map_or(redacted.len(), |len| start + len);
+        if start < end {
+            redacted = Cow::Owned(format!(
+                "{}[redacted]{}",
+                &redacted[..start],
+                &redacted[end..]
+            ));
+        }
+    }
+    redacted
+}
+
+/// Longest a link may be set to last: a year.
+const MAX_EXPIRES_IN_SECS: u64 = 365 * 24 * 60 * 60;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewShare {
+    /// Share only todos carrying one of these; all of them when empty.
+    #[serde(default)]
+    tags: Vec<String>,
+    /// The link stops working this long after it is created; never when
+    /// absent.
+    expires_in_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreatedShare {
+    token: String,
+    url: String,
+    tags: Vec<String>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SharedList {
+    tags: Vec<String>,
+    expires_at: Option<DateTime<Utc>>,
+    todos: Vec<TodoResponse>,
+}
+
+struct Share {
+    user_id: String,
+    tags: Vec<String>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+/// The live share for `token`. An expired one is deleted and reported as
+/// `None`, like one that was never there or was revoked.
+#[tracing::instrument(skip_all)]
+async fn find(db: &SqlitePool, token: &str) -> Result<Option<Share>, sqlx::Error> {
+    let id = token_digest(token);
+    let row: Option<(String, String, Option<DateTime<Utc>>)> =
+        sqlx::query_as("SELECT user_id, tags, expires_at FROM shares WHERE id = ?")
+            .bind(&id)
+            .fetch_optional(db)
+            .await?;
+    let Some((user_id, tags, expires_at)) = row else {
+        return Ok(None);
+    };
+
+    if expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+        sqlx::query("DELETE FROM shares WHERE id = ?")
+            .bind(&id)
+            .execute(db)
+            .await?;
+        return Ok(None);
+    }
+
+    Ok(Some(Share {
+        user_id,
+        tags: tags
+            .split(',')
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_owned)
+            .collect(),
+        expires_at,
+    }))
+}
+
+/// `POST /shares`: creates a read-only link to the user's todos, or to
+/// those carrying one of `"tags"`, optionally expiring after
+/// `"expires_in_secs"`. `201` with the token, which is shown only here.
+#[utoipa::path(
+    post,
+    path = "/shares",
+    operation_id = "create_share",
+    tag = "shares",
+    request_body = NewShare,
+    responses(
+        (status = 201, description = "The link", body = CreatedShare),
+        (status = 422, description = "Invalid tags or expiry", body = ErrorBody),
+    )
+)]
+pub async fn create(
+    State(db): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    UserId(user): UserId,
+    JsonBody(share): JsonBody<NewShare>,
+) -> Result<Response, ApiError> {
+    let tags = tags::normalize(share.tags)?;
+    let expires_at = match share.expires_in_secs {
+        Some(secs) if !(1..=MAX_EXPIRES_IN_SECS).contains(&secs) => {
+            return Err(ApiError::validation(format!(
+                "expires_in_secs must be between 1 and {}",
+                MAX_EXPIRES_IN_SECS
+            )));
+        }
+        Some(secs) => Some(Utc::now() + ChronoDuration::seconds(secs as i64)),
+        None => None,
+    };
+
+    let token = random_token();
+    sqlx::query(
+        "INSERT INTO shares (id, user_id, tags, created_at, expires_at) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(token_digest(&token))
+    .bind(&user)
+    .bind(tags.join(","))
+    .bind(Utc::now())
+    .bind(expires_at)
+    .execute(&db)
+    .await?;
+    tracing::info!(%user, ?tags, "created share link");
+
+    let created = CreatedShare {
+        url: format!("{}{}", config.server.base_path.url(SHARED_PREFIX), token),
+        token,
+        tags,
+        expires_at,
+    };
+    Ok((StatusCode::CREATED, Json(created)).into_response())
+}
+
+/// `DELETE /shares/:token`: revokes one of the user's links. `404` for
+/// someone else's, like for an unknown one.
+#[utoipa::path(
+    delete,
+    path = "/shares/{token}",
+    operation_id = "revoke_share",
+    tag = "shares",
+    params(("token" = String, Path, description = "The link's token")),
+    responses(
+        (status = 204, description = "Revoked"),
+        (status = 404, description = "Not one of the user's links", body = ErrorBody),
+    )
+)]
+pub async fn revoke(
+    State(db): State<SqlitePool>,
+    UserId(user): UserId,
+    Path(token): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let result = sqlx::query("DELETE FROM shares WHERE id = ? AND user_id = ?")
+        .bind(token_digest(&token))
+        .bind(&user)
+        .execute(&db)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::new(StatusCode::NOT_FOUND, "share not found"));
+    }
+    tracing::info!(%user, "revoked share link");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn render(list: &SharedList) -> String {
+    let items: String = list
+        .todos
+        .iter()
+        .map(|todo| {
+            format!(
+                "    <li><input type=\"checkbox\" disabled{} /> {}</li>\n",
+                if todo.completed { " checked" } else { "" },
+                crate::escape_html(&todo.title)
+            )
+        })
+        .collect();
+    let heading = if list.tags.is_empty() {
+        "Shared todos".to_string()
+    } else {
+        format!(
+            "Shared todos: {}",
+            crate::escape_html(&list.tags.join(", "))
+        )
+    };
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="UTF-8" />
+<meta name="viewport" content="width=device-width, initial-scale=1" />
+<meta name="robots" content="noindex" />
+<title>{heading}</title>
+<style>
+  body {{ font-family: Arial, sans-serif; margin: 2rem; }}
+  ul {{ list-style: none; padding: 0; }}
+</style>
+</head>
+<body>
+  <h1>{heading}</h1>
+  <ul>
+{items}  </ul>
+</body>
+</html>
+"#
+    )
+}
+
+/// `GET /shared/:token`: the shared todos, read-only, as JSON or, for
+/// browsers, as a plain HTML page. Needs no credentials; an unknown,
+/// revoked or expired token gets `404`.
+#[utoipa::path(
+    get,
+    path = "/shared/{token}",
+    operation_id = "view_shared_list",
+    tag = "shares",
+    params(("token" = String, Path, description = "The link's token")),
+    responses(
+        (status = 200, description = "The shared todos", content(
+            ("application/json" = SharedList),
+            ("text/html" = String),
+        )),
+        (status = 404, description = "Unknown, revoked or expired", body = ErrorBody),
+    )
+)]
+pub async fn view(
+    State(db): State<SqlitePool>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let Some(share) = find(&db, &token).await? else {
+        return Err(ApiError::new(StatusCode::NOT_FOUND, "share not found"));
+    };
+    let filter = TodoFilter {
+        tags: share.tags.clone(),
+        ..TodoFilter::default()
+    };
+    let todos = db::list_todos(&db, &share.user_id, &filter).await?;
+
+    let list = SharedList {
+        tags: share.tags,
+        expires_at: share.expires_at,
+        todos: responses(todos),
+    };
+    if crate::prefers(&headers, "text/html") {
+        Ok(Html(render(&list)).into_response())
+    } else {
+        Ok(Json(list).into_response())
+    }
+}
@@ -0,0 +1,62 @@
+use std::io::ErrorKind;
+use std::path::Path;
+
+use axum::{
+    handler::Handler,
+    http::{header, HeaderValue, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+use tower::ServiceBuilder;
+use tower_http::services::ServeDir;
+use tower_http::set_header::SetResponseHeaderLayer;
+
+use crate::{frontend, AppState};
+
+/// Serves the files under `dir` for paths no route matches, with their
+/// MIME type and `Last-Modified`. Browsers are told to revalidate every
+/// time, so an edited file shows on the next reload. `..` segments and
+/// directory listings are refused. Paths that name no file there fall
+/// through to the built-in frontend, so `dir` need only hold the files
+/// that differ.
+pub fn serve(router: Router<AppState>, dir: &Path, state: AppState) -> Router<AppState> {
+    let files = ServeDir::new(dir)
+        .append_index_html_on_directories(false)
+        .call_fallback_on_method_not_allowed(true)
+        .fallback(frontend::asset.with_state(state));
+    router.fallback_service(
+        ServiceBuilder::new()
+            .layer(middleware::from_fn(no_directories))
+            .layer(SetResponseHeaderLayer::if_not_present(
+                header::CACHE_CONTROL,
+                HeaderValue::from_static("no-cache"),
+            ))
+            .service(files),
+    )
+}
+
+/// `ServeDir` redirects a directory to its path with a trailing slash,
+/// which the trailing-slash rewrite would undo again; directories get the
+/// `404` any other path without a file gets.
+async fn no_directories<B>(req: Request<B>, next: Next<B>) -> Response {
+    let uri = req.uri().clone();
+    let response = next.run(req).await;
+    if response.status() == StatusCode::TEMPORARY_REDIRECT {
+        return crate::handlers::fallback(uri).await.into_response();
+    }
+    response
+}
+
+/// `index.html` from `dir`, read afresh for every request. `None` when it
+/// is not there, so the built-in page is served instead.
+pub async fn index(dir: &Path) -> Option<String> {
+    match tokio::fs::read_to_string(dir.join("index.html")).await {
+        Ok(html) => Some(html),
+        Err(err) if err.kind() == ErrorKind::NotFound => None,
+        Err(err) => {
+            tracing::warn!(error = %err, dir = %dir.display(), "failed to read index.html");
+            None
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! `GET /todos/changes`: what changed in the user's todos since a cursor,
+//! for clients that sync now and then rather than staying connected.
+//! Triggers number every write to a todo in the `todo_sync` table, one
+//! sequence for all users, and keep a todo's row once it is purged as its
+//! tombstone; the cursor is a number from that sequence, so the client's
+//! clock never comes into it.
+
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{QueryBuilder, Sqlite};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::auth::UserId;
+use crate::error::ApiError;
+use crate::models::{TodoResponse, TodoRow};
+use crate::{db, Db};
+
+/// Changes returned when no `limit` is given.
+pub const DEFAULT_PAGE_SIZE: i64 = 200;
+pub const MAX_PAGE_SIZE: i64 = 1000;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ChangesParams {
+    /// The `cursor` of an earlier response; everything when absent.
+    since: Option<String>,
+    /// 1 to 1000; 200 when absent.
+    limit: Option<i64>,
+}
+
+/// One todo's latest state: the whole todo, or, once deleted, only its id.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Change {
+    pub id: String,
+    pub deleted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub todo: Option<TodoResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Changes {
+    /// In the order they were made, each todo at most once.
+    pub changes: Vec<Change>,
+    /// Where to pick up from next time.
+    pub cursor: String,
+    /// More changes follow this page; ask again with `cursor` straight away.
+    pub has_more: bool,
+}
+
+/// `GET /todos/changes`: the user's todos written since `?since=`, each
+/// with its current state, deleted ones (soft-deleted or purged) as
+/// tombstones, and the cursor to send next time. Without `since`, the
+/// user's current todos, archived ones included, and no tombstones. A
+/// cursor past anything this server has handed out, as after restoring an
+/// older backup, gets `410`: the client should drop its copy and sync from
+/// scratch.
+#[utoipa::path(
+    get,
+    path = "/todos/changes",
+    tag = "todos",
+    params(ChangesParams),
+    responses(
+        (status = 200, description = "A page of changes, oldest first", body = Changes),
+        (status = 400, description = "A `since` that is not a cursor", body = ErrorBody),
+        (status = 410, description = "A cursor from another database", body = ErrorBody),
+        (status = 422, description = "A bad `limit`", body = ErrorBody),
+    )
+)]
+pub async fn changes(
+    State(db): State<Db>,
+    UserId(user): UserId,
+    Query(params): Query<ChangesParams>,
+) -> Result<Json<Changes>, ApiError> {
+    let since = match params.since.as_deref() {
+        None => None,
+        Some(cursor) => Some(
+            cursor
+                .parse::<i64>()
+                .ok()
+                .filter(|seq| *seq >= 0)
+                .ok_or_else(|| {
+                    ApiError::new(
+                        StatusCode::BAD_REQUEST,
+                        "since must be the cursor of an earlier response, not a time",
+                    )
+                })?,
+        ),
+    };
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(ApiError::validation(format!(
+            "limit must be between 1 and {}",
+            MAX_PAGE_SIZE
+        )));
+    }
+
+    // One transaction, so the todos match the numbers they were read with.
+    let mut tx = db.begin().await?;
+    let latest: i64 = sqlx::query_scalar("SELECT COALESCE(MAX(seq), 0) FROM todo_sync")
+        .fetch_one(&mut *tx)
+        .await?;
+    if since.is_some_and(|since| since > latest) {
+        return Err(
+            ApiError::new(StatusCode::GONE, "cursor is not from this database")
+                .with_detail("code", "cursor_unknown"),
+        );
+    }
+
+    // One row past the page tells whether there is more without a COUNT.
+    let rows: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT seq, todo_id FROM todo_sync
+         WHERE user_id = ?1 AND seq > ?2
+         AND (?3 OR EXISTS (SELECT 1 FROM todos WHERE todos.id = todo_sync.todo_id AND deleted_at IS NULL))
+         ORDER BY seq LIMIT ?4",
+    )
+    .bind(&user)
+    .bind(since.unwrap_or(0))
+    .bind(since.is_some())
+    .bind(limit + 1)
+    .fetch_all(&mut *tx)
+    .await?;
+    let has_more = rows.len() as i64 > limit;
+    let rows = &rows[..rows.len().min(limit as usize)];
+
+    let mut todos = HashMap::new();
+    if !rows.is_empty() {
+        let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(format!(
+            "SELECT {} FROM todos WHERE deleted_at IS NULL AND id IN (",
+            db::todo_columns()
+        ));
+        let mut ids = query.separated(", ");
+        for (_, id) in rows {
+            ids.push_bind(id);
+        }
+        query.push(")");
+        for todo in query
+            .build_query_as::<TodoRow>()
+            .fetch_all(&mut *tx)
+            .await?
+        {
+            todos.insert(todo.id.clone(), todo);
+        }
+    }
+
+    // Past the last change listed, or, on the last page, past the user's
+    // last change of all, which may be a tombstone left out above.
+    let cursor = match rows.last() {
+        Some((seq, _)) if has_more => *seq,
+        _ => {
+            sqlx::query_scalar(
+                "SELECT MAX(COALESCE(MAX(seq), 0), ?) FROM todo_sync WHERE user_id = ?",
+            )
+            .bind(since.unwrap_or(0))
+            .bind(&user)
+            .fetch_one(&mut *tx)
+            .await?
+        }
+    };
+    tx.commit().await?;
+
+    let changes = rows
+        .iter()
+        .map(|(_, id)| {
+            let todo = todos.remove(id).map(TodoResponse::from);
+            Change {
+                id: id.clone(),
+                deleted: todo.is_none(),
+                todo,
+            }
+        })
+        .collect();
+    Ok(Json(Changes {
+        changes,
+        cursor: cursor.to_string(),
+        has_more,
+    }))
+}
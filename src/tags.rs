@@ -0,0 +1,173 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::SqliteConnection;
+use utoipa::ToSchema;
+
+use crate::error::ApiError;
+use crate::history::{self, Action};
+
+pub const MAX_TAG_LEN: usize = 32;
+
+/// Selects a todo's tags as one comma-separated column, so a list of todos
+/// and their tags comes back from a single query.
+pub const TAGS_COLUMN: &str =
+    "(SELECT group_concat(tag, ',') FROM todo_tags WHERE todo_id = todos.id) AS tags";
+
+/// Tags attached to a todo, serialized as a plain JSON array.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(transparent)]
+pub struct Tags(pub Vec<String>);
+
+impl From<Option<String>> for Tags {
+    fn from(column: Option<String>) -> Self {
+        let mut tags: Vec<String> = column
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_owned)
+            .collect();
+        tags.sort();
+        Tags(tags)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMode {
+    /// Todos carrying at least one of the listed tags.
+    #[default]
+    Any,
+    /// Todos carrying every listed tag.
+    All,
+}
+
+/// Lowercases, trims, de-duplicates and sorts tags. Tags are limited to
+/// letters, digits, `-`, `_`, `:` and `.`, which keeps them safe to pass
+/// comma-separated in query strings.
+pub fn normalize(tags: Vec<String>) -> Result<Vec<String>, ApiError> {
+    let mut normalized = Vec::with_capacity(tags.len());
+
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if tag.is_empty() || tag.chars().count() > MAX_TAG_LEN {
+            return Err(ApiError::validation(format!(
+                "tags must be 1 to {} characters long",
+                MAX_TAG_LEN
+            )));
+        }
+        if !tag
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | ':' | '.'))
+        {
+            return Err(ApiError::validation(format!(
+                "tag '{}' may only contain letters, digits, '-', '_', ':' and '.'",
+                tag
+            )));
+        }
+        normalized.push(tag);
+    }
+
+    normalized.sort();
+    normalized.dedup();
+    Ok(normalized)
+}
+
+/// Parses a `?tags=work,urgent` query value.
+pub fn parse_list(param: &str) -> Result<Vec<String>, ApiError> {
+    normalize(
+        param
+            .split(',')
+            .filter(|tag| !tag.trim().is_empty())
+            .map(str::to_owned)
+            .collect(),
+    )
+}
+
+/// Replaces every tag on `todo_id` with `tags`.
+#[tracing::instrument(skip_all)]
+pub async fn replace(
+    conn: &mut SqliteConnection,
+    todo_id: &str,
+    tags: &[String],
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM todo_tags WHERE todo_id = ?")
+        .bind(todo_id)
+        .execute(&mut *conn)
+        .await?;
+
+    for tag in tags {
+        sqlx::query("INSERT INTO todo_tags (todo_id, tag) VALUES (?, ?)")
+            .bind(todo_id)
+            .bind(tag)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Adds `add` to and removes `remove` from every listed todo of the user
+/// that exists, skipping tags a todo already has or lacks. Returns how many
+/// of the todos exist. Todos whose tags actually changed get a history entry. Run in a
+/// transaction so the change lands on all or none.
+#[tracing::instrument(skip_all)]
+pub async fn apply(
+    conn: &mut SqliteConnection,
+    user_id: &str,
+    ids: &[String],
+    add: &[String],
+    remove: &[String],
+) -> Result<u64, sqlx::Error> {
+    let mut updated = 0;
+
+    for id in ids {
+        let exists: Option<String> = sqlx::query_scalar(
+            "SELECT id FROM todos WHERE id = ? AND user_id = ? AND deleted_at IS NULL",
+        )
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+        if exists.is_none() {
+            continue;
+        }
+        updated += 1;
+
+        let mut changed = 0;
+        for tag in add {
+            changed += sqlx::query("INSERT OR IGNORE INTO todo_tags (todo_id, tag) VALUES (?, ?)")
+                .bind(id)
+                .bind(tag)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+        }
+        for tag in remove {
+            changed += sqlx::query("DELETE FROM todo_tags WHERE todo_id = ? AND tag = ?")
+                .bind(id)
+                .bind(tag)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+        }
+
+        if changed > 0 {
+            sqlx::query("UPDATE todos SET updated_at = ? WHERE id = ?")
+                .bind(Utc::now())
+                .bind(id)
+                .execute(&mut *conn)
+                .await?;
+            let tags: Vec<String> =
+                sqlx::query_scalar("SELECT tag FROM todo_tags WHERE todo_id = ? ORDER BY tag")
+                    .bind(id)
+                    .fetch_all(&mut *conn)
+                    .await?;
+            let mut changes = serde_json::Map::new();
+            changes.insert("tags".into(), tags.into());
+            history::record(conn, id, Action::Updated, changes).await?;
+        }
+    }
+
+    Ok(updated)
+}
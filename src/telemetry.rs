@@ -0,0 +1,133 @@
+//! Optional OpenTelemetry trace export, compiled in with the `otel` feature
+//! and switched on at runtime by `OTEL_EXPORTER_OTLP_ENDPOINT`. Without the
+//! feature, or without an endpoint, every function here is a no-op.
+
+use axum::http::HeaderMap;
+use tracing::Span;
+use tracing_subscriber::{Layer, Registry};
+
+/// Sits directly on the registry, below the log output layer.
+pub type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+#[cfg(feature = "otel")]
+mod otlp {
+    use std::sync::OnceLock;
+
+    use axum::http::HeaderMap;
+    use opentelemetry::propagation::{Extractor, TextMapPropagator};
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry::KeyValue;
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+    use opentelemetry_sdk::trace::{Config, TracerProvider};
+    use opentelemetry_sdk::{runtime, Resource};
+    use tracing::level_filters::LevelFilter;
+    use tracing::Span;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::Layer;
+
+    use super::BoxedLayer;
+
+    const ENDPOINT_VARS: [&str; 2] = [
+        "OTEL_EXPORTER_OTLP_ENDPOINT",
+        "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+    ];
+
+    static PROVIDER: OnceLock<TracerProvider> = OnceLock::new();
+
+    pub fn enabled() -> bool {
+        PROVIDER.get().is_some()
+    }
+
+    pub fn layer() -> Result<Option<BoxedLayer>, String> {
+        let configured = ENDPOINT_VARS
+            .iter()
+            .any(|var| std::env::var(var).is_ok_and(|value| !value.trim().is_empty()));
+        if !configured {
+            return Ok(None);
+        }
+
+        // OTEL_SERVICE_NAME still wins when it is set.
+        let mut resource = Resource::default();
+        if std::env::var_os("OTEL_SERVICE_NAME").is_none() {
+            resource = resource.merge(&Resource::new([KeyValue::new(
+                "service.name",
+                env!("CARGO_PKG_NAME"),
+            )]));
+        }
+
+        // The tonic exporter reads the endpoint, headers and timeout from
+        // the standard OTEL_EXPORTER_OTLP_* variables.
+        let provider = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(opentelemetry_otlp::new_exporter().tonic())
+            .with_trace_config(Config::default().with_resource(resource))
+            .install_batch(runtime::Tokio)
+            .map_err(|err| err.to_string())?;
+        let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+        let _ = PROVIDER.set(provider);
+
+        Ok(Some(
+            tracing_opentelemetry::layer()
+                .with_tracer(tracer)
+                .with_filter(LevelFilter::INFO)
+                .boxed(),
+        ))
+    }
+
+    pub fn set_parent(span: &Span, headers: &HeaderMap) {
+        let context = TraceContextPropagator::new().extract(&HeaderExtractor(headers));
+        span.set_parent(context);
+    }
+
+    pub fn shutdown() {
+        if let Some(provider) = PROVIDER.get() {
+            if let Err(err) = provider.shutdown() {
+                tracing::warn!(error = %err, "failed to flush traces");
+            }
+        }
+    }
+
+    struct HeaderExtractor<'a>(&'a HeaderMap);
+
+    impl Extractor for HeaderExtractor<'_> {
+        fn get(&self, key: &str) -> Option<&str> {
+            self.0.get(key).and_then(|value| value.to_str().ok())
+        }
+
+        fn keys(&self) -> Vec<&str> {
+            self.0.keys().map(|key| key.as_str()).collect()
+        }
+    }
+}
+
+/// Whether spans are being exported. Checked once per request, so a build
+/// without an endpoint pays for a single branch.
+pub fn enabled() -> bool {
+    #[cfg(feature = "otel")]
+    return otlp::enabled();
+    #[cfg(not(feature = "otel"))]
+    false
+}
+
+/// The export layer, or `None` when no OTLP endpoint is configured. Must be
+/// called from inside the Tokio runtime; the batch exporter runs on it.
+pub fn layer() -> Result<Option<BoxedLayer>, String> {
+    #[cfg(feature = "otel")]
+    return otlp::layer();
+    #[cfg(not(feature = "otel"))]
+    Ok(None)
+}
+
+/// Continues the trace named by an incoming W3C `traceparent` header, if any.
+pub fn set_parent(span: &Span, headers: &HeaderMap) {
+    #[cfg(feature = "otel")]
+    otlp::set_parent(span, headers);
+    #[cfg(not(feature = "otel"))]
+    let _ = (span, headers);
+}
+
+/// Flushes buffered spans. Called once the server has drained.
+pub fn shutdown() {
+    #[cfg(feature = "otel")]
+    otlp::shutdown();
+}
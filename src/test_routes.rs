@@ -0,0 +1,68 @@
+//! Routes that exist only so the integration tests can drive the middleware
+//! into its failure paths. Compiled with the `test-routes` feature, which
+//! the crate's own dev-dependency on itself turns on; a normal build has
+//! none of them.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, post, MethodRouter},
+    Json,
+};
+use serde::Deserialize;
+
+use crate::auth::UserId;
+use crate::config::Config;
+use crate::error::{ApiError, FieldErrors};
+use crate::extract::JsonBody;
+use crate::handlers::todos::insert_todo;
+use crate::models::{CreateTodo, TodoResponse};
+use crate::quotas::Quotas;
+use crate::{AppState, Db};
+
+/// Mounted among the API routes, behind the request timeout, and left out
+/// of [`crate::server::paths`].
+pub fn routes() -> Vec<(&'static str, MethodRouter<AppState>)> {
+    vec![
+        ("/__test/slow", get(slow)),
+        ("/__test/panic", get(panic)),
+        ("/__test/todos/:id", post(create_with_id)),
+    ]
+}
+
+#[derive(Debug, Deserialize)]
+struct SlowParams {
+    ms: u64,
+}
+
+/// `204` after `?ms=` milliseconds.
+async fn slow(Query(params): Query<SlowParams>) -> StatusCode {
+    tokio::time::sleep(Duration::from_millis(params.ms)).await;
+    StatusCode::NO_CONTENT
+}
+
+/// Panics, as a handler with a bug would.
+async fn panic() -> StatusCode {
+    panic!("test route panicked")
+}
+
+/// `POST /todos`, with the id the path gives instead of a generated one,
+/// as if the generator had come up with it.
+async fn create_with_id(
+    Path(id): Path<String>,
+    State(db): State<Db>,
+    State(config): State<Arc<Config>>,
+    State(quotas): State<Arc<Quotas>>,
+    UserId(user): UserId,
+    JsonBody(payload): JsonBody<CreateTodo>,
+) -> Result<Json<TodoResponse>, ApiError> {
+    let mut todo = payload
+        .check(&config, FieldErrors::default())
+        .map_err(FieldErrors::into_error)?;
+    todo.id = id;
+    insert_todo(&db, &config, &quotas, &user, &mut todo).await?;
+    Ok(Json(TodoResponse::from(todo)))
+}
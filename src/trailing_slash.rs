@@ -0,0 +1,99 @@
+use std::str::FromStr;
+
+use axum::{
+    extract::State,
+    http::{header, uri::PathAndQuery, HeaderValue, Request, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+
+/// What to do with a request path ending in `/`, such as `/todos/`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrailingSlash {
+    /// Route it as if the slash were not there.
+    #[default]
+    Rewrite,
+    /// Answer `308 Permanent Redirect` to the path without it.
+    Redirect,
+    /// Leave it alone; such paths usually match no route.
+    Strict,
+}
+
+impl FromStr for TrailingSlash {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "rewrite" => Ok(TrailingSlash::Rewrite),
+            "redirect" => Ok(TrailingSlash::Redirect),
+            "strict" => Ok(TrailingSlash::Strict),
+            _ => Err("expected rewrite, redirect or strict".to_string()),
+        }
+    }
+}
+
+impl TrailingSlash {
+    pub fn describe(self) -> &'static str {
+        match self {
+            TrailingSlash::Rewrite => "trailing slashes are ignored (/todos/ is served as /todos)",
+            TrailingSlash::Redirect => {
+                "trailing slashes are redirected (/todos/ answers 308 to /todos)"
+            }
+            TrailingSlash::Strict => "trailing slashes are significant (/todos/ is not /todos)",
+        }
+    }
+}
+
+/// `uri` with trailing slashes trimmed from its path, or `None` when there
+/// are none. `/` itself is left alone.
+fn trimmed(uri: &Uri) -> Option<Uri> {
+    let path = uri.path();
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.len() == path.len() || trimmed.is_empty() {
+        return None;
+    }
+
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{}?{}", trimmed, query),
+        None => trimmed.to_string(),
+    };
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(PathAndQuery::from_str(&path_and_query).ok()?);
+    Uri::from_parts(parts).ok()
+}
+
+/// Wraps the whole router, since rewriting the path only changes which route
+/// matches when it happens before routing.
+pub async fn normalize<B>(
+    State(mode): State<TrailingSlash>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let Some(uri) = trimmed(req.uri()) else {
+        return next.run(req).await;
+    };
+
+    match mode {
+        TrailingSlash::Rewrite => {
+            *req.uri_mut() = uri;
+            next.run(req).await
+        }
+        TrailingSlash::Redirect => {
+            let location = uri
+                .path_and_query()
+                .map(PathAndQuery::as_str)
+                .unwrap_or("/");
+            match HeaderValue::from_str(location) {
+                Ok(location) => (
+                    StatusCode::PERMANENT_REDIRECT,
+                    [(header::LOCATION, location)],
+                )
+                    .into_response(),
+                Err(_) => next.run(req).await,
+            }
+        }
+        TrailingSlash::Strict => next.run(req).await,
+    }
+}
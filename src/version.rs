@@ -0,0 +1,66 @@
+use axum::Json;
+use chrono::DateTime;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+pub const VERSION_PATH: &str = "/version";
+
+/// What was built, from compile-time data only, so it is available even
+/// when the database is not.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_commit: &'static str,
+    pub build_time: String,
+    pub backend: &'static str,
+    pub features: Vec<&'static str>,
+}
+
+pub fn build_info() -> BuildInfo {
+    let build_time = env!("TODO_API_BUILD_EPOCH")
+        .parse()
+        .ok()
+        .and_then(|epoch| DateTime::from_timestamp(epoch, 0))
+        .map(|time| time.to_rfc3339())
+        .unwrap_or_default();
+
+    let mut features = Vec::new();
+    if cfg!(feature = "systemd") {
+        features.push("systemd");
+    }
+
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_commit: env!("TODO_API_GIT_COMMIT"),
+        build_time,
+        backend: "sqlite",
+        features,
+    }
+}
+
+impl BuildInfo {
+    /// One line for the startup log and the HTML footer, e.g.
+    /// `todo-api 0.1.0 (3fc66ce1a2b4, built 2026-10-15T04:00:00+00:00, sqlite)`.
+    pub fn describe(&self) -> String {
+        let mut parts = vec![
+            self.git_commit.to_string(),
+            format!("built {}", self.build_time),
+            self.backend.to_string(),
+        ];
+        if !self.features.is_empty() {
+            parts.push(format!("features: {}", self.features.join(",")));
+        }
+        format!("todo-api {} ({})", self.version, parts.join(", "))
+    }
+}
+
+/// `GET /version`.
+#[utoipa::path(
+    get,
+    path = "/version",
+    tag = "probes",
+    responses((status = 200, description = "What is running", body = BuildInfo))
+)]
+pub async fn version() -> Json<BuildInfo> {
+    Json(build_info())
+}
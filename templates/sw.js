@@ -0,0 +1,35 @@
+// Generated by the server for this build; a new build changes the cache
+// name, which makes the browser install this worker afresh.
+const CACHE = {{ cache_name }};
+const PRECACHE = {{ precache }};
+
+self.addEventListener('install', (event) => {
+  event.waitUntil(
+    caches.open(CACHE)
+      .then((cache) => cache.addAll(PRECACHE.map((url) => new Request(url, { cache: 'reload' }))))
+      .then(() => self.skipWaiting())
+  );
+});
+
+// Drops the caches of earlier builds.
+self.addEventListener('activate', (event) => {
+  event.waitUntil(
+    caches.keys()
+      .then((names) => Promise.all(names.filter((name) => name !== CACHE).map((name) => caches.delete(name))))
+      .then(() => self.clients.claim())
+  );
+});
+
+// Only the precached files are answered from the cache. The page, the API
+// and the fragments always go to the network, so nothing personal or
+// stale is ever served from here.
+self.addEventListener('fetch', (event) => {
+  const url = new URL(event.request.url);
+  if (event.request.method !== 'GET' || url.origin !== self.location.origin || !PRECACHE.includes(url.pathname)) {
+    return;
+  }
+  event.respondWith(
+    caches.match(event.request, { ignoreSearch: true })
+      .then((cached) => cached || fetch(event.request))
+  );
+});
@@ -0,0 +1,2243 @@
+//! The router driven in-process with `ServiceExt::oneshot`: every route,
+//! each against a database of its own, without binding a port.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use axum::body::{Body, Bytes};
+use axum::http::{header, HeaderMap, Method, Request, StatusCode};
+use axum::Router;
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+use todo_api::config::Config;
+use todo_api::AppState;
+
+/// The app with the default configuration on a fresh in-memory database.
+async fn app() -> Router {
+    let db = todo_api::init_db("sqlite::memory:")
+        .await
+        .expect("in-memory database");
+    todo_api::app(db).await
+}
+
+/// A database file under the temp dir, removed with its WAL on drop, for
+/// routes that read the file itself.
+struct TempDb(PathBuf);
+
+impl TempDb {
+    fn new() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "todo_api-test-{}-{}.db",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        );
+        TempDb(std::env::temp_dir().join(name))
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let mut path = self.0.clone().into_os_string();
+            path.push(suffix);
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// The app configured by `toml`, on the file `db`.
+async fn app_with(db: &TempDb, toml: &str) -> Router {
+    let mut config: Config = toml::from_str(toml).expect("test configuration");
+    config.database.path = db.0.clone();
+    let pool = todo_api::init_db(&format!("sqlite://{}", db.0.display()))
+        .await
+        .expect("database file");
+    todo_api::server::build(config, pool)
+        .await
+        .expect("app for the test configuration")
+        .app
+}
+
+struct Reply {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl Reply {
+    fn json(&self) -> Value {
+        serde_json::from_slice(&self.body)
+            .unwrap_or_else(|err| panic!("{}: {}", err, String::from_utf8_lossy(&self.body)))
+    }
+
+    fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    fn header(&self, name: &str) -> &str {
+        self.headers
+            .get(name)
+            .unwrap_or_else(|| panic!("no {} header", name))
+            .to_str()
+            .unwrap()
+    }
+}
+
+async fn send(app: &Router, req: Request<Body>) -> Reply {
+    let response = app.clone().oneshot(req).await.unwrap();
+    let (parts, body) = response.into_parts();
+    Reply {
+        status: parts.status,
+        headers: parts.headers,
+        body: hyper::body::to_bytes(body).await.unwrap(),
+    }
+}
+
+async fn get(app: &Router, uri: &str) -> Reply {
+    send(app, Request::get(uri).body(Body::empty()).unwrap()).await
+}
+
+async fn call(app: &Router, method: Method, uri: &str, body: Value) -> Reply {
+    let req = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    send(app, req).await
+}
+
+async fn delete(app: &Router, uri: &str) -> Reply {
+    let req = Request::delete(uri).body(Body::empty()).unwrap();
+    send(app, req).await
+}
+
+async fn create(app: &Router, body: Value) -> Value {
+    let reply = call(app, Method::POST, "/todos", body).await;
+    assert_eq!(reply.status, StatusCode::OK, "{}", reply.text());
+    reply.json()
+}
+
+fn id(todo: &Value) -> &str {
+    todo["id"].as_str().unwrap()
+}
+
+/// Log output collected in memory, for a subscriber set as the test's
+/// default.
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Captured {
+    fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+impl std::io::Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for Captured {
+    type Writer = Captured;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+#[tokio::test]
+async fn crud_cycle() {
+    let app = app().await;
+
+    let todo = create(
+        &app,
+        json!({"title": "Buy milk", "tags": ["Shop"], "due_date": "2030-01-02", "priority": 4}),
+    )
+    .await;
+    assert_eq!(todo["title"], "Buy milk");
+    assert_eq!(todo["tags"], json!(["shop"]));
+    assert_eq!(todo["due_date"], "2030-01-02T00:00:00Z");
+    assert_eq!(todo["completed"], false);
+    let uri = format!("/todos/{}", id(&todo));
+
+    let fetched = get(&app, &uri).await;
+    assert_eq!(fetched.status, StatusCode::OK);
+    assert_eq!(fetched.json(), todo);
+
+    let list = get(&app, "/todos").await;
+    assert_eq!(list.status, StatusCode::OK);
+    assert_eq!(list.header("x-total-count"), "1");
+    assert_eq!(list.json(), json!([todo]));
+
+    let updated = call(
+        &app,
+        Method::PUT,
+        &uri,
+        json!({"completed": true, "due_date": null}),
+    )
+    .await;
+    assert_eq!(updated.status, StatusCode::OK);
+    let updated = updated.json();
+    assert_eq!(updated["completed"], true);
+    assert!(updated["completed_at"].is_string());
+    assert_eq!(updated["due_date"], Value::Null);
+    assert_eq!(updated["title"], "Buy milk");
+
+    let plain = Request::get(&uri)
+        .header(header::ACCEPT, "text/plain")
+        .body(Body::empty())
+        .unwrap();
+    let plain = send(&app, plain).await;
+    assert_eq!(plain.text(), format!("[x] Buy milk (id: {})\n", id(&todo)));
+
+    let history = get(&app, &format!("{}/history", uri)).await;
+    assert_eq!(history.status, StatusCode::OK);
+    assert_eq!(history.json()["entries"].as_array().unwrap().len(), 2);
+
+    assert_eq!(delete(&app, &uri).await.status, StatusCode::NO_CONTENT);
+    assert_eq!(get(&app, &uri).await.status, StatusCode::NOT_FOUND);
+    assert_eq!(delete(&app, &uri).await.status, StatusCode::NOT_FOUND);
+    assert_eq!(get(&app, "/todos").await.json(), json!([]));
+
+    // The same create as a plain HTML form.
+    let form = |body: &'static str| {
+        Request::post("/todos")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(body))
+            .unwrap()
+    };
+    let created = send(
+        &app,
+        form("title=Buy+bread&tags=Shop%2C+home&due_date=&priority=2"),
+    )
+    .await;
+    assert_eq!(created.status, StatusCode::OK, "{}", created.text());
+    let created = created.json();
+    assert_eq!(created["title"], "Buy bread");
+    assert_eq!(created["tags"], json!(["home", "shop"]));
+    assert_eq!(created["due_date"], Value::Null);
+    assert_eq!(created["priority"], 2);
+    let rejected = send(&app, form("title=&priority=soon")).await;
+    assert_eq!(rejected.status, StatusCode::UNPROCESSABLE_ENTITY);
+    let errors = &rejected.json()["errors"];
+    assert!(errors["title"].is_array() && errors["priority"].is_array());
+    // Media types are case-insensitive.
+    let mixed_case = Request::post("/todos")
+        .header(
+            header::CONTENT_TYPE,
+            "Application/X-WWW-Form-Urlencoded; charset=UTF-8",
+        )
+        .body(Body::from("title=Buy+eggs"))
+        .unwrap();
+    let created = send(&app, mixed_case).await;
+    assert_eq!(created.status, StatusCode::OK, "{}", created.text());
+    assert_eq!(created.json()["title"], "Buy eggs");
+}
+
+#[tokio::test]
+async fn invalid_input_is_rejected() {
+    let app = app().await;
+
+    let reply = call(
+        &app,
+        Method::POST,
+        "/todos",
+        json!({"title": " ", "priority": 9}),
+    )
+    .await;
+    assert_eq!(reply.status, StatusCode::UNPROCESSABLE_ENTITY);
+    let errors = &reply.json()["errors"];
+    assert!(errors["title"].is_array() && errors["priority"].is_array());
+
+    for due_in in ["3D", "P", "PT", "P1H", "P-1D", "PT1D"] {
+        let reply = call(
+            &app,
+            Method::POST,
+            "/todos",
+            json!({"title": "Later", "due_in": due_in}),
+        )
+        .await;
+        assert_eq!(reply.status, StatusCode::UNPROCESSABLE_ENTITY, "{}", due_in);
+        assert!(reply.json()["errors"]["due_in"].is_array());
+    }
+    let both = json!({"title": "Later", "due_date": "2030-01-01", "due_in": "P3D"});
+    let reply = call(&app, Method::POST, "/todos", both).await;
+    assert_eq!(reply.status, StatusCode::BAD_REQUEST);
+    let todo = create(&app, json!({"title": "Later", "due_in": "P1W2DT3H"})).await;
+    let due = chrono::DateTime::parse_from_rfc3339(todo["due_date"].as_str().unwrap()).unwrap();
+    let ahead = due.signed_duration_since(chrono::Utc::now());
+    assert!(
+        ahead > chrono::Duration::hours(9 * 24 + 2) && ahead <= chrono::Duration::hours(9 * 24 + 3)
+    );
+
+    let missing = call(&app, Method::PUT, "/todos/nope", json!({"title": "x"})).await;
+    assert_eq!(missing.status, StatusCode::NOT_FOUND);
+
+    // Bodies axum cannot parse get the same envelope as every other error.
+    let malformed = Request::post("/todos")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from("{\"title\": "))
+        .unwrap();
+    let reply = send(&app, malformed).await;
+    assert_eq!(reply.status, StatusCode::BAD_REQUEST);
+    assert!(reply.json()["request_id"].is_string(), "{}", reply.text());
+    let uri = format!("/todos/{}", id(&todo));
+    let reply = call(&app, Method::PUT, &uri, json!({"completed": "true"})).await;
+    assert_eq!(reply.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(reply.header("content-type"), "application/json");
+    assert!(reply.json()["request_id"].is_string());
+    // Type errors from deserializing are keyed like hand-written checks.
+    assert!(reply.json()["errors"]["completed"].is_array());
+    let reply = call(&app, Method::PUT, "/todos/batch", json!([{"title": "x"}])).await;
+    assert_eq!(reply.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(
+        reply.json()["errors"]["[0].id"].is_array(),
+        "{}",
+        reply.text()
+    );
+    let reply = call(&app, Method::PUT, "/preferences", json!({"theme": "neon"})).await;
+    assert_eq!(reply.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(
+        reply.json()["errors"]["theme"].is_array(),
+        "{}",
+        reply.text()
+    );
+    let untyped = Request::post("/tags/apply")
+        .body(Body::from("ids=1"))
+        .unwrap();
+    let reply = send(&app, untyped).await;
+    assert_eq!(reply.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert!(reply.json()["error"].is_string());
+
+    let reply = get(&app, "/todos?limit=0").await;
+    assert_eq!(reply.status, StatusCode::UNPROCESSABLE_ENTITY);
+    let reply = get(&app, "/todos?limit=201").await;
+    assert_eq!(reply.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        reply.json()["error"],
+        "limit must be between 1 and 200, not 201"
+    );
+
+    let reply = get(&app, "/no/such/route").await;
+    assert_eq!(reply.status, StatusCode::NOT_FOUND);
+    assert_eq!(reply.json()["path"], "/no/such/route");
+}
+
+#[tokio::test]
+async fn lenient_json_is_per_router() {
+    let db = TempDb::new();
+    let lenient = app_with(&db, "[server]\nlenient_json = true\n").await;
+    let strict = app().await;
+
+    for (app, expected) in [
+        (&lenient, StatusCode::OK),
+        (&strict, StatusCode::UNPROCESSABLE_ENTITY),
+        (&lenient, StatusCode::OK),
+    ] {
+        let todo = create(app, json!({"title": "Loosely typed"})).await;
+        let uri = format!("/todos/{}", id(&todo));
+        let reply = call(app, Method::PUT, &uri, json!({"completed": "true"})).await;
+        assert_eq!(reply.status, expected, "{}", reply.text());
+        if expected == StatusCode::OK {
+            assert_eq!(reply.json()["completed"], true);
+        }
+    }
+}
+
+#[tokio::test]
+async fn prefer_minimal() {
+    let app = app().await;
+
+    let req = Request::post("/todos")
+        .header(header::CONTENT_TYPE, "application/json")
+        .header("prefer", "return=minimal")
+        .body(Body::from(r#"{"title": "Quiet"}"#))
+        .unwrap();
+    let reply = send(&app, req).await;
+    assert_eq!(reply.status, StatusCode::NO_CONTENT);
+    let location = reply.header("location").to_string();
+
+    let reply = call(
+        &app,
+        Method::PUT,
+        &format!("{}?return=minimal", location),
+        json!({"title": "Still quiet"}),
+    )
+    .await;
+    assert_eq!(reply.status, StatusCode::NO_CONTENT);
+    assert_eq!(get(&app, &location).await.json()["title"], "Still quiet");
+}
+
+#[tokio::test]
+async fn nested_under_a_base_path() {
+    let db = todo_api::init_db("sqlite::memory:")
+        .await
+        .expect("in-memory database");
+    let mut config = Config::default();
+    config.server.base_path = "/tools/todos".parse().unwrap();
+    let state = AppState::new(config, db).await.expect("state");
+    let app = Router::new().nest("/tools/todos", todo_api::server::todo_router(state));
+
+    let req = Request::post("/tools/todos/todos")
+        .header(header::CONTENT_TYPE, "application/json")
+        .header("prefer", "return=minimal")
+        .body(Body::from(r#"{"title": "Nested"}"#))
+        .unwrap();
+    let reply = send(&app, req).await;
+    assert_eq!(reply.status, StatusCode::NO_CONTENT);
+    let location = reply.header("location").to_string();
+    assert!(location.starts_with("/tools/todos/todos/"), "{}", location);
+    assert_eq!(get(&app, &location).await.json()["title"], "Nested");
+
+    let list = get(&app, "/tools/todos/todos").await.json();
+    assert_eq!(list.as_array().unwrap().len(), 1);
+    let share = call(&app, Method::POST, "/tools/todos/shares", json!({})).await;
+    assert!(share.json()["url"]
+        .as_str()
+        .unwrap()
+        .starts_with("/tools/todos/shared/"));
+    assert!(get(&app, "/tools/todos")
+        .await
+        .text()
+        .contains(r#"<base href="/tools/todos/" />"#));
+    let manifest = get(&app, "/tools/todos/manifest.webmanifest").await.json();
+    assert_eq!(manifest["start_url"], "/tools/todos");
+
+    assert_eq!(delete(&app, &location).await.status, StatusCode::NO_CONTENT);
+    assert_eq!(get(&app, &location).await.status, StatusCode::NOT_FOUND);
+    assert_eq!(get(&app, "/todos").await.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn listing_filtering_and_paging() {
+    let app = app().await;
+    create(
+        &app,
+        json!({"title": "Write report", "tags": ["work"], "priority": 5}),
+    )
+    .await;
+    let done = create(&app, json!({"title": "Water plants", "tags": ["home"]})).await;
+    create(
+        &app,
+        json!({"title": "Plan trip", "tags": ["home", "fun"], "priority": 1}),
+    )
+    .await;
+    call(
+        &app,
+        Method::PUT,
+        &format!("/todos/{}", id(&done)),
+        json!({"completed": true}),
+    )
+    .await;
+
+    let titles = |reply: Reply| -> Vec<String> {
+        reply
+            .json()
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|todo| todo["title"].as_str().unwrap().to_string())
+            .collect()
+    };
+    assert_eq!(
+        titles(get(&app, "/todos?tags=home").await),
+        ["Water plants", "Plan trip"]
+    );
+    assert_eq!(
+        titles(get(&app, "/todos?tags=home,fun&tag_mode=all").await),
+        ["Plan trip"]
+    );
+    assert_eq!(
+        titles(get(&app, "/todos?completed=true").await),
+        ["Water plants"]
+    );
+    assert_eq!(
+        titles(get(&app, "/todos?completed=true,false").await).len(),
+        3
+    );
+    assert_eq!(
+        get(&app, "/todos?completed=maybe").await.status,
+        StatusCode::BAD_REQUEST
+    );
+    // Open, or completed since the given time.
+    assert_eq!(
+        titles(get(&app, "/todos?completed_after=2000-01-01").await).len(),
+        3
+    );
+    assert_eq!(
+        titles(get(&app, "/todos?completed_after=2999-01-01").await),
+        ["Write report", "Plan trip"]
+    );
+    assert_eq!(titles(get(&app, "/todos?q=REPORT").await), ["Write report"]);
+
+    let page = get(&app, "/todos?limit=1&offset=1").await;
+    assert_eq!(page.header("x-total-count"), "3");
+    assert_eq!(titles(page), ["Water plants"]);
+
+    let list = get(&app, "/todos").await;
+    let etag = list.header("etag").to_string();
+    let again = Request::get("/todos")
+        .header(header::IF_NONE_MATCH, &etag)
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(send(&app, again).await.status, StatusCode::NOT_MODIFIED);
+
+    let search = get(&app, "/search?q=plan").await;
+    assert_eq!(search.status, StatusCode::OK);
+    assert_eq!(titles(search), ["Plan trip", "Water plants"]);
+    assert_eq!(
+        get(&app, "/search?q=").await.status,
+        StatusCode::UNPROCESSABLE_ENTITY
+    );
+
+    let groups = get(&app, "/todos/group?by=completed").await;
+    assert_eq!(
+        groups.json(),
+        json!([{"key": false, "count": 2}, {"key": true, "count": 1}])
+    );
+    assert_eq!(
+        get(&app, "/todos/group?by=colour").await.status,
+        StatusCode::BAD_REQUEST
+    );
+
+    let random = get(&app, "/todos/random?tag=work").await;
+    assert_eq!(random.json()["title"], "Write report");
+
+    let export = get(&app, "/todos/export.md?group_by=none").await;
+    assert_eq!(export.status, StatusCode::OK);
+    assert!(export.header("content-type").starts_with("text/markdown"));
+    assert!(export.text().contains("- [x] Water plants"));
+
+    let stream = get(&app, "/todos/stream").await;
+    assert_eq!(stream.header("content-type"), "application/x-ndjson");
+    assert_eq!(stream.text().lines().count(), 3);
+
+    // Untagged, alone and with other filters.
+    create(&app, json!({"title": "Sort the plan"})).await;
+    assert_eq!(
+        titles(get(&app, "/todos?untagged=true").await),
+        ["Sort the plan"]
+    );
+    assert_eq!(
+        titles(get(&app, "/todos?untagged=true&q=plan").await),
+        ["Sort the plan"]
+    );
+    assert!(titles(get(&app, "/todos?untagged=true&completed=true").await).is_empty());
+    assert_eq!(
+        get(&app, "/todos?untagged=true&tags=home").await.status,
+        StatusCode::BAD_REQUEST
+    );
+}
+
+#[tokio::test]
+async fn batches_moves_merges_and_tags() {
+    let app = app().await;
+    let first = create(&app, json!({"title": "First"})).await;
+    let second = create(&app, json!({"title": "Second", "tags": ["b"]})).await;
+    let third = create(&app, json!({"title": "Third", "tags": ["c"]})).await;
+
+    let reply = call(
+        &app,
+        Method::PUT,
+        "/todos/batch",
+        json!([
+            {"id": id(&first), "priority": 5},
+            {"id": "missing", "completed": true},
+            {"id": id(&second), "title": ""},
+        ]),
+    )
+    .await;
+    let result = reply.json();
+    assert_eq!(result["updated"][0]["priority"], 5);
+    assert_eq!(result["not_found"], json!(["missing"]));
+    assert_eq!(result["errors"][0]["id"], id(&second));
+
+    let reply = call(
+        &app,
+        Method::PUT,
+        "/todos/batch?atomic=true",
+        json!([{"id": "missing", "completed": true}]),
+    )
+    .await;
+    assert_eq!(reply.status, StatusCode::NOT_FOUND);
+
+    let reply = call(
+        &app,
+        Method::POST,
+        &format!("/todos/{}/move", id(&third)),
+        json!({"before": id(&first)}),
+    )
+    .await;
+    assert_eq!(reply.status, StatusCode::OK);
+    let order: Vec<Value> = get(&app, "/todos").await.json().as_array().unwrap().clone();
+    assert_eq!(order[0]["title"], "Third");
+
+    let reply = call(
+        &app,
+        Method::POST,
+        "/tags/apply",
+        json!({"ids": [id(&first), id(&second)], "add": ["x"], "remove": ["b"]}),
+    )
+    .await;
+    assert_eq!(reply.json(), json!({"updated": 2}));
+
+    let reply = call(
+        &app,
+        Method::POST,
+        &format!("/todos/{}/merge", id(&second)),
+        json!({"into": id(&third)}),
+    )
+    .await;
+    assert_eq!(reply.status, StatusCode::OK);
+    assert_eq!(reply.json()["tags"], json!(["c", "x"]));
+    let second_uri = format!("/todos/{}", id(&second));
+    assert_eq!(get(&app, &second_uri).await.status, StatusCode::NOT_FOUND);
+
+    let reply = call(
+        &app,
+        Method::POST,
+        "/todos/delete-batch?purge=true",
+        json!({"ids": [id(&first), "missing"]}),
+    )
+    .await;
+    assert_eq!(
+        reply.json(),
+        json!({"soft_deleted": [], "purged": [id(&first)], "already_gone": ["missing"]})
+    );
+    assert_eq!(
+        delete(&app, &format!("/todos/{}?purge=true", id(&third)))
+            .await
+            .status,
+        StatusCode::NO_CONTENT
+    );
+}
+
+#[tokio::test]
+async fn preferences_shares_and_usage() {
+    let app = app().await;
+    create(&app, json!({"title": "Shared", "tags": ["trip"]})).await;
+    create(&app, json!({"title": "Private"})).await;
+
+    assert_eq!(get(&app, "/preferences").await.json(), json!({}));
+    let saved = call(&app, Method::PUT, "/preferences", json!({"theme": "dark"})).await;
+    assert_eq!(saved.status, StatusCode::OK);
+    assert_eq!(
+        get(&app, "/preferences").await.json(),
+        json!({"theme": "dark"})
+    );
+    let unknown = call(&app, Method::PUT, "/preferences", json!({"colour": "red"})).await;
+    assert_eq!(unknown.status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let share = call(&app, Method::POST, "/shares", json!({"tags": ["trip"]})).await;
+    assert_eq!(share.status, StatusCode::CREATED, "{}", share.text());
+    let token = share.json()["token"].as_str().unwrap().to_string();
+    let shared = get(&app, &format!("/shared/{}", token)).await;
+    assert_eq!(shared.status, StatusCode::OK);
+    let todos = &shared.json()["todos"];
+    assert_eq!(todos.as_array().unwrap().len(), 1);
+    assert_eq!(todos[0]["title"], "Shared");
+    let revoked = delete(&app, &format!("/shares/{}", token)).await;
+    assert_eq!(revoked.status, StatusCode::NO_CONTENT);
+    assert_eq!(
+        get(&app, &format!("/shared/{}", token)).await.status,
+        StatusCode::NOT_FOUND
+    );
+
+    let usage = get(&app, "/me/usage").await;
+    assert_eq!(usage.status, StatusCode::OK);
+    assert_eq!(usage.json()["todos"]["used"], 2);
+}
+
+#[tokio::test]
+async fn share_tokens_stay_out_of_logs() {
+    let db = TempDb::new();
+    let access_log = db.0.with_extension("access.log");
+    let toml = format!(
+        "[access_log]\npath = {:?}\nformat = \"json\"\n",
+        access_log.display().to_string()
+    );
+    let app = app_with(&db, &toml).await;
+    let logs = Captured::default();
+    let _default = tracing::subscriber::set_default(
+        tracing_subscriber::fmt()
+            .with_writer(logs.clone())
+            .with_ansi(false)
+            .finish(),
+    );
+
+    let share = call(&app, Method::POST, "/shares", json!({})).await.json();
+    let token = share["token"].as_str().unwrap();
+    let shared = Request::get(format!("/shared/{}", token))
+        .header(
+            header::REFERER,
+            format!("https://example.com/shared/{}", token),
+        )
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(send(&app, shared).await.status, StatusCode::OK);
+
+    let logged = logs.text();
+    assert!(logged.contains("/shared/[redacted]"), "{}", logged);
+    assert!(!logged.contains(token), "{}", logged);
+    // The access log is written by its own thread.
+    let mut lines = String::new();
+    for _ in 0..50 {
+        lines = std::fs::read_to_string(&access_log).unwrap_or_default();
+        if lines.contains("/shared/") {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    let _ = std::fs::remove_file(&access_log);
+    let line = lines
+        .lines()
+        .find(|line| line.contains("/shared/"))
+        .unwrap();
+    let entry: Value = serde_json::from_str(line).unwrap();
+    assert_eq!(entry["path"], "/shared/[redacted]");
+    assert_eq!(entry["referer"], "https://example.com/shared/[redacted]");
+    assert!(!lines.contains(token), "{}", lines);
+}
+
+#[tokio::test]
+async fn json_logs_are_one_flat_line_per_request() {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let app = app().await;
+    let logs = Captured::default();
+    let _default = tracing::subscriber::set_default(
+        tracing_subscriber::registry().with(todo_api::logging::json_layer(logs.clone())),
+    );
+
+    let reply = get(&app, "/todos?limit=5").await;
+    assert_eq!(reply.status, StatusCode::OK);
+    let request_id = reply.header("x-request-id").to_string();
+
+    let logged = logs.text();
+    let records: Vec<Value> = logged
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|err| panic!("{}: {}", err, line)))
+        .filter(|record: &Value| record.get("latency_ms").is_some())
+        .collect();
+    assert_eq!(records.len(), 1, "{}", logged);
+    let record = &records[0];
+    assert_eq!(record["request_id"], request_id.as_str());
+    assert_eq!(record["method"], "GET");
+    assert_eq!(record["path"], "/todos");
+    assert_eq!(record["status"], 200);
+    assert!(record["latency_ms"].is_u64(), "{}", record);
+}
+
+#[tokio::test]
+async fn slow_handlers_time_out() {
+    let db = TempDb::new();
+    let app = app_with(&db, "[server]\nrequest_timeout_secs = 1\n").await;
+
+    let started = std::time::Instant::now();
+    let slow = get(&app, "/__test/slow?ms=30000").await;
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    assert_eq!(
+        slow.status,
+        StatusCode::SERVICE_UNAVAILABLE,
+        "{}",
+        slow.text()
+    );
+    assert_eq!(slow.header("content-type"), "application/json");
+    let body = slow.json();
+    assert_eq!(body["code"], "request_timeout");
+    assert_eq!(body["request_id"], slow.header("x-request-id"));
+
+    // The timed-out handler is gone; the next request runs normally.
+    assert_eq!(
+        get(&app, "/__test/slow?ms=10").await.status,
+        StatusCode::NO_CONTENT
+    );
+    assert_eq!(get(&app, "/todos").await.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn large_lists_are_gzipped() {
+    use std::io::Read;
+
+    let app = app().await;
+    for n in 0..50 {
+        create(
+            &app,
+            json!({ "title": format!("Todo number {} with a longer title", n) }),
+        )
+        .await;
+    }
+
+    let plain = get(&app, "/todos?limit=100").await;
+    assert_eq!(plain.status, StatusCode::OK);
+    assert!(plain.headers.get(header::CONTENT_ENCODING).is_none());
+    assert!(plain.body.len() > 1024, "{}", plain.body.len());
+
+    let gzipped = send(
+        &app,
+        Request::get("/todos?limit=100")
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(gzipped.status, StatusCode::OK);
+    assert_eq!(gzipped.header("content-encoding"), "gzip");
+    assert!(gzipped
+        .headers
+        .get_all(header::VARY)
+        .iter()
+        .any(|value| value
+            .to_str()
+            .unwrap()
+            .to_lowercase()
+            .contains("accept-encoding")));
+    assert!(gzipped.body.len() < plain.body.len());
+
+    let mut inflated = Vec::new();
+    flate2::read::GzDecoder::new(&gzipped.body[..])
+        .read_to_end(&mut inflated)
+        .unwrap();
+    assert_eq!(inflated, plain.body);
+}
+
+#[tokio::test]
+async fn handler_panics_become_500s() {
+    let app = app().await;
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = hyper::Server::from_tcp(listener)
+        .unwrap()
+        .serve(app.into_make_service());
+    tokio::spawn(server);
+    let client = hyper::Client::new();
+    let fetch = |path: &str| {
+        let request = client.get(format!("http://{}{}", addr, path).parse().unwrap());
+        async move {
+            let response = request.await.unwrap();
+            let (parts, body) = response.into_parts();
+            Reply {
+                status: parts.status,
+                headers: parts.headers,
+                body: hyper::body::to_bytes(body).await.unwrap(),
+            }
+        }
+    };
+
+    let panicked = fetch("/__test/panic").await;
+    assert_eq!(panicked.status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(panicked.header("content-type"), "application/json");
+    let body = panicked.json();
+    assert_eq!(body["error"], "internal server error");
+    assert_eq!(body["request_id"], panicked.header("x-request-id"));
+    assert!(!panicked.text().contains("test route panicked"));
+
+    // The server, and the connection, keep serving.
+    let after = fetch("/todos").await;
+    assert_eq!(after.status, StatusCode::OK);
+    assert_eq!(after.json(), json!([]));
+}
+
+#[tokio::test]
+async fn tag_rows_need_their_todo() {
+    let db = TempDb::new();
+    let mut config = Config::default().database;
+    config.path = db.0.clone();
+    let file = todo_api::db::connect(&config).await.unwrap();
+    todo_api::db::migrate(&file).await.unwrap();
+    let memory = todo_api::init_db("sqlite::memory:").await.unwrap();
+
+    for pool in [&file, &memory] {
+        let err = sqlx::query("INSERT INTO todo_tags (todo_id, tag) VALUES ('no-such-todo', 'x')")
+            .execute(pool)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("FOREIGN KEY"), "{}", err);
+        let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM todo_tags")
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        assert_eq!(rows, 0);
+    }
+}
+
+#[tokio::test]
+async fn completion_times_follow_transitions() {
+    use chrono::{DateTime, Utc};
+
+    let db = TempDb::new();
+    let app = app_with(&db, "").await;
+    let file = todo_api::init_db(&format!("sqlite://{}", db.0.display()))
+        .await
+        .unwrap();
+    let todo = create(&app, json!({ "title": "Water the plants" })).await;
+    let path = format!("/todos/{}", id(&todo));
+    let stored = || {
+        sqlx::query_as::<_, (Option<DateTime<Utc>>, DateTime<Utc>)>(
+            "SELECT completed_at, updated_at FROM todos WHERE id = ?",
+        )
+        .bind(id(&todo))
+        .fetch_one(&file)
+    };
+    let (app, path) = (&app, path.as_str());
+    let put = |completed: bool| async move {
+        // Far enough apart for every write to get a later `updated_at`.
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        let reply = call(app, Method::PUT, path, json!({ "completed": completed })).await;
+        assert_eq!(reply.status, StatusCode::OK, "{}", reply.text());
+        reply.json()
+    };
+    let (completed_at, created) = stored().await.unwrap();
+    assert_eq!(completed_at, None);
+
+    // false -> true stamps the completion time.
+    let before = Utc::now();
+    let done = put(true).await;
+    let (completed_at, first) = stored().await.unwrap();
+    let completed_at = completed_at.expect("completed_at once completed");
+    assert!(before <= completed_at && completed_at <= Utc::now());
+    assert_eq!(
+        done["completed_at"]
+            .as_str()
+            .unwrap()
+            .parse::<DateTime<Utc>>()
+            .unwrap(),
+        completed_at
+    );
+    assert!(first > created);
+
+    // true -> true keeps it, though the todo was written.
+    let again = put(true).await;
+    assert_eq!(again["completed_at"], done["completed_at"]);
+    let (kept, second) = stored().await.unwrap();
+    assert_eq!(kept, Some(completed_at));
+    assert!(second > first);
+
+    // true -> false clears it.
+    let reopened = put(false).await;
+    assert_eq!(reopened["completed_at"], Value::Null);
+    let (cleared, third) = stored().await.unwrap();
+    assert_eq!(cleared, None);
+    assert!(third > second);
+}
+
+#[tokio::test]
+async fn errors_follow_the_accept_header() {
+    const BROWSER: &str = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+
+    let app = app().await;
+    let request = |method: Method, uri: &str, accept: &str| {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::ACCEPT, accept)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    // A handler's error, the router's bare 405 and a panic's 500.
+    for (method, uri, status, message) in [
+        (
+            Method::GET,
+            "/todos/no-such-todo",
+            StatusCode::NOT_FOUND,
+            "todo not found",
+        ),
+        (
+            Method::PATCH,
+            "/todos",
+            StatusCode::METHOD_NOT_ALLOWED,
+            "Method Not Allowed",
+        ),
+        (
+            Method::GET,
+            "/__test/panic",
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal server error",
+        ),
+    ] {
+        let page = send(&app, request(method.clone(), uri, BROWSER)).await;
+        assert_eq!(page.status, status, "{} {}", method, uri);
+        assert_eq!(page.header("content-type"), "text/html; charset=utf-8");
+        let html = page.text();
+        assert!(
+            html.contains(&format!(
+                "<span class=\"status\">{}</span>",
+                status.as_u16()
+            )),
+            "{}",
+            html
+        );
+        assert!(html.contains(message), "{}", html);
+        assert!(html.contains(page.header("x-request-id")), "{}", html);
+        assert!(html.contains("Back to your todos"), "{}", html);
+
+        for accept in ["application/json", "*/*"] {
+            let json = send(&app, request(method.clone(), uri, accept)).await;
+            assert_eq!(json.status, status, "{} {} {}", method, uri, accept);
+            if status != StatusCode::METHOD_NOT_ALLOWED {
+                assert_eq!(json.header("content-type"), "application/json");
+                let body = json.json();
+                assert_eq!(body["error"], message);
+                assert_eq!(body["request_id"], json.header("x-request-id"));
+            } else {
+                assert!(json.body.is_empty());
+            }
+        }
+    }
+
+    // htmx shows errors itself, whatever it accepts.
+    let mut htmx = request(Method::GET, "/todos/no-such-todo", BROWSER);
+    htmx.headers_mut()
+        .insert("hx-request", "true".parse().unwrap());
+    let htmx = send(&app, htmx).await;
+    assert_eq!(htmx.header("content-type"), "application/json");
+}
+
+#[test]
+fn replica_url_from_the_environment() {
+    use todo_api::config::Cli;
+
+    // The only test that sets these; nothing else reads them.
+    std::env::remove_var("READ_DATABASE_PATH");
+    std::env::set_var("READ_DATABASE_URL", "sqlite:///litefs/todos.db");
+    let config = Config::load(&Cli::default()).unwrap();
+    assert_eq!(
+        config.database.read_path.as_deref(),
+        Some(Path::new("/litefs/todos.db"))
+    );
+
+    for url in [
+        "postgres://replica/todos",
+        "sqlite::memory:",
+        "sqlite:todos.db?mode=ro",
+    ] {
+        std::env::set_var("READ_DATABASE_URL", url);
+        let err = Config::load(&Cli::default()).unwrap_err().to_string();
+        assert!(err.contains("READ_DATABASE_URL"), "{}: {}", url, err);
+    }
+
+    std::env::set_var("READ_DATABASE_URL", "sqlite:replica.db");
+    std::env::set_var("READ_DATABASE_PATH", "replica.db");
+    assert!(Config::load(&Cli::default()).is_err());
+
+    std::env::remove_var("READ_DATABASE_URL");
+    let config = Config::load(&Cli::default()).unwrap();
+    assert_eq!(
+        config.database.read_path.as_deref(),
+        Some(Path::new("replica.db"))
+    );
+    std::env::remove_var("READ_DATABASE_PATH");
+}
+
+#[tokio::test]
+async fn rate_limits_forget_the_least_recently_seen_client() {
+    let db = TempDb::new();
+    let app = app_with(
+        &db,
+        "[rate_limit]\ntrust_forwarded_for = true\nmax_tracked_clients = 2\n\
+         read = { per_second = 0.001, burst = 1 }\nwrite = { per_second = 0.001, burst = 1 }\n",
+    )
+    .await;
+    let from = |client: &str| {
+        let request = Request::get("/todos")
+            .header("x-forwarded-for", client)
+            .body(Body::empty())
+            .unwrap();
+        send(&app, request)
+    };
+
+    assert_eq!(from("10.0.0.1").await.status, StatusCode::OK);
+    let limited = from("10.0.0.1").await;
+    assert_eq!(limited.status, StatusCode::TOO_MANY_REQUESTS);
+    assert!(limited.headers.contains_key(header::RETRY_AFTER));
+    assert_eq!(from("10.0.0.2").await.status, StatusCode::OK);
+
+    // Seen again, 10.0.0.1 stays tracked, and a third client pushes out
+    // 10.0.0.2 instead.
+    assert_eq!(from("10.0.0.1").await.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(from("10.0.0.3").await.status, StatusCode::OK);
+    assert_eq!(from("10.0.0.1").await.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(from("10.0.0.2").await.status, StatusCode::OK);
+
+    // Many clients later, every one is still limited while it is tracked.
+    for n in 0..200 {
+        let client = format!("10.0.1.{}", n);
+        assert_eq!(from(&client).await.status, StatusCode::OK);
+        assert_eq!(from(&client).await.status, StatusCode::TOO_MANY_REQUESTS);
+    }
+}
+
+#[tokio::test]
+async fn head_answers_like_get_without_a_body() {
+    let app = app().await;
+    let todo = create(&app, json!({ "title": "Check on HEAD" })).await;
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = hyper::Server::from_tcp(listener)
+        .unwrap()
+        .serve(app.into_make_service());
+    tokio::spawn(server);
+    let client = hyper::Client::new();
+    let fetch = |method: Method, path: &str| {
+        let request = Request::builder()
+            .method(method)
+            .uri(format!("http://{}{}", addr, path))
+            .body(Body::empty())
+            .unwrap();
+        let response = client.request(request);
+        async move {
+            let (parts, body) = response.await.unwrap().into_parts();
+            Reply {
+                status: parts.status,
+                headers: parts.headers,
+                body: hyper::body::to_bytes(body).await.unwrap(),
+            }
+        }
+    };
+
+    for path in [
+        "/todos".to_string(),
+        format!("/todos/{}", id(&todo)),
+        "/todos/no-such-todo".to_string(),
+    ] {
+        let get = fetch(Method::GET, &path).await;
+        let head = fetch(Method::HEAD, &path).await;
+        assert_eq!(head.status, get.status, "{}", path);
+        assert!(head.body.is_empty(), "{}", path);
+        assert_eq!(
+            head.header("content-length"),
+            get.body.len().to_string(),
+            "{}",
+            path
+        );
+        // Everything but what differs per request or per charge.
+        for (name, value) in &get.headers {
+            if [
+                "date",
+                "x-request-id",
+                "x-ratelimit-remaining",
+                "x-ratelimit-reset",
+            ]
+            .contains(&name.as_str())
+            {
+                continue;
+            }
+            assert_eq!(head.headers.get(name), Some(value), "{} {}", path, name);
+        }
+        assert_eq!(head.headers.len(), get.headers.len(), "{}", path);
+    }
+    let list = fetch(Method::HEAD, "/todos").await;
+    assert!(list.header("etag").starts_with('"'));
+}
+
+#[tokio::test]
+async fn probes_and_static_files() {
+    let app = app().await;
+
+    for (uri, content_type) in [
+        ("/health", "application/json"),
+        ("/livez", "application/json"),
+        ("/readyz", "application/json"),
+        ("/version", "application/json"),
+        ("/metrics", "text/plain"),
+        ("/", "text/html"),
+        ("/favicon.ico", "image/"),
+        ("/favicon.svg", "image/svg+xml"),
+        ("/icon-192.png", "image/png"),
+        ("/icon-512.png", "image/png"),
+        ("/robots.txt", "text/plain"),
+        ("/manifest.webmanifest", "application/manifest+json"),
+        ("/sw.js", "application/javascript"),
+        ("/docs", "text/html"),
+        ("/docs/openapi.json", "application/json"),
+        ("/docs/swagger-ui.css", "text/css"),
+    ] {
+        let reply = get(&app, uri).await;
+        assert_eq!(reply.status, StatusCode::OK, "{}", uri);
+        assert!(
+            reply.header("content-type").starts_with(content_type),
+            "{}: {}",
+            uri,
+            reply.header("content-type")
+        );
+    }
+
+    let spec = get(&app, "/docs/openapi.json").await.json();
+    assert!(spec["paths"]["/todos/{id}"]["put"].is_object());
+    assert_eq!(
+        get(&app, "/docs/index.html").await.status,
+        StatusCode::NOT_FOUND
+    );
+
+    assert!(!get(&app, "/health")
+        .await
+        .headers
+        .contains_key("server-timing"));
+    let db = TempDb::new();
+    let timed = app_with(&db, "[latency_budget]\nserver_timing = true\n").await;
+    let timing = get(&timed, "/todos")
+        .await
+        .header("server-timing")
+        .to_string();
+    assert!(timing.starts_with("app;dur="), "{}", timing);
+}
+
+/// Form posts from `/app` and the htmx page's fragments.
+#[tokio::test]
+async fn html_pages_and_fragments() {
+    let app = app().await;
+
+    let form = |uri: &str, body: &str| {
+        Request::post(uri)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+    let created = send(
+        &app,
+        form(
+            "/app/todos",
+            "title=From+the+form&due_date=&priority=&csrf_token=",
+        ),
+    )
+    .await;
+    assert_eq!(created.status, StatusCode::SEE_OTHER);
+    let page = get(&app, "/app").await;
+    assert_eq!(page.status, StatusCode::OK);
+    assert!(page.text().contains("From the form"));
+
+    let todo = get(&app, "/todos").await.json()[0].clone();
+    let toggled = send(
+        &app,
+        form(
+            &format!("/app/todos/{}/toggle", id(&todo)),
+            "completed=true",
+        ),
+    )
+    .await;
+    assert_eq!(toggled.status, StatusCode::SEE_OTHER);
+    assert_eq!(get(&app, "/todos").await.json()[0]["completed"], true);
+    let deleted = send(&app, form(&format!("/app/todos/{}/delete", id(&todo)), "")).await;
+    assert_eq!(deleted.status, StatusCode::SEE_OTHER);
+    assert_eq!(get(&app, "/todos").await.json(), json!([]));
+
+    let htmx = |method: Method, uri: &str, body: &str| {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("hx-request", "true")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+    let created = send(
+        &app,
+        htmx(
+            Method::POST,
+            "/fragments/todos",
+            "title=Fragment&due_date=&priority=&csrf_token=",
+        ),
+    )
+    .await;
+    assert_eq!(created.status, StatusCode::OK, "{}", created.text());
+    assert_eq!(created.header("hx-trigger"), "todos-changed");
+    let todo = get(&app, "/todos").await.json()[0].clone();
+    let row = format!("/fragments/todos/{}", id(&todo));
+
+    for uri in [
+        "/fragments/todos".to_string(),
+        "/fragments/todos/new".to_string(),
+        row.clone(),
+        format!("{}/edit", row),
+    ] {
+        let reply = send(&app, htmx(Method::GET, &uri, "")).await;
+        assert_eq!(reply.status, StatusCode::OK, "{}", uri);
+        assert!(reply.header("content-type").starts_with("text/html"));
+    }
+
+    let updated = send(
+        &app,
+        htmx(Method::PUT, &row, "title=Renamed&due_date=&priority=3"),
+    )
+    .await;
+    assert_eq!(updated.status, StatusCode::OK, "{}", updated.text());
+    let toggled = send(
+        &app,
+        htmx(Method::POST, &format!("{}/toggle", row), "completed=true"),
+    )
+    .await;
+    assert_eq!(toggled.status, StatusCode::OK, "{}", toggled.text());
+    let todo = get(&app, &format!("/todos/{}", id(&todo))).await.json();
+    assert_eq!(
+        (todo["title"].as_str(), todo["completed"].as_bool()),
+        (Some("Renamed"), Some(true))
+    );
+    let deleted = send(&app, htmx(Method::DELETE, &row, "")).await;
+    assert_eq!(deleted.status, StatusCode::OK, "{}", deleted.text());
+    assert_eq!(get(&app, "/todos").await.json(), json!([]));
+
+    // Without htmx a fragment URL is the whole page.
+    let page = get(&app, "/fragments/todos").await;
+    assert!(page.text().contains("<!DOCTYPE html>"));
+}
+
+#[tokio::test]
+async fn websocket_pushes_changes() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    async fn read_text(socket: &mut tokio::net::TcpStream) -> Value {
+        let mut head = [0; 2];
+        socket.read_exact(&mut head).await.unwrap();
+        assert_eq!(head[0], 0x81, "a whole text frame");
+        let len = match head[1] {
+            126 => socket.read_u16().await.unwrap() as usize,
+            len => len as usize,
+        };
+        let mut payload = vec![0; len];
+        socket.read_exact(&mut payload).await.unwrap();
+        serde_json::from_slice(&payload).unwrap()
+    }
+
+    let app = app().await;
+    let plain = get(&app, "/ws").await;
+    assert_eq!(plain.status, StatusCode::BAD_REQUEST, "{}", plain.text());
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = hyper::Server::from_tcp(listener)
+        .unwrap()
+        .serve(app.clone().into_make_service());
+    tokio::spawn(server);
+
+    let mut socket = tokio::net::TcpStream::connect(addr).await.unwrap();
+    // The key and accept value from RFC 6455.
+    let handshake = format!(
+        "GET /ws HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        addr
+    );
+    socket.write_all(handshake.as_bytes()).await.unwrap();
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        response.push(socket.read_u8().await.unwrap());
+    }
+    let response = String::from_utf8(response).unwrap().to_lowercase();
+    assert!(response.starts_with("http/1.1 101"), "{}", response);
+    assert!(
+        response.contains("sec-websocket-accept: s3pplmbitxaq9kygzzhzrbk+xoo="),
+        "{}",
+        response
+    );
+
+    let todo = create(&app, json!({ "title": "Seen live" })).await;
+    let event = read_text(&mut socket).await;
+    assert_eq!(
+        (event["type"].as_str(), event["id"].as_str()),
+        (Some("created"), Some(id(&todo)))
+    );
+    assert_eq!(event["todo"], todo);
+
+    let toggled = call(
+        &app,
+        Method::PUT,
+        &format!("/todos/{}", id(&todo)),
+        json!({ "completed": true }),
+    )
+    .await;
+    assert_eq!(toggled.status, StatusCode::OK);
+    assert_eq!(read_text(&mut socket).await["type"], "toggled");
+
+    // Client frames are masked; a zero mask leaves the text as it is.
+    let ask = br#"{"type":"snapshot"}"#;
+    let mut frame = vec![0x81, 0x80 | ask.len() as u8, 0, 0, 0, 0];
+    frame.extend_from_slice(ask);
+    socket.write_all(&frame).await.unwrap();
+    let snapshot = read_text(&mut socket).await;
+    assert_eq!(snapshot["type"], "snapshot");
+    assert_eq!(snapshot["todos"].as_array().unwrap().len(), 1);
+
+    assert_eq!(
+        delete(&app, &format!("/todos/{}", id(&todo))).await.status,
+        StatusCode::NO_CONTENT
+    );
+    let event = read_text(&mut socket).await;
+    assert_eq!(event, json!({ "type": "deleted", "id": id(&todo) }));
+}
+
+#[tokio::test]
+async fn serves_over_a_unix_socket() {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    /// One request over a fresh connection to `socket`.
+    async fn over(socket: &Path, method: Method, uri: &str, body: Option<Value>) -> Reply {
+        let stream = tokio::net::UnixStream::connect(socket).await.unwrap();
+        let (mut sender, connection) = hyper::client::conn::handshake(stream).await.unwrap();
+        tokio::spawn(connection);
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::HOST, "localhost");
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        };
+        let response = sender.send_request(request.unwrap()).await.unwrap();
+        let (parts, body) = response.into_parts();
+        Reply {
+            status: parts.status,
+            headers: parts.headers,
+            body: hyper::body::to_bytes(body).await.unwrap(),
+        }
+    }
+
+    let db = TempDb::new();
+    let socket = db.0.with_extension("sock");
+    let mut config: Config = toml::from_str(&format!(
+        "[server]\nunix_socket = {:?}\nunix_socket_mode = \"0600\"",
+        socket
+    ))
+    .unwrap();
+    config.database.path = db.0.clone();
+    let pool = todo_api::init_db(&format!("sqlite://{}", db.0.display()))
+        .await
+        .unwrap();
+    let server = tokio::spawn(todo_api::server::serve(config, pool));
+
+    let mut waited = 0;
+    while !socket.exists() {
+        assert!(waited < 100, "the socket never appeared");
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        waited += 1;
+    }
+    let metadata = std::fs::metadata(&socket).unwrap();
+    assert!(metadata.file_type().is_socket());
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+    // Nothing is left of the directory it was bound in.
+    let staging = std::fs::read_dir(socket.parent().unwrap())
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            name.starts_with(&*socket.file_name().unwrap().to_string_lossy())
+                && name.ends_with(".tmp")
+        })
+        .count();
+    assert_eq!(staging, 0);
+
+    let created = over(
+        &socket,
+        Method::POST,
+        "/todos",
+        Some(json!({ "title": "Local" })),
+    )
+    .await;
+    assert_eq!(created.status, StatusCode::OK, "{}", created.text());
+    let todo = created.json();
+    let path = format!("/todos/{}", id(&todo));
+
+    let read = over(&socket, Method::GET, &path, None).await;
+    assert_eq!(read.status, StatusCode::OK);
+    assert_eq!(read.json()["title"], "Local");
+
+    let updated = over(
+        &socket,
+        Method::PUT,
+        &path,
+        Some(json!({ "completed": true })),
+    )
+    .await;
+    assert_eq!(updated.status, StatusCode::OK, "{}", updated.text());
+    assert_eq!(updated.json()["completed"], true);
+
+    let list = over(&socket, Method::GET, "/todos", None).await;
+    assert_eq!(list.json().as_array().unwrap().len(), 1);
+
+    let deleted = over(&socket, Method::DELETE, &path, None).await;
+    assert_eq!(deleted.status, StatusCode::NO_CONTENT);
+    let gone = over(&socket, Method::GET, &path, None).await;
+    assert_eq!(gone.status, StatusCode::NOT_FOUND);
+
+    server.abort();
+    let _ = server.await;
+    let _ = std::fs::remove_file(&socket);
+}
+
+#[tokio::test]
+async fn ipv6_wildcard_takes_both_families() {
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    /// `GET /health` at `addr`, retried while the server starts.
+    async fn health(addr: SocketAddr) -> StatusCode {
+        let uri: hyper::Uri = format!("http://{}/health", addr).parse().unwrap();
+        for _ in 0..100 {
+            if let Ok(response) = hyper::Client::new().get(uri.clone()).await {
+                return response.status();
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        panic!("nothing answered at {}", addr);
+    }
+
+    // A port free on both families; the listener is closed again right away.
+    let Ok(probe) = std::net::TcpListener::bind((Ipv6Addr::UNSPECIFIED, 0)) else {
+        eprintln!("skipping: this host has no IPv6");
+        return;
+    };
+    let port = probe.local_addr().unwrap().port();
+    drop(probe);
+    if std::net::TcpListener::bind((Ipv6Addr::LOCALHOST, 0)).is_err() {
+        eprintln!("skipping: this host has no IPv6 loopback");
+        return;
+    }
+
+    let db = TempDb::new();
+    let mut config: Config =
+        toml::from_str(&format!("[server]\naddr = \"[::]:{}\"", port)).unwrap();
+    config.database.path = db.0.clone();
+    let pool = todo_api::init_db(&format!("sqlite://{}", db.0.display()))
+        .await
+        .unwrap();
+    let server = tokio::spawn(todo_api::server::serve(config, pool));
+
+    let v4 = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    let v6 = SocketAddr::from((Ipv6Addr::LOCALHOST, port));
+    assert_eq!(health(v4).await, StatusCode::OK);
+    assert_eq!(health(v6).await, StatusCode::OK);
+
+    server.abort();
+    let _ = server.await;
+}
+
+#[tokio::test]
+async fn event_stream_follows_changes() {
+    use axum::body::BoxBody;
+    use hyper::body::HttpBody;
+
+    /// The next `count` events as `(id, event, data)`.
+    async fn next_events(body: &mut BoxBody, count: usize) -> Vec<(String, String, Value)> {
+        let mut text = String::new();
+        let mut events = Vec::new();
+        while events.len() < count {
+            let chunk = tokio::time::timeout(std::time::Duration::from_secs(5), body.data())
+                .await
+                .expect("an event in time")
+                .expect("stream open")
+                .unwrap();
+            text.push_str(std::str::from_utf8(&chunk).unwrap());
+            while let Some((block, rest)) = text.split_once("\n\n") {
+                let field = |name: &str| {
+                    block
+                        .lines()
+                        .find_map(|line| line.strip_prefix(name))
+                        .map(|value| value.strip_prefix(' ').unwrap_or(value))
+                        .unwrap_or_default()
+                        .to_string()
+                };
+                let data = field("data:");
+                let data = if data.is_empty() {
+                    Value::Null
+                } else {
+                    serde_json::from_str(&data).unwrap()
+                };
+                if !block.starts_with(':') {
+                    events.push((field("id:"), field("event:"), data));
+                }
+                text = rest.to_string();
+            }
+        }
+        events
+    }
+
+    async fn follow(app: &Router, last_event_id: Option<&str>) -> BoxBody {
+        let mut req = Request::get("/todos/events");
+        if let Some(id) = last_event_id {
+            req = req.header("last-event-id", id);
+        }
+        let response = app
+            .clone()
+            .oneshot(req.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/event-stream"
+        );
+        response.into_body()
+    }
+
+    let app = app().await;
+    let mut stream = follow(&app, None).await;
+
+    let todo = create(&app, json!({ "title": "Streamed" })).await;
+    let uri = format!("/todos/{}", id(&todo));
+    assert_eq!(
+        call(&app, Method::PUT, &uri, json!({ "title": "Renamed" }))
+            .await
+            .status,
+        StatusCode::OK
+    );
+    assert_eq!(
+        call(&app, Method::PUT, &uri, json!({ "completed": true }))
+            .await
+            .status,
+        StatusCode::OK
+    );
+    // Rejected writes announce nothing.
+    assert_eq!(
+        call(&app, Method::PUT, &uri, json!({ "title": "" }))
+            .await
+            .status,
+        StatusCode::UNPROCESSABLE_ENTITY
+    );
+    assert_eq!(
+        call(&app, Method::PUT, "/todos/nope", json!({ "title": "x" }))
+            .await
+            .status,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(delete(&app, &uri).await.status, StatusCode::NO_CONTENT);
+
+    let events = next_events(&mut stream, 4).await;
+    let kinds: Vec<_> = events.iter().map(|(_, kind, _)| kind.as_str()).collect();
+    assert_eq!(kinds, ["created", "updated", "toggled", "deleted"]);
+    assert!(events.iter().all(|(_, _, data)| data["id"] == todo["id"]));
+    assert_eq!(events[0].2["todo"], todo);
+    assert_eq!(events[1].2["todo"]["title"], "Renamed");
+    assert_eq!(events[2].2["todo"]["completed"], true);
+    let ids: Vec<u64> = events
+        .iter()
+        .map(|(id, _, _)| id.parse().unwrap())
+        .collect();
+    assert!(ids.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", ids);
+
+    // Reconnecting replays what came after the last event seen.
+    let mut resumed = follow(&app, Some(&events[1].0)).await;
+    let replayed = next_events(&mut resumed, 2).await;
+    assert_eq!(replayed, events[2..]);
+
+    // An id that is not kept asks the client to start over.
+    let mut stale = follow(&app, Some("999999")).await;
+    assert_eq!(next_events(&mut stale, 1).await[0].1, "reset");
+}
+
+#[tokio::test]
+async fn delta_sync_with_tombstones() {
+    let app = app().await;
+    let kept = create(&app, json!({"title": "Kept"})).await;
+    let removed = create(&app, json!({"title": "Removed"})).await;
+    let purged = create(&app, json!({"title": "Purged"})).await;
+
+    let first = get(&app, "/todos/changes").await.json();
+    assert_eq!(first["changes"].as_array().unwrap().len(), 3);
+    assert_eq!(first["changes"][0]["todo"], kept);
+    assert_eq!(first["has_more"], false);
+    let cursor = first["cursor"].as_str().unwrap();
+
+    call(
+        &app,
+        Method::PUT,
+        &format!("/todos/{}", id(&kept)),
+        json!({"title": "Kept, renamed"}),
+    )
+    .await;
+    delete(&app, &format!("/todos/{}", id(&removed))).await;
+    delete(&app, &format!("/todos/{}?purge=true", id(&purged))).await;
+    // Created and purged in between: the client never saw it, but still
+    // gets its tombstone.
+    let brief = create(&app, json!({"title": "Brief"})).await;
+    delete(&app, &format!("/todos/{}?purge=true", id(&brief))).await;
+
+    let since = format!("/todos/changes?since={}", cursor);
+    let reply = get(&app, &since).await;
+    let delta = reply.json();
+    let changes: Vec<(&str, bool)> = delta["changes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|change| (id(change), change["deleted"].as_bool().unwrap()))
+        .collect();
+    assert_eq!(
+        changes,
+        [
+            (id(&kept), false),
+            (id(&removed), true),
+            (id(&purged), true),
+            (id(&brief), true),
+        ]
+    );
+    assert_eq!(delta["changes"][0]["todo"]["title"], "Kept, renamed");
+    assert!(delta["changes"][1].get("todo").is_none());
+    // The same cursor gives the same answer.
+    assert_eq!(get(&app, &since).await.body, reply.body);
+
+    // Paged, the same changes in the same order.
+    let page = get(&app, &format!("{}&limit=3", since)).await.json();
+    assert_eq!(page["has_more"], true);
+    assert_eq!(
+        page["changes"].as_array().unwrap()[..],
+        delta["changes"].as_array().unwrap()[..3]
+    );
+    let rest = format!(
+        "/todos/changes?since={}&limit=3",
+        page["cursor"].as_str().unwrap()
+    );
+    let rest = get(&app, &rest).await.json();
+    assert_eq!(
+        rest["changes"].as_array().unwrap()[..],
+        delta["changes"].as_array().unwrap()[3..]
+    );
+    assert_eq!(rest["cursor"], delta["cursor"]);
+
+    let next = format!("/todos/changes?since={}", delta["cursor"].as_str().unwrap());
+    let caught_up = get(&app, &next).await.json();
+    assert_eq!(caught_up["changes"], json!([]));
+    assert_eq!(caught_up["cursor"], delta["cursor"]);
+
+    // Starting afresh lists no tombstones.
+    let fresh = get(&app, "/todos/changes").await.json();
+    assert_eq!(fresh["changes"].as_array().unwrap().len(), 1);
+    assert_eq!(fresh["cursor"], delta["cursor"]);
+
+    // The cursor is the server's, never the client's clock.
+    let reply = get(&app, "/todos/changes?since=2026-01-01T00:00:00Z").await;
+    assert_eq!(reply.status, StatusCode::BAD_REQUEST);
+    let reply = get(&app, "/todos/changes?since=999999").await;
+    assert_eq!(reply.status, StatusCode::GONE);
+    assert_eq!(reply.json()["code"], "cursor_unknown");
+    let reply = get(&app, "/todos/changes?limit=0").await;
+    assert_eq!(reply.status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn admin_routes() {
+    let db = TempDb::new();
+    let app = app_with(&db, "[admin]\napi_key = \"admin-secret\"\n").await;
+    let admin = |method: Method, uri: &str, body: Option<Value>| {
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("x-api-key", "admin-secret");
+        match body {
+            Some(body) => req
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => req.body(Body::empty()),
+        }
+        .unwrap()
+    };
+    create(&app, json!({"title": "Counted"})).await;
+
+    assert_eq!(
+        get(&app, "/admin/overview").await.status,
+        StatusCode::UNAUTHORIZED
+    );
+
+    let dashboard = send(&app, admin(Method::GET, "/admin", None)).await;
+    assert_eq!(dashboard.status, StatusCode::OK);
+    let overview = send(&app, admin(Method::GET, "/admin/overview", None)).await;
+    assert_eq!(overview.json()["todos"]["total"], 1);
+    let storage = send(&app, admin(Method::GET, "/admin/storage", None)).await;
+    assert_eq!(storage.status, StatusCode::OK);
+    let vacuum = send(&app, admin(Method::POST, "/admin/vacuum", None)).await;
+    assert_eq!(vacuum.status, StatusCode::OK, "{}", vacuum.text());
+    let backup = send(&app, admin(Method::GET, "/admin/backup", None)).await;
+    assert_eq!(backup.status, StatusCode::OK, "{}", backup.text());
+    assert!(backup.body.starts_with(b"SQLite format 3\0"));
+
+    let trashed = create(&app, json!({"title": "Trashed"})).await;
+    let uri = format!("/todos/{}", id(&trashed));
+    assert_eq!(delete(&app, &uri).await.status, StatusCode::NO_CONTENT);
+    assert_eq!(
+        delete(&app, "/todos/trash").await.status,
+        StatusCode::UNAUTHORIZED
+    );
+    let purge = send(&app, admin(Method::DELETE, "/todos/trash", None)).await;
+    assert_eq!(purge.json(), json!({"purged": 1}));
+    let again = send(&app, admin(Method::DELETE, "/todos/trash", None)).await;
+    assert_eq!(again.json(), json!({"purged": 0}));
+
+    let quota = send(
+        &app,
+        admin(
+            Method::PUT,
+            "/admin/users/default/quota",
+            Some(json!({"max_todos": 1})),
+        ),
+    )
+    .await;
+    assert_eq!(quota.status, StatusCode::OK, "{}", quota.text());
+    let over = call(
+        &app,
+        Method::POST,
+        "/todos",
+        json!({"title": "One too many"}),
+    )
+    .await;
+    assert_eq!(over.status, StatusCode::FORBIDDEN);
+
+    let role = send(
+        &app,
+        admin(
+            Method::PUT,
+            "/admin/users/nobody/role",
+            Some(json!({"role": "admin"})),
+        ),
+    )
+    .await;
+    assert_eq!(role.status, StatusCode::NOT_FOUND, "{}", role.text());
+
+    let switched = send(
+        &app,
+        admin(
+            Method::POST,
+            "/admin/maintenance",
+            Some(json!({"mode": "read_only"})),
+        ),
+    )
+    .await;
+    assert_eq!(switched.status, StatusCode::OK, "{}", switched.text());
+    let status = send(&app, admin(Method::GET, "/admin/maintenance", None)).await;
+    assert_eq!(status.json()["mode"], "read_only");
+    let refused = delete(&app, "/todos/anything").await;
+    assert_eq!(refused.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(get(&app, "/todos").await.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn a_taken_id_is_not_overwritten() {
+    let db = TempDb::new();
+    let app = app_with(
+        &db,
+        "[auth]\nregistration = \"open\"\n[auth.jwt]\nsigning_key = \"0123456789abcdef0123456789abcdef\"\n",
+    )
+    .await;
+    let mut bearers = Vec::new();
+    for name in ["alice", "bob"] {
+        let credentials = json!({"username": name, "password": "correct horse"});
+        call(&app, Method::POST, "/auth/register", credentials.clone()).await;
+        let login = call(&app, Method::POST, "/auth/login", credentials).await;
+        bearers.push(format!(
+            "Bearer {}",
+            login.json()["access_token"].as_str().unwrap()
+        ));
+    }
+    let as_user = |bearer: &str, method: Method, uri: &str, body: Option<Value>| {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, bearer)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+            .unwrap();
+        send(&app, request)
+    };
+    let (alice, bob) = (&bearers[0], &bearers[1]);
+
+    let mine = as_user(
+        alice,
+        Method::POST,
+        "/__test/todos/shared-id",
+        Some(json!({"title": "Alice's"})),
+    )
+    .await;
+    assert_eq!(mine.status, StatusCode::OK, "{}", mine.text());
+
+    // The generator handing Bob the same id must not give him Alice's todo.
+    let clash = as_user(
+        bob,
+        Method::POST,
+        "/__test/todos/shared-id",
+        Some(json!({"title": "Bob's"})),
+    )
+    .await;
+    assert_eq!(clash.status, StatusCode::CONFLICT, "{}", clash.text());
+    assert_eq!(clash.json()["code"], "id_taken");
+
+    let kept = as_user(alice, Method::GET, "/todos/shared-id", None).await;
+    assert_eq!(kept.json()["title"], "Alice's");
+    let theirs = as_user(bob, Method::GET, "/todos/shared-id", None).await;
+    assert_eq!(theirs.status, StatusCode::NOT_FOUND);
+    let listed = as_user(bob, Method::GET, "/todos", None).await;
+    assert_eq!(listed.json(), json!([]));
+}
+
+#[tokio::test]
+async fn admin_routes_need_the_admin_role() {
+    let db = TempDb::new();
+    let app = app_with(
+        &db,
+        "[admin]\napi_key = \"admin-secret\"\n[auth]\nregistration = \"open\"\n[auth.jwt]\nsigning_key = \"0123456789abcdef0123456789abcdef\"\n",
+    )
+    .await;
+    // The first to register becomes an admin by default; the other two
+    // start out as members, and one is promoted.
+    let mut bearers = Vec::new();
+    for name in ["first", "member", "promoted"] {
+        let credentials = json!({"username": name, "password": "correct horse"});
+        let registered = call(&app, Method::POST, "/auth/register", credentials.clone()).await;
+        assert_eq!(
+            registered.status,
+            StatusCode::CREATED,
+            "{}",
+            registered.text()
+        );
+        let login = call(&app, Method::POST, "/auth/login", credentials).await;
+        bearers.push(format!(
+            "Bearer {}",
+            login.json()["access_token"].as_str().unwrap()
+        ));
+    }
+    let promote = Request::put("/admin/users/promoted/role")
+        .header("x-api-key", "admin-secret")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({"role": "admin"}).to_string()))
+        .unwrap();
+    assert_eq!(send(&app, promote).await.status, StatusCode::OK);
+    let (member, admin) = (&bearers[1], &bearers[2]);
+
+    let routes = [
+        (Method::GET, "/admin", None),
+        (Method::GET, "/admin/overview", None),
+        (Method::POST, "/admin/vacuum", None),
+        (Method::DELETE, "/todos/trash", None),
+        (Method::GET, "/admin/storage", None),
+        (Method::GET, "/admin/backup", None),
+        (
+            Method::PUT,
+            "/admin/users/member/role",
+            Some(json!({"role": "member"})),
+        ),
+        (
+            Method::PUT,
+            "/admin/users/member/quota",
+            Some(json!({"max_todos": 100})),
+        ),
+        (Method::GET, "/admin/maintenance", None),
+        (
+            Method::POST,
+            "/admin/maintenance",
+            Some(json!({"mode": "off"})),
+        ),
+    ];
+    for (method, uri, body) in routes {
+        let request = |bearer: Option<&str>| {
+            let mut req = Request::builder().method(method.clone()).uri(uri);
+            if let Some(bearer) = bearer {
+                req = req.header(header::AUTHORIZATION, bearer);
+            }
+            match &body {
+                Some(body) => req
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(body.to_string())),
+                None => req.body(Body::empty()),
+            }
+            .unwrap()
+        };
+        let anonymous = send(&app, request(None)).await;
+        assert_eq!(
+            anonymous.status,
+            StatusCode::UNAUTHORIZED,
+            "{} {}",
+            method,
+            uri
+        );
+        let refused = send(&app, request(Some(member))).await;
+        assert_eq!(refused.status, StatusCode::FORBIDDEN, "{} {}", method, uri);
+        assert_eq!(refused.json()["code"], "admin_required");
+        let allowed = send(&app, request(Some(admin))).await;
+        assert!(
+            allowed.status.is_success(),
+            "{} {}: {} {}",
+            method,
+            uri,
+            allowed.status,
+            allowed.text()
+        );
+    }
+}
+
+#[tokio::test]
+async fn admins_can_log_in_during_maintenance() {
+    let db = TempDb::new();
+    // No admin key: the admin role is the only way back out.
+    let app = app_with(
+        &db,
+        "[auth]\nregistration = \"open\"\n[auth.jwt]\nsigning_key = \"0123456789abcdef0123456789abcdef\"\n",
+    )
+    .await;
+    let credentials = json!({"username": "first", "password": "correct horse"});
+    call(&app, Method::POST, "/auth/register", credentials.clone()).await;
+    let bearer =
+        |login: &Reply| format!("Bearer {}", login.json()["access_token"].as_str().unwrap());
+    let switch = |bearer: &str, mode: &str| {
+        Request::post("/admin/maintenance")
+            .header(header::AUTHORIZATION, bearer)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(json!({"mode": mode}).to_string()))
+            .unwrap()
+    };
+    let list = |bearer: &str| {
+        Request::get("/todos")
+            .header(header::AUTHORIZATION, bearer)
+            .body(Body::empty())
+            .unwrap()
+    };
+    let login = call(&app, Method::POST, "/auth/login", credentials.clone()).await;
+    let on = send(&app, switch(&bearer(&login), "full")).await;
+    assert_eq!(on.status, StatusCode::OK, "{}", on.text());
+    let refused = send(&app, list(&bearer(&login))).await;
+    assert_eq!(refused.status, StatusCode::SERVICE_UNAVAILABLE);
+    let registered = call(
+        &app,
+        Method::POST,
+        "/auth/register",
+        json!({"username": "second", "password": "correct horse"}),
+    )
+    .await;
+    assert_eq!(registered.status, StatusCode::SERVICE_UNAVAILABLE);
+
+    // The earlier token has expired, say; a fresh login still works.
+    let session = call(&app, Method::POST, "/auth/session", credentials.clone()).await;
+    assert_eq!(session.status, StatusCode::OK, "{}", session.text());
+    let login = call(&app, Method::POST, "/auth/login", credentials).await;
+    assert_eq!(login.status, StatusCode::OK, "{}", login.text());
+    let off = send(&app, switch(&bearer(&login), "off")).await;
+    assert_eq!(off.status, StatusCode::OK, "{}", off.text());
+    let listed = send(&app, list(&bearer(&login))).await;
+    assert_eq!(listed.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn accounts_tokens_and_sessions() {
+    let db = TempDb::new();
+    let app = app_with(
+        &db,
+        "[auth]\nregistration = \"open\"\n[auth.jwt]\nsigning_key = \"0123456789abcdef0123456789abcdef\"\n",
+    )
+    .await;
+    let credentials = json!({"username": "alice", "password": "correct horse"});
+
+    assert_eq!(get(&app, "/todos").await.status, StatusCode::UNAUTHORIZED);
+    let registered = call(&app, Method::POST, "/auth/register", credentials.clone()).await;
+    assert_eq!(
+        registered.status,
+        StatusCode::CREATED,
+        "{}",
+        registered.text()
+    );
+
+    let login = call(&app, Method::POST, "/auth/login", credentials.clone()).await;
+    assert_eq!(login.status, StatusCode::OK, "{}", login.text());
+    let bearer = format!("Bearer {}", login.json()["access_token"].as_str().unwrap());
+    let authed = |method: Method, uri: &str, body: Value| {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, &bearer)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+    let created = send(
+        &app,
+        authed(Method::POST, "/todos", json!({"title": "Mine"})),
+    )
+    .await;
+    assert_eq!(created.status, StatusCode::OK);
+    let listed = send(&app, authed(Method::GET, "/todos", json!(null))).await;
+    assert_eq!(listed.json().as_array().unwrap().len(), 1);
+
+    let refreshed = send(&app, authed(Method::POST, "/auth/refresh", json!(null))).await;
+    assert_eq!(refreshed.status, StatusCode::OK, "{}", refreshed.text());
+    assert!(refreshed.json()["access_token"].is_string());
+
+    let changed = send(
+        &app,
+        authed(
+            Method::POST,
+            "/auth/password",
+            json!({"current_password": "correct horse", "new_password": "battery staple"}),
+        ),
+    )
+    .await;
+    assert_eq!(changed.status, StatusCode::NO_CONTENT, "{}", changed.text());
+    let stale = call(&app, Method::POST, "/auth/login", credentials).await;
+    assert_eq!(stale.status, StatusCode::UNAUTHORIZED);
+
+    let session = call(
+        &app,
+        Method::POST,
+        "/auth/session",
+        json!({"username": "alice", "password": "battery staple"}),
+    )
+    .await;
+    assert_eq!(session.status, StatusCode::OK, "{}", session.text());
+    let cookie = session
+        .header("set-cookie")
+        .split(';')
+        .next()
+        .unwrap()
+        .to_string();
+    let csrf = session.json()["csrf_token"].as_str().unwrap().to_string();
+    let with_cookie = Request::get("/todos")
+        .header(header::COOKIE, &cookie)
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(send(&app, with_cookie).await.json()[0]["title"], "Mine");
+    let logout = Request::delete("/auth/session")
+        .header(header::COOKIE, &cookie)
+        .header("x-csrf-token", &csrf)
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(send(&app, logout).await.status, StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn github_sign_in_redirects() {
+    let db = TempDb::new();
+    let app = app_with(
+        &db,
+        "[auth.jwt]\nsigning_key = \"0123456789abcdef0123456789abcdef\"\n\
+         [auth.github]\nclient_id = \"client\"\nclient_secret = \"secret\"\n\
+         callback_url = \"http://localhost/auth/github/callback\"\n",
+    )
+    .await;
+
+    let start = get(&app, "/auth/github").await;
+    assert_eq!(start.status, StatusCode::SEE_OTHER);
+    assert!(start
+        .header("location")
+        .starts_with("https://github.com/login/oauth/authorize?"));
+    // A callback without the state cookie never reaches GitHub.
+    let callback = get(&app, "/auth/github/callback?code=c&state=s").await;
+    assert_eq!(callback.status, StatusCode::FORBIDDEN);
+}
+
+/// Routes served for browsers rather than API clients, and so left out of
+/// the OpenAPI document. Everything else `server.rs` routes must be in it.
+const UNDOCUMENTED: &[&str] = &[
+    "/",
+    "/admin",
+    "/app",
+    "/app/todos",
+    "/app/todos/{id}/delete",
+    "/app/todos/{id}/toggle",
+    "/auth/github",
+    "/auth/github/callback",
+    "/docs",
+    "/docs/openapi.json",
+    "/docs/{file}",
+    "/favicon.ico",
+    "/favicon.svg",
+    "/fragments/todos",
+    "/fragments/todos/new",
+    "/fragments/todos/{id}",
+    "/fragments/todos/{id}/edit",
+    "/fragments/todos/{id}/toggle",
+    "/graphiql",
+    "/graphql",
+    "/icon-192.png",
+    "/icon-512.png",
+    "/manifest.webmanifest",
+    "/robots.txt",
+    "/sw.js",
+    "/ws",
+];
+
+/// Every documented path in [`todo_api::server::paths`], as OpenAPI writes
+/// it, with the lowercase methods `app` serves it for: those the `Allow`
+/// header of its `405` for another method lists, `HEAD` aside. Requests
+/// are sent with `bearer`, or to the admin routes with the admin key
+/// `admin-secret`, so they get as far as the router.
+async fn registered_routes(app: &Router, bearer: &str) -> BTreeMap<String, BTreeSet<String>> {
+    let mut routes = BTreeMap::<String, BTreeSet<String>>::new();
+    for path in todo_api::server::paths() {
+        let segments = path.split('/');
+        let documented = segments
+            .clone()
+            .map(|segment| match segment.strip_prefix(':') {
+                Some(param) => format!("{{{}}}", param),
+                None => segment.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        if UNDOCUMENTED.contains(&documented.as_str()) {
+            continue;
+        }
+        let uri = segments
+            .map(|segment| {
+                if segment.starts_with(':') {
+                    "x"
+                } else {
+                    segment
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let mut request = Request::builder().method(Method::TRACE).uri(&uri);
+        request = if path.starts_with("/admin") || path == "/todos/trash" {
+            request.header("x-api-key", "admin-secret")
+        } else {
+            request.header(header::AUTHORIZATION, bearer)
+        };
+        let reply = send(app, request.body(Body::empty()).unwrap()).await;
+        assert_eq!(reply.status, StatusCode::METHOD_NOT_ALLOWED, "{}", path);
+        let methods = routes.entry(documented).or_default();
+        for method in reply.header("allow").split(',') {
+            let method = method.trim().to_lowercase();
+            if method != "head" {
+                methods.insert(method);
+            }
+        }
+    }
+    routes
+}
+
+#[tokio::test]
+async fn every_route_is_documented() {
+    let db = TempDb::new();
+    let app = app_with(
+        &db,
+        "[admin]\napi_key = \"admin-secret\"\n[auth]\nregistration = \"open\"\n[auth.jwt]\nsigning_key = \"0123456789abcdef0123456789abcdef\"\n",
+    )
+    .await;
+    let spec = get(&app, "/docs/openapi.json").await.json();
+    let documented: BTreeMap<String, BTreeSet<String>> = spec["paths"]
+        .as_object()
+        .unwrap()
+        .iter()
+        .map(|(path, item)| {
+            let methods = item.as_object().unwrap().keys().cloned().collect();
+            (path.clone(), methods)
+        })
+        .collect();
+
+    assert_eq!(
+        spec["paths"]["/todos"]["get"]["responses"]["500"]["content"]["application/json"]["schema"]
+            ["$ref"],
+        "#/components/schemas/ErrorBody"
+    );
+
+    let credentials = json!({"username": "docs", "password": "correct horse"});
+    call(&app, Method::POST, "/auth/register", credentials.clone()).await;
+    let login = call(&app, Method::POST, "/auth/login", credentials).await;
+    let bearer = format!("Bearer {}", login.json()["access_token"].as_str().unwrap());
+    let routed = registered_routes(&app, &bearer).await;
+    assert_eq!(routed, documented);
+}
@@ -0,0 +1,293 @@
+//! `/graphql` driven in-process, next to the REST routes on the same
+//! state. Runs with `cargo test --features graphql`.
+
+#![cfg(feature = "graphql")]
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use axum::Router;
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+use todo_api::config::Config;
+
+const TODO_FIELDS: &str = "id title completed completedAt tags dueDate priority archived";
+
+/// The app configured by `toml` on a fresh in-memory database.
+async fn app_with(toml: &str) -> Router {
+    let config: Config = toml::from_str(toml).expect("test configuration");
+    let db = todo_api::init_db("sqlite::memory:")
+        .await
+        .expect("in-memory database");
+    todo_api::server::build(config, db)
+        .await
+        .expect("app for the test configuration")
+        .app
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body = serde_json::from_slice(&body)
+        .unwrap_or_else(|err| panic!("{}: {}", err, String::from_utf8_lossy(&body)));
+    (status, body)
+}
+
+/// POSTs `query` with `variables`, expecting a GraphQL response.
+async fn graphql(app: &Router, query: &str, variables: Value) -> Value {
+    let request = Request::post("/graphql")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            json!({"query": query, "variables": variables}).to_string(),
+        ))
+        .unwrap();
+    let (status, body) = send(app, request).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    body
+}
+
+/// The `data` of a response that must have no errors.
+async fn data(app: &Router, query: &str, variables: Value) -> Value {
+    let body = graphql(app, query, variables).await;
+    assert!(body.get("errors").is_none(), "{}", body);
+    body["data"].clone()
+}
+
+#[tokio::test]
+async fn round_trips_a_todo() {
+    let app = app_with("").await;
+
+    let created = data(
+        &app,
+        &format!(
+            "mutation Create($input: CreateTodoInput!) {{ createTodo(input: $input) {{ {} }} }}",
+            TODO_FIELDS
+        ),
+        json!({"input": {"title": "Call the plumber", "tags": ["Home"], "dueIn": "P2D"}}),
+    )
+    .await["createTodo"]
+        .clone();
+    assert_eq!(created["title"], "Call the plumber");
+    assert_eq!(created["tags"], json!(["home"]));
+    assert_eq!(created["priority"], 3);
+    assert!(created["dueDate"].is_string(), "{}", created);
+    let id = created["id"].as_str().unwrap().to_string();
+
+    // The same todo, through the REST routes on the same state.
+    let (status, rest) = send(
+        &app,
+        Request::get(format!("/todos/{}", id))
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(rest["title"], "Call the plumber");
+
+    let fetched = data(
+        &app,
+        &format!("query($id: ID!) {{ todo(id: $id) {{ {} }} }}", TODO_FIELDS),
+        json!({"id": id}),
+    )
+    .await;
+    assert_eq!(fetched["todo"], created);
+
+    let updated = data(
+        &app,
+        "mutation($id: ID!) { updateTodo(id: $id, input: {priority: 5, dueDate: null}) { priority dueDate } }",
+        json!({"id": id}),
+    )
+    .await;
+    assert_eq!(
+        updated["updateTodo"],
+        json!({"priority": 5, "dueDate": null})
+    );
+
+    let toggled = data(
+        &app,
+        "mutation($id: ID!) { toggleTodo(id: $id) { completed completedAt } }",
+        json!({"id": id}),
+    )
+    .await;
+    assert_eq!(toggled["toggleTodo"]["completed"], true);
+    assert!(toggled["toggleTodo"]["completedAt"].is_string());
+
+    let deleted = data(
+        &app,
+        "mutation($id: ID!) { deleteTodo(id: $id) }",
+        json!({"id": id}),
+    )
+    .await;
+    assert_eq!(deleted["deleteTodo"], id);
+    let gone = data(
+        &app,
+        "query($id: ID!) { todo(id: $id) { id } }",
+        json!({"id": id}),
+    )
+    .await;
+    assert_eq!(gone["todo"], Value::Null);
+}
+
+#[tokio::test]
+async fn pages_and_filters_the_list() {
+    let app = app_with("").await;
+    for (title, tags) in [
+        ("Buy milk", json!(["shop"])),
+        ("Buy eggs", json!(["shop"])),
+        ("Fix the sink", json!(["home"])),
+    ] {
+        data(
+            &app,
+            "mutation($input: CreateTodoInput!) { createTodo(input: $input) { id } }",
+            json!({"input": {"title": title, "tags": tags}}),
+        )
+        .await;
+    }
+
+    let query = "query($after: String) {
+        todos(filter: {tags: [\"shop\"], tagMode: ANY}, limit: 1, after: $after) {
+            items { title } totalCount endCursor hasNextPage
+        }
+    }";
+    let first = data(&app, query, json!({})).await["todos"].clone();
+    assert_eq!(first["items"], json!([{"title": "Buy milk"}]));
+    assert_eq!(first["totalCount"], 2);
+    assert_eq!(first["hasNextPage"], true);
+    let second = data(&app, query, json!({"after": first["endCursor"]})).await["todos"].clone();
+    assert_eq!(second["items"], json!([{"title": "Buy eggs"}]));
+    assert_eq!(second["hasNextPage"], false);
+
+    // Fragments, aliases, directives and GET, with the whole list.
+    let query = "query($q: Boolean!) {
+        all: todos { ...Page }
+        open: todos(filter: {completed: false}) @include(if: $q) { totalCount }
+    }
+    fragment Page on TodoPage { totalCount items { __typename title } }";
+    let (status, body) = send(
+        &app,
+        get(&[("query", query), ("variables", r#"{"q": true}"#)]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["data"]["all"]["totalCount"], 3);
+    assert_eq!(
+        body["data"]["all"]["items"][2],
+        json!({"__typename": "Todo", "title": "Fix the sink"})
+    );
+    assert_eq!(body["data"]["open"]["totalCount"], 3);
+
+    // Introspection, as GraphiQL asks for it.
+    let schema = data(
+        &app,
+        "{ __schema { queryType { name } types { name kind } } __type(name: \"Todo\") { fields { name type { kind ofType { name } } } } }",
+        json!({}),
+    )
+    .await;
+    assert_eq!(schema["__schema"]["queryType"]["name"], "Query");
+    assert!(schema["__schema"]["types"]
+        .as_array()
+        .unwrap()
+        .contains(&json!({"name": "TodoPage", "kind": "OBJECT"})));
+    assert_eq!(
+        schema["__type"]["fields"][0],
+        json!({"name": "id", "type": {"kind": "NON_NULL", "ofType": {"name": "ID"}}})
+    );
+}
+
+#[tokio::test]
+async fn errors_carry_the_rest_codes() {
+    let app = app_with("").await;
+
+    let parse = graphql(&app, "{ todos { ", json!({})).await;
+    assert!(parse.get("data").is_none(), "{}", parse);
+    assert_eq!(
+        parse["errors"][0]["extensions"]["code"],
+        "graphql_parse_failed"
+    );
+    assert_eq!(parse["errors"][0]["locations"][0]["line"], 1);
+
+    let unknown = graphql(&app, "{ todos { items { nope } } }", json!({})).await;
+    assert!(unknown.get("data").is_none(), "{}", unknown);
+    assert_eq!(
+        unknown["errors"][0]["message"],
+        "Cannot query field \"nope\" on type \"Todo\"."
+    );
+    assert_eq!(
+        unknown["errors"][0]["extensions"]["code"],
+        "graphql_validation_failed"
+    );
+
+    // Validation fails as `POST /todos` does, field by field.
+    let invalid = graphql(
+        &app,
+        "mutation { createTodo(input: {title: \"\", priority: 9}) { id } }",
+        json!({}),
+    )
+    .await;
+    assert_eq!(invalid["data"], Value::Null);
+    let error = &invalid["errors"][0];
+    assert_eq!(error["path"], json!(["createTodo"]));
+    assert_eq!(error["extensions"]["status"], 422);
+    assert!(
+        error["extensions"]["errors"]["title"].is_array(),
+        "{}",
+        error
+    );
+    assert!(
+        error["extensions"]["errors"]["priority"].is_array(),
+        "{}",
+        error
+    );
+    assert!(error["extensions"]["request_id"].is_string(), "{}", error);
+
+    // A nullable field fails alone; the rest of the data is kept.
+    let missing = graphql(
+        &app,
+        "{ todos { totalCount } todo(id: \"nope\") { id } }",
+        json!({}),
+    )
+    .await;
+    assert_eq!(missing["data"]["todos"]["totalCount"], 0);
+    assert_eq!(missing["data"]["todo"], Value::Null);
+    assert!(missing.get("errors").is_none(), "{}", missing);
+    let not_found = graphql(&app, "mutation { deleteTodo(id: \"nope\") }", json!({})).await;
+    assert_eq!(not_found["errors"][0]["extensions"]["status"], 404);
+
+    // Mutations are not run from a GET.
+    let request = get(&[("query", "mutation { deleteTodo(id: \"x\") }")]);
+    let (status, _) = send(&app, request).await;
+    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+}
+
+#[tokio::test]
+async fn mutations_are_refused_in_read_only_maintenance() {
+    let app = app_with("[admin]\napi_key = \"admin-secret\"\n").await;
+    let request = Request::post("/admin/maintenance")
+        .header("x-api-key", "admin-secret")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({"mode": "read_only"}).to_string()))
+        .unwrap();
+    let (status, body) = send(&app, request).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    // Queries are POSTed too, and still answered.
+    data(&app, "{ todos { totalCount } }", json!({})).await;
+    let request = Request::post("/graphql")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            json!({"query": "mutation { createTodo(input: {title: \"x\"}) { id } }"}).to_string(),
+        ))
+        .unwrap();
+    let (status, body) = send(&app, request).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["code"], "maintenance");
+}
+
+/// `GET /graphql` with `params` in the query string.
+fn get(params: &[(&str, &str)]) -> Request<Body> {
+    let query = serde_urlencoded::to_string(params).unwrap();
+    Request::get(format!("/graphql?{}", query))
+        .body(Body::empty())
+        .unwrap()
+}
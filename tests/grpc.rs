@@ -0,0 +1,151 @@
+//! `TodoService` over a real port, driven by its client, next to the
+//! router on the same state. Runs with `cargo test --features grpc`.
+
+#![cfg(feature = "grpc")]
+
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use tokio::sync::watch;
+use tonic::Code;
+use tower::ServiceExt;
+
+use todo_api::config::Config;
+use todo_api::grpc::proto::{
+    CreateTodoRequest, DeleteTodoRequest, GetTodoRequest, ListTodosRequest, TodoServiceClient,
+    UpdateTodoRequest, WatchTodosRequest,
+};
+use todo_api::AppState;
+
+#[tokio::test]
+async fn round_trips_a_todo() {
+    let db = todo_api::init_db("sqlite::memory:")
+        .await
+        .expect("in-memory database");
+    let state = AppState::new(Config::default(), db).await.expect("state");
+    let app = todo_api::server::todo_router(state.clone());
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (shutdown, stopped) = watch::channel(());
+    let server = tokio::spawn(todo_api::grpc::serve(state, listener, stopped));
+    let mut client = TodoServiceClient::connect(format!("http://{}", addr))
+        .await
+        .expect("connect");
+    let mut events = client
+        .watch(WatchTodosRequest {})
+        .await
+        .expect("watch")
+        .into_inner();
+
+    let created = client
+        .create(CreateTodoRequest {
+            title: "Call the plumber".into(),
+            tags: vec!["Home".into()],
+            due_in: Some("P2D".into()),
+            ..Default::default()
+        })
+        .await
+        .expect("create")
+        .into_inner();
+    assert_eq!(created.title, "Call the plumber");
+    assert_eq!(created.tags, ["home"]);
+    assert_eq!(created.priority, 3);
+    assert!(!created.due_date.is_empty());
+
+    let fetched = client
+        .get(GetTodoRequest {
+            id: created.id.clone(),
+        })
+        .await
+        .expect("get")
+        .into_inner();
+    assert_eq!(fetched, created);
+
+    // The same todo, through the REST routes on the same state.
+    let reply = app
+        .clone()
+        .oneshot(
+            Request::get(format!("/todos/{}", created.id))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(reply.status(), StatusCode::OK);
+
+    let updated = client
+        .update(UpdateTodoRequest {
+            id: created.id.clone(),
+            completed: Some(true),
+            clear_due_date: true,
+            ..Default::default()
+        })
+        .await
+        .expect("update")
+        .into_inner();
+    assert!(updated.completed);
+    assert!(!updated.completed_at.is_empty());
+    assert!(updated.due_date.is_empty());
+    assert_eq!(updated.tags, ["home"]);
+
+    let list = client
+        .list(ListTodosRequest {
+            completed: Some("true".into()),
+            ..Default::default()
+        })
+        .await
+        .expect("list")
+        .into_inner();
+    assert_eq!(list.total, 1);
+    assert_eq!(list.todos, [updated]);
+
+    let event = tokio::time::timeout(Duration::from_secs(5), events.message())
+        .await
+        .expect("an event in time")
+        .expect("stream open")
+        .expect("an event");
+    assert_eq!(event.action, "created");
+    assert_eq!(event.todo_id, created.id);
+
+    // Validation and missing todos fail as they do over REST.
+    let invalid = client
+        .create(CreateTodoRequest {
+            priority: Some(9),
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(invalid.code(), Code::InvalidArgument);
+    let details: serde_json::Value = serde_json::from_slice(invalid.details()).unwrap();
+    assert!(details["errors"]["title"].is_array(), "{}", details);
+    assert!(details["errors"]["priority"].is_array(), "{}", details);
+
+    client
+        .delete(DeleteTodoRequest {
+            id: created.id.clone(),
+            purge: false,
+        })
+        .await
+        .expect("delete");
+    let gone = client
+        .get(GetTodoRequest { id: created.id })
+        .await
+        .unwrap_err();
+    assert_eq!(gone.code(), Code::NotFound);
+
+    // Shutdown ends the watch and lets the server return.
+    shutdown.send(()).unwrap();
+    let ended = tokio::time::timeout(Duration::from_secs(5), async {
+        while events.message().await.ok().flatten().is_some() {}
+    })
+    .await;
+    assert!(ended.is_ok(), "watch still open after shutdown");
+    drop(client);
+    tokio::time::timeout(Duration::from_secs(5), server)
+        .await
+        .expect("server stopped in time")
+        .unwrap()
+        .expect("server ran cleanly");
+}
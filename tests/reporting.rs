@@ -0,0 +1,89 @@
+//! Sentry reports, caught by a transport that keeps them in memory instead
+//! of sending them. Runs with `cargo test --features sentry`; reporting is
+//! process-wide, so it lives in a test binary of its own.
+
+#![cfg(feature = "sentry")]
+
+use std::sync::{Arc, Mutex};
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use tower::ServiceExt;
+
+use todo_api::reporting::{self, ReportingConfig};
+
+#[derive(Default)]
+struct Recorded(Mutex<Vec<sentry::Envelope>>);
+
+impl Recorded {
+    /// The events sent since the last call.
+    fn take(&self) -> Vec<sentry::protocol::Event<'static>> {
+        self.0
+            .lock()
+            .unwrap()
+            .drain(..)
+            .filter_map(|envelope| envelope.event().cloned())
+            .collect()
+    }
+}
+
+impl sentry::Transport for Recorded {
+    fn send_envelope(&self, envelope: sentry::Envelope) {
+        self.0.lock().unwrap().push(envelope);
+    }
+}
+
+#[tokio::test]
+async fn internal_errors_and_panics_are_reported() {
+    let recorded = Arc::new(Recorded::default());
+    let config = ReportingConfig {
+        sentry_dsn: Some("https://key@sentry.invalid/1".parse().unwrap()),
+    };
+    let _guard = reporting::init_with_transport(&config, Some(Arc::new(recorded.clone())));
+
+    let db = todo_api::init_db("sqlite::memory:")
+        .await
+        .expect("in-memory database");
+    let app = todo_api::app(db.clone()).await;
+    let get = |uri: &str| {
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let request_id = response.headers()["x-request-id"]
+                .to_str()
+                .unwrap()
+                .to_string();
+            (response.status(), request_id)
+        }
+    };
+
+    // Client errors are not reported.
+    let (status, _) = get("/todos/no-such-todo").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = get("/todos?limit=0").await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(recorded.take().is_empty());
+
+    let (status, request_id) = get("/__test/panic").await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    let events = recorded.take();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].tags["request_id"], request_id);
+    assert_eq!(events[0].tags["http.method"], "GET");
+    assert_eq!(events[0].tags["http.path"], "/__test/panic");
+    assert!(events[0]
+        .message
+        .as_deref()
+        .unwrap_or_default()
+        .contains("test route panicked"));
+
+    // A database that went away.
+    db.close().await;
+    let (status, request_id) = get("/todos").await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    let events = recorded.take();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].tags["request_id"], request_id);
+    assert_eq!(events[0].tags["http.path"], "/todos");
+}